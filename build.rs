@@ -8,7 +8,7 @@ use naga::{
 };
 
 fn main() {
-    let shader_vert = fs::write(
+    fs::write(
         "./shaders/vert.spv",
         compile_shader(
             &fs::read_to_string("./shaders/shader.vert").unwrap(),
@@ -16,10 +16,11 @@ fn main() {
         )
         .unwrap()
         .iter()
-        .map(|value| *value as u8)
+        .flat_map(|value| value.to_le_bytes())
         .collect::<Vec<u8>>(),
-    );
-    let shader_vert = fs::write(
+    )
+    .unwrap();
+    fs::write(
         "./shaders/frag.spv",
         compile_shader(
             &fs::read_to_string("./shaders/shader.frag").unwrap(),
@@ -27,9 +28,10 @@ fn main() {
         )
         .unwrap()
         .iter()
-        .map(|value| *value as u8)
+        .flat_map(|value| value.to_le_bytes())
         .collect::<Vec<u8>>(),
-    );
+    )
+    .unwrap();
 }
 
 fn compile_shader(src: &str, stage: ShaderStage) -> Result<Vec<u32>, String> {
@@ -1,12 +1,64 @@
-use alovak::{vulkan::Vulkan, win32::WindowWin32, Window};
+use std::time::Duration;
+
+use alovak::{
+    record::{RecordOutput, Recorder},
+    Alovak, App, AppContext, ElementState, Event,
+};
 use casopis::Casopis;
 use log::Level;
 
-#[tokio::main]
-async fn main() {
+struct Demo;
+
+impl App for Demo {
+    fn init(&mut self, ctx: &mut AppContext) -> alovak::Result<()> {
+        log::info!("window size: {:?}", ctx.window().inner_size()?);
+        Ok(())
+    }
+
+    fn event(&mut self, ctx: &mut AppContext, event: &Event) {
+        log::trace!("{:?}", event);
+        // F12 takes a screenshot.
+        if let Event::KeyboardInput {
+            key: 0x7b,
+            state: ElementState::Pressed,
+            ..
+        } = event
+        {
+            ctx.capture().unwrap();
+        }
+        // F9 starts and stops recording numbered PNGs at 60 frames per second.
+        if let Event::KeyboardInput {
+            key: 0x78,
+            state: ElementState::Pressed,
+            ..
+        } = event
+        {
+            if ctx.recorder().is_some() {
+                ctx.stop_recording().unwrap();
+                log::info!("recording saved to recording/");
+            } else {
+                ctx.start_recording(Recorder::new(RecordOutput::png("recording"), 60))
+                    .unwrap();
+            }
+        }
+    }
+
+    fn update(&mut self, ctx: &mut AppContext, _dt: Duration) {
+        if let Some(screenshot) = ctx.take_capture().unwrap() {
+            screenshot.save_png("screenshot.png").unwrap();
+            log::info!("screenshot saved to screenshot.png");
+        }
+    }
+}
+
+fn main() {
     Casopis::init(Level::Trace).unwrap();
 
-    let win: WindowWin32 = WindowWin32::create("alovak\0").unwrap();
-    
-    let vulkan = Vulkan::init(win.handle().unwrap()).unwrap();
+    Alovak::builder()
+        .title("alovak")
+        .size(800, 600)
+        .clear_color([0.1, 0.1, 0.12, 1.0])
+        .build()
+        .run(Demo)
+        .unwrap();
 }
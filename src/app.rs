@@ -0,0 +1,635 @@
+use std::{
+    thread,
+    time::{Duration, Instant},
+};
+
+use tokio::runtime::{Handle, Runtime};
+
+use crate::{
+    error::Result,
+    record::Recorder,
+    vulkan::{ColorFormat, DepthBuffer, Frame, PresentMode, SurfaceId, Vulkan},
+    AssetServer, Error, Event, RgbaImage, Window,
+};
+
+#[derive(Debug, Clone)]
+pub struct WindowConfig {
+    pub title: String,
+    /// Client area size in pixels, `None` lets the system decide.
+    pub size: Option<(u32, u32)>,
+}
+
+impl Default for WindowConfig {
+    fn default() -> Self {
+        WindowConfig {
+            title: "alovak".to_owned(),
+            size: None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct RendererConfig {
+    /// Color every frame starts with.
+    pub clear_color: [f32; 4],
+    /// Presentation policy; `None` follows `TimingConfig::frame_pacing`.
+    pub present_mode: Option<PresentMode>,
+    /// Requested swapchain format; falls back to 8-bit sRGB when the display can't do it.
+    pub color_format: ColorFormat,
+    pub depth_buffer: Option<DepthBuffer>,
+    /// Samples per pixel, lowered to what the device supports; 1 turns MSAA off.
+    pub samples: u32,
+}
+
+impl Default for RendererConfig {
+    fn default() -> Self {
+        RendererConfig {
+            clear_color: [0.0, 0.0, 0.0, 1.0],
+            present_mode: None,
+            color_format: ColorFormat::Srgb8,
+            depth_buffer: None,
+            samples: 1,
+        }
+    }
+}
+
+/// How the run loop paces rendered frames. Unless a present mode is set explicitly, each
+/// option picks the matching one.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FramePacing {
+    /// Presents once per display refresh, with `PresentMode::VSync`.
+    VSync,
+    /// Renders at most this many frames per second, with `PresentMode::VSyncOff`.
+    Limit(u32),
+    /// Renders as fast as possible, with `PresentMode::LowLatency`.
+    Uncapped,
+}
+
+impl FramePacing {
+    pub fn present_mode(self) -> PresentMode {
+        match self {
+            FramePacing::VSync => PresentMode::VSync,
+            FramePacing::Limit(_) => PresentMode::VSyncOff,
+            FramePacing::Uncapped => PresentMode::LowLatency,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct TimingConfig {
+    /// Interval between `App::fixed_update` calls.
+    pub fixed_timestep: Duration,
+    /// Most fixed updates run in one frame. When the simulation falls further behind, the
+    /// rest of the time is dropped instead of piling up.
+    pub max_fixed_steps: u32,
+    pub frame_pacing: FramePacing,
+}
+
+impl Default for TimingConfig {
+    fn default() -> Self {
+        TimingConfig {
+            fixed_timestep: Duration::from_secs(1) / 60,
+            max_fixed_steps: 5,
+            frame_pacing: FramePacing::VSync,
+        }
+    }
+}
+
+/// Hooks called by `Alovak::run`. Every hook has an empty default.
+pub trait App {
+    /// Called once, after the window and the renderer are created.
+    fn init(&mut self, _ctx: &mut AppContext) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called for every window event, before `update`.
+    fn event(&mut self, _ctx: &mut AppContext, _event: &Event) {}
+
+    /// Advances the simulation by exactly `step`, the configured fixed timestep. Runs zero
+    /// or more times per frame, before `update`.
+    fn fixed_update(&mut self, _ctx: &mut AppContext, _step: Duration) {}
+
+    /// Called once per frame; `dt` is the time since the previous frame.
+    fn update(&mut self, _ctx: &mut AppContext, _dt: Duration) {}
+
+    /// Records the frame into `frame.command_buffer()`. Not called while the window is
+    /// minimized. `alpha` in `0.0..1.0` is how far the frame lies between the last fixed
+    /// update and the next one, for interpolating simulation state.
+    fn render(&mut self, _ctx: &mut AppContext, _frame: &Frame, _alpha: f32) -> Result<()> {
+        Ok(())
+    }
+
+    /// Called once before shutdown, with the device idle and the renderer still alive.
+    fn exit(&mut self, _ctx: &mut AppContext) {}
+}
+
+/// What the hooks of an `App` get to work with.
+pub struct AppContext<'w> {
+    window: &'w dyn Window,
+    vulkan: Vulkan<'w>,
+    surface: SurfaceId,
+    assets: AssetServer,
+    timing: TimingConfig,
+    present_mode: Option<PresentMode>,
+    recorder: Option<Recorder>,
+    exit: bool,
+}
+
+impl<'w> AppContext<'w> {
+    pub fn window(&self) -> &'w dyn Window {
+        self.window
+    }
+
+    pub fn vulkan(&self) -> &Vulkan<'w> {
+        &self.vulkan
+    }
+
+    pub fn vulkan_mut(&mut self) -> &mut Vulkan<'w> {
+        &mut self.vulkan
+    }
+
+    /// Surface of the application window.
+    pub fn surface(&self) -> SurfaceId {
+        self.surface
+    }
+
+    /// Assets loading in the background. The run loop uploads them every frame, so they
+    /// are ready from the `render` of the frame their loading finished in.
+    pub fn assets(&self) -> &AssetServer {
+        &self.assets
+    }
+
+    pub fn assets_mut(&mut self) -> &mut AssetServer {
+        &mut self.assets
+    }
+
+    pub fn timing(&self) -> &TimingConfig {
+        &self.timing
+    }
+
+    /// Switches frame pacing, rebuilding the swapchain if the present mode follows it.
+    pub fn set_frame_pacing(&mut self, pacing: FramePacing) -> Result<()> {
+        self.timing.frame_pacing = pacing;
+        self.apply_present_mode()
+    }
+
+    /// Present mode in effect, explicit or derived from the frame pacing.
+    pub fn present_mode(&self) -> PresentMode {
+        self.present_mode
+            .unwrap_or(self.timing.frame_pacing.present_mode())
+    }
+
+    /// Overrides the present mode of the frame pacing, `None` goes back to it. Rebuilds the
+    /// swapchain when the mode changes.
+    pub fn set_present_mode(&mut self, mode: Option<PresentMode>) -> Result<()> {
+        self.present_mode = mode;
+        self.apply_present_mode()
+    }
+
+    /// Applies the renderer settings and the window size, which the first swapchain was
+    /// made without, with a single rebuild.
+    fn configure(&mut self, renderer: &RendererConfig) -> Result<()> {
+        let (width, height) = self.window.inner_size()?;
+        let mode = self.present_mode();
+        if let Some(surface) = self.vulkan.surface_mut(self.surface) {
+            surface.set_clear_color(renderer.clear_color);
+            surface.set_window_size(width, height);
+            surface.set_preferred_present_modes(mode.candidates());
+            surface.request_color_format(renderer.color_format);
+            surface.request_depth_buffer(renderer.depth_buffer);
+            surface.request_samples(renderer.samples);
+        }
+        self.vulkan.recreate_swapchain(self.surface)?;
+        log::trace!("alovak surface format {:?}", self.color_format());
+        Ok(())
+    }
+
+    fn apply_present_mode(&mut self) -> Result<()> {
+        let mode = self.present_mode();
+        self.vulkan.set_present_mode(self.surface, mode)
+    }
+
+    /// Color format the swapchain actually got, which may differ from the requested one.
+    pub fn color_format(&self) -> Option<ColorFormat> {
+        self.vulkan
+            .surface(self.surface)
+            .and_then(|surface| surface.color_format())
+    }
+
+    /// Requests another swapchain color format, rebuilding the swapchain if it changes.
+    pub fn set_color_format(&mut self, color_format: ColorFormat) -> Result<()> {
+        self.vulkan.set_color_format(self.surface, color_format)
+    }
+
+    /// Captures the next presented frame, e.g. for a screenshot hotkey. Pick it up with
+    /// `take_capture` a few frames later.
+    pub fn capture(&mut self) -> Result<()> {
+        self.vulkan.capture(self.surface)
+    }
+
+    pub fn take_capture(&mut self) -> Result<Option<RgbaImage>> {
+        self.vulkan.take_capture(self.surface)
+    }
+
+    /// Starts recording frames, ending a recording already running. While recording, the
+    /// run loop advances by the recorder's timestep every frame instead of the wall clock,
+    /// and captures go to the recorder instead of `take_capture`.
+    pub fn start_recording(&mut self, recorder: Recorder) -> Result<()> {
+        self.stop_recording()?;
+        self.recorder = Some(recorder);
+        Ok(())
+    }
+
+    /// Waits for the frames still in flight, writes them and finishes the recording.
+    pub fn stop_recording(&mut self) -> Result<()> {
+        if self.recorder.is_none() {
+            return Ok(());
+        }
+        self.vulkan.flush_captures(self.surface)?;
+        self.write_captures()?;
+        match self.recorder.take() {
+            Some(recorder) => recorder.finish(),
+            None => Ok(()),
+        }
+    }
+
+    pub fn recorder(&self) -> Option<&Recorder> {
+        self.recorder.as_ref()
+    }
+
+    /// Hands finished captures to the recorder, finishing it once its limit is reached.
+    fn write_captures(&mut self) -> Result<()> {
+        let Some(recorder) = &mut self.recorder else {
+            return Ok(());
+        };
+        while let Some(image) = self.vulkan.take_capture(self.surface)? {
+            recorder.write(&image)?;
+        }
+        if recorder.is_done() {
+            if let Some(recorder) = self.recorder.take() {
+                recorder.finish()?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Stops the run loop after the current frame and closes the window.
+    pub fn exit(&mut self) {
+        self.exit = true;
+    }
+}
+
+/// Application entry point: creates the window and the renderer, then drives an `App`
+/// until the window is closed.
+pub struct Alovak {
+    window: WindowConfig,
+    renderer: RendererConfig,
+    timing: TimingConfig,
+    record: Option<Recorder>,
+}
+
+impl Alovak {
+    pub fn builder() -> AlovakBuilder {
+        AlovakBuilder::default()
+    }
+
+    pub fn window_config(&self) -> &WindowConfig {
+        &self.window
+    }
+
+    pub fn renderer_config(&self) -> &RendererConfig {
+        &self.renderer
+    }
+
+    pub fn timing_config(&self) -> &TimingConfig {
+        &self.timing
+    }
+
+    /// Blocks until the window is closed or the app calls `AppContext::exit`. Uses the
+    /// current tokio runtime, or starts one if there is none.
+    pub fn run<A: App>(self, app: A) -> Result<()> {
+        let runtime = match Handle::try_current() {
+            Ok(_) => None,
+            Err(_) => Some(Runtime::new().map_err(Error::Io)?),
+        };
+        let _guard = runtime.as_ref().map(|runtime| runtime.enter());
+
+        #[cfg(windows)]
+        {
+            let title = format!("{}\0", self.window.title);
+            let window = match self.window.size {
+                Some((width, height)) => {
+                    crate::win32::WindowWin32::create_with_size(&title, width, height)?
+                }
+                None => crate::win32::WindowWin32::create(&title)?,
+            };
+            self.run_in(&window, app)
+        }
+        #[cfg(not(windows))]
+        {
+            _ = app;
+            Err(Error::Other(
+                "Window backend isn`t available on this platform".to_owned(),
+            ))
+        }
+    }
+
+    #[cfg_attr(not(windows), allow(dead_code))]
+    fn run_in<W: Window, A: App>(self, window: &W, mut app: A) -> Result<()> {
+        let vulkan = Vulkan::init(window)?;
+        let surface = vulkan.surface_ids().next().unwrap();
+        let mut ctx = AppContext {
+            window,
+            vulkan,
+            surface,
+            assets: AssetServer::new()?,
+            timing: self.timing,
+            present_mode: self.renderer.present_mode,
+            recorder: self.record,
+            exit: false,
+        };
+        let result = ctx
+            .configure(&self.renderer)
+            .and_then(|()| Self::run_loop(&mut ctx, &mut app));
+        let recorded = ctx.stop_recording();
+
+        _ = unsafe { ctx.vulkan.context().device.device_wait_idle() };
+        app.exit(&mut ctx);
+        ctx.assets.destroy(ctx.vulkan.context());
+        // Closing the window only reports it, the window goes once Vulkan is done with it.
+        drop(ctx);
+        let closed = window.close();
+        result.and(recorded).and(closed)
+    }
+
+    fn run_loop<A: App>(ctx: &mut AppContext, app: &mut A) -> Result<()> {
+        app.init(ctx)?;
+        log::trace!("alovak app initialized");
+
+        let mut last_frame = Instant::now();
+        let mut next_frame = last_frame;
+        let mut fixed_step = FixedStep::new(ctx.timing.fixed_timestep, ctx.timing.max_fixed_steps);
+        loop {
+            let mut closed = false;
+            while let Some(event) = ctx.window.poll_event() {
+                match event {
+                    Event::Resized { width, height } if width > 0 && height > 0 => {
+                        ctx.vulkan.set_window_size(ctx.surface, width, height)?;
+                        ctx.vulkan.recreate_swapchain(ctx.surface)?;
+                    }
+                    Event::CloseRequested | Event::Destroyed => closed = true,
+                    _ => {}
+                }
+                app.event(ctx, &event);
+            }
+            if closed || ctx.exit {
+                log::trace!("alovak app closing");
+                return Ok(());
+            }
+
+            // Recording runs on a simulated clock, as fast as frames can be rendered.
+            if let (FramePacing::Limit(fps), None) = (ctx.timing.frame_pacing, &ctx.recorder) {
+                wait_for_frame(&mut next_frame, fps);
+            }
+            let now = Instant::now();
+            let dt = match &ctx.recorder {
+                Some(recorder) => recorder.timestep(),
+                None => now - last_frame,
+            };
+            last_frame = now;
+
+            for _ in 0..fixed_step.advance(dt) {
+                app.fixed_update(ctx, fixed_step.step);
+            }
+            let alpha = fixed_step.alpha();
+            app.update(ctx, dt);
+
+            if ctx.recorder.as_mut().is_some_and(Recorder::next_frame) {
+                ctx.vulkan.capture(ctx.surface)?;
+            }
+            match ctx.vulkan.begin_frame(ctx.surface)? {
+                Some(frame) => {
+                    ctx.assets.update(ctx.vulkan.context());
+                    app.render(ctx, &frame, alpha)?;
+                    ctx.vulkan.end_frame(ctx.surface, frame)?;
+                }
+                // Minimized: nothing to present, so don't spin.
+                None => thread::sleep(Duration::from_millis(10)),
+            }
+            ctx.write_captures()?;
+        }
+    }
+}
+
+/// Splits frame time into fixed updates, carrying what is left over to the next frame.
+#[derive(Debug, Clone)]
+struct FixedStep {
+    /// Zero turns fixed updates off.
+    step: Duration,
+    max_steps: u32,
+    accumulator: Duration,
+}
+
+impl FixedStep {
+    fn new(step: Duration, max_steps: u32) -> Self {
+        FixedStep {
+            step,
+            max_steps,
+            accumulator: Duration::ZERO,
+        }
+    }
+
+    /// Adds `dt` and returns how many fixed updates are due, at most `max_steps`. Time
+    /// beyond that is dropped, so a slow frame doesn't make the next one slower still.
+    fn advance(&mut self, dt: Duration) -> u32 {
+        if self.step.is_zero() {
+            return 0;
+        }
+        self.accumulator += dt;
+        let max_lag = self.step * self.max_steps;
+        if self.accumulator > max_lag {
+            log::trace!(
+                "alovak app dropped {:?} of simulation",
+                self.accumulator - max_lag
+            );
+            self.accumulator = max_lag;
+        }
+        let steps = (self.accumulator.as_nanos() / self.step.as_nanos()) as u32;
+        self.accumulator -= self.step * steps;
+        steps
+    }
+
+    /// How far the time left over lies towards the next fixed update, in `0.0..1.0`.
+    fn alpha(&self) -> f32 {
+        if self.step.is_zero() {
+            return 0.0;
+        }
+        let alpha = self.accumulator.as_secs_f64() / self.step.as_secs_f64();
+        // Rounding to f32 could turn a fraction just below 1 into 1.
+        (alpha as f32).min(1.0 - f32::EPSILON / 2.0)
+    }
+}
+
+/// Sleeps until `next_frame`, then schedules the frame after it `1 / fps` later.
+fn wait_for_frame(next_frame: &mut Instant, fps: u32) {
+    let interval = Duration::from_secs(1) / fps.max(1);
+    let now = Instant::now();
+    if *next_frame <= now {
+        // Running late: start a new cadence instead of rushing to catch up.
+        *next_frame = now + interval;
+        return;
+    }
+
+    // Sleep overshoots by up to a scheduler tick, so the last millisecond is spun.
+    let remaining = *next_frame - now;
+    if remaining > Duration::from_millis(1) {
+        thread::sleep(remaining - Duration::from_millis(1));
+    }
+    while Instant::now() < *next_frame {
+        std::hint::spin_loop();
+    }
+    *next_frame += interval;
+}
+
+#[derive(Default)]
+pub struct AlovakBuilder {
+    window: WindowConfig,
+    renderer: RendererConfig,
+    timing: TimingConfig,
+    record: Option<Recorder>,
+}
+
+impl AlovakBuilder {
+    pub fn title(mut self, title: &str) -> Self {
+        self.window.title = title.to_owned();
+        self
+    }
+
+    /// Client area size in pixels.
+    pub fn size(mut self, width: u32, height: u32) -> Self {
+        self.window.size = Some((width, height));
+        self
+    }
+
+    pub fn clear_color(mut self, color: [f32; 4]) -> Self {
+        self.renderer.clear_color = color;
+        self
+    }
+
+    /// Overrides the present mode picked by the frame pacing.
+    pub fn present_mode(mut self, mode: PresentMode) -> Self {
+        self.renderer.present_mode = Some(mode);
+        self
+    }
+
+    /// Requests a swapchain color format, e.g. `ColorFormat::Hdr10`. Check
+    /// `AppContext::color_format` for the one actually in use.
+    pub fn color_format(mut self, color_format: ColorFormat) -> Self {
+        self.renderer.color_format = color_format;
+        self
+    }
+
+    pub fn depth_buffer(mut self, depth_buffer: DepthBuffer) -> Self {
+        self.renderer.depth_buffer = Some(depth_buffer);
+        self
+    }
+
+    /// Multisampling with `samples` per pixel, lowered to what the device supports.
+    pub fn samples(mut self, samples: u32) -> Self {
+        self.renderer.samples = samples;
+        self
+    }
+
+    /// Rate of `App::fixed_update`, e.g. 60 for 60 Hz.
+    pub fn fixed_rate(mut self, hz: u32) -> Self {
+        self.timing.fixed_timestep = Duration::from_secs(1) / hz.max(1);
+        self
+    }
+
+    pub fn max_fixed_steps(mut self, steps: u32) -> Self {
+        self.timing.max_fixed_steps = steps;
+        self
+    }
+
+    pub fn frame_pacing(mut self, pacing: FramePacing) -> Self {
+        self.timing.frame_pacing = pacing;
+        self
+    }
+
+    pub fn window(mut self, config: WindowConfig) -> Self {
+        self.window = config;
+        self
+    }
+
+    pub fn renderer(mut self, config: RendererConfig) -> Self {
+        self.renderer = config;
+        self
+    }
+
+    pub fn timing(mut self, config: TimingConfig) -> Self {
+        self.timing = config;
+        self
+    }
+
+    /// Records frames from the start, see `AppContext::start_recording`.
+    pub fn record(mut self, recorder: Recorder) -> Self {
+        self.record = Some(recorder);
+        self
+    }
+
+    pub fn build(self) -> Alovak {
+        Alovak {
+            window: self.window,
+            renderer: self.renderer,
+            timing: self.timing,
+            record: self.record,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const STEP: Duration = Duration::from_millis(10);
+
+    #[test]
+    fn fixed_step_catches_up_to_the_cap() {
+        let mut fixed_step = FixedStep::new(STEP, 5);
+        assert_eq!(fixed_step.advance(Duration::from_millis(25)), 2);
+        assert_eq!(fixed_step.advance(Duration::from_millis(5)), 1);
+        assert_eq!(fixed_step.advance(Duration::from_millis(50)), 5);
+        assert_eq!(fixed_step.advance(Duration::ZERO), 0);
+    }
+
+    #[test]
+    fn fixed_step_drops_time_beyond_the_cap() {
+        let mut fixed_step = FixedStep::new(STEP, 3);
+        // A long stall runs the capped number of steps once instead of piling up.
+        assert_eq!(fixed_step.advance(Duration::from_secs(10)), 3);
+        assert_eq!(fixed_step.advance(STEP), 1);
+        assert_eq!(fixed_step.alpha(), 0.0);
+    }
+
+    #[test]
+    fn fixed_step_alpha_stays_below_one() {
+        let mut fixed_step = FixedStep::new(STEP, 5);
+        assert_eq!(fixed_step.advance(Duration::from_millis(14)), 1);
+        assert!((fixed_step.alpha() - 0.4).abs() < 1e-6);
+
+        let mut fixed_step = FixedStep::new(Duration::from_nanos(16_666_667), 5);
+        fixed_step.advance(Duration::from_nanos(16_666_666));
+        assert!((0.0..1.0).contains(&fixed_step.alpha()));
+        for nanos in [1, 999_999, 7_000_001, 16_666_666] {
+            fixed_step.advance(Duration::from_nanos(nanos));
+            assert!((0.0..1.0).contains(&fixed_step.alpha()));
+        }
+    }
+
+    #[test]
+    fn fixed_step_zero_is_off() {
+        let mut fixed_step = FixedStep::new(Duration::ZERO, 5);
+        assert_eq!(fixed_step.advance(Duration::from_secs(1)), 0);
+        assert_eq!(fixed_step.alpha(), 0.0);
+    }
+}
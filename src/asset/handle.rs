@@ -0,0 +1,140 @@
+use std::{
+    fmt,
+    hash::{Hash, Hasher},
+    marker::PhantomData,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, AtomicU8, Ordering},
+        Arc,
+    },
+};
+
+/// Where an asset is in its life.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum LoadState {
+    /// Read, decoded or waiting to be uploaded.
+    Loading,
+    Ready,
+    /// See `AssetServer::error` for why.
+    Failed,
+}
+
+impl LoadState {
+    fn from_u8(value: u8) -> Self {
+        match value {
+            0 => LoadState::Loading,
+            1 => LoadState::Ready,
+            _ => LoadState::Failed,
+        }
+    }
+}
+
+/// Shared by every handle to one asset. The server only keeps a weak reference, so the
+/// asset is unused once the last handle is dropped.
+#[derive(Debug)]
+pub(super) struct HandleInner {
+    pub id: u64,
+    pub path: PathBuf,
+    state: AtomicU8,
+    generation: AtomicU64,
+}
+
+impl HandleInner {
+    pub fn new(id: u64, path: PathBuf, state: LoadState, generation: u64) -> Arc<Self> {
+        Arc::new(HandleInner {
+            id,
+            path,
+            state: AtomicU8::new(state as u8),
+            generation: AtomicU64::new(generation),
+        })
+    }
+
+    pub fn state(&self) -> LoadState {
+        LoadState::from_u8(self.state.load(Ordering::Acquire))
+    }
+
+    pub fn set_state(&self, state: LoadState) {
+        self.state.store(state as u8, Ordering::Release);
+    }
+
+    pub fn generation(&self) -> u64 {
+        self.generation.load(Ordering::Acquire)
+    }
+
+    pub fn set_generation(&self, generation: u64) {
+        self.generation.store(generation, Ordering::Release);
+    }
+}
+
+/// A reference counted handle to an asset of an `AssetServer`. Cloning is cheap; the
+/// asset is unloaded some frames after the last clone is dropped.
+pub struct Handle<T> {
+    pub(super) inner: Arc<HandleInner>,
+    marker: PhantomData<fn() -> T>,
+}
+
+impl<T> Handle<T> {
+    pub(super) fn new(inner: Arc<HandleInner>) -> Self {
+        Handle {
+            inner,
+            marker: PhantomData,
+        }
+    }
+
+    /// Unique among the assets of one server.
+    pub fn id(&self) -> u64 {
+        self.inner.id
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.inner.path
+    }
+
+    pub fn state(&self) -> LoadState {
+        self.inner.state()
+    }
+
+    pub fn is_ready(&self) -> bool {
+        self.state() == LoadState::Ready
+    }
+
+    /// How many times an asset was swapped in behind the handle: 1 once loaded, then one
+    /// more for every reload. Device handles copied out of the asset, like image views
+    /// written to descriptor sets, belong to one generation: rebuild them when it changes,
+    /// as the replaced asset is destroyed `FRAMES_IN_FLIGHT` frames after the swap.
+    pub fn generation(&self) -> u64 {
+        self.inner.generation()
+    }
+}
+
+impl<T> Clone for Handle<T> {
+    fn clone(&self) -> Self {
+        Self::new(self.inner.clone())
+    }
+}
+
+impl<T> PartialEq for Handle<T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.inner.id == other.inner.id
+    }
+}
+
+impl<T> Eq for Handle<T> {}
+
+impl<T> Hash for Handle<T> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.inner.id.hash(state);
+    }
+}
+
+impl<T> fmt::Debug for Handle<T> {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        formatter
+            .debug_struct("Handle")
+            .field("id", &self.inner.id)
+            .field("path", &self.inner.path)
+            .field("state", &self.state())
+            .field("generation", &self.generation())
+            .finish()
+    }
+}
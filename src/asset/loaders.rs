@@ -0,0 +1,151 @@
+use std::path::Path;
+
+use ash::vk::Sampler;
+
+use crate::{
+    error::Result,
+    mesh::Mesh,
+    vulkan::{Context, DeviceTexture, SamplerCache, TextureUsage},
+    Error, Ktx2, Model, RgbaImage,
+};
+
+use super::{Asset, UploadContext};
+
+/// How an image file becomes a texture. KTX2 files bring their own format and mips.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct TextureSettings {
+    pub usage: TextureUsage,
+    pub mipmaps: bool,
+}
+
+impl Default for TextureSettings {
+    fn default() -> Self {
+        TextureSettings {
+            usage: TextureUsage::Color,
+            mipmaps: true,
+        }
+    }
+}
+
+/// A decoded texture file.
+#[derive(Debug, Clone)]
+pub enum TextureData {
+    Image(RgbaImage),
+    Ktx2(Ktx2),
+}
+
+/// PNG, JPEG or KTX2 files. Basis Universal KTX2 files need a transcoder, see
+/// `AssetServer::set_basis_transcoder`.
+impl Asset for DeviceTexture {
+    type Settings = TextureSettings;
+    type Source = TextureData;
+
+    fn decode(_path: &Path, bytes: Vec<u8>, _settings: &TextureSettings) -> Result<TextureData> {
+        if bytes.starts_with(b"\xABKTX 20\xBB") {
+            Ok(TextureData::Ktx2(Ktx2::from_bytes(&bytes)?))
+        } else {
+            Ok(TextureData::Image(RgbaImage::decode(&bytes)?))
+        }
+    }
+
+    fn upload(
+        upload: &UploadContext,
+        source: TextureData,
+        settings: &TextureSettings,
+    ) -> Result<Self> {
+        match source {
+            TextureData::Image(image) => {
+                DeviceTexture::new(upload.context(), &image, settings.usage, settings.mipmaps)
+            }
+            TextureData::Ktx2(ktx2) => {
+                DeviceTexture::from_ktx2(upload.context(), &ktx2, upload.basis_transcoder())
+            }
+        }
+    }
+
+    fn destroy(&mut self, context: &Context) {
+        DeviceTexture::destroy(self, context);
+    }
+}
+
+/// A model with its meshes and textures on the device.
+pub struct DeviceModel {
+    pub model: Model,
+    /// In the same order as `model.meshes`.
+    pub meshes: Vec<Mesh>,
+    /// In the same order as `model.textures`.
+    pub textures: Vec<(DeviceTexture, Sampler)>,
+    samplers: SamplerCache,
+}
+
+impl DeviceModel {
+    pub fn new(context: &Context, model: Model) -> Result<Self> {
+        let mut samplers = SamplerCache::new();
+        let textures = match model.upload_textures(context, &mut samplers) {
+            Ok(textures) => textures,
+            Err(error) => {
+                samplers.destroy(context);
+                return Err(error);
+            }
+        };
+        let meshes = match model.upload_meshes(context) {
+            Ok(meshes) => meshes,
+            Err(error) => {
+                for (mut texture, _) in textures {
+                    texture.destroy(context);
+                }
+                samplers.destroy(context);
+                return Err(error);
+            }
+        };
+        Ok(DeviceModel {
+            model,
+            meshes,
+            textures,
+            samplers,
+        })
+    }
+
+    /// The device must be done with the model.
+    pub fn destroy(&mut self, context: &Context) {
+        for mesh in &mut self.meshes {
+            mesh.destroy(context);
+        }
+        for (texture, _) in &mut self.textures {
+            texture.destroy(context);
+        }
+        self.meshes.clear();
+        self.textures.clear();
+        self.samplers.destroy(context);
+    }
+}
+
+/// glTF (`.gltf`, `.glb`) or Wavefront OBJ (`.obj`) files, told apart by extension.
+impl Asset for DeviceModel {
+    type Settings = ();
+    type Source = Model;
+
+    fn decode(path: &Path, bytes: Vec<u8>, _settings: &()) -> Result<Model> {
+        let base = path.parent().unwrap_or(Path::new("."));
+        let extension = path
+            .extension()
+            .and_then(|extension| extension.to_str())
+            .map(str::to_ascii_lowercase);
+        match extension.as_deref() {
+            Some("gltf" | "glb") => Model::from_gltf(&bytes, Some(base)),
+            Some("obj") => Model::from_obj(&String::from_utf8_lossy(&bytes), Some(base)),
+            _ => Err(Error::Other(format!(
+                "Model format of {} isn`t known",
+                path.display()
+            ))),
+        }
+    }
+
+    fn upload(upload: &UploadContext, model: Model, _settings: &()) -> Result<Self> {
+        DeviceModel::new(upload.context(), model)
+    }
+
+    fn destroy(&mut self, context: &Context) {
+        DeviceModel::destroy(self, context);
+    }
+}
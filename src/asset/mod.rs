@@ -0,0 +1,510 @@
+//! Assets loaded in the background: files are read and decoded on tokio tasks, then
+//! uploaded on the render thread by `AssetServer::update`. Game code holds `Handle`s and
+//! looks the assets up by them; hot reloading swaps assets behind the same handles and
+//! bumps `Handle::generation`, which tells when descriptors referring to them are stale.
+
+mod handle;
+mod loaders;
+mod watch;
+
+pub use handle::{Handle, LoadState};
+pub use loaders::{DeviceModel, TextureData, TextureSettings};
+
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    fmt::Debug,
+    fs,
+    hash::Hash,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Weak,
+    },
+    time::Duration,
+};
+
+use crate::{
+    error::Result,
+    vulkan::{Context, FRAMES_IN_FLIGHT},
+    BasisTranscoder, Error,
+};
+
+use handle::HandleInner;
+use watch::Watcher;
+
+/// Something an `AssetServer` can load.
+pub trait Asset: Sized + 'static {
+    /// Options changing how a file is loaded. Loads of one path with different settings
+    /// are different assets.
+    type Settings: Debug + Clone + Default + PartialEq + Eq + Hash + Send + Sync + 'static;
+    /// What decoding produces, e.g. pixels or vertices on the host.
+    type Source: Send + 'static;
+
+    /// Decodes the `bytes` of the file at `path`. Runs on a blocking tokio thread, so it may
+    /// read files the asset refers to.
+    fn decode(path: &Path, bytes: Vec<u8>, settings: &Self::Settings) -> Result<Self::Source>;
+
+    /// Creates the device resources. Runs on the thread calling `AssetServer::update`, so
+    /// copies made with `Context::submit_and_wait` block it until the graphic queue is done.
+    fn upload(
+        upload: &UploadContext,
+        source: Self::Source,
+        settings: &Self::Settings,
+    ) -> Result<Self>;
+
+    /// The device must be done with the asset.
+    fn destroy(&mut self, context: &Context);
+}
+
+/// What `Asset::upload` gets to create device resources with.
+pub struct UploadContext<'c> {
+    context: &'c Context,
+    basis_transcoder: Option<&'c dyn BasisTranscoder>,
+}
+
+impl UploadContext<'_> {
+    pub fn context(&self) -> &Context {
+        self.context
+    }
+
+    /// The transcoder set with `AssetServer::set_basis_transcoder`.
+    pub fn basis_transcoder(&self) -> Option<&dyn BasisTranscoder> {
+        self.basis_transcoder
+    }
+}
+
+/// Asset id, load generation and what decoding gave.
+type Loaded<T> = (u64, u64, Result<<T as Asset>::Source>);
+
+struct Entry<T: Asset> {
+    path: PathBuf,
+    settings: T::Settings,
+    handle: Weak<HandleInner>,
+    value: Option<T>,
+    error: Option<Error>,
+    /// Bumped by every reload, so loads it supersedes are dropped when they finish.
+    generation: u64,
+    /// Assets swapped in so far, see `Handle::generation`.
+    swaps: u64,
+}
+
+/// The assets of one type.
+struct Storage<T: Asset> {
+    entries: HashMap<u64, Entry<T>>,
+    ids: HashMap<(PathBuf, T::Settings), u64>,
+    sender: Sender<Loaded<T>>,
+    receiver: Receiver<Loaded<T>>,
+    /// Assets no handle refers to anymore or replaced by a reload, with the frame they
+    /// were dropped in.
+    retired: Vec<(u64, T)>,
+}
+
+impl<T: Asset> Storage<T> {
+    fn new() -> Self {
+        let (sender, receiver) = mpsc::channel();
+        Storage {
+            entries: HashMap::new(),
+            ids: HashMap::new(),
+            sender,
+            receiver,
+            retired: Vec::new(),
+        }
+    }
+}
+
+/// Lets the server keep storages of every asset type in one map.
+trait AnyStorage {
+    /// Uploads at most `budget` assets, taking the ones uploaded from it, and returns the
+    /// paths of the assets unloaded.
+    fn update(&mut self, upload: &UploadContext, frame: u64, budget: &mut usize) -> Vec<PathBuf>;
+    /// Loads the assets from `path` again.
+    fn reload(&mut self, runtime: &tokio::runtime::Handle, path: &Path);
+    fn paths(&self) -> Vec<PathBuf>;
+    fn len(&self) -> usize;
+    fn destroy(&mut self, context: &Context);
+    fn as_any(&self) -> &dyn Any;
+    fn as_any_mut(&mut self) -> &mut dyn Any;
+}
+
+impl<T: Asset> AnyStorage for Storage<T> {
+    fn update(&mut self, upload: &UploadContext, frame: u64, budget: &mut usize) -> Vec<PathBuf> {
+        // Loads past the budget stay in the channel for the next update.
+        while *budget > 0 {
+            let Ok((id, generation, source)) = self.receiver.try_recv() else {
+                break;
+            };
+            // Dropped or reloaded again before it finished loading.
+            let Some(entry) = self
+                .entries
+                .get_mut(&id)
+                .filter(|entry| entry.generation == generation)
+            else {
+                continue;
+            };
+            let uploaded = source.and_then(|source| {
+                *budget -= 1;
+                T::upload(upload, source, &entry.settings)
+            });
+            let state = match uploaded {
+                Ok(value) => {
+                    log::trace!("alovak asset {} loaded", entry.path.display());
+                    // Frames in flight may still use the old one.
+                    if let Some(old) = entry.value.replace(value) {
+                        self.retired.push((frame, old));
+                    }
+                    entry.error = None;
+                    entry.swaps += 1;
+                    if let Some(handle) = entry.handle.upgrade() {
+                        handle.set_generation(entry.swaps);
+                    }
+                    LoadState::Ready
+                }
+                // A failed reload keeps the asset loaded before.
+                Err(error) if entry.value.is_some() => {
+                    log::trace!(
+                        "alovak asset {} reload failed: {}",
+                        entry.path.display(),
+                        error
+                    );
+                    entry.error = Some(error);
+                    LoadState::Ready
+                }
+                Err(error) => {
+                    log::trace!("alovak asset {} failed: {}", entry.path.display(), error);
+                    entry.error = Some(error);
+                    LoadState::Failed
+                }
+            };
+            if let Some(handle) = entry.handle.upgrade() {
+                handle.set_state(state);
+            }
+        }
+
+        let unused: Vec<u64> = self
+            .entries
+            .iter()
+            .filter(|(_, entry)| entry.handle.strong_count() == 0)
+            .map(|(id, _)| *id)
+            .collect();
+        let mut unloaded = Vec::with_capacity(unused.len());
+        for id in unused {
+            let entry = self.entries.remove(&id).unwrap();
+            self.ids.remove(&(entry.path.clone(), entry.settings));
+            log::trace!("alovak asset {} unused", entry.path.display());
+            if let Some(value) = entry.value {
+                self.retired.push((frame, value));
+            }
+            unloaded.push(entry.path);
+        }
+
+        // Frames recorded before the asset was dropped are done once the frames in flight
+        // after them have begun.
+        let mut index = 0;
+        while index < self.retired.len() {
+            if frame >= self.retired[index].0 + FRAMES_IN_FLIGHT as u64 {
+                let (_, mut value) = self.retired.swap_remove(index);
+                value.destroy(upload.context);
+            } else {
+                index += 1;
+            }
+        }
+        unloaded
+    }
+
+    fn reload(&mut self, runtime: &tokio::runtime::Handle, path: &Path) {
+        for (id, entry) in &mut self.entries {
+            if entry.path == path {
+                log::trace!("alovak asset {} changed", path.display());
+                entry.generation += 1;
+                spawn_load::<T>(
+                    runtime,
+                    *id,
+                    entry.generation,
+                    entry.path.clone(),
+                    entry.settings.clone(),
+                    self.sender.clone(),
+                );
+            }
+        }
+    }
+
+    fn paths(&self) -> Vec<PathBuf> {
+        self.entries
+            .values()
+            .map(|entry| entry.path.clone())
+            .collect()
+    }
+
+    fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    fn destroy(&mut self, context: &Context) {
+        for (_, mut entry) in self.entries.drain() {
+            if let Some(handle) = entry.handle.upgrade() {
+                handle.set_state(LoadState::Failed);
+            }
+            if let Some(value) = &mut entry.value {
+                value.destroy(context);
+            }
+        }
+        self.ids.clear();
+        for (_, mut value) in self.retired.drain(..) {
+            value.destroy(context);
+        }
+    }
+
+    fn as_any(&self) -> &dyn Any {
+        self
+    }
+
+    fn as_any_mut(&mut self) -> &mut dyn Any {
+        self
+    }
+}
+
+/// Reads and decodes `path` on a tokio task and sends the result back to the storage.
+fn spawn_load<T: Asset>(
+    runtime: &tokio::runtime::Handle,
+    id: u64,
+    generation: u64,
+    path: PathBuf,
+    settings: T::Settings,
+    sender: Sender<Loaded<T>>,
+) {
+    runtime.spawn(async move {
+        let source = match tokio::fs::read(&path).await {
+            Ok(bytes) => tokio::task::spawn_blocking(move || T::decode(&path, bytes, &settings))
+                .await
+                .unwrap_or_else(|error| {
+                    Err(Error::Other(format!("Asset decoding panicked: {}", error)))
+                }),
+            Err(error) => Err(Error::Io(error)),
+        };
+        // The server is gone, nobody wants the asset anymore.
+        _ = sender.send((id, generation, source));
+    });
+}
+
+/// Assets `AssetServer::update` uploads per call unless `set_upload_budget` changes it.
+pub const DEFAULT_UPLOAD_BUDGET: usize = 4;
+
+/// Loads assets on tokio tasks and hands out handles to them. Loading one path twice with
+/// the same settings gives the same asset, and assets are unloaded once no handle refers
+/// to them. With `watch`, assets are loaded again when their files change.
+pub struct AssetServer {
+    runtime: tokio::runtime::Handle,
+    storages: HashMap<TypeId, Box<dyn AnyStorage>>,
+    watcher: Option<Watcher>,
+    basis_transcoder: Option<Box<dyn BasisTranscoder>>,
+    upload_budget: usize,
+    next_id: u64,
+    frame: u64,
+}
+
+impl AssetServer {
+    /// Loads on the current tokio runtime.
+    pub fn new() -> Result<Self> {
+        let runtime = tokio::runtime::Handle::try_current()
+            .map_err(|error| Error::Other(format!("Asset server needs a runtime: {}", error)))?;
+        Ok(Self::with_runtime(runtime))
+    }
+
+    pub fn with_runtime(runtime: tokio::runtime::Handle) -> Self {
+        AssetServer {
+            runtime,
+            storages: HashMap::new(),
+            watcher: None,
+            basis_transcoder: None,
+            upload_budget: DEFAULT_UPLOAD_BUDGET,
+            next_id: 0,
+            frame: 0,
+        }
+    }
+
+    /// Starts loading `path` with default settings, see `load_with`.
+    pub fn load<T: Asset>(&mut self, path: impl AsRef<Path>) -> Handle<T> {
+        self.load_with(path, T::Settings::default())
+    }
+
+    /// Starts loading `path` unless it is loaded or loading already, and returns a handle
+    /// that becomes ready once `update` has uploaded the asset.
+    pub fn load_with<T: Asset>(
+        &mut self,
+        path: impl AsRef<Path>,
+        settings: T::Settings,
+    ) -> Handle<T> {
+        let path = path.as_ref();
+        let path = fs::canonicalize(path).unwrap_or_else(|_| path.to_owned());
+        let key = (path.clone(), settings.clone());
+        let next_id = self.next_id;
+        let storage = self.storage_mut::<T>();
+
+        if let Some(&id) = storage.ids.get(&key) {
+            let entry = storage.entries.get_mut(&id).unwrap();
+            if let Some(inner) = entry.handle.upgrade() {
+                return Handle::new(inner);
+            }
+            // Dropped, but not unloaded yet.
+            let state = match (&entry.value, &entry.error) {
+                (Some(_), _) => LoadState::Ready,
+                (_, Some(_)) => LoadState::Failed,
+                _ => LoadState::Loading,
+            };
+            let inner = HandleInner::new(id, path, state, entry.swaps);
+            entry.handle = Arc::downgrade(&inner);
+            return Handle::new(inner);
+        }
+
+        let inner = HandleInner::new(next_id, path.clone(), LoadState::Loading, 0);
+        storage.ids.insert(key, next_id);
+        storage.entries.insert(
+            next_id,
+            Entry {
+                path: path.clone(),
+                settings: settings.clone(),
+                handle: Arc::downgrade(&inner),
+                value: None,
+                error: None,
+                generation: 0,
+                swaps: 0,
+            },
+        );
+        let sender = storage.sender.clone();
+        self.next_id += 1;
+        if let Some(watcher) = &self.watcher {
+            watcher.add(&path);
+        }
+        spawn_load::<T>(&self.runtime, next_id, 0, path, settings, sender);
+        Handle::new(inner)
+    }
+
+    /// Starts reloading assets whose files change, checking them every `interval`.
+    /// Reloaded assets replace the old ones in `update`, behind the same handles, and the
+    /// old ones are destroyed once the frames in flight are done with them: anything
+    /// holding their device handles, like descriptor sets, has to be rebuilt when
+    /// `Handle::generation` changes. When a reload fails, the old asset stays and `error`
+    /// tells why. Only the files assets are loaded from are watched, not files they refer
+    /// to like the textures of a model.
+    pub fn watch(&mut self, interval: Duration) {
+        let watcher = Watcher::new(&self.runtime, interval);
+        for storage in self.storages.values() {
+            for path in storage.paths() {
+                watcher.add(&path);
+            }
+        }
+        self.watcher = Some(watcher);
+    }
+
+    pub fn unwatch(&mut self) {
+        self.watcher = None;
+    }
+
+    pub fn is_watching(&self) -> bool {
+        self.watcher.is_some()
+    }
+
+    /// Transcodes Basis Universal KTX2 textures, which fail to load without one. alovak
+    /// doesn't ship a transcoder; implement `BasisTranscoder`, e.g. with bindings to the
+    /// reference `basisu` transcoder.
+    pub fn set_basis_transcoder(&mut self, transcoder: impl BasisTranscoder + 'static) {
+        self.basis_transcoder = Some(Box::new(transcoder));
+    }
+
+    /// How many assets `update` uploads at most, `DEFAULT_UPLOAD_BUDGET` unless set. Every
+    /// upload is submitted to the graphic queue and waited for, there is no transfer queue
+    /// copying in the background, so a burst of loads is spread over several frames
+    /// instead of stalling one; `usize::MAX` uploads everything decoded at once.
+    pub fn set_upload_budget(&mut self, uploads: usize) {
+        self.upload_budget = uploads.max(1);
+    }
+
+    pub fn upload_budget(&self) -> usize {
+        self.upload_budget
+    }
+
+    /// The asset, once it is ready. It belongs to the current `Handle::generation`.
+    pub fn get<T: Asset>(&self, handle: &Handle<T>) -> Option<&T> {
+        self.storage::<T>()?
+            .entries
+            .get(&handle.id())?
+            .value
+            .as_ref()
+    }
+
+    /// Why the asset failed to load, or failed to reload the last time.
+    pub fn error<T: Asset>(&self, handle: &Handle<T>) -> Option<&Error> {
+        self.storage::<T>()?
+            .entries
+            .get(&handle.id())?
+            .error
+            .as_ref()
+    }
+
+    /// Assets of every type, loading or not, that aren't unloaded yet.
+    pub fn len(&self) -> usize {
+        self.storages.values().map(|storage| storage.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Uploads up to the upload budget of the assets decoded since the last call, leaving
+    /// the rest for the next one, and unloads unused ones. Call it once per frame, after
+    /// `begin_frame`: assets dropped are destroyed once the frames that might still use
+    /// them are done.
+    pub fn update(&mut self, context: &Context) {
+        self.frame += 1;
+        if let Some(watcher) = &self.watcher {
+            for path in watcher.changed() {
+                for storage in self.storages.values_mut() {
+                    storage.reload(&self.runtime, &path);
+                }
+            }
+        }
+        let upload = UploadContext {
+            context,
+            basis_transcoder: self.basis_transcoder.as_deref(),
+        };
+        // Each update starts at another asset type, so one with many loads waiting doesn't
+        // take the whole budget every frame.
+        let mut storages: Vec<&mut Box<dyn AnyStorage>> = self.storages.values_mut().collect();
+        if !storages.is_empty() {
+            let start = (self.frame % storages.len() as u64) as usize;
+            storages.rotate_left(start);
+        }
+        let mut budget = self.upload_budget;
+        for storage in storages {
+            for path in storage.update(&upload, self.frame, &mut budget) {
+                if let Some(watcher) = &self.watcher {
+                    watcher.remove(&path);
+                }
+            }
+        }
+    }
+
+    /// Destroys every asset; handles still around turn `Failed`. The device must be idle.
+    pub fn destroy(&mut self, context: &Context) {
+        self.watcher = None;
+        for storage in self.storages.values_mut() {
+            storage.destroy(context);
+        }
+    }
+
+    fn storage<T: Asset>(&self) -> Option<&Storage<T>> {
+        self.storages
+            .get(&TypeId::of::<T>())
+            .and_then(|storage| storage.as_any().downcast_ref())
+    }
+
+    fn storage_mut<T: Asset>(&mut self) -> &mut Storage<T> {
+        self.storages
+            .entry(TypeId::of::<T>())
+            .or_insert_with(|| Box::new(Storage::<T>::new()))
+            .as_any_mut()
+            .downcast_mut()
+            .unwrap()
+    }
+}
@@ -0,0 +1,99 @@
+use std::{
+    collections::HashMap,
+    path::{Path, PathBuf},
+    sync::{
+        mpsc::{self, Receiver},
+        Arc, Mutex,
+    },
+    time::{Duration, SystemTime},
+};
+
+use tokio::{task::JoinHandle, time::MissedTickBehavior};
+
+#[derive(Debug, Default)]
+struct WatchedFile {
+    /// Assets loaded from the file.
+    users: usize,
+    /// `None` until the first check.
+    modified: Option<SystemTime>,
+    /// Changed at the last check. Editors may write a file in several steps, so it is only
+    /// reported once a check finds it unchanged again.
+    pending: bool,
+}
+
+/// Polls the modification times of asset files on a tokio task. Polling works the same
+/// on every platform and file system, network shares included.
+pub(super) struct Watcher {
+    files: Arc<Mutex<HashMap<PathBuf, WatchedFile>>>,
+    receiver: Receiver<PathBuf>,
+    task: JoinHandle<()>,
+}
+
+impl Watcher {
+    pub fn new(runtime: &tokio::runtime::Handle, interval: Duration) -> Self {
+        let files: Arc<Mutex<HashMap<PathBuf, WatchedFile>>> = Arc::default();
+        let (sender, receiver) = mpsc::channel();
+        let watched = files.clone();
+        let task = runtime.spawn(async move {
+            let mut ticker = tokio::time::interval(interval);
+            ticker.set_missed_tick_behavior(MissedTickBehavior::Delay);
+            loop {
+                ticker.tick().await;
+                let paths: Vec<PathBuf> = watched.lock().unwrap().keys().cloned().collect();
+                for path in paths {
+                    let Ok(modified) = tokio::fs::metadata(&path)
+                        .await
+                        .and_then(|metadata| metadata.modified())
+                    else {
+                        // Missing for now, e.g. while an editor replaces it.
+                        continue;
+                    };
+                    let mut files = watched.lock().unwrap();
+                    let Some(file) = files.get_mut(&path) else {
+                        continue;
+                    };
+                    if file.modified.is_some_and(|known| known != modified) {
+                        file.pending = true;
+                    } else if file.pending {
+                        file.pending = false;
+                        if sender.send(path).is_err() {
+                            return;
+                        }
+                    }
+                    file.modified = Some(modified);
+                }
+            }
+        });
+        Watcher {
+            files,
+            receiver,
+            task,
+        }
+    }
+
+    pub fn add(&self, path: &Path) {
+        let mut files = self.files.lock().unwrap();
+        files.entry(path.to_owned()).or_default().users += 1;
+    }
+
+    pub fn remove(&self, path: &Path) {
+        let mut files = self.files.lock().unwrap();
+        if let Some(file) = files.get_mut(path) {
+            file.users -= 1;
+            if file.users == 0 {
+                files.remove(path);
+            }
+        }
+    }
+
+    /// Files changed since the last call.
+    pub fn changed(&self) -> Vec<PathBuf> {
+        self.receiver.try_iter().collect()
+    }
+}
+
+impl Drop for Watcher {
+    fn drop(&mut self) {
+        self.task.abort();
+    }
+}
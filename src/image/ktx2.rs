@@ -0,0 +1,551 @@
+//! KTX 2.0 containers: native Vulkan formats with their mips, cubemap faces and array layers,
+//! or Basis Universal data (ETC1S or UASTC) that a transcoder turns into one. Levels are
+//! unpacked from Zstandard and zlib supercompression; BasisLZ is left to the transcoder.
+//!
+//! alovak reads Basis Universal textures but doesn't transcode them itself: that takes a
+//! `BasisTranscoder` from the application, e.g. bindings to the reference `basisu`
+//! transcoder. Without one, loading them fails.
+
+use std::{fs, io::Read, path::Path};
+
+use ash::vk::Format;
+use flate2::read::ZlibDecoder;
+use ruzstd::decoding::StreamingDecoder;
+
+use crate::{error::Result, Error};
+
+const IDENTIFIER: [u8; 12] = [
+    0xAB, 0x4B, 0x54, 0x58, 0x20, 0x32, 0x30, 0xBB, 0x0D, 0x0A, 0x1A, 0x0A,
+];
+const HEADER_SIZE: usize = 80;
+const LEVEL_INDEX_SIZE: usize = 24;
+
+const SUPERCOMPRESSION_NONE: u32 = 0;
+const SUPERCOMPRESSION_BASIS_LZ: u32 = 1;
+const SUPERCOMPRESSION_ZSTD: u32 = 2;
+const SUPERCOMPRESSION_ZLIB: u32 = 3;
+
+const COLOR_MODEL_ETC1S: u8 = 163;
+const COLOR_MODEL_UASTC: u8 = 166;
+const TRANSFER_SRGB: u8 = 2;
+const CHANNEL_ALPHA: u8 = 15;
+const CHANNEL_UASTC_RGBA: u8 = 3;
+const CHANNEL_UASTC_RRRG: u8 = 5;
+
+/// Which Basis Universal codec a texture was encoded with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BasisFormat {
+    /// Small and lossy, supercompressed with BasisLZ.
+    Etc1s,
+    /// 4x4 blocks of 16 bytes close to the source, optionally Zstandard supercompressed.
+    Uastc,
+}
+
+/// A block compressed or plain format Basis Universal textures can be transcoded to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TranscodeTarget {
+    Astc4x4,
+    Bc7,
+    Etc2Rgba,
+    /// ETC2 without alpha, half the size of `Etc2Rgba`.
+    Etc2Rgb,
+    /// Uncompressed, for devices without any block compressed family.
+    Rgba8,
+}
+
+impl TranscodeTarget {
+    pub fn format(self, srgb: bool) -> Format {
+        match (self, srgb) {
+            (TranscodeTarget::Astc4x4, false) => Format::ASTC_4X4_UNORM_BLOCK,
+            (TranscodeTarget::Astc4x4, true) => Format::ASTC_4X4_SRGB_BLOCK,
+            (TranscodeTarget::Bc7, false) => Format::BC7_UNORM_BLOCK,
+            (TranscodeTarget::Bc7, true) => Format::BC7_SRGB_BLOCK,
+            (TranscodeTarget::Etc2Rgba, false) => Format::ETC2_R8G8B8A8_UNORM_BLOCK,
+            (TranscodeTarget::Etc2Rgba, true) => Format::ETC2_R8G8B8A8_SRGB_BLOCK,
+            (TranscodeTarget::Etc2Rgb, false) => Format::ETC2_R8G8B8_UNORM_BLOCK,
+            (TranscodeTarget::Etc2Rgb, true) => Format::ETC2_R8G8B8_SRGB_BLOCK,
+            (TranscodeTarget::Rgba8, false) => Format::R8G8B8A8_UNORM,
+            (TranscodeTarget::Rgba8, true) => Format::R8G8B8A8_SRGB,
+        }
+    }
+
+    /// Block width, height and size in bytes.
+    pub(crate) fn block(self) -> (u32, u32, u32) {
+        match self {
+            TranscodeTarget::Etc2Rgb => (4, 4, 8),
+            TranscodeTarget::Rgba8 => (1, 1, 4),
+            _ => (4, 4, 16),
+        }
+    }
+}
+
+/// Turns Basis Universal levels into blocks of a format the device can sample, e.g. through
+/// bindings to the reference `basisu` transcoder. None is built in.
+pub trait BasisTranscoder {
+    /// Every layer and face of `level`, in the order KTX2 stores them, as tightly packed
+    /// `target` blocks. ETC1S textures carry their codebooks in `Ktx2::global_data`.
+    fn transcode(&self, texture: &Ktx2, level: usize, target: TranscodeTarget) -> Result<Vec<u8>>;
+}
+
+/// A 2D texture, texture array or cubemap from a KTX 2.0 file.
+#[derive(Debug, Clone)]
+pub struct Ktx2 {
+    /// `UNDEFINED` for Basis Universal textures.
+    pub format: Format,
+    pub width: u32,
+    pub height: u32,
+    /// Array elements, 0 when the texture isn't an array.
+    pub layers: u32,
+    /// 6 for cubemaps, 1 otherwise.
+    pub faces: u32,
+    /// Level 0 first, each with every layer and face in order.
+    pub levels: Vec<Vec<u8>>,
+    /// The file leaves mip generation to the loader and has only level 0.
+    pub generate_mips: bool,
+    pub basis: Option<BasisFormat>,
+    /// Whether colors are sRGB encoded, which Basis textures need to pick a format.
+    pub srgb: bool,
+    /// Whether a Basis texture has alpha, so transcoding can pick a format without it.
+    pub alpha: bool,
+    /// BasisLZ codebooks and image descriptions shared by all levels.
+    pub global_data: Vec<u8>,
+}
+
+/// Width and height of a texel block of `format` and its size in bytes, for the formats a
+/// texture can be sampled in. Taken from the format rather than the data format descriptor,
+/// which leaves the size 0 in supercompressed files.
+pub(crate) fn format_block(format: Format) -> Option<(u32, u32, u32)> {
+    let raw = format.as_raw();
+    let within = |first: Format, last: Format| (first.as_raw()..=last.as_raw()).contains(&raw);
+    let block = match format {
+        Format::R4G4_UNORM_PACK8 => (1, 1, 1),
+        _ if within(Format::R4G4B4A4_UNORM_PACK16, Format::A1R5G5B5_UNORM_PACK16) => (1, 1, 2),
+        _ if within(Format::R8_UNORM, Format::R8_SRGB) => (1, 1, 1),
+        _ if within(Format::R8G8_UNORM, Format::R8G8_SRGB) => (1, 1, 2),
+        _ if within(Format::R8G8B8_UNORM, Format::B8G8R8_SRGB) => (1, 1, 3),
+        _ if within(Format::R8G8B8A8_UNORM, Format::A2B10G10R10_SINT_PACK32) => (1, 1, 4),
+        _ if within(Format::R16_UNORM, Format::R16_SFLOAT) => (1, 1, 2),
+        _ if within(Format::R16G16_UNORM, Format::R16G16_SFLOAT) => (1, 1, 4),
+        _ if within(Format::R16G16B16_UNORM, Format::R16G16B16_SFLOAT) => (1, 1, 6),
+        _ if within(Format::R16G16B16A16_UNORM, Format::R16G16B16A16_SFLOAT) => (1, 1, 8),
+        _ if within(Format::R32_UINT, Format::R32_SFLOAT) => (1, 1, 4),
+        _ if within(Format::R32G32_UINT, Format::R32G32_SFLOAT) => (1, 1, 8),
+        _ if within(Format::R32G32B32_UINT, Format::R32G32B32_SFLOAT) => (1, 1, 12),
+        _ if within(Format::R32G32B32A32_UINT, Format::R32G32B32A32_SFLOAT) => (1, 1, 16),
+        _ if within(Format::R64_UINT, Format::R64_SFLOAT) => (1, 1, 8),
+        _ if within(Format::R64G64_UINT, Format::R64G64_SFLOAT) => (1, 1, 16),
+        _ if within(Format::R64G64B64_UINT, Format::R64G64B64_SFLOAT) => (1, 1, 24),
+        _ if within(Format::R64G64B64A64_UINT, Format::R64G64B64A64_SFLOAT) => (1, 1, 32),
+        Format::B10G11R11_UFLOAT_PACK32 | Format::E5B9G9R9_UFLOAT_PACK32 => (1, 1, 4),
+        _ if within(Format::BC1_RGB_UNORM_BLOCK, Format::BC1_RGBA_SRGB_BLOCK) => (4, 4, 8),
+        _ if within(Format::BC4_UNORM_BLOCK, Format::BC4_SNORM_BLOCK) => (4, 4, 8),
+        _ if within(Format::BC2_UNORM_BLOCK, Format::BC7_SRGB_BLOCK) => (4, 4, 16),
+        _ if within(
+            Format::ETC2_R8G8B8_UNORM_BLOCK,
+            Format::ETC2_R8G8B8A1_SRGB_BLOCK,
+        ) =>
+        {
+            (4, 4, 8)
+        }
+        _ if within(
+            Format::ETC2_R8G8B8A8_UNORM_BLOCK,
+            Format::ETC2_R8G8B8A8_SRGB_BLOCK,
+        ) =>
+        {
+            (4, 4, 16)
+        }
+        Format::EAC_R11_UNORM_BLOCK | Format::EAC_R11_SNORM_BLOCK => (4, 4, 8),
+        Format::EAC_R11G11_UNORM_BLOCK | Format::EAC_R11G11_SNORM_BLOCK => (4, 4, 16),
+        _ if within(Format::ASTC_4X4_UNORM_BLOCK, Format::ASTC_12X12_SRGB_BLOCK) => {
+            const EXTENTS: [(u32, u32); 14] = [
+                (4, 4),
+                (5, 4),
+                (5, 5),
+                (6, 5),
+                (6, 6),
+                (8, 5),
+                (8, 6),
+                (8, 8),
+                (10, 5),
+                (10, 6),
+                (10, 8),
+                (10, 10),
+                (12, 10),
+                (12, 12),
+            ];
+            // Every extent comes as UNORM and SRGB.
+            let (width, height) =
+                EXTENTS[((raw - Format::ASTC_4X4_UNORM_BLOCK.as_raw()) / 2) as usize];
+            (width, height, 16)
+        }
+        _ => return None,
+    };
+    Some(block)
+}
+
+fn error(reason: String) -> Error {
+    Error::Other(format!("KTX2: {}", reason))
+}
+
+fn u32_at(data: &[u8], offset: usize) -> Result<u32> {
+    data.get(offset..offset + 4)
+        .map(|bytes| u32::from_le_bytes(bytes.try_into().unwrap()))
+        .ok_or_else(|| error("file is truncated".to_owned()))
+}
+
+fn u64_at(data: &[u8], offset: usize) -> Result<usize> {
+    data.get(offset..offset + 8)
+        .map(|bytes| u64::from_le_bytes(bytes.try_into().unwrap()) as usize)
+        .ok_or_else(|| error("file is truncated".to_owned()))
+}
+
+fn slice(data: &[u8], offset: usize, length: usize) -> Result<&[u8]> {
+    offset
+        .checked_add(length)
+        .and_then(|end| data.get(offset..end))
+        .ok_or_else(|| error("section points past the end of the file".to_owned()))
+}
+
+impl Ktx2 {
+    pub fn from_bytes(data: &[u8]) -> Result<Self> {
+        if !data.starts_with(&IDENTIFIER) || data.len() < HEADER_SIZE {
+            return Err(error("file isn`t KTX 2.0".to_owned()));
+        }
+        let format = Format::from_raw(u32_at(data, 12)? as i32);
+        let width = u32_at(data, 20)?;
+        let height = u32_at(data, 24)?.max(1);
+        let depth = u32_at(data, 28)?;
+        let layers = u32_at(data, 32)?;
+        let faces = u32_at(data, 36)?;
+        // 0 asks the loader to generate the mips from the one level stored.
+        let level_count = u32_at(data, 40)?;
+        let generate_mips = level_count == 0;
+        let level_count = level_count.max(1) as usize;
+        let supercompression = u32_at(data, 44)?;
+        if width == 0 {
+            return Err(error("texture can`t be empty".to_owned()));
+        }
+        if depth > 0 {
+            return Err(error("3D textures aren`t supported".to_owned()));
+        }
+        if faces != 1 && faces != 6 {
+            return Err(error(format!("{} faces, must be 1 or 6", faces)));
+        }
+        if level_count > 32 - width.max(height).leading_zeros() as usize {
+            return Err(error(format!(
+                "{} levels for {}x{}",
+                level_count, width, height
+            )));
+        }
+
+        let dfd = slice(data, u32_at(data, 48)? as usize, u32_at(data, 52)? as usize)?;
+        let global_data = slice(data, u64_at(data, 64)?, u64_at(data, 72)?)?.to_vec();
+        // The descriptor starts with its total size, then the basic block: vendor and type,
+        // version and block size, the fields below and 16 bytes for every sample.
+        let block = dfd
+            .get(4..28)
+            .ok_or_else(|| error("data format descriptor is truncated".to_owned()))?;
+        let color_model = block[8];
+        let srgb = block[10] == TRANSFER_SRGB;
+        let samples_end = 4 + u16::from_le_bytes([block[6], block[7]]) as usize;
+        let channels: Vec<u8> = dfd
+            .get(28..samples_end)
+            .unwrap_or_default()
+            .chunks_exact(16)
+            .map(|sample| sample[3] & 15)
+            .collect();
+
+        let basis = match (format, color_model) {
+            (Format::UNDEFINED, COLOR_MODEL_ETC1S) => Some(BasisFormat::Etc1s),
+            (Format::UNDEFINED, COLOR_MODEL_UASTC) => Some(BasisFormat::Uastc),
+            (Format::UNDEFINED, _) => {
+                return Err(error(format!("unknown color model {}", color_model)))
+            }
+            _ => None,
+        };
+        let alpha = match basis {
+            Some(BasisFormat::Uastc) => channels
+                .iter()
+                .any(|channel| matches!(*channel, CHANNEL_UASTC_RGBA | CHANNEL_UASTC_RRRG)),
+            _ => channels.contains(&CHANNEL_ALPHA),
+        };
+        match (supercompression, basis) {
+            (SUPERCOMPRESSION_BASIS_LZ, Some(BasisFormat::Etc1s)) => {}
+            (SUPERCOMPRESSION_BASIS_LZ, _) | (_, Some(BasisFormat::Etc1s)) => {
+                return Err(error("BasisLZ goes with ETC1S and only with it".to_owned()))
+            }
+            (SUPERCOMPRESSION_NONE | SUPERCOMPRESSION_ZSTD | SUPERCOMPRESSION_ZLIB, _) => {}
+            _ => {
+                return Err(error(format!(
+                    "unknown supercompression scheme {}",
+                    supercompression
+                )))
+            }
+        }
+
+        let mut ktx2 = Ktx2 {
+            format,
+            width,
+            height,
+            layers,
+            faces,
+            levels: Vec::with_capacity(level_count),
+            generate_mips,
+            basis,
+            srgb,
+            alpha,
+            global_data,
+        };
+        // ETC1S sizes are only known after transcoding, every other texture has a block.
+        let block = ktx2.block();
+        if block.is_none() && basis.is_none() {
+            return Err(error(format!("format {:?} isn`t supported", format)));
+        }
+        for level in 0..level_count {
+            let index = HEADER_SIZE + level * LEVEL_INDEX_SIZE;
+            let stored = slice(data, u64_at(data, index)?, u64_at(data, index + 8)?)?;
+            let length = u64_at(data, index + 16)?;
+            // Checked before decompressing, so a bogus length can't make it allocate.
+            if let Some(block) = block {
+                let expected = ktx2.level_size(level, block);
+                if length != expected {
+                    return Err(error(format!(
+                        "level {} has {} bytes, {:?} needs {}",
+                        level, length, format, expected
+                    )));
+                }
+            }
+            let unpack = |reader: &mut dyn Read| {
+                let mut level_data = Vec::new();
+                level_data
+                    .try_reserve_exact(length)
+                    .map_err(|reason| error(format!("level {}: {}", level, reason)))?;
+                reader
+                    .take(length as u64 + 1)
+                    .read_to_end(&mut level_data)
+                    .map_err(|reason| error(format!("level {}: {}", level, reason)))?;
+                Ok::<_, Error>(level_data)
+            };
+            let level_data = match supercompression {
+                SUPERCOMPRESSION_ZSTD => unpack(
+                    &mut StreamingDecoder::new(stored)
+                        .map_err(|reason| error(format!("level {}: {}", level, reason)))?,
+                )?,
+                SUPERCOMPRESSION_ZLIB => unpack(&mut ZlibDecoder::new(stored))?,
+                _ => stored.to_vec(),
+            };
+            if supercompression != SUPERCOMPRESSION_BASIS_LZ && level_data.len() != length {
+                return Err(error(format!(
+                    "level {} has {} bytes, expected {}",
+                    level,
+                    level_data.len(),
+                    length
+                )));
+            }
+            ktx2.levels.push(level_data);
+        }
+        Ok(ktx2)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_bytes(&fs::read(path).map_err(Error::Io)?)
+    }
+
+    /// Layers of the Vulkan image: array elements times faces.
+    pub fn array_layers(&self) -> u32 {
+        self.layers.max(1) * self.faces
+    }
+
+    pub fn is_cubemap(&self) -> bool {
+        self.faces == 6
+    }
+
+    /// Width and height of a texel block and its size in bytes, `None` for ETC1S, whose
+    /// size is only known after transcoding.
+    pub(crate) fn block(&self) -> Option<(u32, u32, u32)> {
+        match self.basis {
+            Some(BasisFormat::Etc1s) => None,
+            Some(BasisFormat::Uastc) => Some((4, 4, 16)),
+            None => format_block(self.format),
+        }
+    }
+
+    pub fn level_extent(&self, level: usize) -> (u32, u32) {
+        ((self.width >> level).max(1), (self.height >> level).max(1))
+    }
+
+    /// Bytes of `level` in a format with `block` width, height and size.
+    pub(crate) fn level_size(&self, level: usize, block: (u32, u32, u32)) -> usize {
+        let (width, height) = self.level_extent(level);
+        (width.div_ceil(block.0.max(1)) as usize)
+            .saturating_mul(height.div_ceil(block.1.max(1)) as usize)
+            .saturating_mul(block.2 as usize)
+            .saturating_mul(self.array_layers() as usize)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::io::Write;
+
+    use flate2::{write::ZlibEncoder, Compression};
+    use ruzstd::encoding::{compress_to_vec, CompressionLevel};
+
+    use super::*;
+
+    const RGBA8: Format = Format::R8G8B8A8_UNORM;
+    const COLOR_MODEL_RGBSDA: u8 = 1;
+
+    /// A 2D file with `levels` of stored bytes and their uncompressed lengths.
+    fn file(
+        format: Format,
+        (width, height): (u32, u32),
+        level_count: u32,
+        supercompression: u32,
+        levels: &[(Vec<u8>, u64)],
+    ) -> Vec<u8> {
+        let mut dfd = vec![0; 28];
+        dfd[0..4].copy_from_slice(&28u32.to_le_bytes());
+        dfd[10..12].copy_from_slice(&24u16.to_le_bytes());
+        dfd[12] = if format == Format::UNDEFINED {
+            COLOR_MODEL_UASTC
+        } else {
+            COLOR_MODEL_RGBSDA
+        };
+        let dfd_offset = HEADER_SIZE + levels.len() * LEVEL_INDEX_SIZE;
+
+        let mut data = IDENTIFIER.to_vec();
+        for value in [
+            format.as_raw() as u32,
+            1,
+            width,
+            height,
+            0,
+            0,
+            1,
+            level_count,
+        ] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        for value in [supercompression, dfd_offset as u32, dfd.len() as u32, 0, 0] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.extend_from_slice(&[0; 16]);
+        let mut offset = dfd_offset + dfd.len();
+        for (stored, length) in levels {
+            for value in [offset as u64, stored.len() as u64, *length] {
+                data.extend_from_slice(&value.to_le_bytes());
+            }
+            offset += stored.len();
+        }
+        data.extend_from_slice(&dfd);
+        for (stored, _) in levels {
+            data.extend_from_slice(stored);
+        }
+        data
+    }
+
+    fn stored(bytes: Vec<u8>) -> (Vec<u8>, u64) {
+        let length = bytes.len() as u64;
+        (bytes, length)
+    }
+
+    fn zlib(bytes: &[u8]) -> Vec<u8> {
+        let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+        encoder.write_all(bytes).unwrap();
+        encoder.finish().unwrap()
+    }
+
+    #[test]
+    fn levels_are_read_with_the_size_of_their_format() {
+        let levels = [stored(vec![1; 16]), stored(vec![2; 4])];
+        let ktx2 = Ktx2::from_bytes(&file(RGBA8, (2, 2), 2, 0, &levels)).unwrap();
+        assert_eq!(ktx2.levels, vec![vec![1; 16], vec![2; 4]]);
+        assert!(!ktx2.generate_mips);
+
+        // 3 byte texels and 4x4 blocks of 8 bytes.
+        let rgb = [stored(vec![0; 3 * 3 * 3])];
+        assert!(Ktx2::from_bytes(&file(Format::R8G8B8_UNORM, (3, 3), 1, 0, &rgb)).is_ok());
+        let bc1 = [stored(vec![0; 2 * 8])];
+        assert!(Ktx2::from_bytes(&file(Format::BC1_RGB_UNORM_BLOCK, (5, 4), 1, 0, &bc1)).is_ok());
+        let astc = [stored(vec![0; 2 * 2 * 16])];
+        assert!(Ktx2::from_bytes(&file(Format::ASTC_6X5_SRGB_BLOCK, (7, 10), 1, 0, &astc)).is_ok());
+    }
+
+    #[test]
+    fn zero_levels_leave_mips_to_the_loader() {
+        let ktx2 = Ktx2::from_bytes(&file(RGBA8, (4, 4), 0, 0, &[stored(vec![0; 64])])).unwrap();
+        assert!(ktx2.generate_mips);
+        assert_eq!(ktx2.levels.len(), 1);
+    }
+
+    #[test]
+    fn truncated_files_fail() {
+        let levels = [stored(vec![1; 16]), stored(vec![2; 4])];
+        let data = file(RGBA8, (2, 2), 2, 0, &levels);
+        for end in 0..data.len() {
+            assert!(Ktx2::from_bytes(&data[..end]).is_err(), "cut at {}", end);
+        }
+    }
+
+    #[test]
+    fn supercompressed_levels_are_unpacked() {
+        let pixels: Vec<u8> = (0..64).collect();
+        let zstd = compress_to_vec(&pixels[..], CompressionLevel::Fastest);
+        let ktx2 = Ktx2::from_bytes(&file(
+            RGBA8,
+            (4, 4),
+            1,
+            SUPERCOMPRESSION_ZSTD,
+            &[(zstd, 64)],
+        ))
+        .unwrap();
+        assert_eq!(ktx2.levels[0], pixels);
+
+        let uastc = Ktx2::from_bytes(&file(
+            Format::UNDEFINED,
+            (8, 4),
+            1,
+            SUPERCOMPRESSION_ZLIB,
+            &[(zlib(&pixels), 32)],
+        ));
+        assert!(uastc.is_err(), "2 UASTC blocks are 32 bytes, not 64");
+        let uastc = Ktx2::from_bytes(&file(
+            Format::UNDEFINED,
+            (8, 8),
+            1,
+            SUPERCOMPRESSION_ZLIB,
+            &[(zlib(&pixels), 64)],
+        ))
+        .unwrap();
+        assert_eq!(uastc.basis, Some(BasisFormat::Uastc));
+        assert_eq!(uastc.levels[0], pixels);
+    }
+
+    #[test]
+    fn wrong_level_lengths_fail() {
+        let short = [stored(vec![0; 15])];
+        assert!(Ktx2::from_bytes(&file(RGBA8, (2, 2), 1, 0, &short)).is_err());
+
+        // Lengths are checked against the format before anything is allocated.
+        let huge = [(zlib(&[0; 16]), u64::MAX)];
+        assert!(Ktx2::from_bytes(&file(RGBA8, (2, 2), 1, SUPERCOMPRESSION_ZLIB, &huge)).is_err());
+        let huge = [(
+            compress_to_vec(&[0; 16][..], CompressionLevel::Fastest),
+            u64::MAX,
+        )];
+        assert!(Ktx2::from_bytes(&file(RGBA8, (2, 2), 1, SUPERCOMPRESSION_ZSTD, &huge)).is_err());
+
+        // Data unpacking to fewer or more bytes than the level index claims.
+        let less = [(zlib(&[0; 12]), 16)];
+        assert!(Ktx2::from_bytes(&file(RGBA8, (2, 2), 1, SUPERCOMPRESSION_ZLIB, &less)).is_err());
+        let more = [(compress_to_vec(&[0; 20][..], CompressionLevel::Fastest), 16)];
+        assert!(Ktx2::from_bytes(&file(RGBA8, (2, 2), 1, SUPERCOMPRESSION_ZSTD, &more)).is_err());
+    }
+
+    #[test]
+    fn unknown_formats_fail() {
+        let levels = [stored(vec![0; 8])];
+        assert!(Ktx2::from_bytes(&file(Format::D32_SFLOAT, (2, 1), 1, 0, &levels)).is_err());
+    }
+}
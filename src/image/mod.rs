@@ -0,0 +1,122 @@
+mod ktx2;
+pub(crate) mod y4m;
+
+pub use ktx2::{BasisFormat, BasisTranscoder, Ktx2, TranscodeTarget};
+
+use std::{fs, path::Path};
+
+use ::image::{
+    codecs::png::PngEncoder, DynamicImage, ExtendedColorType, ImageEncoder, ImageFormat,
+    ImageResult,
+};
+
+use crate::{error::Result, Error};
+
+/// 8-bit RGBA pixels in the sRGB color space, rows top to bottom.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RgbaImage {
+    width: u32,
+    height: u32,
+    pixels: Vec<u8>,
+}
+
+impl RgbaImage {
+    /// `pixels` must hold `width * height * 4` bytes.
+    pub fn new(width: u32, height: u32, pixels: Vec<u8>) -> Result<Self> {
+        if pixels.len() != width as usize * height as usize * 4 {
+            return Err(Error::Other(format!(
+                "Image of {}x{} needs {} bytes, got {}",
+                width,
+                height,
+                width as usize * height as usize * 4,
+                pixels.len()
+            )));
+        }
+        Ok(RgbaImage {
+            width,
+            height,
+            pixels,
+        })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn pixels(&self) -> &[u8] {
+        &self.pixels
+    }
+
+    pub fn pixels_mut(&mut self) -> &mut [u8] {
+        &mut self.pixels
+    }
+
+    pub fn into_pixels(self) -> Vec<u8> {
+        self.pixels
+    }
+
+    pub fn pixel(&self, x: u32, y: u32) -> [u8; 4] {
+        let offset = (y as usize * self.width as usize + x as usize) * 4;
+        self.pixels[offset..offset + 4].try_into().unwrap()
+    }
+
+    /// Decodes a PNG file, converting every color type and bit depth to 8-bit RGBA.
+    pub fn from_png(png: &[u8]) -> Result<Self> {
+        Self::from_decoded(
+            ::image::load_from_memory_with_format(png, ImageFormat::Png),
+            "PNG",
+        )
+    }
+
+    pub fn load_png(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_png(&fs::read(path).map_err(Error::Io)?)
+    }
+
+    /// Decodes a baseline or progressive JPEG file.
+    pub fn from_jpeg(jpeg: &[u8]) -> Result<Self> {
+        Self::from_decoded(
+            ::image::load_from_memory_with_format(jpeg, ImageFormat::Jpeg),
+            "JPEG",
+        )
+    }
+
+    /// Decodes a PNG or JPEG file, telling them apart by their signatures.
+    pub fn decode(data: &[u8]) -> Result<Self> {
+        Self::from_decoded(::image::load_from_memory(data), "Image")
+    }
+
+    /// Converts what the `image` crate decoded to 8-bit RGBA.
+    fn from_decoded(decoded: ImageResult<DynamicImage>, format: &str) -> Result<Self> {
+        let image = decoded
+            .map_err(|error| Error::Other(format!("{}: {}", format, error)))?
+            .into_rgba8();
+        Self::new(image.width(), image.height(), image.into_raw())
+    }
+
+    /// Loads a PNG or JPEG file.
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Self::decode(&fs::read(path).map_err(Error::Io)?)
+    }
+
+    /// The image as a PNG file.
+    pub fn to_png(&self) -> Result<Vec<u8>> {
+        let mut png = Vec::new();
+        PngEncoder::new(&mut png)
+            .write_image(
+                &self.pixels,
+                self.width,
+                self.height,
+                ExtendedColorType::Rgba8,
+            )
+            .map_err(|error| Error::Other(format!("PNG: {}", error)))?;
+        Ok(png)
+    }
+
+    pub fn save_png(&self, path: impl AsRef<Path>) -> Result<()> {
+        fs::write(path, self.to_png()?).map_err(Error::Io)
+    }
+}
@@ -0,0 +1,38 @@
+//! YUV4MPEG2 streams, the uncompressed format ffmpeg and most encoders read from a pipe.
+
+fn gcd(a: u32, b: u32) -> u32 {
+    if b == 0 {
+        a
+    } else {
+        gcd(b, a % b)
+    }
+}
+
+/// Stream header for frames of `width` by `height` at `numerator / denominator` frames per
+/// second, full resolution chroma and limited range.
+pub(crate) fn header(width: u32, height: u32, numerator: u32, denominator: u32) -> String {
+    let divisor = gcd(numerator, denominator).max(1);
+    format!(
+        "YUV4MPEG2 W{} H{} F{}:{} Ip A1:1 C444 XCOLORRANGE=LIMITED\n",
+        width,
+        height,
+        numerator / divisor,
+        denominator / divisor
+    )
+}
+
+/// Appends one frame of 8-bit sRGB RGBA pixels as BT.601 Y'CbCr planes.
+pub(crate) fn frame(rgba: &[u8], out: &mut Vec<u8>) {
+    let count = rgba.len() / 4;
+    out.extend(b"FRAME\n");
+    let start = out.len();
+    out.resize(start + count * 3, 0);
+    let (y, chroma) = out[start..].split_at_mut(count);
+    let (cb, cr) = chroma.split_at_mut(count);
+    for (index, pixel) in rgba.chunks_exact(4).enumerate() {
+        let (r, g, b) = (pixel[0] as i32, pixel[1] as i32, pixel[2] as i32);
+        y[index] = (((66 * r + 129 * g + 25 * b + 128) >> 8) + 16) as u8;
+        cb[index] = (((-38 * r - 74 * g + 112 * b + 128) >> 8) + 128) as u8;
+        cr[index] = (((112 * r - 94 * g - 18 * b + 128) >> 8) + 128) as u8;
+    }
+}
@@ -0,0 +1,575 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fmt::{self, Display},
+    fs,
+    path::Path,
+    str::FromStr,
+};
+
+use crate::{
+    error::{Error, Result},
+    ElementState, Event, GamepadAxis, GamepadButton, GamepadEvent, GamepadId, MouseButton,
+};
+
+/// Names of keyboard keys, mapped to the virtual key codes reported in
+/// `Event::KeyboardInput`. Letters and digits are handled separately.
+const KEY_NAMES: [(&str, u32); 30] = [
+    ("Backspace", 0x08),
+    ("Tab", 0x09),
+    ("Enter", 0x0d),
+    ("Shift", 0x10),
+    ("Ctrl", 0x11),
+    ("Alt", 0x12),
+    ("Escape", 0x1b),
+    ("Space", 0x20),
+    ("PageUp", 0x21),
+    ("PageDown", 0x22),
+    ("End", 0x23),
+    ("Home", 0x24),
+    ("Left", 0x25),
+    ("Up", 0x26),
+    ("Right", 0x27),
+    ("Down", 0x28),
+    ("Insert", 0x2d),
+    ("Delete", 0x2e),
+    ("F1", 0x70),
+    ("F2", 0x71),
+    ("F3", 0x72),
+    ("F4", 0x73),
+    ("F5", 0x74),
+    ("F6", 0x75),
+    ("F7", 0x76),
+    ("F8", 0x77),
+    ("F9", 0x78),
+    ("F10", 0x79),
+    ("F11", 0x7a),
+    ("F12", 0x7b),
+];
+
+const GAMEPAD_BUTTON_NAMES: [(&str, GamepadButton); 15] = [
+    ("South", GamepadButton::South),
+    ("East", GamepadButton::East),
+    ("West", GamepadButton::West),
+    ("North", GamepadButton::North),
+    ("LeftBumper", GamepadButton::LeftBumper),
+    ("RightBumper", GamepadButton::RightBumper),
+    ("Select", GamepadButton::Select),
+    ("Start", GamepadButton::Start),
+    ("Mode", GamepadButton::Mode),
+    ("LeftStick", GamepadButton::LeftStick),
+    ("RightStick", GamepadButton::RightStick),
+    ("DPadUp", GamepadButton::DPadUp),
+    ("DPadDown", GamepadButton::DPadDown),
+    ("DPadLeft", GamepadButton::DPadLeft),
+    ("DPadRight", GamepadButton::DPadRight),
+];
+
+const GAMEPAD_AXIS_NAMES: [(&str, GamepadAxis); 6] = [
+    ("LeftStickX", GamepadAxis::LeftStickX),
+    ("LeftStickY", GamepadAxis::LeftStickY),
+    ("RightStickX", GamepadAxis::RightStickX),
+    ("RightStickY", GamepadAxis::RightStickY),
+    ("LeftTrigger", GamepadAxis::LeftTrigger),
+    ("RightTrigger", GamepadAxis::RightTrigger),
+];
+
+/// A single input that can be held down.
+///
+/// In config files keys are written by name (`Space`, `W`, `7`, `F5`) or as `Key 0x20`,
+/// mouse buttons as `Mouse Left` and gamepad buttons as `Gamepad South`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Binding {
+    /// Virtual key code, as reported in `Event::KeyboardInput`.
+    Key(u32),
+    Mouse(MouseButton),
+    /// The button on any connected gamepad.
+    Gamepad(GamepadButton),
+}
+
+impl Display for Binding {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Binding::Key(key @ (0x30..=0x39 | 0x41..=0x5a)) => {
+                write!(formatter, "{}", char::from(*key as u8))
+            }
+            Binding::Key(key) => match KEY_NAMES.iter().find(|(_, code)| code == key) {
+                Some((name, _)) => formatter.write_str(name),
+                None => write!(formatter, "Key {:#x}", key),
+            },
+            Binding::Mouse(MouseButton::Left) => formatter.write_str("Mouse Left"),
+            Binding::Mouse(MouseButton::Right) => formatter.write_str("Mouse Right"),
+            Binding::Mouse(MouseButton::Middle) => formatter.write_str("Mouse Middle"),
+            Binding::Mouse(MouseButton::Other(button)) => write!(formatter, "Mouse {}", button),
+            Binding::Gamepad(button) => {
+                let (name, _) = GAMEPAD_BUTTON_NAMES
+                    .iter()
+                    .find(|(_, b)| b == button)
+                    .unwrap();
+                write!(formatter, "Gamepad {}", name)
+            }
+        }
+    }
+}
+
+impl FromStr for Binding {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<Self> {
+        let text = text.trim();
+        let error = || Error::Other(format!("Unknown binding `{}`", text));
+
+        if let Some(name) = text.strip_prefix("Gamepad ") {
+            return GAMEPAD_BUTTON_NAMES
+                .iter()
+                .find(|(n, _)| n.eq_ignore_ascii_case(name.trim()))
+                .map(|(_, button)| Binding::Gamepad(*button))
+                .ok_or_else(error);
+        }
+        if let Some(name) = text.strip_prefix("Mouse ") {
+            let button = match name.trim().to_ascii_lowercase().as_str() {
+                "left" => MouseButton::Left,
+                "right" => MouseButton::Right,
+                "middle" => MouseButton::Middle,
+                other => MouseButton::Other(other.parse().map_err(|_| error())?),
+            };
+            return Ok(Binding::Mouse(button));
+        }
+        if let Some(code) = text.strip_prefix("Key ") {
+            let code = code.trim();
+            let parsed = match code.strip_prefix("0x") {
+                Some(hex) => u32::from_str_radix(hex, 16),
+                None => code.parse(),
+            };
+            return parsed.map(Binding::Key).map_err(|_| error());
+        }
+
+        let mut chars = text.chars();
+        if let (Some(c), None) = (chars.next(), chars.next()) {
+            if c.is_ascii_alphanumeric() {
+                return Ok(Binding::Key(c.to_ascii_uppercase() as u32));
+            }
+        }
+        KEY_NAMES
+            .iter()
+            .find(|(name, _)| name.eq_ignore_ascii_case(text))
+            .map(|(_, code)| Binding::Key(*code))
+            .ok_or_else(error)
+    }
+}
+
+/// Source of an analog value in `-1.0..=1.0`.
+///
+/// In config files written as `A/D` (negative/positive pair) or `Gamepad LeftStickX`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum AxisBinding {
+    /// Two bindings that drive the axis to -1 and 1.
+    Digital {
+        negative: Binding,
+        positive: Binding,
+    },
+    /// The axis of any connected gamepad, with its dead zone applied.
+    Gamepad(GamepadAxis),
+}
+
+impl Display for AxisBinding {
+    fn fmt(&self, formatter: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AxisBinding::Digital { negative, positive } => {
+                write!(formatter, "{}/{}", negative, positive)
+            }
+            AxisBinding::Gamepad(axis) => {
+                let (name, _) = GAMEPAD_AXIS_NAMES.iter().find(|(_, a)| a == axis).unwrap();
+                write!(formatter, "Gamepad {}", name)
+            }
+        }
+    }
+}
+
+impl FromStr for AxisBinding {
+    type Err = Error;
+
+    fn from_str(text: &str) -> Result<Self> {
+        let text = text.trim();
+        if let Some(name) = text.strip_prefix("Gamepad ") {
+            if let Some((_, axis)) = GAMEPAD_AXIS_NAMES
+                .iter()
+                .find(|(n, _)| n.eq_ignore_ascii_case(name.trim()))
+            {
+                return Ok(AxisBinding::Gamepad(*axis));
+            }
+        }
+        match text.split_once('/') {
+            Some((negative, positive)) => Ok(AxisBinding::Digital {
+                negative: negative.parse()?,
+                positive: positive.parse()?,
+            }),
+            None => Err(Error::Other(format!("Unknown axis binding `{}`", text))),
+        }
+    }
+}
+
+/// Named actions and axes bound to keyboard, mouse and gamepad input.
+///
+/// Feed it every window and gamepad event, then call `update` once per frame before
+/// querying. An action is bound to one or more chords; a chord is active while all of
+/// its bindings are held, so `Ctrl+S` needs both keys.
+///
+/// Bindings can be loaded from a config file with one binding per line:
+///
+/// ```text
+/// # comment
+/// jump = Space | Gamepad South
+/// save = Ctrl+S
+/// axis move_x = A/D | Left/Right | Gamepad LeftStickX
+/// ```
+#[derive(Default)]
+pub struct ActionMap {
+    actions: HashMap<String, Vec<Vec<Binding>>>,
+    axes: HashMap<String, Vec<AxisBinding>>,
+    /// Keyboard and mouse bindings currently held.
+    held: HashSet<Binding>,
+    gamepad_held: HashSet<(GamepadId, GamepadButton)>,
+    gamepad_axes: HashMap<(GamepadId, GamepadAxis), f32>,
+    /// Bindings pressed since the last `update`, so taps shorter than a frame still count.
+    tapped: HashSet<Binding>,
+    active: HashSet<String>,
+    previous: HashSet<String>,
+}
+
+impl ActionMap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Adds a chord to `action`, keeping its existing bindings.
+    pub fn bind(&mut self, action: &str, chord: &[Binding]) {
+        if !chord.is_empty() {
+            self.actions
+                .entry(action.to_owned())
+                .or_default()
+                .push(chord.to_vec());
+        }
+    }
+
+    pub fn bind_axis(&mut self, axis: &str, binding: AxisBinding) {
+        self.axes.entry(axis.to_owned()).or_default().push(binding);
+    }
+
+    /// Removes all bindings of an action or axis.
+    pub fn unbind(&mut self, name: &str) {
+        self.actions.remove(name);
+        self.axes.remove(name);
+        self.active.remove(name);
+        self.previous.remove(name);
+    }
+
+    /// Parses bindings in the config format, see `ActionMap`.
+    pub fn from_config(config: &str) -> Result<Self> {
+        let mut map = ActionMap::new();
+        map.apply_config(config)?;
+        Ok(map)
+    }
+
+    pub fn load(path: impl AsRef<Path>) -> Result<Self> {
+        Self::from_config(&fs::read_to_string(path).map_err(Error::Io)?)
+    }
+
+    /// Rebinds the actions and axes named in `config`, leaving others untouched.
+    pub fn apply_config(&mut self, config: &str) -> Result<()> {
+        let mut actions: HashMap<String, Vec<Vec<Binding>>> = HashMap::new();
+        let mut axes: HashMap<String, Vec<AxisBinding>> = HashMap::new();
+
+        for (number, line) in config.lines().enumerate() {
+            let line = line.split('#').next().unwrap_or_default().trim();
+            if line.is_empty() {
+                continue;
+            }
+            let error = |e: Error| Error::Other(format!("Line {}: {}", number + 1, e));
+            let (name, bindings) = line.split_once('=').ok_or_else(|| {
+                Error::Other(format!("Line {}: expected `name = bindings`", number + 1))
+            })?;
+
+            if let Some(axis) = name.trim().strip_prefix("axis ") {
+                let entry = axes.entry(axis.trim().to_owned()).or_default();
+                for binding in bindings.split('|') {
+                    entry.push(binding.parse().map_err(error)?);
+                }
+            } else {
+                let entry = actions.entry(name.trim().to_owned()).or_default();
+                for chord in bindings.split('|') {
+                    let chord = chord
+                        .split('+')
+                        .map(str::parse)
+                        .collect::<Result<Vec<Binding>>>()
+                        .map_err(error)?;
+                    entry.push(chord);
+                }
+            }
+        }
+
+        self.actions.extend(actions);
+        self.axes.extend(axes);
+        Ok(())
+    }
+
+    /// Writes all bindings in the config format.
+    pub fn to_config(&self) -> String {
+        let mut lines: Vec<String> = self
+            .actions
+            .iter()
+            .map(|(name, chords)| {
+                let chords: Vec<String> = chords
+                    .iter()
+                    .map(|chord| {
+                        let bindings: Vec<String> = chord.iter().map(|b| b.to_string()).collect();
+                        bindings.join("+")
+                    })
+                    .collect();
+                format!("{} = {}", name, chords.join(" | "))
+            })
+            .collect();
+        lines.sort();
+
+        let mut axes: Vec<String> = self
+            .axes
+            .iter()
+            .map(|(name, bindings)| {
+                let bindings: Vec<String> = bindings.iter().map(|b| b.to_string()).collect();
+                format!("axis {} = {}", name, bindings.join(" | "))
+            })
+            .collect();
+        axes.sort();
+        lines.extend(axes);
+
+        let mut config = lines.join("\n");
+        config.push('\n');
+        config
+    }
+
+    pub fn handle_event(&mut self, event: &Event) {
+        let (binding, state) = match event {
+            Event::KeyboardInput { key, state, .. } => (Binding::Key(*key), *state),
+            Event::MouseInput { button, state } => (Binding::Mouse(*button), *state),
+            // Releases are lost while unfocused, so nothing stays stuck down.
+            Event::Focused(false) => {
+                self.held.clear();
+                return;
+            }
+            _ => return,
+        };
+        match state {
+            ElementState::Pressed => {
+                self.held.insert(binding);
+                self.tapped.insert(binding);
+            }
+            ElementState::Released => {
+                self.held.remove(&binding);
+            }
+        }
+    }
+
+    pub fn handle_gamepad_event(&mut self, event: &GamepadEvent) {
+        match *event {
+            GamepadEvent::Button { id, button, state } => match state {
+                ElementState::Pressed => {
+                    self.gamepad_held.insert((id, button));
+                    self.tapped.insert(Binding::Gamepad(button));
+                }
+                ElementState::Released => {
+                    self.gamepad_held.remove(&(id, button));
+                }
+            },
+            GamepadEvent::Axis { id, axis, value } => {
+                self.gamepad_axes.insert((id, axis), value);
+            }
+            GamepadEvent::Disconnected(id) => {
+                self.gamepad_held.retain(|(held, _)| *held != id);
+                self.gamepad_axes.retain(|(held, _), _| *held != id);
+            }
+            GamepadEvent::Connected(_) => {}
+        }
+    }
+
+    fn is_down(&self, binding: &Binding) -> bool {
+        match binding {
+            Binding::Gamepad(button) => self.gamepad_held.iter().any(|(_, b)| b == button),
+            binding => self.held.contains(binding),
+        }
+    }
+
+    /// Ends the frame: the state queried until the next `update` reflects the events
+    /// handled so far.
+    pub fn update(&mut self) {
+        self.previous = std::mem::take(&mut self.active);
+        for (name, chords) in &self.actions {
+            let active = chords.iter().any(|chord| {
+                chord
+                    .iter()
+                    .all(|binding| self.is_down(binding) || self.tapped.contains(binding))
+            });
+            if active {
+                self.active.insert(name.clone());
+            }
+        }
+        self.tapped.clear();
+    }
+
+    /// Became active this frame.
+    pub fn pressed(&self, action: &str) -> bool {
+        self.active.contains(action) && !self.previous.contains(action)
+    }
+
+    /// Became inactive this frame.
+    pub fn released(&self, action: &str) -> bool {
+        !self.active.contains(action) && self.previous.contains(action)
+    }
+
+    pub fn held(&self, action: &str) -> bool {
+        self.active.contains(action)
+    }
+
+    /// Sum of the axis bindings, clamped to `-1.0..=1.0`. Unknown axes read zero.
+    pub fn axis(&self, axis: &str) -> f32 {
+        let Some(bindings) = self.axes.get(axis) else {
+            return 0.0;
+        };
+        let value: f32 = bindings
+            .iter()
+            .map(|binding| match binding {
+                AxisBinding::Digital { negative, positive } => {
+                    self.is_down(positive) as i32 as f32 - self.is_down(negative) as i32 as f32
+                }
+                AxisBinding::Gamepad(axis) => self
+                    .gamepad_axes
+                    .iter()
+                    .filter(|((_, a), _)| a == axis)
+                    .map(|(_, value)| *value)
+                    .fold(
+                        0.0,
+                        |max: f32, value| {
+                            if value.abs() > max.abs() {
+                                value
+                            } else {
+                                max
+                            }
+                        },
+                    ),
+            })
+            .sum();
+        value.clamp(-1.0, 1.0)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const CONFIG: &str = "\
+# movement
+jump = Space | Gamepad South
+save = Ctrl+S
+fire = Mouse Left | Key 0xe2
+axis move_x = A/D | Left/Right | Gamepad LeftStickX
+";
+
+    fn key(key: u32, state: ElementState) -> Event {
+        Event::KeyboardInput {
+            scancode: 0,
+            key,
+            state,
+        }
+    }
+
+    #[test]
+    fn config_round_trips() {
+        let map = ActionMap::from_config(CONFIG).unwrap();
+        assert_eq!(
+            map.actions["save"],
+            [vec![Binding::Key(0x11), Binding::Key(0x53)]]
+        );
+        assert_eq!(
+            map.axes["move_x"][2],
+            AxisBinding::Gamepad(GamepadAxis::LeftStickX)
+        );
+
+        let config = map.to_config();
+        assert_eq!(
+            config,
+            "fire = Mouse Left | Key 0xe2\n\
+             jump = Space | Gamepad South\n\
+             save = Ctrl+S\n\
+             axis move_x = A/D | Left/Right | Gamepad LeftStickX\n"
+        );
+        let reparsed = ActionMap::from_config(&config).unwrap();
+        assert_eq!(reparsed.actions, map.actions);
+        assert_eq!(reparsed.axes, map.axes);
+    }
+
+    #[test]
+    fn chords_need_every_binding_held() {
+        let mut map = ActionMap::from_config(CONFIG).unwrap();
+        map.handle_event(&key(0x53, ElementState::Pressed));
+        map.handle_event(&key(0x53, ElementState::Released));
+        map.update();
+        assert!(!map.held("save"));
+
+        map.handle_event(&key(0x11, ElementState::Pressed));
+        map.update();
+        assert!(!map.held("save"));
+
+        map.handle_event(&key(0x53, ElementState::Pressed));
+        map.update();
+        assert!(map.pressed("save"));
+        map.update();
+        assert!(map.held("save") && !map.pressed("save"));
+
+        map.handle_event(&key(0x11, ElementState::Released));
+        map.update();
+        assert!(map.released("save"));
+    }
+
+    #[test]
+    fn taps_shorter_than_a_frame_count() {
+        let mut map = ActionMap::from_config(CONFIG).unwrap();
+        map.handle_event(&key(0x20, ElementState::Pressed));
+        map.handle_event(&key(0x20, ElementState::Released));
+        map.update();
+        assert!(map.pressed("jump"));
+        map.update();
+        assert!(map.released("jump"));
+    }
+
+    #[test]
+    fn losing_focus_releases_held_keys() {
+        let mut map = ActionMap::from_config(CONFIG).unwrap();
+        map.handle_event(&key(0x20, ElementState::Pressed));
+        map.handle_event(&key(0x44, ElementState::Pressed));
+        map.update();
+        assert!(map.held("jump"));
+        assert_eq!(map.axis("move_x"), 1.0);
+
+        map.handle_event(&Event::Focused(false));
+        map.update();
+        assert!(map.released("jump"));
+        assert_eq!(map.axis("move_x"), 0.0);
+    }
+
+    #[test]
+    fn parse_errors_name_the_line() {
+        let error = ActionMap::from_config("jump = Space\n\n# x\nsave = Ctrl+Nope\n")
+            .err()
+            .unwrap();
+        assert!(
+            error.to_string().contains("Line 4: Unknown binding `Nope`"),
+            "{}",
+            error
+        );
+
+        let error = ActionMap::from_config("jump = Space\njump Space\n")
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("Line 2"), "{}", error);
+        let error = ActionMap::from_config("axis move = A-D\n").err().unwrap();
+        assert!(error.to_string().contains("Line 1"), "{}", error);
+    }
+}
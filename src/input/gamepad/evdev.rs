@@ -0,0 +1,427 @@
+use std::{
+    collections::HashMap,
+    ffi::CStr,
+    fs::{self, File, OpenOptions},
+    io::{self, Read, Write},
+    mem,
+    os::{
+        fd::{AsRawFd, FromRawFd, OwnedFd},
+        unix::fs::OpenOptionsExt,
+    },
+    path::{Path, PathBuf},
+    slice,
+    time::{Duration, Instant},
+};
+
+use super::{Backend, GamepadAxis, GamepadButton, GamepadId, RawGamepad};
+use crate::error::{Error, Result};
+
+const INPUT_DIR: &str = "/dev/input";
+/// Rescan interval when inotify isn't available.
+const RESCAN_INTERVAL: Duration = Duration::from_secs(2);
+
+const EV_SYN: u16 = 0x00;
+const EV_KEY: u16 = 0x01;
+const EV_ABS: u16 = 0x03;
+const EV_FF: u16 = 0x15;
+const SYN_DROPPED: u16 = 3;
+
+const KEY_MAX: usize = 0x2ff;
+const ABS_MAX: usize = 0x3f;
+
+const BTN_SOUTH: u16 = 0x130;
+const BTN_TL2: u16 = 0x138;
+const BTN_TR2: u16 = 0x139;
+
+const ABS_X: u16 = 0x00;
+const ABS_Y: u16 = 0x01;
+const ABS_Z: u16 = 0x02;
+const ABS_RX: u16 = 0x03;
+const ABS_RY: u16 = 0x04;
+const ABS_RZ: u16 = 0x05;
+const ABS_HAT0X: u16 = 0x10;
+const ABS_HAT0Y: u16 = 0x11;
+
+const FF_RUMBLE: u16 = 0x50;
+
+const fn ioc(dir: u64, nr: u64, size: usize) -> u64 {
+    (dir << 30) | ((size as u64) << 16) | ((b'E' as u64) << 8) | nr
+}
+
+const fn eviocgname(len: usize) -> u64 {
+    ioc(2, 0x06, len)
+}
+
+const fn eviocgkey(len: usize) -> u64 {
+    ioc(2, 0x18, len)
+}
+
+const fn eviocgbit(ev: u16, len: usize) -> u64 {
+    ioc(2, 0x20 + ev as u64, len)
+}
+
+const fn eviocgabs(abs: u16) -> u64 {
+    ioc(2, 0x40 + abs as u64, mem::size_of::<libc::input_absinfo>())
+}
+
+const EVIOCSFF: u64 = ioc(1, 0x80, mem::size_of::<libc::ff_effect>());
+
+fn button(code: u16) -> Option<GamepadButton> {
+    Some(match code {
+        0x130 => GamepadButton::South,
+        0x131 => GamepadButton::East,
+        0x133 => GamepadButton::North,
+        0x134 => GamepadButton::West,
+        0x136 => GamepadButton::LeftBumper,
+        0x137 => GamepadButton::RightBumper,
+        0x13a => GamepadButton::Select,
+        0x13b => GamepadButton::Start,
+        0x13c => GamepadButton::Mode,
+        0x13d => GamepadButton::LeftStick,
+        0x13e => GamepadButton::RightStick,
+        0x220 => GamepadButton::DPadUp,
+        0x221 => GamepadButton::DPadDown,
+        0x222 => GamepadButton::DPadLeft,
+        0x223 => GamepadButton::DPadRight,
+        _ => return None,
+    })
+}
+
+fn test_bit(bits: &[u8], bit: usize) -> bool {
+    bits.get(bit / 8)
+        .is_some_and(|byte| byte & (1 << (bit % 8)) != 0)
+}
+
+/// Wraps an ioctl taking a pointer argument.
+unsafe fn ioctl<T>(file: &File, request: u64, arg: *mut T) -> io::Result<()> {
+    if libc::ioctl(file.as_raw_fd(), request as _, arg) < 0 {
+        Err(io::Error::last_os_error())
+    } else {
+        Ok(())
+    }
+}
+
+struct Device {
+    file: File,
+    path: PathBuf,
+    /// Calibration of the absolute axes the device has, indexed by `ABS_*` code.
+    abs: HashMap<u16, libc::input_absinfo>,
+    /// Id of the uploaded rumble effect, -1 until the first upload.
+    effect: i16,
+    writable: bool,
+}
+
+impl Device {
+    fn open(path: &Path) -> Option<Self> {
+        let mut writable = true;
+        let file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK | libc::O_CLOEXEC)
+            .open(path)
+            .or_else(|_| {
+                writable = false;
+                OpenOptions::new()
+                    .read(true)
+                    .custom_flags(libc::O_NONBLOCK | libc::O_CLOEXEC)
+                    .open(path)
+            })
+            .ok()?;
+
+        let mut keys = [0u8; KEY_MAX / 8 + 1];
+        unsafe { ioctl(&file, eviocgbit(EV_KEY, keys.len()), keys.as_mut_ptr()) }.ok()?;
+        if !test_bit(&keys, BTN_SOUTH as usize) {
+            return None;
+        }
+
+        let mut abs_bits = [0u8; ABS_MAX / 8 + 1];
+        unsafe {
+            ioctl(
+                &file,
+                eviocgbit(EV_ABS, abs_bits.len()),
+                abs_bits.as_mut_ptr(),
+            )
+        }
+        .ok()?;
+
+        let mut abs = HashMap::new();
+        for code in [
+            ABS_X, ABS_Y, ABS_Z, ABS_RX, ABS_RY, ABS_RZ, ABS_HAT0X, ABS_HAT0Y,
+        ] {
+            if test_bit(&abs_bits, code as usize) {
+                let mut info: libc::input_absinfo = unsafe { mem::zeroed() };
+                if unsafe { ioctl(&file, eviocgabs(code), &mut info) }.is_ok() {
+                    abs.insert(code, info);
+                }
+            }
+        }
+
+        Some(Device {
+            file,
+            path: path.to_owned(),
+            abs,
+            effect: -1,
+            writable,
+        })
+    }
+
+    fn name(&self) -> String {
+        let mut name = [0u8; 256];
+        match unsafe { ioctl(&self.file, eviocgname(name.len()), name.as_mut_ptr()) } {
+            Ok(()) => CStr::from_bytes_until_nul(&name)
+                .map(|name| name.to_string_lossy().into_owned())
+                .unwrap_or_default(),
+            Err(_) => String::new(),
+        }
+    }
+
+    /// Reads the whole device state, used after opening and after the kernel dropped events.
+    fn sync(&mut self, state: &mut RawGamepad) -> io::Result<()> {
+        let mut keys = [0u8; KEY_MAX / 8 + 1];
+        unsafe { ioctl(&self.file, eviocgkey(keys.len()), keys.as_mut_ptr()) }?;
+        for code in 0x130..=0x223u16 {
+            self.key(state, code, test_bit(&keys, code as usize) as i32);
+        }
+
+        let codes: Vec<u16> = self.abs.keys().copied().collect();
+        for code in codes {
+            let mut info: libc::input_absinfo = unsafe { mem::zeroed() };
+            unsafe { ioctl(&self.file, eviocgabs(code), &mut info) }?;
+            self.abs.insert(code, info);
+            self.axis(state, code, info.value);
+        }
+        Ok(())
+    }
+
+    fn key(&self, state: &mut RawGamepad, code: u16, value: i32) {
+        if let Some(button) = button(code) {
+            state.buttons[button as usize] = value != 0;
+        } else if code == BTN_TL2 && !self.abs.contains_key(&ABS_Z) {
+            state.axes[GamepadAxis::LeftTrigger as usize] = (value != 0) as i32 as f32;
+        } else if code == BTN_TR2 && !self.abs.contains_key(&ABS_RZ) {
+            state.axes[GamepadAxis::RightTrigger as usize] = (value != 0) as i32 as f32;
+        }
+    }
+
+    fn axis(&self, state: &mut RawGamepad, code: u16, value: i32) {
+        let Some(info) = self.abs.get(&code) else {
+            return;
+        };
+        let range = (info.maximum - info.minimum).max(1) as f32;
+        let unit = ((value - info.minimum) as f32 / range).clamp(0.0, 1.0);
+        let stick = unit * 2.0 - 1.0;
+
+        match code {
+            ABS_X => state.axes[GamepadAxis::LeftStickX as usize] = stick,
+            ABS_Y => state.axes[GamepadAxis::LeftStickY as usize] = -stick,
+            ABS_RX => state.axes[GamepadAxis::RightStickX as usize] = stick,
+            ABS_RY => state.axes[GamepadAxis::RightStickY as usize] = -stick,
+            ABS_Z => state.axes[GamepadAxis::LeftTrigger as usize] = unit,
+            ABS_RZ => state.axes[GamepadAxis::RightTrigger as usize] = unit,
+            ABS_HAT0X => {
+                state.buttons[GamepadButton::DPadLeft as usize] = value < 0;
+                state.buttons[GamepadButton::DPadRight as usize] = value > 0;
+            }
+            ABS_HAT0Y => {
+                state.buttons[GamepadButton::DPadUp as usize] = value < 0;
+                state.buttons[GamepadButton::DPadDown as usize] = value > 0;
+            }
+            _ => {}
+        }
+    }
+
+    /// Applies pending events. Returns `false` once the device is gone.
+    fn read(&mut self, state: &mut RawGamepad) -> bool {
+        let mut events: [libc::input_event; 64] = unsafe { mem::zeroed() };
+        loop {
+            let buf = unsafe {
+                slice::from_raw_parts_mut(events.as_mut_ptr() as *mut u8, mem::size_of_val(&events))
+            };
+            let count = match self.file.read(buf) {
+                Ok(0) => return false,
+                Ok(read) => read / mem::size_of::<libc::input_event>(),
+                Err(e) if e.kind() == io::ErrorKind::WouldBlock => return true,
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(_) => return false,
+            };
+
+            for event in &events[..count] {
+                match event.type_ {
+                    EV_KEY => self.key(state, event.code, event.value),
+                    EV_ABS => self.axis(state, event.code, event.value),
+                    EV_SYN if event.code == SYN_DROPPED && self.sync(state).is_err() => {
+                        return false;
+                    }
+                    _ => {}
+                }
+            }
+        }
+    }
+
+    fn write_event(&mut self, type_: u16, code: u16, value: i32) -> io::Result<()> {
+        let mut event: libc::input_event = unsafe { mem::zeroed() };
+        event.type_ = type_;
+        event.code = code;
+        event.value = value;
+        let buf = unsafe {
+            slice::from_raw_parts(
+                &event as *const libc::input_event as *const u8,
+                mem::size_of::<libc::input_event>(),
+            )
+        };
+        self.file.write_all(buf)
+    }
+
+    fn rumble(&mut self, strong: f32, weak: f32, duration: Duration) -> io::Result<()> {
+        if !self.writable {
+            return Err(io::Error::from(io::ErrorKind::PermissionDenied));
+        }
+        if strong == 0.0 && weak == 0.0 {
+            if self.effect >= 0 {
+                self.write_event(EV_FF, self.effect as u16, 0)?;
+            }
+            return Ok(());
+        }
+
+        let mut effect: libc::ff_effect = unsafe { mem::zeroed() };
+        effect.type_ = FF_RUMBLE;
+        effect.id = self.effect;
+        effect.replay.length = duration.as_millis().min(u16::MAX as u128) as u16;
+        let rumble = libc::ff_rumble_effect {
+            strong_magnitude: (strong * u16::MAX as f32) as u16,
+            weak_magnitude: (weak * u16::MAX as f32) as u16,
+        };
+        unsafe {
+            (effect.u.as_mut_ptr() as *mut libc::ff_rumble_effect).write(rumble);
+            ioctl(&self.file, EVIOCSFF, &mut effect)?;
+        }
+        self.effect = effect.id;
+        self.write_event(EV_FF, self.effect as u16, 1)
+    }
+}
+
+pub(crate) struct Evdev {
+    devices: HashMap<GamepadId, Device>,
+    next_id: u32,
+    /// Watches `/dev/input` for new devices. Without it the directory is rescanned
+    /// periodically.
+    inotify: Option<OwnedFd>,
+    rescan: bool,
+    last_scan: Instant,
+}
+
+impl Evdev {
+    pub fn new() -> Result<Self> {
+        let inotify = unsafe {
+            let fd = libc::inotify_init1(libc::IN_NONBLOCK | libc::IN_CLOEXEC);
+            if fd < 0 {
+                None
+            } else {
+                let fd = OwnedFd::from_raw_fd(fd);
+                let dir = c"/dev/input";
+                let mask = libc::IN_CREATE | libc::IN_ATTRIB | libc::IN_MOVED_TO;
+                if libc::inotify_add_watch(fd.as_raw_fd(), dir.as_ptr(), mask) < 0 {
+                    None
+                } else {
+                    Some(fd)
+                }
+            }
+        };
+        if inotify.is_none() {
+            log::trace!("gamepad inotify unavailable, rescanning {}", INPUT_DIR);
+        }
+
+        Ok(Evdev {
+            devices: HashMap::new(),
+            next_id: 0,
+            inotify,
+            rescan: true,
+            last_scan: Instant::now(),
+        })
+    }
+
+    fn drain_inotify(&mut self) {
+        let Some(fd) = &self.inotify else {
+            if self.last_scan.elapsed() >= RESCAN_INTERVAL {
+                self.rescan = true;
+            }
+            return;
+        };
+        let mut buf = [0u8; 4096];
+        while unsafe { libc::read(fd.as_raw_fd(), buf.as_mut_ptr() as *mut _, buf.len()) } > 0 {
+            self.rescan = true;
+        }
+    }
+
+    fn scan(&mut self, gamepads: &mut HashMap<GamepadId, RawGamepad>) {
+        self.rescan = false;
+        self.last_scan = Instant::now();
+
+        let Ok(entries) = fs::read_dir(INPUT_DIR) else {
+            return;
+        };
+        for entry in entries.flatten() {
+            let path = entry.path();
+            let is_event = path
+                .file_name()
+                .and_then(|name| name.to_str())
+                .is_some_and(|name| name.starts_with("event"));
+            if !is_event || self.devices.values().any(|device| device.path == path) {
+                continue;
+            }
+
+            let Some(mut device) = Device::open(&path) else {
+                continue;
+            };
+            let mut state = RawGamepad {
+                name: device.name(),
+                ..Default::default()
+            };
+            if device.sync(&mut state).is_err() {
+                continue;
+            }
+
+            let id = GamepadId(self.next_id);
+            self.next_id += 1;
+            log::trace!("gamepad {:?} connected: {} ({:?})", id, state.name, path);
+            self.devices.insert(id, device);
+            gamepads.insert(id, state);
+        }
+    }
+}
+
+impl Backend for Evdev {
+    fn poll(&mut self, gamepads: &mut HashMap<GamepadId, RawGamepad>) {
+        self.drain_inotify();
+        if self.rescan {
+            self.scan(gamepads);
+        }
+
+        self.devices.retain(|id, device| {
+            let state = gamepads.entry(*id).or_default();
+            if device.read(state) {
+                true
+            } else {
+                log::trace!("gamepad {:?} disconnected", id);
+                gamepads.remove(id);
+                false
+            }
+        });
+    }
+
+    fn set_rumble(
+        &mut self,
+        id: GamepadId,
+        strong: f32,
+        weak: f32,
+        duration: Duration,
+    ) -> Result<()> {
+        let device = self
+            .devices
+            .get_mut(&id)
+            .ok_or_else(|| Error::Other(format!("Gamepad {:?} dont found", id)))?;
+        device
+            .rumble(strong, weak, duration)
+            .map_err(|e| Error::Other(format!("Gamepad {:?} rumble failed: {}", id, e)))
+    }
+}
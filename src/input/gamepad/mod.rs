@@ -0,0 +1,325 @@
+use std::{
+    collections::{HashMap, VecDeque},
+    time::Duration,
+};
+
+use crate::{error::Result, ElementState};
+
+#[cfg(target_os = "linux")]
+mod evdev;
+#[cfg(windows)]
+mod xinput;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct GamepadId(pub(crate) u32);
+
+/// Buttons of the standard layout, named by position so that the south button is A on
+/// Xbox and Cross on PlayStation controllers.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GamepadButton {
+    South,
+    East,
+    West,
+    North,
+    LeftBumper,
+    RightBumper,
+    Select,
+    Start,
+    Mode,
+    LeftStick,
+    RightStick,
+    DPadUp,
+    DPadDown,
+    DPadLeft,
+    DPadRight,
+}
+
+impl GamepadButton {
+    pub const ALL: [GamepadButton; 15] = [
+        GamepadButton::South,
+        GamepadButton::East,
+        GamepadButton::West,
+        GamepadButton::North,
+        GamepadButton::LeftBumper,
+        GamepadButton::RightBumper,
+        GamepadButton::Select,
+        GamepadButton::Start,
+        GamepadButton::Mode,
+        GamepadButton::LeftStick,
+        GamepadButton::RightStick,
+        GamepadButton::DPadUp,
+        GamepadButton::DPadDown,
+        GamepadButton::DPadLeft,
+        GamepadButton::DPadRight,
+    ];
+}
+
+/// Axes of the standard layout. Sticks are in `-1.0..=1.0` with up and right positive,
+/// triggers in `0.0..=1.0`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum GamepadAxis {
+    LeftStickX,
+    LeftStickY,
+    RightStickX,
+    RightStickY,
+    LeftTrigger,
+    RightTrigger,
+}
+
+impl GamepadAxis {
+    pub const ALL: [GamepadAxis; 6] = [
+        GamepadAxis::LeftStickX,
+        GamepadAxis::LeftStickY,
+        GamepadAxis::RightStickX,
+        GamepadAxis::RightStickY,
+        GamepadAxis::LeftTrigger,
+        GamepadAxis::RightTrigger,
+    ];
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum GamepadEvent {
+    Connected(GamepadId),
+    Disconnected(GamepadId),
+    Button {
+        id: GamepadId,
+        button: GamepadButton,
+        state: ElementState,
+    },
+    Axis {
+        id: GamepadId,
+        axis: GamepadAxis,
+        value: f32,
+    },
+}
+
+/// Dead zones as a fraction of the full range. Sticks use a radial dead zone, and values
+/// outside of the dead zones are rescaled to start at zero.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DeadZone {
+    pub left_stick: f32,
+    pub right_stick: f32,
+    pub trigger: f32,
+}
+
+impl Default for DeadZone {
+    /// The values XInput recommends.
+    fn default() -> Self {
+        DeadZone {
+            left_stick: 7849.0 / 32767.0,
+            right_stick: 8689.0 / 32767.0,
+            trigger: 30.0 / 255.0,
+        }
+    }
+}
+
+impl DeadZone {
+    fn apply_stick(zone: f32, x: f32, y: f32) -> (f32, f32) {
+        let magnitude = (x * x + y * y).sqrt();
+        if magnitude <= zone || zone >= 1.0 {
+            return (0.0, 0.0);
+        }
+        let scaled = ((magnitude - zone) / (1.0 - zone)).min(1.0);
+        (x / magnitude * scaled, y / magnitude * scaled)
+    }
+
+    fn apply_trigger(zone: f32, value: f32) -> f32 {
+        if value <= zone || zone >= 1.0 {
+            0.0
+        } else {
+            ((value - zone) / (1.0 - zone)).min(1.0)
+        }
+    }
+
+    fn apply(&self, axes: &[f32; 6]) -> [f32; 6] {
+        let (left_x, left_y) = Self::apply_stick(self.left_stick, axes[0], axes[1]);
+        let (right_x, right_y) = Self::apply_stick(self.right_stick, axes[2], axes[3]);
+        [
+            left_x,
+            left_y,
+            right_x,
+            right_y,
+            Self::apply_trigger(self.trigger, axes[4]),
+            Self::apply_trigger(self.trigger, axes[5]),
+        ]
+    }
+}
+
+/// Device state as reported by a backend, already in the standard layout but without
+/// dead zones applied.
+#[derive(Debug, Clone, Default)]
+pub(crate) struct RawGamepad {
+    pub name: String,
+    pub buttons: [bool; 15],
+    pub axes: [f32; 6],
+}
+
+pub(crate) trait Backend: Send {
+    /// Handles hot-plug and reads pending input, updating `gamepads` in place.
+    fn poll(&mut self, gamepads: &mut HashMap<GamepadId, RawGamepad>);
+
+    /// Motor speeds are in `0.0..=1.0`.
+    fn set_rumble(
+        &mut self,
+        id: GamepadId,
+        strong: f32,
+        weak: f32,
+        duration: Duration,
+    ) -> Result<()>;
+}
+
+/// Platforms without a gamepad backend simply never report a gamepad.
+#[cfg(not(any(target_os = "linux", windows)))]
+struct NullBackend;
+
+#[cfg(not(any(target_os = "linux", windows)))]
+impl Backend for NullBackend {
+    fn poll(&mut self, _gamepads: &mut HashMap<GamepadId, RawGamepad>) {}
+
+    fn set_rumble(&mut self, id: GamepadId, _: f32, _: f32, _: Duration) -> Result<()> {
+        Err(crate::Error::Other(format!("Gamepad {:?} dont found", id)))
+    }
+}
+
+pub struct Gamepad {
+    name: String,
+    buttons: [bool; 15],
+    axes: [f32; 6],
+}
+
+impl Gamepad {
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn is_pressed(&self, button: GamepadButton) -> bool {
+        self.buttons[button as usize]
+    }
+
+    /// Axis value with the dead zone applied.
+    pub fn axis(&self, axis: GamepadAxis) -> f32 {
+        self.axes[axis as usize]
+    }
+}
+
+/// Connected gamepads. Call `poll` once per frame, then drain `next_event` or query the
+/// gamepads directly.
+pub struct Gamepads {
+    backend: Box<dyn Backend>,
+    raw: HashMap<GamepadId, RawGamepad>,
+    gamepads: HashMap<GamepadId, Gamepad>,
+    events: VecDeque<GamepadEvent>,
+    dead_zone: DeadZone,
+}
+
+impl Gamepads {
+    pub fn new() -> Result<Self> {
+        #[cfg(target_os = "linux")]
+        let backend: Box<dyn Backend> = Box::new(evdev::Evdev::new()?);
+        #[cfg(windows)]
+        let backend: Box<dyn Backend> = Box::new(xinput::XInput::new());
+        #[cfg(not(any(target_os = "linux", windows)))]
+        let backend: Box<dyn Backend> = Box::new(NullBackend);
+
+        Ok(Self::with_backend(backend))
+    }
+
+    pub(crate) fn with_backend(backend: Box<dyn Backend>) -> Self {
+        Gamepads {
+            backend,
+            raw: HashMap::new(),
+            gamepads: HashMap::new(),
+            events: VecDeque::new(),
+            dead_zone: DeadZone::default(),
+        }
+    }
+
+    pub fn dead_zone(&self) -> DeadZone {
+        self.dead_zone
+    }
+
+    pub fn set_dead_zone(&mut self, dead_zone: DeadZone) {
+        self.dead_zone = dead_zone;
+    }
+
+    /// Picks up connected and disconnected gamepads and queues events for every change.
+    pub fn poll(&mut self) {
+        self.backend.poll(&mut self.raw);
+
+        let removed: Vec<GamepadId> = self
+            .gamepads
+            .keys()
+            .filter(|id| !self.raw.contains_key(id))
+            .copied()
+            .collect();
+        for id in removed {
+            self.gamepads.remove(&id);
+            self.events.push_back(GamepadEvent::Disconnected(id));
+        }
+
+        let mut ids: Vec<GamepadId> = self.raw.keys().copied().collect();
+        ids.sort();
+        for id in ids {
+            let raw = &self.raw[&id];
+            let gamepad = self.gamepads.entry(id).or_insert_with(|| {
+                self.events.push_back(GamepadEvent::Connected(id));
+                Gamepad {
+                    name: raw.name.clone(),
+                    buttons: [false; 15],
+                    axes: [0.0; 6],
+                }
+            });
+
+            for button in GamepadButton::ALL {
+                let pressed = raw.buttons[button as usize];
+                if gamepad.buttons[button as usize] != pressed {
+                    gamepad.buttons[button as usize] = pressed;
+                    self.events.push_back(GamepadEvent::Button {
+                        id,
+                        button,
+                        state: if pressed {
+                            ElementState::Pressed
+                        } else {
+                            ElementState::Released
+                        },
+                    });
+                }
+            }
+
+            let axes = self.dead_zone.apply(&raw.axes);
+            for axis in GamepadAxis::ALL {
+                let value = axes[axis as usize];
+                if gamepad.axes[axis as usize] != value {
+                    gamepad.axes[axis as usize] = value;
+                    self.events
+                        .push_back(GamepadEvent::Axis { id, axis, value });
+                }
+            }
+        }
+    }
+
+    pub fn next_event(&mut self) -> Option<GamepadEvent> {
+        self.events.pop_front()
+    }
+
+    pub fn gamepad(&self, id: GamepadId) -> Option<&Gamepad> {
+        self.gamepads.get(&id)
+    }
+
+    pub fn gamepads(&self) -> impl Iterator<Item = (GamepadId, &Gamepad)> {
+        self.gamepads.iter().map(|(id, gamepad)| (*id, gamepad))
+    }
+
+    /// Runs the strong (low frequency) and weak (high frequency) motors for `duration`.
+    /// Speeds are clamped to `0.0..=1.0`; zero speeds stop the rumble.
+    pub fn set_rumble(
+        &mut self,
+        id: GamepadId,
+        strong: f32,
+        weak: f32,
+        duration: Duration,
+    ) -> Result<()> {
+        self.backend
+            .set_rumble(id, strong.clamp(0.0, 1.0), weak.clamp(0.0, 1.0), duration)
+    }
+}
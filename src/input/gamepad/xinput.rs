@@ -0,0 +1,158 @@
+use std::{
+    collections::HashMap,
+    time::{Duration, Instant},
+};
+
+use windows::Win32::{
+    Foundation::{ERROR_DEVICE_NOT_CONNECTED, ERROR_SUCCESS},
+    UI::Input::XboxController::*,
+};
+
+use super::{Backend, GamepadAxis, GamepadButton, GamepadId, RawGamepad};
+use crate::error::{Error, Result};
+
+/// Querying an empty slot is slow, so disconnected slots are only checked this often.
+const RECONNECT_INTERVAL: Duration = Duration::from_secs(1);
+
+/// The guide button isn't exposed by XInput, so `GamepadButton::Mode` is never reported.
+const BUTTONS: [(XINPUT_GAMEPAD_BUTTON_FLAGS, GamepadButton); 14] = [
+    (XINPUT_GAMEPAD_A, GamepadButton::South),
+    (XINPUT_GAMEPAD_B, GamepadButton::East),
+    (XINPUT_GAMEPAD_X, GamepadButton::West),
+    (XINPUT_GAMEPAD_Y, GamepadButton::North),
+    (XINPUT_GAMEPAD_LEFT_SHOULDER, GamepadButton::LeftBumper),
+    (XINPUT_GAMEPAD_RIGHT_SHOULDER, GamepadButton::RightBumper),
+    (XINPUT_GAMEPAD_BACK, GamepadButton::Select),
+    (XINPUT_GAMEPAD_START, GamepadButton::Start),
+    (XINPUT_GAMEPAD_LEFT_THUMB, GamepadButton::LeftStick),
+    (XINPUT_GAMEPAD_RIGHT_THUMB, GamepadButton::RightStick),
+    (XINPUT_GAMEPAD_DPAD_UP, GamepadButton::DPadUp),
+    (XINPUT_GAMEPAD_DPAD_DOWN, GamepadButton::DPadDown),
+    (XINPUT_GAMEPAD_DPAD_LEFT, GamepadButton::DPadLeft),
+    (XINPUT_GAMEPAD_DPAD_RIGHT, GamepadButton::DPadRight),
+];
+
+#[derive(Default)]
+struct Slot {
+    connected: bool,
+    packet: u32,
+    last_check: Option<Instant>,
+    rumble_until: Option<Instant>,
+}
+
+/// Polls the four XInput user slots. Slot `n` is reported as `GamepadId(n)`.
+pub(crate) struct XInput {
+    slots: [Slot; XUSER_MAX_COUNT as usize],
+}
+
+impl XInput {
+    pub fn new() -> Self {
+        XInput {
+            slots: Default::default(),
+        }
+    }
+
+    fn read(gamepad: &XINPUT_GAMEPAD, state: &mut RawGamepad) {
+        for (flag, button) in BUTTONS {
+            state.buttons[button as usize] = gamepad.wButtons.0 & flag.0 != 0;
+        }
+
+        let stick = |value: i16| (value as f32 / i16::MAX as f32).max(-1.0);
+        state.axes[GamepadAxis::LeftStickX as usize] = stick(gamepad.sThumbLX);
+        state.axes[GamepadAxis::LeftStickY as usize] = stick(gamepad.sThumbLY);
+        state.axes[GamepadAxis::RightStickX as usize] = stick(gamepad.sThumbRX);
+        state.axes[GamepadAxis::RightStickY as usize] = stick(gamepad.sThumbRY);
+        state.axes[GamepadAxis::LeftTrigger as usize] = gamepad.bLeftTrigger as f32 / 255.0;
+        state.axes[GamepadAxis::RightTrigger as usize] = gamepad.bRightTrigger as f32 / 255.0;
+    }
+
+    fn vibrate(index: u32, strong: u16, weak: u16) -> u32 {
+        let vibration = XINPUT_VIBRATION {
+            wLeftMotorSpeed: strong,
+            wRightMotorSpeed: weak,
+        };
+        unsafe { XInputSetState(index, &vibration) }
+    }
+}
+
+impl Backend for XInput {
+    fn poll(&mut self, gamepads: &mut HashMap<GamepadId, RawGamepad>) {
+        let now = Instant::now();
+        for (index, slot) in self.slots.iter_mut().enumerate() {
+            let id = GamepadId(index as u32);
+            if !slot.connected
+                && slot
+                    .last_check
+                    .is_some_and(|last| now - last < RECONNECT_INTERVAL)
+            {
+                continue;
+            }
+            slot.last_check = Some(now);
+
+            let mut state = XINPUT_STATE::default();
+            if unsafe { XInputGetState(index as u32, &mut state) } != ERROR_SUCCESS.0 {
+                if slot.connected {
+                    log::trace!("gamepad {:?} disconnected", id);
+                    gamepads.remove(&id);
+                    *slot = Slot {
+                        last_check: Some(now),
+                        ..Default::default()
+                    };
+                }
+                continue;
+            }
+
+            if slot.rumble_until.is_some_and(|until| now >= until) {
+                slot.rumble_until = None;
+                Self::vibrate(index as u32, 0, 0);
+            }
+
+            if !slot.connected {
+                log::trace!("gamepad {:?} connected", id);
+                slot.connected = true;
+                gamepads.insert(
+                    id,
+                    RawGamepad {
+                        name: format!("XInput Controller {}", index + 1),
+                        ..Default::default()
+                    },
+                );
+            } else if slot.packet == state.dwPacketNumber {
+                continue;
+            }
+            slot.packet = state.dwPacketNumber;
+            Self::read(&state.Gamepad, gamepads.entry(id).or_default());
+        }
+    }
+
+    fn set_rumble(
+        &mut self,
+        id: GamepadId,
+        strong: f32,
+        weak: f32,
+        duration: Duration,
+    ) -> Result<()> {
+        let slot = self
+            .slots
+            .get_mut(id.0 as usize)
+            .filter(|slot| slot.connected)
+            .ok_or_else(|| Error::Other(format!("Gamepad {:?} dont found", id)))?;
+
+        let result = Self::vibrate(
+            id.0,
+            (strong * u16::MAX as f32) as u16,
+            (weak * u16::MAX as f32) as u16,
+        );
+        if result == ERROR_DEVICE_NOT_CONNECTED.0 {
+            return Err(Error::Other(format!("Gamepad {:?} dont found", id)));
+        } else if result != ERROR_SUCCESS.0 {
+            return Err(Error::Other(format!(
+                "Gamepad {:?} rumble failed: {}",
+                id, result
+            )));
+        }
+
+        slot.rumble_until = (strong > 0.0 || weak > 0.0).then(|| Instant::now() + duration);
+        Ok(())
+    }
+}
@@ -0,0 +1,7 @@
+pub mod action;
+pub mod gamepad;
+
+pub use action::{ActionMap, AxisBinding, Binding};
+pub use gamepad::{
+    DeadZone, Gamepad, GamepadAxis, GamepadButton, GamepadEvent, GamepadId, Gamepads,
+};
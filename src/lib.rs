@@ -1,9 +1,19 @@
+mod app;
+mod asset;
 mod error;
+mod image;
+mod input;
+mod model;
 mod render;
 mod window;
 
+pub use app::{
+    Alovak, AlovakBuilder, App, AppContext, FramePacing, RendererConfig, TimingConfig, WindowConfig,
+};
+pub use asset::*;
 pub use error::{Error, Result};
+pub use image::*;
+pub use input::*;
+pub use model::*;
 pub use render::*;
 pub use window::*;
-
-pub struct Alovak {}
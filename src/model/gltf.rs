@@ -0,0 +1,790 @@
+//! glTF 2.0 import, `.gltf` with embedded or external resources and binary `.glb`.
+
+use std::{fmt::Display, fs, path::Path};
+
+use ::gltf::{
+    accessor::{sparse::IndexType, DataType, Dimensions},
+    animation::{Interpolation as GltfInterpolation, Property},
+    buffer,
+    camera::Projection as GltfProjection,
+    image::{self, Format},
+    material::AlphaMode as GltfAlphaMode,
+    mesh::{Mode, Semantic},
+    texture::{self, MagFilter, MinFilter, WrappingMode},
+    Accessor, Document, Gltf,
+};
+use ash::vk::{Filter, SamplerAddressMode, SamplerMipmapMode};
+
+use crate::{error::Result, mesh::SubMesh, vulkan::SamplerInfo, Error, RgbaImage};
+
+use super::{
+    AlphaMode, Animation, AnimationProperty, Camera, Channel, Interpolation, Material, MeshData,
+    Model, ModelVertex, Node, Primitive, Projection, Scene, Skin, Texture, TextureRef,
+    TextureTransform, Transform,
+};
+
+const FLOAT: &[(DataType, bool)] = &[(DataType::F32, false)];
+const UNORM: &[(DataType, bool)] = &[
+    (DataType::F32, false),
+    (DataType::U8, true),
+    (DataType::U16, true),
+];
+const NORM: &[(DataType, bool)] = &[
+    (DataType::F32, false),
+    (DataType::I8, true),
+    (DataType::U8, true),
+    (DataType::I16, true),
+    (DataType::U16, true),
+];
+const UINT: &[(DataType, bool)] = &[
+    (DataType::U8, false),
+    (DataType::U16, false),
+    (DataType::U32, false),
+];
+
+fn gltf_error(error: ::gltf::Error) -> Error {
+    Error::Other(format!("glTF: {}", error))
+}
+
+fn accessor_error(accessor: &Accessor, path: &str, reason: impl Display) -> Error {
+    Error::Other(format!(
+        "glTF accessor {} ({}): {}",
+        accessor.index(),
+        path,
+        reason
+    ))
+}
+
+fn component(data_type: DataType, bytes: &[u8], normalized: bool) -> f64 {
+    let (value, max) = match data_type {
+        DataType::I8 => (bytes[0] as i8 as f64, i8::MAX as f64),
+        DataType::U8 => (bytes[0] as f64, u8::MAX as f64),
+        DataType::I16 => (
+            i16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+            i16::MAX as f64,
+        ),
+        DataType::U16 => (
+            u16::from_le_bytes([bytes[0], bytes[1]]) as f64,
+            u16::MAX as f64,
+        ),
+        DataType::U32 => (
+            u32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64,
+            u32::MAX as f64,
+        ),
+        DataType::F32 => {
+            return f32::from_le_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as f64
+        }
+    };
+    if normalized {
+        (value / max).max(-1.0)
+    } else {
+        value
+    }
+}
+
+/// Range of `length` bytes at `offset` into `view`, checked against the view and its buffer.
+fn view_range<'b>(
+    view: &buffer::View,
+    buffers: &'b [buffer::Data],
+    offset: usize,
+    length: usize,
+) -> std::result::Result<&'b [u8], String> {
+    let data = &buffers[view.buffer().index()];
+    let end = offset
+        .checked_add(length)
+        .filter(|end| *end <= view.length())
+        .ok_or_else(|| {
+            format!(
+                "reads {} bytes at {} of buffer view {}, which has {}",
+                length,
+                offset,
+                view.index(),
+                view.length()
+            )
+        })?;
+    view.offset()
+        .checked_add(offset)
+        .zip(view.offset().checked_add(end))
+        .and_then(|(start, end)| data.get(start..end))
+        .ok_or_else(|| {
+            format!(
+                "buffer view {} is out of buffer {}",
+                view.index(),
+                view.buffer().index()
+            )
+        })
+}
+
+/// Every component of `accessor` in order, sparse substitutions applied and normalized
+/// integers mapped to [0, 1] or [-1, 1]. Accessors without a buffer view read as zeros.
+/// Sizes from the file are checked against the data before anything is allocated.
+fn read(accessor: &Accessor, buffers: &[buffer::Data], path: &str) -> Result<Vec<f64>> {
+    let data_type = accessor.data_type();
+    let size = data_type.size();
+    let components = accessor.dimensions().multiplicity();
+    let element = size * components;
+    let count = accessor.count();
+    let normalized = accessor.normalized();
+    let too_large = |what: &str| {
+        accessor_error(
+            accessor,
+            path,
+            format!("{} of {} elements are too large", what, count),
+        )
+    };
+
+    let mut data = None;
+    if let Some(view) = accessor.view() {
+        let stride = view.stride().unwrap_or(element);
+        if stride < element {
+            return Err(accessor_error(
+                accessor,
+                path,
+                format!(
+                    "byte stride {} is less than the element size {}",
+                    stride, element
+                ),
+            ));
+        }
+        if count > 0 {
+            let length = stride
+                .checked_mul(count - 1)
+                .and_then(|length| length.checked_add(element))
+                .ok_or_else(|| too_large("bytes"))?;
+            let bytes = view_range(&view, buffers, accessor.offset(), length)
+                .map_err(|reason| accessor_error(accessor, path, reason))?;
+            data = Some((bytes, stride));
+        }
+    }
+
+    // Without a buffer view nothing bounds the count, so a bogus one fails here instead
+    // of aborting.
+    let length = count
+        .checked_mul(components)
+        .ok_or_else(|| too_large("components"))?;
+    let mut values = Vec::new();
+    values
+        .try_reserve_exact(length)
+        .map_err(|reason| accessor_error(accessor, path, reason))?;
+    values.resize(length, 0.0);
+    if let Some((bytes, stride)) = data {
+        for (index, value) in values.chunks_exact_mut(components).enumerate() {
+            for (c, v) in value.iter_mut().enumerate() {
+                *v = component(data_type, &bytes[index * stride + c * size..], normalized);
+            }
+        }
+    }
+
+    if let Some(sparse) = accessor.sparse() {
+        let indices = sparse.indices();
+        let index_size = match indices.index_type() {
+            IndexType::U8 => 1,
+            IndexType::U16 => 2,
+            IndexType::U32 => 4,
+        };
+        let index_length = sparse
+            .count()
+            .checked_mul(index_size)
+            .ok_or_else(|| too_large("sparse indices"))?;
+        let index_data = view_range(&indices.view(), buffers, indices.offset(), index_length)
+            .map_err(|reason| {
+                accessor_error(accessor, path, format!("sparse indices {}", reason))
+            })?;
+        let sparse_values = sparse.values();
+        let value_length = sparse
+            .count()
+            .checked_mul(element)
+            .ok_or_else(|| too_large("sparse values"))?;
+        let value_data = view_range(
+            &sparse_values.view(),
+            buffers,
+            sparse_values.offset(),
+            value_length,
+        )
+        .map_err(|reason| accessor_error(accessor, path, format!("sparse values {}", reason)))?;
+        for (i, bytes) in index_data.chunks_exact(index_size).enumerate() {
+            let index = component(
+                match index_size {
+                    1 => DataType::U8,
+                    2 => DataType::U16,
+                    _ => DataType::U32,
+                },
+                bytes,
+                false,
+            ) as usize;
+            if index >= count {
+                return Err(accessor_error(
+                    accessor,
+                    path,
+                    format!("sparse index {} is out of {} elements", index, count),
+                ));
+            }
+            for c in 0..components {
+                values[index * components + c] =
+                    component(data_type, &value_data[i * element + c * size..], normalized);
+            }
+        }
+    }
+    Ok(values)
+}
+
+/// Checks the type of `accessor` against what `path` allows, then reads it.
+fn read_checked(
+    accessor: &Accessor,
+    buffers: &[buffer::Data],
+    path: &str,
+    dimensions: &[Dimensions],
+    types: &[(DataType, bool)],
+) -> Result<Vec<f64>> {
+    let found = (accessor.data_type(), accessor.normalized());
+    if !dimensions.contains(&accessor.dimensions()) || !types.contains(&found) {
+        return Err(accessor_error(
+            accessor,
+            path,
+            format!(
+                "{:?} of {:?}{} isn`t allowed here, expected {:?} of {}",
+                accessor.dimensions(),
+                found.0,
+                if found.1 { " normalized" } else { "" },
+                dimensions,
+                types
+                    .iter()
+                    .map(|(data_type, normalized)| {
+                        format!(
+                            "{:?}{}",
+                            data_type,
+                            if *normalized { " normalized" } else { "" }
+                        )
+                    })
+                    .collect::<Vec<_>>()
+                    .join(", ")
+            ),
+        ));
+    }
+    read(accessor, buffers, path)
+}
+
+impl Model {
+    /// Loads a `.gltf` or `.glb` file. External buffers and images are resolved relative to
+    /// the file.
+    pub fn load_gltf(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let bytes = fs::read(path).map_err(Error::Io)?;
+        Self::from_gltf(&bytes, Some(path.parent().unwrap_or(Path::new("."))))
+    }
+
+    /// Loads glTF or GLB from memory. Without `base` only embedded resources can be read.
+    pub fn from_gltf(bytes: &[u8], base: Option<&Path>) -> Result<Self> {
+        let Gltf { document, blob } = Gltf::from_slice(bytes).map_err(gltf_error)?;
+        let buffers = ::gltf::import_buffers(&document, base, blob).map_err(gltf_error)?;
+        let images = ::gltf::import_images(&document, base, &buffers).map_err(gltf_error)?;
+        log::trace!(
+            "glTF loaded {} buffers and {} images",
+            buffers.len(),
+            images.len()
+        );
+
+        Ok(Model {
+            meshes: document
+                .meshes()
+                .map(|mesh| load_mesh(&mesh, &buffers))
+                .collect::<Result<_>>()?,
+            materials: document.materials().map(|m| load_material(&m)).collect(),
+            textures: document.textures().map(|t| load_texture(&t)).collect(),
+            images: images
+                .into_iter()
+                .enumerate()
+                .map(|(index, data)| load_image(index, data))
+                .collect::<Result<_>>()?,
+            nodes: load_nodes(&document),
+            scenes: document
+                .scenes()
+                .map(|scene| Scene {
+                    name: scene.name().map(str::to_owned),
+                    nodes: scene.nodes().map(|node| node.index()).collect(),
+                })
+                .collect(),
+            default_scene: document.default_scene().map(|scene| scene.index()),
+            cameras: document
+                .cameras()
+                .map(|camera| Camera {
+                    name: camera.name().map(str::to_owned),
+                    projection: match camera.projection() {
+                        GltfProjection::Perspective(p) => Projection::Perspective {
+                            yfov: p.yfov(),
+                            aspect_ratio: p.aspect_ratio(),
+                            znear: p.znear(),
+                            zfar: p.zfar(),
+                        },
+                        GltfProjection::Orthographic(o) => Projection::Orthographic {
+                            xmag: o.xmag(),
+                            ymag: o.ymag(),
+                            znear: o.znear(),
+                            zfar: o.zfar(),
+                        },
+                    },
+                })
+                .collect(),
+            skins: document
+                .skins()
+                .map(|skin| load_skin(&skin, &buffers))
+                .collect::<Result<_>>()?,
+            animations: document
+                .animations()
+                .map(|animation| load_animation(&animation, &buffers))
+                .collect::<Result<_>>()?,
+        })
+    }
+}
+
+fn load_mesh(mesh: &::gltf::Mesh, buffers: &[buffer::Data]) -> Result<MeshData> {
+    let mut data = MeshData {
+        name: mesh.name().map(str::to_owned),
+        ..Default::default()
+    };
+    for primitive in mesh.primitives() {
+        let path = |name: &str| {
+            format!(
+                "meshes[{}].primitives[{}].{}",
+                mesh.index(),
+                primitive.index(),
+                name
+            )
+        };
+        let mode = primitive.mode();
+        if !matches!(
+            mode,
+            Mode::Triangles | Mode::TriangleStrip | Mode::TriangleFan
+        ) {
+            log::warn!(
+                "glTF {} is {:?}, only triangles are loaded",
+                path("mode"),
+                mode
+            );
+            continue;
+        }
+        let Some(positions) = primitive.get(&Semantic::Positions) else {
+            return Err(Error::Other(format!(
+                "glTF {} has no POSITION",
+                path("attributes")
+            )));
+        };
+        let count = positions.count();
+
+        // Reads one attribute, checking it has an element for every vertex.
+        let attribute = |semantic: Semantic,
+                         dimensions: &[Dimensions],
+                         types: &[(DataType, bool)]|
+         -> Result<Option<(usize, Vec<f64>)>> {
+            let Some(accessor) = primitive.get(&semantic) else {
+                return Ok(None);
+            };
+            let path = path(&("attributes.".to_owned() + &semantic.to_string()));
+            if accessor.count() != count {
+                return Err(accessor_error(
+                    &accessor,
+                    &path,
+                    format!(
+                        "has {} elements but POSITION has {}",
+                        accessor.count(),
+                        count
+                    ),
+                ));
+            }
+            let values = read_checked(&accessor, buffers, &path, dimensions, types)?;
+            Ok(Some((accessor.dimensions().multiplicity(), values)))
+        };
+
+        let vec3 = &[Dimensions::Vec3];
+        let vec4 = &[Dimensions::Vec4];
+        // Read first, so a count the data doesn't back fails before the vertices exist.
+        let positions = attribute(Semantic::Positions, vec3, FLOAT)?;
+        let mut vertices = vec![ModelVertex::default(); count];
+        let fill = |vertices: &mut [ModelVertex],
+                    values: Option<(usize, Vec<f64>)>,
+                    set: &mut dyn FnMut(&mut ModelVertex, &[f64])| {
+            if let Some((components, values)) = values {
+                for (vertex, value) in vertices.iter_mut().zip(values.chunks_exact(components)) {
+                    set(vertex, value);
+                }
+            }
+        };
+        fill(&mut vertices, positions, &mut |vertex, value| {
+            vertex.position = [0, 1, 2].map(|i| value[i] as f32)
+        });
+        fill(
+            &mut vertices,
+            attribute(Semantic::Normals, vec3, FLOAT)?,
+            &mut |vertex, value| vertex.normal = [0, 1, 2].map(|i| value[i] as f32),
+        );
+        fill(
+            &mut vertices,
+            attribute(Semantic::Tangents, vec4, FLOAT)?,
+            &mut |vertex, value| vertex.tangent = [0, 1, 2, 3].map(|i| value[i] as f32),
+        );
+        fill(
+            &mut vertices,
+            attribute(Semantic::TexCoords(0), &[Dimensions::Vec2], UNORM)?,
+            &mut |vertex, value| vertex.uv = [0, 1].map(|i| value[i] as f32),
+        );
+        fill(
+            &mut vertices,
+            attribute(Semantic::TexCoords(1), &[Dimensions::Vec2], UNORM)?,
+            &mut |vertex, value| vertex.uv1 = [0, 1].map(|i| value[i] as f32),
+        );
+        fill(
+            &mut vertices,
+            attribute(
+                Semantic::Colors(0),
+                &[Dimensions::Vec3, Dimensions::Vec4],
+                UNORM,
+            )?,
+            &mut |vertex, value| {
+                vertex.color = [0, 1, 2, 3].map(|i| value.get(i).copied().unwrap_or(1.0) as f32)
+            },
+        );
+        fill(
+            &mut vertices,
+            attribute(
+                Semantic::Joints(0),
+                vec4,
+                &[(DataType::U8, false), (DataType::U16, false)],
+            )?,
+            &mut |vertex, value| vertex.joints = [0, 1, 2, 3].map(|i| value[i] as u32),
+        );
+        fill(
+            &mut vertices,
+            attribute(Semantic::Weights(0), vec4, UNORM)?,
+            &mut |vertex, value| vertex.weights = [0, 1, 2, 3].map(|i| value[i] as f32),
+        );
+
+        let indices: Vec<u32> = match primitive.indices() {
+            Some(accessor) => {
+                let path = path("indices");
+                let indices = read_checked(&accessor, buffers, &path, &[Dimensions::Scalar], UINT)?;
+                if let Some(index) = indices.iter().find(|index| **index as usize >= count) {
+                    return Err(accessor_error(
+                        &accessor,
+                        &path,
+                        format!("index {} is out of {} vertices", index, count),
+                    ));
+                }
+                if mode == Mode::Triangles && indices.len() % 3 != 0 {
+                    return Err(accessor_error(
+                        &accessor,
+                        &path,
+                        format!("{} indices don`t make whole triangles", indices.len()),
+                    ));
+                }
+                indices.into_iter().map(|index| index as u32).collect()
+            }
+            None => (0..count as u32).collect(),
+        };
+        let indices = match mode {
+            Mode::TriangleStrip => (0..indices.len().saturating_sub(2))
+                .flat_map(|i| {
+                    if i % 2 == 0 {
+                        [indices[i], indices[i + 1], indices[i + 2]]
+                    } else {
+                        [indices[i + 1], indices[i], indices[i + 2]]
+                    }
+                })
+                .collect(),
+            Mode::TriangleFan => (1..indices.len().saturating_sub(1))
+                .flat_map(|i| [indices[0], indices[i], indices[i + 1]])
+                .collect(),
+            _ => {
+                let whole = indices.len() / 3 * 3;
+                indices[..whole].to_vec()
+            }
+        };
+
+        data.primitives.push(Primitive {
+            submesh: SubMesh {
+                first: data.indices.len() as u32,
+                count: indices.len() as u32,
+                vertex_offset: data.vertices.len() as i32,
+            },
+            material: primitive.material().index(),
+        });
+        data.vertices.extend(vertices);
+        data.indices.extend(indices);
+    }
+    Ok(data)
+}
+
+fn texture_ref(
+    texture: texture::Texture,
+    tex_coord: u32,
+    info: Option<&texture::Info>,
+) -> TextureRef {
+    let transform = info.and_then(|info| info.texture_transform());
+    TextureRef {
+        texture: texture.index(),
+        uv_set: transform
+            .as_ref()
+            .and_then(|transform| transform.tex_coord())
+            .unwrap_or(tex_coord),
+        transform: transform.map(|transform| TextureTransform {
+            offset: transform.offset(),
+            rotation: transform.rotation(),
+            scale: transform.scale(),
+        }),
+    }
+}
+
+fn info_ref(info: texture::Info) -> TextureRef {
+    texture_ref(info.texture(), info.tex_coord(), Some(&info))
+}
+
+fn load_material(material: &::gltf::Material) -> Material {
+    let pbr = material.pbr_metallic_roughness();
+    let strength = material.emissive_strength().unwrap_or(1.0);
+    Material {
+        name: material.name().map(str::to_owned),
+        base_color: pbr.base_color_factor(),
+        base_color_texture: pbr.base_color_texture().map(info_ref),
+        metallic: pbr.metallic_factor(),
+        roughness: pbr.roughness_factor(),
+        metallic_roughness_texture: pbr.metallic_roughness_texture().map(info_ref),
+        normal_texture: material
+            .normal_texture()
+            .map(|normal| texture_ref(normal.texture(), normal.tex_coord(), None)),
+        normal_scale: material
+            .normal_texture()
+            .map_or(1.0, |normal| normal.scale()),
+        occlusion_texture: material
+            .occlusion_texture()
+            .map(|occlusion| texture_ref(occlusion.texture(), occlusion.tex_coord(), None)),
+        occlusion_strength: material
+            .occlusion_texture()
+            .map_or(1.0, |occlusion| occlusion.strength()),
+        emissive: material.emissive_factor().map(|value| value * strength),
+        emissive_texture: material.emissive_texture().map(info_ref),
+        alpha_mode: match material.alpha_mode() {
+            GltfAlphaMode::Opaque => AlphaMode::Opaque,
+            GltfAlphaMode::Mask => AlphaMode::Mask,
+            GltfAlphaMode::Blend => AlphaMode::Blend,
+        },
+        alpha_cutoff: material.alpha_cutoff().unwrap_or(0.5),
+        double_sided: material.double_sided(),
+    }
+}
+
+fn load_texture(texture: &texture::Texture) -> Texture {
+    let sampler = texture.sampler();
+    let address = |mode: WrappingMode| match mode {
+        WrappingMode::ClampToEdge => SamplerAddressMode::CLAMP_TO_EDGE,
+        WrappingMode::MirroredRepeat => SamplerAddressMode::MIRRORED_REPEAT,
+        WrappingMode::Repeat => SamplerAddressMode::REPEAT,
+    };
+    let (min_filter, mipmap_mode, mipmaps) = match sampler.min_filter() {
+        Some(MinFilter::Nearest) => (Filter::NEAREST, SamplerMipmapMode::NEAREST, false),
+        Some(MinFilter::Linear) => (Filter::LINEAR, SamplerMipmapMode::NEAREST, false),
+        Some(MinFilter::NearestMipmapNearest) => {
+            (Filter::NEAREST, SamplerMipmapMode::NEAREST, true)
+        }
+        Some(MinFilter::LinearMipmapNearest) => (Filter::LINEAR, SamplerMipmapMode::NEAREST, true),
+        Some(MinFilter::NearestMipmapLinear) => (Filter::NEAREST, SamplerMipmapMode::LINEAR, true),
+        Some(MinFilter::LinearMipmapLinear) | None => {
+            (Filter::LINEAR, SamplerMipmapMode::LINEAR, true)
+        }
+    };
+    Texture {
+        name: texture.name().map(str::to_owned),
+        image: texture.source().index(),
+        sampler: SamplerInfo {
+            mag_filter: match sampler.mag_filter() {
+                Some(MagFilter::Nearest) => Filter::NEAREST,
+                Some(MagFilter::Linear) | None => Filter::LINEAR,
+            },
+            min_filter,
+            mipmap_mode,
+            mipmaps,
+            address_u: address(sampler.wrap_s()),
+            address_v: address(sampler.wrap_t()),
+        },
+    }
+}
+
+/// Converts decoded pixels of any format to 8-bit RGBA. One and two channel images are
+/// luminance and luminance with alpha.
+fn load_image(index: usize, data: image::Data) -> Result<RgbaImage> {
+    let pixels = &data.pixels;
+    let u16s = || {
+        pixels
+            .chunks_exact(2)
+            .map(|bytes| (u16::from_ne_bytes([bytes[0], bytes[1]]) >> 8) as u8)
+            .collect::<Vec<u8>>()
+    };
+    let f32s = || {
+        pixels
+            .chunks_exact(4)
+            .map(|b| {
+                (f32::from_ne_bytes([b[0], b[1], b[2], b[3]]).clamp(0.0, 1.0) * 255.0 + 0.5) as u8
+            })
+            .collect::<Vec<u8>>()
+    };
+    let (channels, values) = match data.format {
+        Format::R8 => (1, pixels.clone()),
+        Format::R8G8 => (2, pixels.clone()),
+        Format::R8G8B8 => (3, pixels.clone()),
+        Format::R8G8B8A8 => (4, pixels.clone()),
+        Format::R16 => (1, u16s()),
+        Format::R16G16 => (2, u16s()),
+        Format::R16G16B16 => (3, u16s()),
+        Format::R16G16B16A16 => (4, u16s()),
+        Format::R32G32B32FLOAT => (3, f32s()),
+        Format::R32G32B32A32FLOAT => (4, f32s()),
+    };
+    let rgba = values
+        .chunks_exact(channels)
+        .flat_map(|pixel| match pixel {
+            [l] => [*l, *l, *l, 255],
+            [l, a] => [*l, *l, *l, *a],
+            [r, g, b] => [*r, *g, *b, 255],
+            [r, g, b, a] => [*r, *g, *b, *a],
+            _ => unreachable!(),
+        })
+        .collect();
+    RgbaImage::new(data.width, data.height, rgba)
+        .map_err(|error| Error::Other(format!("glTF image {}: {}", index, error)))
+}
+
+fn load_nodes(document: &Document) -> Vec<Node> {
+    let mut nodes: Vec<Node> = document
+        .nodes()
+        .map(|node| {
+            let (translation, rotation, scale) = node.transform().decomposed();
+            Node {
+                name: node.name().map(str::to_owned),
+                parent: None,
+                children: node.children().map(|child| child.index()).collect(),
+                transform: Transform {
+                    translation,
+                    rotation,
+                    scale,
+                },
+                mesh: node.mesh().map(|mesh| mesh.index()),
+                camera: node.camera().map(|camera| camera.index()),
+                skin: node.skin().map(|skin| skin.index()),
+            }
+        })
+        .collect();
+    for index in 0..nodes.len() {
+        for child in nodes[index].children.clone() {
+            nodes[child].parent = Some(index);
+        }
+    }
+    nodes
+}
+
+fn load_skin(skin: &::gltf::Skin, buffers: &[buffer::Data]) -> Result<Skin> {
+    let joints: Vec<usize> = skin.joints().map(|joint| joint.index()).collect();
+    let inverse_bind_matrices = match skin.inverse_bind_matrices() {
+        Some(accessor) => {
+            let path = format!("skins[{}].inverseBindMatrices", skin.index());
+            if accessor.count() < joints.len() {
+                return Err(accessor_error(
+                    &accessor,
+                    &path,
+                    format!(
+                        "has {} matrices for {} joints",
+                        accessor.count(),
+                        joints.len()
+                    ),
+                ));
+            }
+            read_checked(&accessor, buffers, &path, &[Dimensions::Mat4], FLOAT)?
+                .chunks_exact(16)
+                .take(joints.len())
+                .map(|m| {
+                    [0, 1, 2, 3].map(|column| [0, 1, 2, 3].map(|row| m[column * 4 + row] as f32))
+                })
+                .collect()
+        }
+        None => vec![super::IDENTITY; joints.len()],
+    };
+    Ok(Skin {
+        name: skin.name().map(str::to_owned),
+        joints,
+        inverse_bind_matrices,
+        skeleton: skin.skeleton().map(|node| node.index()),
+    })
+}
+
+fn load_animation(animation: &::gltf::Animation, buffers: &[buffer::Data]) -> Result<Animation> {
+    let mut channels = Vec::new();
+    for channel in animation.channels() {
+        let sampler = channel.sampler();
+        let target = channel.target();
+        let base = format!(
+            "animations[{}].samplers[{}]",
+            animation.index(),
+            sampler.index()
+        );
+
+        let input = sampler.input();
+        let input_path = format!("{}.input", base);
+        let times: Vec<f32> =
+            read_checked(&input, buffers, &input_path, &[Dimensions::Scalar], FLOAT)?
+                .into_iter()
+                .map(|time| time as f32)
+                .collect();
+        if times.windows(2).any(|pair| pair[1] < pair[0]) {
+            return Err(accessor_error(
+                &input,
+                &input_path,
+                "key times aren`t increasing",
+            ));
+        }
+
+        let (property, dimensions, types) = match target.property() {
+            Property::Translation => (AnimationProperty::Translation, Dimensions::Vec3, FLOAT),
+            Property::Rotation => (AnimationProperty::Rotation, Dimensions::Vec4, NORM),
+            Property::Scale => (AnimationProperty::Scale, Dimensions::Vec3, FLOAT),
+            Property::MorphTargetWeights => {
+                (AnimationProperty::MorphWeights, Dimensions::Scalar, NORM)
+            }
+        };
+        let interpolation = match sampler.interpolation() {
+            GltfInterpolation::Linear => Interpolation::Linear,
+            GltfInterpolation::Step => Interpolation::Step,
+            GltfInterpolation::CubicSpline => Interpolation::CubicSpline,
+        };
+        let output = sampler.output();
+        let output_path = format!("{}.output", base);
+        let values: Vec<f32> = read_checked(&output, buffers, &output_path, &[dimensions], types)?
+            .into_iter()
+            .map(|value| value as f32)
+            .collect();
+        let per_key = if interpolation == Interpolation::CubicSpline {
+            3
+        } else {
+            1
+        };
+        if times.is_empty() || output.count() % (times.len() * per_key) != 0 {
+            return Err(accessor_error(
+                &output,
+                &output_path,
+                format!(
+                    "has {} elements for {} keys with {:?} interpolation",
+                    output.count(),
+                    times.len(),
+                    interpolation
+                ),
+            ));
+        }
+        channels.push(Channel {
+            node: target.node().index(),
+            property,
+            interpolation,
+            times,
+            values,
+        });
+    }
+    Ok(Animation {
+        name: animation.name().map(str::to_owned),
+        channels,
+    })
+}
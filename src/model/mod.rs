@@ -0,0 +1,531 @@
+//! Models loaded from files: meshes with their materials and textures, the node hierarchy,
+//! cameras, skins and animations. Everything lives on the host until `MeshData::upload`.
+
+mod gltf;
+mod obj;
+
+use ash::vk::Sampler;
+
+use crate::{
+    error::Result,
+    impl_vertex,
+    mesh::{Mesh, SubMesh},
+    vulkan::{Context, DeviceTexture, SamplerCache, SamplerInfo, TextureUsage},
+    RgbaImage,
+};
+
+/// Column-major 4x4 matrix, `matrix[column][row]`.
+pub type Matrix = [[f32; 4]; 4];
+
+pub const IDENTITY: Matrix = [
+    [1.0, 0.0, 0.0, 0.0],
+    [0.0, 1.0, 0.0, 0.0],
+    [0.0, 0.0, 1.0, 0.0],
+    [0.0, 0.0, 0.0, 1.0],
+];
+
+pub fn multiply(a: &Matrix, b: &Matrix) -> Matrix {
+    let mut result = [[0.0; 4]; 4];
+    for (column, out) in result.iter_mut().enumerate() {
+        for (row, value) in out.iter_mut().enumerate() {
+            *value = (0..4).map(|k| a[k][row] * b[column][k]).sum();
+        }
+    }
+    result
+}
+
+/// Vertex of every loaded mesh. Attributes a file doesn't have keep their defaults: no
+/// normal, tangent along +X, white and unskinned.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ModelVertex {
+    pub position: [f32; 3],
+    pub normal: [f32; 3],
+    /// Tangent in xyz, handedness of the bitangent in w.
+    pub tangent: [f32; 4],
+    pub uv: [f32; 2],
+    pub uv1: [f32; 2],
+    pub color: [f32; 4],
+    pub joints: [u32; 4],
+    pub weights: [f32; 4],
+}
+
+impl Default for ModelVertex {
+    fn default() -> Self {
+        ModelVertex {
+            position: [0.0; 3],
+            normal: [0.0; 3],
+            tangent: [1.0, 0.0, 0.0, 1.0],
+            uv: [0.0; 2],
+            uv1: [0.0; 2],
+            color: [1.0; 4],
+            joints: [0; 4],
+            weights: [0.0; 4],
+        }
+    }
+}
+
+impl_vertex!(ModelVertex {
+    position: 0,
+    normal: 1,
+    tangent: 2,
+    uv: 3,
+    uv1: 4,
+    color: 5,
+    joints: 6,
+    weights: 7,
+});
+
+/// Part of a mesh drawn with one material.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Primitive {
+    pub submesh: SubMesh,
+    pub material: Option<usize>,
+}
+
+/// Triangle lists of one mesh, all primitives sharing the vertex and index arrays.
+#[derive(Debug, Clone, Default)]
+pub struct MeshData {
+    pub name: Option<String>,
+    pub vertices: Vec<ModelVertex>,
+    pub indices: Vec<u32>,
+    pub primitives: Vec<Primitive>,
+}
+
+impl MeshData {
+    /// Uploads the mesh with one sub-mesh per primitive, in the same order.
+    pub fn upload(&self, context: &Context) -> Result<Mesh> {
+        let mut mesh = Mesh::indexed(context, &self.vertices, &self.indices)?;
+        let submeshes = self
+            .primitives
+            .iter()
+            .map(|primitive| primitive.submesh)
+            .collect();
+        if let Err(error) = mesh.set_submeshes(submeshes) {
+            mesh.destroy(context);
+            return Err(error);
+        }
+        Ok(mesh)
+    }
+}
+
+/// An image of `Model::images` with the sampler to read it through.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Texture {
+    pub name: Option<String>,
+    pub image: usize,
+    pub sampler: SamplerInfo,
+}
+
+/// UV offset, rotation and scale applied before sampling, from `KHR_texture_transform`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextureTransform {
+    pub offset: [f32; 2],
+    /// Counterclockwise, in radians.
+    pub rotation: f32,
+    pub scale: [f32; 2],
+}
+
+impl TextureTransform {
+    /// The transform as a column-major 3x3 matrix for homogeneous UVs.
+    pub fn matrix(&self) -> [[f32; 3]; 3] {
+        let (sin, cos) = self.rotation.sin_cos();
+        let [sx, sy] = self.scale;
+        [
+            [cos * sx, -sin * sx, 0.0],
+            [sin * sy, cos * sy, 0.0],
+            [self.offset[0], self.offset[1], 1.0],
+        ]
+    }
+}
+
+/// Reference from a material to a texture.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TextureRef {
+    pub texture: usize,
+    /// Which UV set to sample with, 0 for `ModelVertex::uv` and 1 for `uv1`.
+    pub uv_set: u32,
+    pub transform: Option<TextureTransform>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AlphaMode {
+    Opaque,
+    /// Fully transparent below the cutoff, opaque otherwise.
+    Mask,
+    Blend,
+}
+
+/// Metallic-roughness PBR material.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Material {
+    pub name: Option<String>,
+    /// Linear RGBA factor, multiplied with the texture.
+    pub base_color: [f32; 4],
+    pub base_color_texture: Option<TextureRef>,
+    pub metallic: f32,
+    pub roughness: f32,
+    /// Roughness in the green channel and metalness in blue.
+    pub metallic_roughness_texture: Option<TextureRef>,
+    pub normal_texture: Option<TextureRef>,
+    pub normal_scale: f32,
+    pub occlusion_texture: Option<TextureRef>,
+    pub occlusion_strength: f32,
+    /// Linear RGB, already multiplied by `KHR_materials_emissive_strength`.
+    pub emissive: [f32; 3],
+    pub emissive_texture: Option<TextureRef>,
+    pub alpha_mode: AlphaMode,
+    pub alpha_cutoff: f32,
+    pub double_sided: bool,
+}
+
+impl Default for Material {
+    fn default() -> Self {
+        Material {
+            name: None,
+            base_color: [1.0; 4],
+            base_color_texture: None,
+            metallic: 1.0,
+            roughness: 1.0,
+            metallic_roughness_texture: None,
+            normal_texture: None,
+            normal_scale: 1.0,
+            occlusion_texture: None,
+            occlusion_strength: 1.0,
+            emissive: [0.0; 3],
+            emissive_texture: None,
+            alpha_mode: AlphaMode::Opaque,
+            alpha_cutoff: 0.5,
+            double_sided: false,
+        }
+    }
+}
+
+/// Local transform of a node, applied scale first, then rotation, then translation.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct Transform {
+    pub translation: [f32; 3],
+    /// Unit quaternion, `[x, y, z, w]`.
+    pub rotation: [f32; 4],
+    pub scale: [f32; 3],
+}
+
+impl Default for Transform {
+    fn default() -> Self {
+        Transform {
+            translation: [0.0; 3],
+            rotation: [0.0, 0.0, 0.0, 1.0],
+            scale: [1.0; 3],
+        }
+    }
+}
+
+impl Transform {
+    pub fn matrix(&self) -> Matrix {
+        let [x, y, z, w] = self.rotation;
+        let [sx, sy, sz] = self.scale;
+        let [tx, ty, tz] = self.translation;
+        [
+            [
+                (1.0 - 2.0 * (y * y + z * z)) * sx,
+                2.0 * (x * y + z * w) * sx,
+                2.0 * (x * z - y * w) * sx,
+                0.0,
+            ],
+            [
+                2.0 * (x * y - z * w) * sy,
+                (1.0 - 2.0 * (x * x + z * z)) * sy,
+                2.0 * (y * z + x * w) * sy,
+                0.0,
+            ],
+            [
+                2.0 * (x * z + y * w) * sz,
+                2.0 * (y * z - x * w) * sz,
+                (1.0 - 2.0 * (x * x + y * y)) * sz,
+                0.0,
+            ],
+            [tx, ty, tz, 1.0],
+        ]
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Node {
+    pub name: Option<String>,
+    pub parent: Option<usize>,
+    pub children: Vec<usize>,
+    pub transform: Transform,
+    pub mesh: Option<usize>,
+    pub camera: Option<usize>,
+    pub skin: Option<usize>,
+}
+
+/// Root nodes of one scene.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Scene {
+    pub name: Option<String>,
+    pub nodes: Vec<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Projection {
+    /// `aspect_ratio` `None` follows the viewport, `zfar` `None` is infinite.
+    Perspective {
+        yfov: f32,
+        aspect_ratio: Option<f32>,
+        znear: f32,
+        zfar: Option<f32>,
+    },
+    Orthographic {
+        xmag: f32,
+        ymag: f32,
+        znear: f32,
+        zfar: f32,
+    },
+}
+
+/// A camera looking down -Z of the node it is attached to.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Camera {
+    pub name: Option<String>,
+    pub projection: Projection,
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Skin {
+    pub name: Option<String>,
+    /// Nodes of the joints, in the order `ModelVertex::joints` refers to them.
+    pub joints: Vec<usize>,
+    /// One per joint, from model space to the joint's space.
+    pub inverse_bind_matrices: Vec<Matrix>,
+    pub skeleton: Option<usize>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AnimationProperty {
+    Translation,
+    Rotation,
+    Scale,
+    MorphWeights,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Interpolation {
+    Linear,
+    Step,
+    /// Every key holds an in-tangent, the value and an out-tangent.
+    CubicSpline,
+}
+
+/// Keyframes of one property of one node.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Channel {
+    pub node: usize,
+    pub property: AnimationProperty,
+    pub interpolation: Interpolation,
+    /// Key times in seconds, increasing.
+    pub times: Vec<f32>,
+    /// Components of all keys one after another, three per key for cubic splines.
+    pub values: Vec<f32>,
+}
+
+impl Channel {
+    /// Components of one value, e.g. 4 for rotations.
+    pub fn components(&self) -> usize {
+        let per_key = if self.interpolation == Interpolation::CubicSpline {
+            3
+        } else {
+            1
+        };
+        self.values.len() / (self.times.len() * per_key).max(1)
+    }
+
+    /// The property at `time`, clamped to the first and the last key.
+    pub fn sample(&self, time: f32) -> Vec<f32> {
+        let components = self.components();
+        let key = |index: usize, part: usize| -> &[f32] {
+            let index = match self.interpolation {
+                Interpolation::CubicSpline => index * 3 + part,
+                _ => index,
+            };
+            &self.values[index * components..(index + 1) * components]
+        };
+        let Some(last) = self.times.len().checked_sub(1) else {
+            return vec![0.0; components];
+        };
+        if time <= self.times[0] {
+            return key(0, 1).to_vec();
+        }
+        if time >= self.times[last] {
+            return key(last, 1).to_vec();
+        }
+        let next = self.times.partition_point(|key_time| *key_time <= time);
+        let previous = next - 1;
+        let span = self.times[next] - self.times[previous];
+        let t = (time - self.times[previous]) / span;
+        let mut value: Vec<f32> = match self.interpolation {
+            Interpolation::Step => key(previous, 1).to_vec(),
+            Interpolation::Linear if self.property == AnimationProperty::Rotation => {
+                slerp(key(previous, 1), key(next, 1), t)
+            }
+            Interpolation::Linear => key(previous, 1)
+                .iter()
+                .zip(key(next, 1))
+                .map(|(a, b)| a + (b - a) * t)
+                .collect(),
+            Interpolation::CubicSpline => {
+                let (t2, t3) = (t * t, t * t * t);
+                (0..components)
+                    .map(|i| {
+                        (2.0 * t3 - 3.0 * t2 + 1.0) * key(previous, 1)[i]
+                            + (t3 - 2.0 * t2 + t) * span * key(previous, 2)[i]
+                            + (-2.0 * t3 + 3.0 * t2) * key(next, 1)[i]
+                            + (t3 - t2) * span * key(next, 0)[i]
+                    })
+                    .collect()
+            }
+        };
+        if self.property == AnimationProperty::Rotation {
+            normalize(&mut value);
+        }
+        value
+    }
+}
+
+fn normalize(value: &mut [f32]) {
+    let length = value.iter().map(|v| v * v).sum::<f32>().sqrt();
+    if length > 0.0 {
+        value.iter_mut().for_each(|v| *v /= length);
+    }
+}
+
+fn slerp(a: &[f32], b: &[f32], t: f32) -> Vec<f32> {
+    let mut dot: f32 = a.iter().zip(b).map(|(a, b)| a * b).sum();
+    // The shorter way around.
+    let sign = if dot < 0.0 { -1.0 } else { 1.0 };
+    dot *= sign;
+    let (wa, wb) = if dot > 0.9995 {
+        (1.0 - t, t)
+    } else {
+        let angle = dot.acos();
+        let sin = angle.sin();
+        (((1.0 - t) * angle).sin() / sin, (t * angle).sin() / sin)
+    };
+    a.iter()
+        .zip(b)
+        .map(|(a, b)| a * wa + b * wb * sign)
+        .collect()
+}
+
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Animation {
+    pub name: Option<String>,
+    pub channels: Vec<Channel>,
+}
+
+impl Animation {
+    /// Time of the last key of any channel.
+    pub fn duration(&self) -> f32 {
+        self.channels
+            .iter()
+            .filter_map(|channel| channel.times.last())
+            .fold(0.0, |duration, time| duration.max(*time))
+    }
+}
+
+/// Everything a model file holds, linked by indices into these arrays.
+#[derive(Debug, Clone, Default)]
+pub struct Model {
+    pub meshes: Vec<MeshData>,
+    pub materials: Vec<Material>,
+    pub textures: Vec<Texture>,
+    /// Decoded images, 8-bit RGBA.
+    pub images: Vec<RgbaImage>,
+    pub nodes: Vec<Node>,
+    pub scenes: Vec<Scene>,
+    pub default_scene: Option<usize>,
+    pub cameras: Vec<Camera>,
+    pub skins: Vec<Skin>,
+    pub animations: Vec<Animation>,
+}
+
+impl Model {
+    /// Transform of `node` relative to the scene root.
+    pub fn world_matrix(&self, node: usize) -> Matrix {
+        let mut matrix = self.nodes[node].transform.matrix();
+        let mut parent = self.nodes[node].parent;
+        while let Some(index) = parent {
+            matrix = multiply(&self.nodes[index].transform.matrix(), &matrix);
+            parent = self.nodes[index].parent;
+        }
+        matrix
+    }
+
+    /// How the materials use `texture`: `Color` for base color and emissive maps, `Data` for
+    /// the others. Unused textures are taken as `Color`.
+    pub fn texture_usage(&self, texture: usize) -> TextureUsage {
+        let uses = |slot: &Option<TextureRef>| slot.is_some_and(|slot| slot.texture == texture);
+        let data = self.materials.iter().any(|material| {
+            uses(&material.metallic_roughness_texture)
+                || uses(&material.normal_texture)
+                || uses(&material.occlusion_texture)
+        });
+        let color = self
+            .materials
+            .iter()
+            .any(|material| uses(&material.base_color_texture) || uses(&material.emissive_texture));
+        if data && !color {
+            TextureUsage::Data
+        } else {
+            TextureUsage::Color
+        }
+    }
+
+    /// Uploads every texture with the mips its sampler asks for, in the same order as
+    /// `textures`. Samplers come from `samplers`, which keeps owning them.
+    pub fn upload_textures(
+        &self,
+        context: &Context,
+        samplers: &mut SamplerCache,
+    ) -> Result<Vec<(DeviceTexture, Sampler)>> {
+        let mut textures: Vec<(DeviceTexture, Sampler)> = Vec::with_capacity(self.textures.len());
+        for (index, texture) in self.textures.iter().enumerate() {
+            let uploaded = samplers.get(context, texture.sampler).and_then(|sampler| {
+                let image = DeviceTexture::new(
+                    context,
+                    &self.images[texture.image],
+                    self.texture_usage(index),
+                    texture.sampler.mipmaps,
+                )?;
+                Ok((image, sampler))
+            });
+            match uploaded {
+                Ok(uploaded) => textures.push(uploaded),
+                Err(error) => {
+                    for (mut texture, _) in textures {
+                        texture.destroy(context);
+                    }
+                    return Err(error);
+                }
+            }
+        }
+        Ok(textures)
+    }
+
+    /// Uploads every mesh, in the same order as `meshes`.
+    pub fn upload_meshes(&self, context: &Context) -> Result<Vec<Mesh>> {
+        let mut meshes = Vec::with_capacity(self.meshes.len());
+        for data in &self.meshes {
+            match data.upload(context) {
+                Ok(mesh) => meshes.push(mesh),
+                Err(error) => {
+                    for mut mesh in meshes {
+                        mesh.destroy(context);
+                    }
+                    return Err(error);
+                }
+            }
+        }
+        Ok(meshes)
+    }
+}
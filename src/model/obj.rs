@@ -0,0 +1,699 @@
+//! Wavefront OBJ with MTL material libraries. Every `o` object becomes a mesh with one node,
+//! its faces grouped into one primitive per material.
+
+use std::{
+    collections::HashMap,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use ash::vk::SamplerAddressMode;
+
+use crate::{error::Result, mesh::SubMesh, vulkan::SamplerInfo, Error, RgbaImage};
+
+use super::{
+    AlphaMode, Material, MeshData, Model, ModelVertex, Node, Primitive, Scene, Texture, TextureRef,
+    TextureTransform,
+};
+
+#[derive(Debug, Clone, Copy)]
+struct Corner {
+    position: usize,
+    uv: Option<usize>,
+    normal: Option<usize>,
+}
+
+#[derive(Debug)]
+struct Face {
+    corners: Vec<Corner>,
+    material: Option<usize>,
+    smooth: bool,
+}
+
+#[derive(Debug, Default)]
+struct Object {
+    name: Option<String>,
+    faces: Vec<Face>,
+}
+
+/// Where a vertex normal comes from, part of the key vertices are deduplicated by.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+enum NormalSource {
+    Given(usize),
+    /// Averaged over the smooth faces around the position.
+    Smooth,
+    /// Of this face only.
+    Flat(usize),
+}
+
+#[derive(Debug, Default)]
+struct Library {
+    materials: Vec<Material>,
+    names: HashMap<String, usize>,
+    textures: Vec<Texture>,
+    texture_keys: HashMap<(usize, SamplerInfo), usize>,
+    images: Vec<RgbaImage>,
+    image_paths: HashMap<PathBuf, usize>,
+}
+
+fn line_error(file: &str, line: usize, reason: impl std::fmt::Display) -> Error {
+    Error::Other(format!("{} line {}: {}", file, line, reason))
+}
+
+fn floats(file: &str, line: usize, args: &[&str], min: usize, max: usize) -> Result<Vec<f32>> {
+    if args.len() < min || args.len() > max {
+        return Err(line_error(
+            file,
+            line,
+            format!("expected {} to {} numbers, got {}", min, max, args.len()),
+        ));
+    }
+    args.iter()
+        .map(|arg| {
+            arg.parse::<f32>()
+                .map_err(|_| line_error(file, line, format!("`{}` isn`t a number", arg)))
+        })
+        .collect()
+}
+
+/// One-based or negative relative OBJ index to a zero-based one.
+fn resolve(line: usize, text: &str, count: usize, kind: &str) -> Result<usize> {
+    let index: i64 = text
+        .parse()
+        .map_err(|_| line_error("OBJ", line, format!("`{}` isn`t an index", text)))?;
+    let resolved = if index < 0 {
+        count as i64 + index
+    } else {
+        index - 1
+    };
+    if index == 0 || resolved < 0 || resolved >= count as i64 {
+        return Err(line_error(
+            "OBJ",
+            line,
+            format!("{} index {} is out of {} {}s", kind, index, count, kind),
+        ));
+    }
+    Ok(resolved as usize)
+}
+
+fn normalized(v: [f32; 3]) -> [f32; 3] {
+    let length = (v[0] * v[0] + v[1] * v[1] + v[2] * v[2]).sqrt();
+    if length > 0.0 {
+        v.map(|c| c / length)
+    } else {
+        [0.0, 0.0, 1.0]
+    }
+}
+
+/// Newell normal of a polygon, its length twice the area.
+fn polygon_normal(points: &[[f32; 3]]) -> [f32; 3] {
+    let mut normal = [0.0; 3];
+    for (i, a) in points.iter().enumerate() {
+        let b = points[(i + 1) % points.len()];
+        normal[0] += (a[1] - b[1]) * (a[2] + b[2]);
+        normal[1] += (a[2] - b[2]) * (a[0] + b[0]);
+        normal[2] += (a[0] - b[0]) * (a[1] + b[1]);
+    }
+    normal
+}
+
+/// Splits a simple polygon into triangles by ear clipping in its plane. Falls back to a fan
+/// if the polygon is degenerate.
+fn triangulate(points: &[[f32; 3]]) -> Vec<[usize; 3]> {
+    if points.len() == 3 {
+        return vec![[0, 1, 2]];
+    }
+    let normal = polygon_normal(points);
+    let axis = (0..3)
+        .max_by(|a, b| normal[*a].abs().total_cmp(&normal[*b].abs()))
+        .unwrap();
+    let (u, v) = ((axis + 1) % 3, (axis + 2) % 3);
+    let sign = if normal[axis] < 0.0 { -1.0 } else { 1.0 };
+    let flat: Vec<[f32; 2]> = points.iter().map(|p| [p[u], p[v]]).collect();
+    let cross = |o: [f32; 2], a: [f32; 2], b: [f32; 2]| {
+        ((a[0] - o[0]) * (b[1] - o[1]) - (a[1] - o[1]) * (b[0] - o[0])) * sign
+    };
+
+    let mut remaining: Vec<usize> = (0..points.len()).collect();
+    let mut triangles = Vec::with_capacity(points.len() - 2);
+    while remaining.len() > 3 {
+        let count = remaining.len();
+        let ear = (0..count).find(|i| {
+            let (a, b, c) = (
+                remaining[(i + count - 1) % count],
+                remaining[*i],
+                remaining[(i + 1) % count],
+            );
+            cross(flat[a], flat[b], flat[c]) > 0.0
+                && remaining.iter().all(|p| {
+                    [a, b, c].contains(p)
+                        || cross(flat[a], flat[b], flat[*p]) < 0.0
+                        || cross(flat[b], flat[c], flat[*p]) < 0.0
+                        || cross(flat[c], flat[a], flat[*p]) < 0.0
+                })
+        });
+        let Some(i) = ear else {
+            break;
+        };
+        triangles.push([
+            remaining[(i + count - 1) % count],
+            remaining[i],
+            remaining[(i + 1) % count],
+        ]);
+        remaining.remove(i);
+    }
+    for i in 1..remaining.len() - 1 {
+        triangles.push([remaining[0], remaining[i], remaining[i + 1]]);
+    }
+    triangles
+}
+
+impl Library {
+    fn texture(
+        &mut self,
+        file: &str,
+        line: usize,
+        args: &[&str],
+        base: &Path,
+    ) -> Result<(TextureRef, f32)> {
+        let mut sampler = SamplerInfo::default();
+        let mut offset = None;
+        let mut scale = None;
+        let mut bump = 1.0;
+        let mut rest = args;
+        while let Some(option) = rest.first().filter(|arg| arg.starts_with('-')) {
+            rest = &rest[1..];
+            let numbers = rest
+                .iter()
+                .take(3)
+                .take_while(|arg| arg.parse::<f32>().is_ok())
+                .count();
+            let arguments = match *option {
+                "-o" | "-s" | "-t" => numbers.max(1),
+                "-mm" => 2,
+                "-blendu" | "-blendv" | "-cc" | "-clamp" | "-bm" | "-boost" | "-texres"
+                | "-imfchan" | "-type" => 1,
+                _ => {
+                    return Err(line_error(
+                        file,
+                        line,
+                        format!("unknown texture option `{}`", option),
+                    ))
+                }
+            };
+            if rest.len() < arguments {
+                return Err(line_error(
+                    file,
+                    line,
+                    format!("`{}` needs {} arguments", option, arguments),
+                ));
+            }
+            let values = &rest[..arguments];
+            match *option {
+                "-o" => {
+                    let numbers = floats(file, line, values, 1, 3)?;
+                    offset = Some([numbers[0], numbers.get(1).copied().unwrap_or(0.0)]);
+                }
+                "-s" => {
+                    let numbers = floats(file, line, values, 1, 3)?;
+                    scale = Some([numbers[0], numbers.get(1).copied().unwrap_or(1.0)]);
+                }
+                "-clamp" if values[0] == "on" => {
+                    sampler.address_u = SamplerAddressMode::CLAMP_TO_EDGE;
+                    sampler.address_v = SamplerAddressMode::CLAMP_TO_EDGE;
+                }
+                "-bm" => bump = floats(file, line, values, 1, 1)?[0],
+                _ => {}
+            }
+            rest = &rest[arguments..];
+        }
+        if rest.is_empty() {
+            return Err(line_error(file, line, "texture has no file name"));
+        }
+
+        let path = base.join(rest.join(" ").replace('\\', "/"));
+        let image = match self.image_paths.get(&path) {
+            Some(image) => *image,
+            None => {
+                let image = RgbaImage::load(&path).map_err(|error| {
+                    line_error(file, line, format!("{}: {}", path.display(), error))
+                })?;
+                self.images.push(image);
+                self.image_paths.insert(path, self.images.len() - 1);
+                self.images.len() - 1
+            }
+        };
+        let texture = *self
+            .texture_keys
+            .entry((image, sampler))
+            .or_insert_with(|| {
+                self.textures.push(Texture {
+                    name: None,
+                    image,
+                    sampler,
+                });
+                self.textures.len() - 1
+            });
+
+        // OBJ puts v = 0 at the bottom of the image and models flip it, so the transform
+        // is flipped to match.
+        let transform = (offset.is_some() || scale.is_some()).then(|| {
+            let [ou, ov] = offset.unwrap_or([0.0, 0.0]);
+            let [su, sv] = scale.unwrap_or([1.0, 1.0]);
+            TextureTransform {
+                offset: [ou, 1.0 - sv - ov],
+                rotation: 0.0,
+                scale: [su, sv],
+            }
+        });
+        Ok((
+            TextureRef {
+                texture,
+                uv_set: 0,
+                transform,
+            },
+            bump,
+        ))
+    }
+
+    /// Reads the materials of an MTL file. Statements without an equivalent in `Material`
+    /// are ignored.
+    fn parse(&mut self, source: &str, file: &str, base: &Path) -> Result<()> {
+        let mut current: Option<usize> = None;
+        let mut explicit_roughness = false;
+        for (index, text) in source.lines().enumerate() {
+            let line = index + 1;
+            let text = text.split('#').next().unwrap_or_default();
+            let words: Vec<&str> = text.split_whitespace().collect();
+            let Some((keyword, args)) = words.split_first() else {
+                continue;
+            };
+            if *keyword == "newmtl" {
+                let name = args.join(" ");
+                self.materials.push(Material {
+                    name: Some(name.clone()),
+                    metallic: 0.0,
+                    ..Default::default()
+                });
+                self.names.insert(name, self.materials.len() - 1);
+                current = Some(self.materials.len() - 1);
+                explicit_roughness = false;
+                continue;
+            }
+            let Some(material) = current else {
+                return Err(line_error(file, line, "statement before `newmtl`"));
+            };
+            match *keyword {
+                "Kd" => {
+                    let color = floats(file, line, args, 3, 3)?;
+                    self.materials[material].base_color[..3].copy_from_slice(&color);
+                }
+                "d" | "Tr" => {
+                    let args: Vec<&str> =
+                        args.iter().copied().filter(|arg| *arg != "-halo").collect();
+                    let value = floats(file, line, &args, 1, 1)?[0];
+                    let alpha = if *keyword == "d" { value } else { 1.0 - value };
+                    let material = &mut self.materials[material];
+                    material.base_color[3] = alpha;
+                    material.alpha_mode = if alpha < 1.0 {
+                        AlphaMode::Blend
+                    } else {
+                        AlphaMode::Opaque
+                    };
+                }
+                "Ke" => {
+                    let color = floats(file, line, args, 3, 3)?;
+                    self.materials[material].emissive = [color[0], color[1], color[2]];
+                }
+                // Blinn-Phong exponent to the closest GGX roughness.
+                "Ns" if !explicit_roughness => {
+                    let shininess = floats(file, line, args, 1, 1)?[0].max(0.0);
+                    self.materials[material].roughness = (2.0 / (shininess + 2.0)).sqrt();
+                }
+                "Pr" => {
+                    self.materials[material].roughness = floats(file, line, args, 1, 1)?[0];
+                    explicit_roughness = true;
+                }
+                "Pm" => self.materials[material].metallic = floats(file, line, args, 1, 1)?[0],
+                "map_Kd" => {
+                    self.materials[material].base_color_texture =
+                        Some(self.texture(file, line, args, base)?.0);
+                }
+                "map_Ke" => {
+                    self.materials[material].emissive_texture =
+                        Some(self.texture(file, line, args, base)?.0);
+                }
+                "map_Bump" | "map_bump" | "bump" | "norm" => {
+                    let (texture, scale) = self.texture(file, line, args, base)?;
+                    self.materials[material].normal_texture = Some(texture);
+                    self.materials[material].normal_scale = scale;
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Model {
+    /// Loads an OBJ file with the MTL libraries it references, relative to the file.
+    pub fn load_obj(path: impl AsRef<Path>) -> Result<Self> {
+        let path = path.as_ref();
+        let bytes = fs::read(path).map_err(Error::Io)?;
+        Self::from_obj(
+            &String::from_utf8_lossy(&bytes),
+            Some(path.parent().unwrap_or(Path::new("."))),
+        )
+    }
+
+    /// Loads OBJ from memory. Without `base` material libraries are skipped.
+    ///
+    /// Texture coordinates are flipped to put v = 0 at the top of images like glTF does.
+    /// Faces without normals get them generated, smoothed unless the face is in `s off`.
+    pub fn from_obj(source: &str, base: Option<&Path>) -> Result<Self> {
+        let mut positions: Vec<[f32; 3]> = Vec::new();
+        let mut colors: Vec<[f32; 3]> = Vec::new();
+        let mut uvs: Vec<[f32; 2]> = Vec::new();
+        let mut normals: Vec<[f32; 3]> = Vec::new();
+        let mut objects = vec![Object::default()];
+        let mut library = Library::default();
+        let mut material = None;
+        let mut smooth = true;
+
+        for (index, text) in source.lines().enumerate() {
+            let line = index + 1;
+            let text = text.split('#').next().unwrap_or_default();
+            let words: Vec<&str> = text.split_whitespace().collect();
+            let Some((keyword, args)) = words.split_first() else {
+                continue;
+            };
+            match *keyword {
+                "v" => {
+                    let values = floats("OBJ", line, args, 3, 7)?;
+                    positions.push([values[0], values[1], values[2]]);
+                    // xyz rgb, the common extension for vertex colors.
+                    colors.push(if values.len() >= 6 {
+                        let rgb = &values[values.len() - 3..];
+                        [rgb[0], rgb[1], rgb[2]]
+                    } else {
+                        [1.0; 3]
+                    });
+                }
+                "vt" => {
+                    let values = floats("OBJ", line, args, 1, 3)?;
+                    uvs.push([values[0], 1.0 - values.get(1).copied().unwrap_or(0.0)]);
+                }
+                "vn" => {
+                    let values = floats("OBJ", line, args, 3, 3)?;
+                    normals.push([values[0], values[1], values[2]]);
+                }
+                "f" => {
+                    if args.len() < 3 {
+                        return Err(line_error(
+                            "OBJ",
+                            line,
+                            format!("face needs 3 vertices, got {}", args.len()),
+                        ));
+                    }
+                    let mut corners = Vec::with_capacity(args.len());
+                    for arg in args {
+                        let mut parts = arg.split('/');
+                        let position =
+                            resolve(line, parts.next().unwrap(), positions.len(), "position")?;
+                        let uv = match parts.next().filter(|part| !part.is_empty()) {
+                            Some(part) => {
+                                Some(resolve(line, part, uvs.len(), "texture coordinate")?)
+                            }
+                            None => None,
+                        };
+                        let normal = match parts.next().filter(|part| !part.is_empty()) {
+                            Some(part) => Some(resolve(line, part, normals.len(), "normal")?),
+                            None => None,
+                        };
+                        corners.push(Corner {
+                            position,
+                            uv,
+                            normal,
+                        });
+                    }
+                    objects.last_mut().unwrap().faces.push(Face {
+                        corners,
+                        material,
+                        smooth,
+                    });
+                }
+                "o" => {
+                    let name = Some(args.join(" "));
+                    let object = objects.last_mut().unwrap();
+                    if object.faces.is_empty() {
+                        object.name = name;
+                    } else {
+                        objects.push(Object {
+                            name,
+                            faces: Vec::new(),
+                        });
+                    }
+                }
+                "s" => smooth = !matches!(args.first(), Some(&"off") | Some(&"0")),
+                "usemtl" => {
+                    let name = args.join(" ");
+                    material = library.names.get(&name).copied();
+                    if material.is_none() {
+                        log::warn!("OBJ line {}: material `{}` dont found", line, name);
+                    }
+                }
+                "mtllib" => {
+                    let Some(base) = base else {
+                        log::warn!(
+                            "OBJ line {}: no base path to load `{}`",
+                            line,
+                            args.join(" ")
+                        );
+                        continue;
+                    };
+                    let path = base.join(args.join(" ").replace('\\', "/"));
+                    let source = fs::read(&path).map_err(|error| {
+                        line_error("OBJ", line, format!("{}: {}", path.display(), error))
+                    })?;
+                    let mtl_base = path.parent().unwrap_or(base).to_owned();
+                    library.parse(
+                        &String::from_utf8_lossy(&source),
+                        &path.display().to_string(),
+                        &mtl_base,
+                    )?;
+                }
+                _ => {}
+            }
+        }
+
+        let mut model = Model::default();
+        for object in objects {
+            let mesh = build_mesh(object, &positions, &colors, &uvs, &normals);
+            if mesh.indices.is_empty() {
+                continue;
+            }
+            model.nodes.push(Node {
+                name: mesh.name.clone(),
+                mesh: Some(model.meshes.len()),
+                ..Default::default()
+            });
+            model.meshes.push(mesh);
+        }
+        model.scenes.push(Scene {
+            name: None,
+            nodes: (0..model.nodes.len()).collect(),
+        });
+        model.default_scene = Some(0);
+        model.materials = library.materials;
+        model.textures = library.textures;
+        model.images = library.images;
+        Ok(model)
+    }
+}
+
+fn build_mesh(
+    object: Object,
+    positions: &[[f32; 3]],
+    colors: &[[f32; 3]],
+    uvs: &[[f32; 2]],
+    normals: &[[f32; 3]],
+) -> MeshData {
+    let face_normals: Vec<[f32; 3]> = object
+        .faces
+        .iter()
+        .map(|face| {
+            let points: Vec<[f32; 3]> =
+                face.corners.iter().map(|c| positions[c.position]).collect();
+            polygon_normal(&points)
+        })
+        .collect();
+
+    // Area weighted sums of the smooth faces around each position.
+    let mut smooth_normals: HashMap<usize, [f32; 3]> = HashMap::new();
+    for (face, normal) in object.faces.iter().zip(&face_normals) {
+        if !face.smooth {
+            continue;
+        }
+        for corner in face.corners.iter().filter(|c| c.normal.is_none()) {
+            let sum = smooth_normals.entry(corner.position).or_insert([0.0; 3]);
+            (0..3).for_each(|i| sum[i] += normal[i]);
+        }
+    }
+
+    let mut mesh = MeshData {
+        name: object.name,
+        ..Default::default()
+    };
+    let mut unique: HashMap<(usize, Option<usize>, NormalSource), u32> = HashMap::new();
+    let mut groups: Vec<(Option<usize>, Vec<u32>)> = Vec::new();
+    for (index, face) in object.faces.iter().enumerate() {
+        let group = match groups.iter().position(|(m, _)| *m == face.material) {
+            Some(group) => group,
+            None => {
+                groups.push((face.material, Vec::new()));
+                groups.len() - 1
+            }
+        };
+        let points: Vec<[f32; 3]> = face.corners.iter().map(|c| positions[c.position]).collect();
+        for triangle in triangulate(&points) {
+            for corner in triangle.map(|i| face.corners[i]) {
+                let source = match corner.normal {
+                    Some(normal) => NormalSource::Given(normal),
+                    None if face.smooth => NormalSource::Smooth,
+                    None => NormalSource::Flat(index),
+                };
+                let vertex = *unique
+                    .entry((corner.position, corner.uv, source))
+                    .or_insert_with(|| {
+                        let normal = match source {
+                            NormalSource::Given(normal) => normals[normal],
+                            NormalSource::Smooth => smooth_normals[&corner.position],
+                            NormalSource::Flat(face) => face_normals[face],
+                        };
+                        let [r, g, b] = colors[corner.position];
+                        mesh.vertices.push(ModelVertex {
+                            position: positions[corner.position],
+                            normal: normalized(normal),
+                            uv: corner.uv.map_or([0.0; 2], |uv| uvs[uv]),
+                            color: [r, g, b, 1.0],
+                            ..Default::default()
+                        });
+                        mesh.vertices.len() as u32 - 1
+                    });
+                groups[group].1.push(vertex);
+            }
+        }
+    }
+
+    for (material, indices) in groups {
+        mesh.primitives.push(Primitive {
+            submesh: SubMesh {
+                first: mesh.indices.len() as u32,
+                count: indices.len() as u32,
+                vertex_offset: 0,
+            },
+            material,
+        });
+        mesh.indices.extend(indices);
+    }
+    mesh
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn mesh(source: &str) -> MeshData {
+        let mut model = Model::from_obj(source, None).unwrap();
+        assert_eq!(model.meshes.len(), 1);
+        model.meshes.remove(0)
+    }
+
+    fn triangles(mesh: &MeshData) -> Vec<[[f32; 3]; 3]> {
+        mesh.indices
+            .chunks(3)
+            .map(|triangle| [0, 1, 2].map(|i| mesh.vertices[triangle[i] as usize].position))
+            .collect()
+    }
+
+    fn assert_close(a: [f32; 3], b: [f32; 3]) {
+        assert!(
+            (0..3).all(|i| (a[i] - b[i]).abs() < 1e-5),
+            "{:?} != {:?}",
+            a,
+            b
+        );
+    }
+
+    #[test]
+    fn concave_quads_are_split_inside_their_outline() {
+        // A dart with the reflex corner at 4, a fan from 1 would cover the notch.
+        let mesh = mesh("v 0 0 0\nv 2 1 0\nv 0 2 0\nv 0.5 1 0\nf 1 2 3 4\n");
+        assert_eq!(mesh.indices.len(), 6);
+        let mut area = 0.0;
+        for [a, b, c] in triangles(&mesh) {
+            let signed = (b[0] - a[0]) * (c[1] - a[1]) - (b[1] - a[1]) * (c[0] - a[0]);
+            assert!(signed > 0.0, "triangle {:?} is flipped", [a, b, c]);
+            area += signed / 2.0;
+        }
+        assert!((area - 1.5f32).abs() < 1e-5, "area {}", area);
+    }
+
+    #[test]
+    fn corners_are_shared_by_position_uv_and_normal() {
+        let mesh = mesh(
+            "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\n\
+             vt 0 0\nvt 1 0\nvt 1 1\nvt 0 1\n\
+             vn 0 0 1\n\
+             f 1/1/1 2/2/1 3/3/1\n\
+             f 1/1/1 3/3/1 4/4/1\n\
+             f 2/2/1 3/1/1 4/4/1\n",
+        );
+        // The last face reuses 2/2/1 and 4/4/1 but 3/1/1 differs from 3/3/1 in uv.
+        assert_eq!(mesh.vertices.len(), 5);
+        assert_eq!(mesh.indices.len(), 9);
+        assert_eq!(mesh.indices[..6], [0, 1, 2, 0, 2, 3]);
+        assert_eq!(mesh.indices[6..], [1, 4, 3]);
+        assert_eq!(mesh.vertices[4].position, [1.0, 1.0, 0.0]);
+        assert_eq!(mesh.vertices[4].uv, [0.0, 1.0]);
+        assert!(mesh.vertices.iter().all(|v| v.normal == [0.0, 0.0, 1.0]));
+    }
+
+    #[test]
+    fn negative_indices_count_back_from_the_last_vertex() {
+        let mesh = mesh("v 0 0 0\nv 1 0 0\nv 0 1 0\nf -1 -2 -3\n");
+        assert_eq!(
+            triangles(&mesh),
+            [[[0.0, 1.0, 0.0], [1.0, 0.0, 0.0], [0.0, 0.0, 0.0]]]
+        );
+
+        let error = Model::from_obj("v 0 0 0\nv 1 0 0\nv 0 1 0\nf -1 -2 -4\n", None).unwrap_err();
+        assert!(error.to_string().contains("line 4"), "{}", error);
+    }
+
+    #[test]
+    fn smoothing_groups_pick_shared_or_face_normals() {
+        // Two triangles folded at a right angle along the edge between 1 and 2.
+        let source = "v 0 0 0\nv 1 0 0\nv 0 1 0\nv 0 0 -1\nf 1 2 3\nf 2 1 4\n";
+
+        let smooth = mesh(source);
+        assert_eq!(smooth.vertices.len(), 4);
+        let half = std::f32::consts::FRAC_1_SQRT_2;
+        assert_close(smooth.vertices[0].normal, [0.0, -half, half]);
+        assert_close(smooth.vertices[1].normal, [0.0, -half, half]);
+        assert_close(smooth.vertices[2].normal, [0.0, 0.0, 1.0]);
+        assert_close(smooth.vertices[3].normal, [0.0, -1.0, 0.0]);
+
+        let flat = mesh(&format!("s off\n{}", source));
+        assert_eq!(flat.vertices.len(), 6);
+        for (triangle, normal) in flat
+            .indices
+            .chunks(3)
+            .zip([[0.0, 0.0, 1.0], [0.0, -1.0, 0.0]])
+        {
+            for index in triangle {
+                assert_close(flat.vertices[*index as usize].normal, normal);
+            }
+        }
+    }
+}
@@ -0,0 +1,105 @@
+use std::{fmt::Write, fs, path::Path};
+
+use crate::{error::Result, Error};
+
+use super::RenderGraph;
+
+impl RenderGraph<'_> {
+    /// The graph in Graphviz DOT format: passes as boxes, culled ones dashed, resources as
+    /// ellipses, imported ones bold. Edges go from read resources into passes and from
+    /// passes into written resources.
+    pub fn to_dot(&self) -> String {
+        let kept = self.kept(&self.dependencies());
+        let mut dot = String::new();
+        _ = writeln!(dot, "digraph render_graph {{");
+        _ = writeln!(dot, "    rankdir=LR;");
+
+        for (index, pass) in self.passes.iter().enumerate() {
+            let style = if kept[index] {
+                ""
+            } else {
+                ", style=dashed, color=gray, fontcolor=gray"
+            };
+            _ = writeln!(
+                dot,
+                "    pass{} [shape=box, label=\"{}\"{}];",
+                index,
+                escape(&pass.name),
+                style
+            );
+        }
+        for (index, image) in self.images.iter().enumerate() {
+            _ = writeln!(
+                dot,
+                "    image{} [shape=ellipse, label=\"{}\\n{:?} {}x{}\"{}];",
+                index,
+                escape(&image.name),
+                image.desc.format,
+                image.desc.extent.width,
+                image.desc.extent.height,
+                if image.imported.is_some() {
+                    ", style=bold"
+                } else {
+                    ""
+                }
+            );
+        }
+        for (index, buffer) in self.buffers.iter().enumerate() {
+            _ = writeln!(
+                dot,
+                "    buffer{} [shape=ellipse, label=\"{}\\n{} bytes\"{}];",
+                index,
+                escape(&buffer.name),
+                buffer.desc.size,
+                if buffer.imported.is_some() {
+                    ", style=bold"
+                } else {
+                    ""
+                }
+            );
+        }
+
+        for (index, pass) in self.passes.iter().enumerate() {
+            let images = pass.images.iter().map(|(id, access)| {
+                (
+                    format!("image{}", id.0),
+                    format!("{:?}", access),
+                    access.is_write(),
+                )
+            });
+            let buffers = pass.buffers.iter().map(|(id, access)| {
+                (
+                    format!("buffer{}", id.0),
+                    format!("{:?}", access),
+                    access.is_write(),
+                )
+            });
+            for (resource, access, write) in images.chain(buffers) {
+                if write {
+                    _ = writeln!(
+                        dot,
+                        "    pass{} -> {} [label=\"{}\"];",
+                        index, resource, access
+                    );
+                } else {
+                    _ = writeln!(
+                        dot,
+                        "    {} -> pass{} [label=\"{}\"];",
+                        resource, index, access
+                    );
+                }
+            }
+        }
+        _ = writeln!(dot, "}}");
+        dot
+    }
+
+    /// Writes `to_dot` into a file, e.g. to render with `dot -Tsvg`.
+    pub fn write_dot(&self, path: impl AsRef<Path>) -> Result<()> {
+        fs::write(path, self.to_dot()).map_err(Error::Io)
+    }
+}
+
+fn escape(name: &str) -> String {
+    name.replace('\\', "\\\\").replace('"', "\\\"")
+}
@@ -0,0 +1,1188 @@
+//! Render graph: passes declare the images and buffers they read and write, and the graph
+//! takes care of barriers, layout transitions and transient resources.
+//!
+//! The execution order follows from what the passes access. A pass reading a resource runs
+//! after the pass whose write it sees: the last one added before it that writes the
+//! resource, or for a transient resource nothing wrote yet, the last one added at all. A
+//! pass writing a resource runs after the previous writer and the passes reading that
+//! write. Passes without a dependency between them keep the order they were added in.
+//!
+//! Passes whose results are never used are culled: a pass is kept when it writes an
+//! imported resource or a resource marked with `mark_output`, and so is every pass whose
+//! writes a kept pass depends on.
+
+mod dot;
+mod pool;
+
+use std::{
+    cmp::Reverse,
+    collections::{BinaryHeap, HashMap, HashSet},
+};
+
+use ash::{
+    vk::{
+        AccessFlags, Buffer, BufferMemoryBarrier, BufferUsageFlags, CommandBuffer, DependencyFlags,
+        DeviceSize, Extent2D, Format, Image, ImageLayout, ImageMemoryBarrier,
+        ImageSubresourceRange, ImageUsageFlags, ImageView, PipelineStageFlags, SampleCountFlags,
+        QUEUE_FAMILY_IGNORED, WHOLE_SIZE,
+    },
+    Device,
+};
+
+use crate::{
+    error::Result,
+    vulkan::{format_aspect, Context},
+    Error,
+};
+
+pub use pool::TransientPool;
+
+use pool::{Allocated, Request, RequestKind};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct ImageId(usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct BufferId(usize);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum Resource {
+    Image(ImageId),
+    Buffer(BufferId),
+}
+
+impl From<ImageId> for Resource {
+    fn from(id: ImageId) -> Self {
+        Resource::Image(id)
+    }
+}
+
+impl From<BufferId> for Resource {
+    fn from(id: BufferId) -> Self {
+        Resource::Buffer(id)
+    }
+}
+
+/// Shape of a graph image. Usage flags needed by the passes are added automatically.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ImageDesc {
+    pub format: Format,
+    pub extent: Extent2D,
+    pub samples: SampleCountFlags,
+    /// Extra usage, e.g. for access outside the graph.
+    pub usage: ImageUsageFlags,
+}
+
+impl ImageDesc {
+    pub fn new(format: Format, extent: Extent2D) -> Self {
+        ImageDesc {
+            format,
+            extent,
+            samples: SampleCountFlags::TYPE_1,
+            usage: ImageUsageFlags::empty(),
+        }
+    }
+
+    pub fn samples(mut self, samples: SampleCountFlags) -> Self {
+        self.samples = samples;
+        self
+    }
+
+    pub fn usage(mut self, usage: ImageUsageFlags) -> Self {
+        self.usage = usage;
+        self
+    }
+}
+
+/// Shape of a graph buffer. Usage flags needed by the passes are added automatically.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct BufferDesc {
+    pub size: DeviceSize,
+    /// Extra usage, e.g. for access outside the graph.
+    pub usage: BufferUsageFlags,
+}
+
+impl BufferDesc {
+    pub fn new(size: DeviceSize) -> Self {
+        BufferDesc {
+            size,
+            usage: BufferUsageFlags::empty(),
+        }
+    }
+
+    pub fn usage(mut self, usage: BufferUsageFlags) -> Self {
+        self.usage = usage;
+        self
+    }
+}
+
+/// How a pass uses an image.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ImageAccess {
+    ColorAttachment,
+    DepthAttachment,
+    /// Depth test without depth writes.
+    DepthRead,
+    Sampled,
+    StorageRead,
+    StorageWrite,
+    TransferSrc,
+    TransferDst,
+}
+
+impl ImageAccess {
+    pub fn is_write(self) -> bool {
+        matches!(
+            self,
+            ImageAccess::ColorAttachment
+                | ImageAccess::DepthAttachment
+                | ImageAccess::StorageWrite
+                | ImageAccess::TransferDst
+        )
+    }
+
+    pub fn layout(self) -> ImageLayout {
+        match self {
+            ImageAccess::ColorAttachment => ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            ImageAccess::DepthAttachment => ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+            ImageAccess::DepthRead => ImageLayout::DEPTH_STENCIL_READ_ONLY_OPTIMAL,
+            ImageAccess::Sampled => ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            ImageAccess::StorageRead | ImageAccess::StorageWrite => ImageLayout::GENERAL,
+            ImageAccess::TransferSrc => ImageLayout::TRANSFER_SRC_OPTIMAL,
+            ImageAccess::TransferDst => ImageLayout::TRANSFER_DST_OPTIMAL,
+        }
+    }
+
+    fn stages(self) -> PipelineStageFlags {
+        match self {
+            ImageAccess::ColorAttachment => PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            ImageAccess::DepthAttachment | ImageAccess::DepthRead => {
+                PipelineStageFlags::EARLY_FRAGMENT_TESTS | PipelineStageFlags::LATE_FRAGMENT_TESTS
+            }
+            ImageAccess::Sampled | ImageAccess::StorageRead | ImageAccess::StorageWrite => {
+                SHADER_STAGES
+            }
+            ImageAccess::TransferSrc | ImageAccess::TransferDst => PipelineStageFlags::TRANSFER,
+        }
+    }
+
+    fn access(self) -> AccessFlags {
+        match self {
+            ImageAccess::ColorAttachment => {
+                AccessFlags::COLOR_ATTACHMENT_READ | AccessFlags::COLOR_ATTACHMENT_WRITE
+            }
+            ImageAccess::DepthAttachment => {
+                AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+                    | AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE
+            }
+            ImageAccess::DepthRead => AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ,
+            ImageAccess::Sampled | ImageAccess::StorageRead => AccessFlags::SHADER_READ,
+            ImageAccess::StorageWrite => AccessFlags::SHADER_READ | AccessFlags::SHADER_WRITE,
+            ImageAccess::TransferSrc => AccessFlags::TRANSFER_READ,
+            ImageAccess::TransferDst => AccessFlags::TRANSFER_WRITE,
+        }
+    }
+
+    fn usage(self) -> ImageUsageFlags {
+        match self {
+            ImageAccess::ColorAttachment => ImageUsageFlags::COLOR_ATTACHMENT,
+            ImageAccess::DepthAttachment | ImageAccess::DepthRead => {
+                ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT
+            }
+            ImageAccess::Sampled => ImageUsageFlags::SAMPLED,
+            ImageAccess::StorageRead | ImageAccess::StorageWrite => ImageUsageFlags::STORAGE,
+            ImageAccess::TransferSrc => ImageUsageFlags::TRANSFER_SRC,
+            ImageAccess::TransferDst => ImageUsageFlags::TRANSFER_DST,
+        }
+    }
+}
+
+/// How a pass uses a buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum BufferAccess {
+    Vertex,
+    Index,
+    Indirect,
+    Uniform,
+    StorageRead,
+    StorageWrite,
+    TransferSrc,
+    TransferDst,
+}
+
+impl BufferAccess {
+    pub fn is_write(self) -> bool {
+        matches!(self, BufferAccess::StorageWrite | BufferAccess::TransferDst)
+    }
+
+    fn stages(self) -> PipelineStageFlags {
+        match self {
+            BufferAccess::Vertex | BufferAccess::Index => PipelineStageFlags::VERTEX_INPUT,
+            BufferAccess::Indirect => PipelineStageFlags::DRAW_INDIRECT,
+            BufferAccess::Uniform | BufferAccess::StorageRead | BufferAccess::StorageWrite => {
+                SHADER_STAGES
+            }
+            BufferAccess::TransferSrc | BufferAccess::TransferDst => PipelineStageFlags::TRANSFER,
+        }
+    }
+
+    fn access(self) -> AccessFlags {
+        match self {
+            BufferAccess::Vertex => AccessFlags::VERTEX_ATTRIBUTE_READ,
+            BufferAccess::Index => AccessFlags::INDEX_READ,
+            BufferAccess::Indirect => AccessFlags::INDIRECT_COMMAND_READ,
+            BufferAccess::Uniform => AccessFlags::UNIFORM_READ,
+            BufferAccess::StorageRead => AccessFlags::SHADER_READ,
+            BufferAccess::StorageWrite => AccessFlags::SHADER_READ | AccessFlags::SHADER_WRITE,
+            BufferAccess::TransferSrc => AccessFlags::TRANSFER_READ,
+            BufferAccess::TransferDst => AccessFlags::TRANSFER_WRITE,
+        }
+    }
+
+    fn usage(self) -> BufferUsageFlags {
+        match self {
+            BufferAccess::Vertex => BufferUsageFlags::VERTEX_BUFFER,
+            BufferAccess::Index => BufferUsageFlags::INDEX_BUFFER,
+            BufferAccess::Indirect => BufferUsageFlags::INDIRECT_BUFFER,
+            BufferAccess::Uniform => BufferUsageFlags::UNIFORM_BUFFER,
+            BufferAccess::StorageRead | BufferAccess::StorageWrite => {
+                BufferUsageFlags::STORAGE_BUFFER
+            }
+            BufferAccess::TransferSrc => BufferUsageFlags::TRANSFER_SRC,
+            BufferAccess::TransferDst => BufferUsageFlags::TRANSFER_DST,
+        }
+    }
+}
+
+/// Shader stages a shader access may come from.
+const SHADER_STAGES: PipelineStageFlags = PipelineStageFlags::from_raw(
+    PipelineStageFlags::VERTEX_SHADER.as_raw()
+        | PipelineStageFlags::FRAGMENT_SHADER.as_raw()
+        | PipelineStageFlags::COMPUTE_SHADER.as_raw(),
+);
+
+#[derive(Debug, Clone, Copy)]
+struct ImportedImage {
+    image: Image,
+    view: ImageView,
+    layout: ImageLayout,
+}
+
+struct ImageResource {
+    name: String,
+    desc: ImageDesc,
+    imported: Option<ImportedImage>,
+}
+
+struct BufferResource {
+    name: String,
+    desc: BufferDesc,
+    imported: Option<Buffer>,
+}
+
+type PassFn<'a> = Box<dyn FnOnce(&PassContext) -> Result<()> + 'a>;
+
+struct Pass<'a> {
+    name: String,
+    images: Vec<(ImageId, ImageAccess)>,
+    buffers: Vec<(BufferId, BufferAccess)>,
+    side_effect: bool,
+    run: Option<PassFn<'a>>,
+}
+
+impl Pass<'_> {
+    fn resources(&self) -> impl Iterator<Item = (Resource, bool)> + '_ {
+        let images = self
+            .images
+            .iter()
+            .map(|(id, access)| (Resource::Image(*id), access.is_write()));
+        let buffers = self
+            .buffers
+            .iter()
+            .map(|(id, access)| (Resource::Buffer(*id), access.is_write()));
+        images.chain(buffers)
+    }
+}
+
+/// Passes of one frame, built anew every frame and consumed by `execute`.
+#[derive(Default)]
+pub struct RenderGraph<'a> {
+    passes: Vec<Pass<'a>>,
+    images: Vec<ImageResource>,
+    buffers: Vec<BufferResource>,
+    outputs: HashSet<Resource>,
+}
+
+impl<'a> RenderGraph<'a> {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// An image living only inside the graph. Its memory comes from the `TransientPool` and
+    /// may be shared with other transient resources whose lifetimes don't overlap.
+    pub fn create_image(&mut self, name: &str, desc: ImageDesc) -> ImageId {
+        self.images.push(ImageResource {
+            name: name.to_owned(),
+            desc,
+            imported: None,
+        });
+        ImageId(self.images.len() - 1)
+    }
+
+    /// An image owned outside the graph, e.g. the swapchain image of a `Frame`. It must be
+    /// in `layout` when the graph runs and is returned to it afterwards.
+    pub fn import_image(
+        &mut self,
+        name: &str,
+        image: Image,
+        view: ImageView,
+        desc: ImageDesc,
+        layout: ImageLayout,
+    ) -> ImageId {
+        self.images.push(ImageResource {
+            name: name.to_owned(),
+            desc,
+            imported: Some(ImportedImage {
+                image,
+                view,
+                layout,
+            }),
+        });
+        ImageId(self.images.len() - 1)
+    }
+
+    /// A buffer living only inside the graph, see `create_image`.
+    pub fn create_buffer(&mut self, name: &str, desc: BufferDesc) -> BufferId {
+        self.buffers.push(BufferResource {
+            name: name.to_owned(),
+            desc,
+            imported: None,
+        });
+        BufferId(self.buffers.len() - 1)
+    }
+
+    /// A buffer owned outside the graph, e.g. a vertex buffer.
+    pub fn import_buffer(&mut self, name: &str, buffer: Buffer, desc: BufferDesc) -> BufferId {
+        self.buffers.push(BufferResource {
+            name: name.to_owned(),
+            desc,
+            imported: Some(buffer),
+        });
+        BufferId(self.buffers.len() - 1)
+    }
+
+    /// Keeps the passes writing `resource` from being culled. Imported resources are
+    /// always outputs.
+    pub fn mark_output(&mut self, resource: impl Into<Resource>) {
+        self.outputs.insert(resource.into());
+    }
+
+    pub fn image_desc(&self, id: ImageId) -> ImageDesc {
+        self.images[id.0].desc
+    }
+
+    pub fn buffer_desc(&self, id: BufferId) -> BufferDesc {
+        self.buffers[id.0].desc
+    }
+
+    /// Adds a pass; declare its resources and its commands on the returned builder.
+    pub fn add_pass(&mut self, name: &str) -> PassBuilder<'_, 'a> {
+        self.passes.push(Pass {
+            name: name.to_owned(),
+            images: Vec::new(),
+            buffers: Vec::new(),
+            side_effect: false,
+            run: None,
+        });
+        PassBuilder {
+            pass: self.passes.last_mut().unwrap(),
+        }
+    }
+
+    fn is_imported(&self, resource: Resource) -> bool {
+        match resource {
+            Resource::Image(id) => self.images[id.0].imported.is_some(),
+            Resource::Buffer(id) => self.buffers[id.0].imported.is_some(),
+        }
+    }
+
+    fn resource_name(&self, resource: Resource) -> &str {
+        match resource {
+            Resource::Image(id) => &self.images[id.0].name,
+            Resource::Buffer(id) => &self.buffers[id.0].name,
+        }
+    }
+
+    /// The passes each pass must run after, by pass index, see the module documentation.
+    /// The flag is set when the pass uses what the other one wrote, and clear when it only
+    /// has to wait for the other one to be done reading.
+    fn dependencies(&self) -> Vec<Vec<(usize, bool)>> {
+        let mut last_writers = HashMap::new();
+        for (index, pass) in self.passes.iter().enumerate() {
+            for (resource, write) in pass.resources() {
+                if write {
+                    last_writers.insert(resource, index);
+                }
+            }
+        }
+
+        let mut dependencies = vec![Vec::new(); self.passes.len()];
+        // The latest write of every resource so far and the passes reading it.
+        let mut versions: HashMap<Resource, (Option<usize>, Vec<usize>)> = HashMap::new();
+        for (index, pass) in self.passes.iter().enumerate() {
+            for (resource, write) in pass.resources() {
+                let (writer, readers) = versions.entry(resource).or_default();
+                if write {
+                    dependencies[index].extend(writer.map(|writer| (writer, true)));
+                    dependencies[index].extend(readers.drain(..).map(|reader| (reader, false)));
+                    *writer = Some(index);
+                } else if let Some(writer) = *writer {
+                    dependencies[index].push((writer, true));
+                    readers.push(index);
+                } else if self.is_imported(resource) {
+                    // Reads what the image or buffer held before the graph ran.
+                    readers.push(index);
+                } else if let Some(&writer) = last_writers.get(&resource) {
+                    dependencies[index].push((writer, true));
+                }
+            }
+            let pass_dependencies = &mut dependencies[index];
+            pass_dependencies.retain(|(other, _)| *other != index);
+            // A pass both using and waiting for another one uses it.
+            pass_dependencies.sort_by_key(|(other, data)| (*other, !*data));
+            pass_dependencies.dedup_by_key(|(other, _)| *other);
+        }
+        dependencies
+    }
+
+    /// Which passes survive culling, by pass index.
+    fn kept(&self, dependencies: &[Vec<(usize, bool)>]) -> Vec<bool> {
+        let mut kept = vec![false; self.passes.len()];
+        let mut stack: Vec<usize> = self
+            .passes
+            .iter()
+            .enumerate()
+            .filter(|(_, pass)| {
+                pass.side_effect
+                    || pass.resources().any(|(resource, write)| {
+                        write && (self.outputs.contains(&resource) || self.is_imported(resource))
+                    })
+            })
+            .map(|(index, _)| index)
+            .collect();
+        while let Some(index) = stack.pop() {
+            if kept[index] {
+                continue;
+            }
+            kept[index] = true;
+            stack.extend(
+                dependencies[index]
+                    .iter()
+                    .filter(|(_, data)| *data)
+                    .map(|(other, _)| *other),
+            );
+        }
+        kept
+    }
+
+    /// Indices of the kept passes in an order satisfying their dependencies. Among passes
+    /// ready to run, the one added first goes first.
+    fn schedule(&self) -> Result<Vec<usize>> {
+        let dependencies = self.dependencies();
+        let kept = self.kept(&dependencies);
+
+        let mut waiting = vec![0; self.passes.len()];
+        let mut dependents = vec![Vec::new(); self.passes.len()];
+        let mut ready = BinaryHeap::new();
+        for (index, pass) in self.passes.iter().enumerate() {
+            if !kept[index] {
+                log::trace!("render graph pass {} culled", pass.name);
+                continue;
+            }
+            for (resource, write) in pass.resources() {
+                let written = || {
+                    self.passes.iter().any(|other| {
+                        other
+                            .resources()
+                            .any(|(other_resource, write)| write && other_resource == resource)
+                    })
+                };
+                if !write && !self.is_imported(resource) && !written() {
+                    return Err(Error::Other(format!(
+                        "Pass {} reads {}, which no pass writes",
+                        pass.name,
+                        self.resource_name(resource)
+                    )));
+                }
+            }
+            for (other, _) in &dependencies[index] {
+                if kept[*other] {
+                    waiting[index] += 1;
+                    dependents[*other].push(index);
+                }
+            }
+            if waiting[index] == 0 {
+                ready.push(Reverse(index));
+            }
+        }
+
+        let mut order = Vec::new();
+        while let Some(Reverse(index)) = ready.pop() {
+            order.push(index);
+            for dependent in &dependents[index] {
+                waiting[*dependent] -= 1;
+                if waiting[*dependent] == 0 {
+                    ready.push(Reverse(*dependent));
+                }
+            }
+        }
+        if order.len() < kept.iter().filter(|kept| **kept).count() {
+            let cycle: Vec<&str> = (0..self.passes.len())
+                .filter(|index| kept[*index] && waiting[*index] > 0)
+                .map(|index| self.passes[index].name.as_str())
+                .collect();
+            return Err(Error::Other(format!(
+                "Passes {} depend on each other",
+                cycle.join(", ")
+            )));
+        }
+        Ok(order)
+    }
+
+    /// Transient resources used by the scheduled passes, with their lifetimes as positions
+    /// in `order`.
+    fn requests(&self, order: &[usize]) -> (Vec<Request>, HashMap<Resource, usize>) {
+        let mut requests: Vec<Request> = Vec::new();
+        let mut indices = HashMap::new();
+        for (position, &index) in order.iter().enumerate() {
+            let pass = &self.passes[index];
+            for (id, access) in &pass.images {
+                if self.images[id.0].imported.is_some() {
+                    continue;
+                }
+                let request = *indices.entry(Resource::Image(*id)).or_insert_with(|| {
+                    let desc = self.images[id.0].desc;
+                    requests.push(Request {
+                        kind: RequestKind::Image {
+                            desc,
+                            aspect: format_aspect(desc.format),
+                        },
+                        first: position,
+                        last: position,
+                    });
+                    requests.len() - 1
+                });
+                requests[request].last = position;
+                if let RequestKind::Image { desc, .. } = &mut requests[request].kind {
+                    desc.usage |= access.usage();
+                }
+            }
+            for (id, access) in &pass.buffers {
+                if self.buffers[id.0].imported.is_some() {
+                    continue;
+                }
+                let request = *indices.entry(Resource::Buffer(*id)).or_insert_with(|| {
+                    requests.push(Request {
+                        kind: RequestKind::Buffer {
+                            desc: self.buffers[id.0].desc,
+                        },
+                        first: position,
+                        last: position,
+                    });
+                    requests.len() - 1
+                });
+                requests[request].last = position;
+                if let RequestKind::Buffer { desc } = &mut requests[request].kind {
+                    desc.usage |= access.usage();
+                }
+            }
+        }
+        (requests, indices)
+    }
+
+    /// Records the graph into `command_buffer`. `slot` picks the set of transient resources
+    /// in `pool`; the previous submission using the same slot must be finished, which holds
+    /// for `Frame::slot` after `begin_frame`.
+    pub fn execute(
+        self,
+        context: &Context,
+        command_buffer: CommandBuffer,
+        slot: usize,
+        pool: &mut TransientPool,
+    ) -> Result<()> {
+        let order = self.schedule()?;
+        let (requests, indices) = self.requests(&order);
+        let allocation = pool.prepare(context, slot, requests)?;
+
+        let images: Vec<(Image, ImageView)> = self
+            .images
+            .iter()
+            .enumerate()
+            .map(|(index, resource)| match resource.imported {
+                Some(imported) => (imported.image, imported.view),
+                None => match indices.get(&Resource::Image(ImageId(index))) {
+                    Some(request) => match allocation.resources[*request] {
+                        Allocated::Image(image, view) => (image, view),
+                        Allocated::Buffer(_) => unreachable!(),
+                    },
+                    None => (Image::null(), ImageView::null()),
+                },
+            })
+            .collect();
+        let buffers: Vec<Buffer> = self
+            .buffers
+            .iter()
+            .enumerate()
+            .map(|(index, resource)| match resource.imported {
+                Some(buffer) => buffer,
+                None => match indices.get(&Resource::Buffer(BufferId(index))) {
+                    Some(request) => match allocation.resources[*request] {
+                        Allocated::Buffer(buffer) => buffer,
+                        Allocated::Image(..) => unreachable!(),
+                    },
+                    None => Buffer::null(),
+                },
+            })
+            .collect();
+
+        // Work before the graph on imported resources is only known to be submitted, so the
+        // first access waits for everything.
+        let outside = State {
+            layout: ImageLayout::UNDEFINED,
+            write_stages: PipelineStageFlags::ALL_COMMANDS,
+            write_access: AccessFlags::MEMORY_WRITE,
+            read_stages: PipelineStageFlags::empty(),
+            read_access: AccessFlags::empty(),
+        };
+        let mut image_states: Vec<State> = self
+            .images
+            .iter()
+            .map(|resource| match resource.imported {
+                Some(imported) => State {
+                    layout: imported.layout,
+                    ..outside
+                },
+                None => State::default(),
+            })
+            .collect();
+        let mut buffer_states: Vec<State> = self
+            .buffers
+            .iter()
+            .map(|resource| match resource.imported {
+                Some(_) => outside,
+                None => State::default(),
+            })
+            .collect();
+        let mut touched = HashSet::new();
+        let mut written = HashSet::new();
+        // Last resource placed in each memory block, whose accesses the next one must wait on.
+        let mut block_last: HashMap<usize, Resource> = HashMap::new();
+        let mut first_use = |resource: Resource,
+                             image_states: &[State],
+                             buffer_states: &[State]|
+         -> Option<State> {
+            let request = indices.get(&resource)?;
+            let previous = block_last.insert(allocation.blocks[*request], resource)?;
+            let state = match previous {
+                Resource::Image(id) => image_states[id.0],
+                Resource::Buffer(id) => buffer_states[id.0],
+            };
+            Some(State {
+                layout: ImageLayout::UNDEFINED,
+                write_stages: state.write_stages | state.read_stages,
+                write_access: state.write_access,
+                ..State::default()
+            })
+        };
+
+        let device = &context.device;
+        let mut passes: Vec<Option<Pass>> = self.passes.into_iter().map(Some).collect();
+        for index in order {
+            let pass = passes[index].take().unwrap();
+            let mut barriers = Barriers::default();
+
+            let mut layouts = HashMap::new();
+            for (id, access) in &pass.images {
+                if *layouts.entry(*id).or_insert(access.layout()) != access.layout() {
+                    return Err(Error::Other(format!(
+                        "Pass {} uses {} in two layouts",
+                        pass.name, self.images[id.0].name
+                    )));
+                }
+                let resource = Resource::Image(*id);
+                if touched.insert(resource) {
+                    if let Some(state) = first_use(resource, &image_states, &buffer_states) {
+                        image_states[id.0] = state;
+                    }
+                }
+                if access.is_write() {
+                    written.insert(resource);
+                }
+                let range = range(self.images[id.0].desc.format);
+                if let Some(barrier) = image_states[id.0].access(
+                    access.stages(),
+                    access.access(),
+                    access.layout(),
+                    access.is_write(),
+                ) {
+                    barriers.image(images[id.0].0, range, barrier);
+                }
+            }
+            for (id, access) in &pass.buffers {
+                let resource = Resource::Buffer(*id);
+                if touched.insert(resource) {
+                    if let Some(state) = first_use(resource, &image_states, &buffer_states) {
+                        buffer_states[id.0] = state;
+                    }
+                }
+                if access.is_write() {
+                    written.insert(resource);
+                }
+                if let Some(barrier) = buffer_states[id.0].access(
+                    access.stages(),
+                    access.access(),
+                    ImageLayout::UNDEFINED,
+                    access.is_write(),
+                ) {
+                    barriers.buffer(buffers[id.0], barrier);
+                }
+            }
+            barriers.record(device, command_buffer);
+
+            if let Some(run) = pass.run {
+                run(&PassContext {
+                    context,
+                    command_buffer,
+                    images: &images,
+                    descs: &self.images,
+                    buffers: &buffers,
+                })?;
+            }
+        }
+
+        // Hands imported resources back in their original layout, with the graph's writes
+        // visible to whatever comes next.
+        let mut barriers = Barriers::default();
+        for (index, resource) in self.images.iter().enumerate() {
+            let Some(imported) = resource.imported else {
+                continue;
+            };
+            let state = image_states[index];
+            if state.layout != imported.layout || written.contains(&Resource::Image(ImageId(index)))
+            {
+                barriers.image(
+                    imported.image,
+                    range(resource.desc.format),
+                    Barrier {
+                        src_stages: state.write_stages | state.read_stages,
+                        src_access: state.write_access,
+                        dst_stages: PipelineStageFlags::ALL_COMMANDS,
+                        dst_access: AccessFlags::MEMORY_READ | AccessFlags::MEMORY_WRITE,
+                        old_layout: state.layout,
+                        new_layout: imported.layout,
+                    },
+                );
+            }
+        }
+        for (index, resource) in self.buffers.iter().enumerate() {
+            let Some(buffer) = resource.imported else {
+                continue;
+            };
+            if written.contains(&Resource::Buffer(BufferId(index))) {
+                let state = buffer_states[index];
+                barriers.buffer(
+                    buffer,
+                    Barrier {
+                        src_stages: state.write_stages | state.read_stages,
+                        src_access: state.write_access,
+                        dst_stages: PipelineStageFlags::ALL_COMMANDS,
+                        dst_access: AccessFlags::MEMORY_READ | AccessFlags::MEMORY_WRITE,
+                        old_layout: ImageLayout::UNDEFINED,
+                        new_layout: ImageLayout::UNDEFINED,
+                    },
+                );
+            }
+        }
+        barriers.record(device, command_buffer);
+        Ok(())
+    }
+}
+
+/// Declares what a pass accesses and records its commands.
+pub struct PassBuilder<'g, 'a> {
+    pass: &'g mut Pass<'a>,
+}
+
+impl<'a> PassBuilder<'_, 'a> {
+    pub fn image(self, id: ImageId, access: ImageAccess) -> Self {
+        self.pass.images.push((id, access));
+        self
+    }
+
+    pub fn buffer(self, id: BufferId, access: BufferAccess) -> Self {
+        self.pass.buffers.push((id, access));
+        self
+    }
+
+    /// Keeps the pass even when nothing uses what it writes, e.g. for a readback.
+    pub fn side_effect(self) -> Self {
+        self.pass.side_effect = true;
+        self
+    }
+
+    /// Commands of the pass, recorded once the barriers for its resources are in place.
+    pub fn execute(self, run: impl FnOnce(&PassContext) -> Result<()> + 'a) {
+        self.pass.run = Some(Box::new(run));
+    }
+}
+
+/// What a pass gets to record its commands with.
+pub struct PassContext<'c> {
+    context: &'c Context,
+    command_buffer: CommandBuffer,
+    images: &'c [(Image, ImageView)],
+    descs: &'c [ImageResource],
+    buffers: &'c [Buffer],
+}
+
+impl PassContext<'_> {
+    pub fn context(&self) -> &Context {
+        self.context
+    }
+
+    pub fn device(&self) -> &Device {
+        &self.context.device
+    }
+
+    pub fn command_buffer(&self) -> CommandBuffer {
+        self.command_buffer
+    }
+
+    pub fn image(&self, id: ImageId) -> Image {
+        self.images[id.0].0
+    }
+
+    pub fn image_view(&self, id: ImageId) -> ImageView {
+        self.images[id.0].1
+    }
+
+    pub fn image_desc(&self, id: ImageId) -> ImageDesc {
+        self.descs[id.0].desc
+    }
+
+    pub fn buffer(&self, id: BufferId) -> Buffer {
+        self.buffers[id.0]
+    }
+}
+
+/// Where the last accesses to a resource happened.
+#[derive(Debug, Clone, Copy, Default)]
+struct State {
+    layout: ImageLayout,
+    write_stages: PipelineStageFlags,
+    write_access: AccessFlags,
+    /// Reads since the last write that already wait for it.
+    read_stages: PipelineStageFlags,
+    read_access: AccessFlags,
+}
+
+impl State {
+    /// Moves to a new access, returning the barrier needed before it, if any.
+    fn access(
+        &mut self,
+        stages: PipelineStageFlags,
+        access: AccessFlags,
+        layout: ImageLayout,
+        write: bool,
+    ) -> Option<Barrier> {
+        let transition = self.layout != layout;
+        let hazard = if write {
+            !self.write_stages.is_empty() || !self.read_stages.is_empty()
+        } else {
+            let synced = self.read_stages.contains(stages) && self.read_access.contains(access);
+            !self.write_stages.is_empty() && !synced
+        };
+
+        let barrier = (transition || hazard).then(|| Barrier {
+            src_stages: if write || transition {
+                self.write_stages | self.read_stages
+            } else {
+                self.write_stages
+            },
+            src_access: self.write_access,
+            dst_stages: stages,
+            dst_access: access,
+            old_layout: self.layout,
+            new_layout: layout,
+        });
+
+        if write {
+            *self = State {
+                layout,
+                write_stages: stages,
+                write_access: access,
+                ..State::default()
+            };
+        } else if transition {
+            // The transition is a write the following reads have to wait for.
+            *self = State {
+                layout,
+                write_stages: stages,
+                write_access: AccessFlags::empty(),
+                read_stages: stages,
+                read_access: access,
+            };
+        } else {
+            self.read_stages |= stages;
+            self.read_access |= access;
+        }
+        barrier
+    }
+}
+
+#[derive(Debug, Clone, Copy)]
+struct Barrier {
+    src_stages: PipelineStageFlags,
+    src_access: AccessFlags,
+    dst_stages: PipelineStageFlags,
+    dst_access: AccessFlags,
+    old_layout: ImageLayout,
+    new_layout: ImageLayout,
+}
+
+/// Barriers recorded together with one `cmd_pipeline_barrier`.
+#[derive(Default)]
+struct Barriers {
+    src_stages: PipelineStageFlags,
+    dst_stages: PipelineStageFlags,
+    images: Vec<ImageMemoryBarrier<'static>>,
+    buffers: Vec<BufferMemoryBarrier<'static>>,
+}
+
+impl Barriers {
+    fn image(&mut self, image: Image, range: ImageSubresourceRange, barrier: Barrier) {
+        self.src_stages |= barrier.src_stages;
+        self.dst_stages |= barrier.dst_stages;
+        self.images.push(
+            ImageMemoryBarrier::default()
+                .old_layout(barrier.old_layout)
+                .new_layout(barrier.new_layout)
+                .src_access_mask(barrier.src_access)
+                .dst_access_mask(barrier.dst_access)
+                .src_queue_family_index(QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(QUEUE_FAMILY_IGNORED)
+                .image(image)
+                .subresource_range(range),
+        );
+    }
+
+    fn buffer(&mut self, buffer: Buffer, barrier: Barrier) {
+        self.src_stages |= barrier.src_stages;
+        self.dst_stages |= barrier.dst_stages;
+        self.buffers.push(
+            BufferMemoryBarrier::default()
+                .src_access_mask(barrier.src_access)
+                .dst_access_mask(barrier.dst_access)
+                .src_queue_family_index(QUEUE_FAMILY_IGNORED)
+                .dst_queue_family_index(QUEUE_FAMILY_IGNORED)
+                .buffer(buffer)
+                .offset(0)
+                .size(WHOLE_SIZE),
+        );
+    }
+
+    fn record(self, device: &Device, command_buffer: CommandBuffer) {
+        if self.images.is_empty() && self.buffers.is_empty() {
+            return;
+        }
+        let src_stages = if self.src_stages.is_empty() {
+            PipelineStageFlags::TOP_OF_PIPE
+        } else {
+            self.src_stages
+        };
+        unsafe {
+            device.cmd_pipeline_barrier(
+                command_buffer,
+                src_stages,
+                self.dst_stages,
+                DependencyFlags::empty(),
+                &[],
+                &self.buffers,
+                &self.images,
+            );
+        }
+    }
+}
+
+fn range(format: Format) -> ImageSubresourceRange {
+    ImageSubresourceRange::default()
+        .aspect_mask(format_aspect(format))
+        .base_mip_level(0)
+        .level_count(1)
+        .base_array_layer(0)
+        .layer_count(1)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn desc() -> ImageDesc {
+        ImageDesc::new(
+            Format::R8G8B8A8_UNORM,
+            Extent2D {
+                width: 1,
+                height: 1,
+            },
+        )
+    }
+
+    fn swapchain(graph: &mut RenderGraph) -> ImageId {
+        graph.import_image(
+            "swapchain",
+            Image::null(),
+            ImageView::null(),
+            desc(),
+            ImageLayout::UNDEFINED,
+        )
+    }
+
+    fn pass(graph: &mut RenderGraph, name: &str, images: &[(ImageId, ImageAccess)]) {
+        let mut builder = graph.add_pass(name);
+        for (id, access) in images {
+            builder = builder.image(*id, *access);
+        }
+        builder.execute(|_| Ok(()));
+    }
+
+    fn names(graph: &RenderGraph) -> Vec<String> {
+        let order = graph.schedule().unwrap();
+        order
+            .into_iter()
+            .map(|index| graph.passes[index].name.clone())
+            .collect()
+    }
+
+    #[test]
+    fn producers_run_before_consumers_added_first() {
+        let mut graph = RenderGraph::new();
+        let output = swapchain(&mut graph);
+        let gbuffer = graph.create_image("gbuffer", desc());
+        let shadows = graph.create_image("shadows", desc());
+        pass(
+            &mut graph,
+            "lighting",
+            &[
+                (gbuffer, ImageAccess::Sampled),
+                (shadows, ImageAccess::Sampled),
+                (output, ImageAccess::ColorAttachment),
+            ],
+        );
+        pass(
+            &mut graph,
+            "shadows",
+            &[(shadows, ImageAccess::DepthAttachment)],
+        );
+        pass(
+            &mut graph,
+            "gbuffer",
+            &[(gbuffer, ImageAccess::ColorAttachment)],
+        );
+        assert_eq!(names(&graph), ["shadows", "gbuffer", "lighting"]);
+    }
+
+    #[test]
+    fn writes_keep_their_order_and_wait_for_readers() {
+        let mut graph = RenderGraph::new();
+        let output = swapchain(&mut graph);
+        let color = graph.create_image("color", desc());
+        pass(&mut graph, "clear", &[(color, ImageAccess::TransferDst)]);
+        pass(&mut graph, "draw", &[(color, ImageAccess::ColorAttachment)]);
+        pass(
+            &mut graph,
+            "blit",
+            &[
+                (color, ImageAccess::TransferSrc),
+                (output, ImageAccess::TransferDst),
+            ],
+        );
+        pass(
+            &mut graph,
+            "overlay",
+            &[(output, ImageAccess::ColorAttachment)],
+        );
+        assert_eq!(names(&graph), ["clear", "draw", "blit", "overlay"]);
+    }
+
+    #[test]
+    fn unused_passes_are_culled() {
+        let mut graph = RenderGraph::new();
+        let output = swapchain(&mut graph);
+        let debug = graph.create_image("debug", desc());
+        let color = graph.create_image("color", desc());
+        pass(
+            &mut graph,
+            "scene",
+            &[(color, ImageAccess::ColorAttachment)],
+        );
+        pass(
+            &mut graph,
+            "debug",
+            &[
+                (color, ImageAccess::Sampled),
+                (debug, ImageAccess::ColorAttachment),
+            ],
+        );
+        // Has to wait for `debug` to read `color`, which mustn't keep `debug` alive.
+        pass(&mut graph, "post", &[(color, ImageAccess::StorageWrite)]);
+        pass(
+            &mut graph,
+            "present",
+            &[
+                (color, ImageAccess::Sampled),
+                (output, ImageAccess::ColorAttachment),
+            ],
+        );
+        assert_eq!(names(&graph), ["scene", "post", "present"]);
+    }
+
+    #[test]
+    fn unwritten_reads_and_cycles_fail() {
+        let mut graph = RenderGraph::new();
+        let output = swapchain(&mut graph);
+        let missing = graph.create_image("missing", desc());
+        pass(
+            &mut graph,
+            "present",
+            &[
+                (missing, ImageAccess::Sampled),
+                (output, ImageAccess::ColorAttachment),
+            ],
+        );
+        assert!(graph.schedule().is_err());
+
+        let mut graph = RenderGraph::new();
+        let output = swapchain(&mut graph);
+        let a = graph.create_image("a", desc());
+        let b = graph.create_image("b", desc());
+        pass(
+            &mut graph,
+            "first",
+            &[(b, ImageAccess::Sampled), (a, ImageAccess::ColorAttachment)],
+        );
+        pass(
+            &mut graph,
+            "second",
+            &[(a, ImageAccess::Sampled), (b, ImageAccess::ColorAttachment)],
+        );
+        pass(
+            &mut graph,
+            "present",
+            &[
+                (b, ImageAccess::Sampled),
+                (output, ImageAccess::ColorAttachment),
+            ],
+        );
+        assert!(graph.schedule().is_err());
+    }
+}
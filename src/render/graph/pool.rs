@@ -0,0 +1,242 @@
+use ash::vk::{
+    Buffer, BufferCreateInfo, DeviceMemory, Extent3D, Image, ImageAspectFlags, ImageCreateInfo,
+    ImageLayout, ImageSubresourceRange, ImageTiling, ImageType, ImageView, ImageViewCreateInfo,
+    ImageViewType, MemoryAllocateInfo, MemoryPropertyFlags, MemoryRequirements, SharingMode,
+};
+
+use crate::{error::Result, vulkan::Context, Error};
+
+use super::{BufferDesc, ImageDesc};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) enum RequestKind {
+    Image {
+        desc: ImageDesc,
+        aspect: ImageAspectFlags,
+    },
+    Buffer {
+        desc: BufferDesc,
+    },
+}
+
+/// A transient resource and the positions of the first and the last pass using it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(super) struct Request {
+    pub kind: RequestKind,
+    pub first: usize,
+    pub last: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+pub(super) enum Allocated {
+    Image(Image, ImageView),
+    Buffer(Buffer),
+}
+
+/// Transient resources of one slot, kept while the graph asks for the same ones.
+#[derive(Default)]
+pub(super) struct Allocation {
+    requests: Vec<Request>,
+    pub resources: Vec<Allocated>,
+    /// Memory block of each resource; resources in the same block alias.
+    pub blocks: Vec<usize>,
+    memory: Vec<DeviceMemory>,
+}
+
+struct Block {
+    size: u64,
+    type_bits: u32,
+    lifetimes: Vec<(usize, usize)>,
+}
+
+impl Allocation {
+    fn create(&mut self, context: &Context, requests: Vec<Request>) -> Result<()> {
+        let device = &context.device;
+        let mut requirements: Vec<MemoryRequirements> = Vec::with_capacity(requests.len());
+        for request in &requests {
+            match request.kind {
+                RequestKind::Image { desc, .. } => {
+                    let image_create_info = ImageCreateInfo::default()
+                        .image_type(ImageType::TYPE_2D)
+                        .format(desc.format)
+                        .extent(Extent3D {
+                            width: desc.extent.width,
+                            height: desc.extent.height,
+                            depth: 1,
+                        })
+                        .mip_levels(1)
+                        .array_layers(1)
+                        .samples(desc.samples)
+                        .tiling(ImageTiling::OPTIMAL)
+                        .usage(desc.usage)
+                        .sharing_mode(SharingMode::EXCLUSIVE)
+                        .initial_layout(ImageLayout::UNDEFINED);
+                    let image = unsafe { device.create_image(&image_create_info, None) }
+                        .map_err(Error::Vulkan)?;
+                    self.resources
+                        .push(Allocated::Image(image, ImageView::null()));
+                    requirements.push(unsafe { device.get_image_memory_requirements(image) });
+                }
+                RequestKind::Buffer { desc } => {
+                    let buffer_create_info = BufferCreateInfo::default()
+                        .size(desc.size)
+                        .usage(desc.usage)
+                        .sharing_mode(SharingMode::EXCLUSIVE);
+                    let buffer = unsafe { device.create_buffer(&buffer_create_info, None) }
+                        .map_err(Error::Vulkan)?;
+                    self.resources.push(Allocated::Buffer(buffer));
+                    requirements.push(unsafe { device.get_buffer_memory_requirements(buffer) });
+                }
+            }
+        }
+
+        // First fit by first use: a resource joins a block when none of the block's
+        // resources is alive at the same time and a memory type suits them all. Everything
+        // is bound at offset 0, so alignment always holds.
+        let mut order: Vec<usize> = (0..requests.len()).collect();
+        order.sort_by_key(|index| requests[*index].first);
+        let mut blocks: Vec<Block> = Vec::new();
+        self.blocks = vec![0; requests.len()];
+        for index in order {
+            let (first, last) = (requests[index].first, requests[index].last);
+            let requirement = requirements[index];
+            let fits = |block: &Block| {
+                block
+                    .lifetimes
+                    .iter()
+                    .all(|(start, end)| last < *start || first > *end)
+                    && context
+                        .memory_type(
+                            block.type_bits & requirement.memory_type_bits,
+                            MemoryPropertyFlags::DEVICE_LOCAL,
+                        )
+                        .is_ok()
+            };
+            let block = match blocks.iter().position(fits) {
+                Some(block) => block,
+                None => {
+                    blocks.push(Block {
+                        size: 0,
+                        type_bits: u32::MAX,
+                        lifetimes: Vec::new(),
+                    });
+                    blocks.len() - 1
+                }
+            };
+            blocks[block].size = blocks[block].size.max(requirement.size);
+            blocks[block].type_bits &= requirement.memory_type_bits;
+            blocks[block].lifetimes.push((first, last));
+            self.blocks[index] = block;
+        }
+
+        for block in &blocks {
+            let allocate_info = MemoryAllocateInfo::default()
+                .allocation_size(block.size)
+                .memory_type_index(
+                    context.memory_type(block.type_bits, MemoryPropertyFlags::DEVICE_LOCAL)?,
+                );
+            let memory =
+                unsafe { device.allocate_memory(&allocate_info, None) }.map_err(Error::Vulkan)?;
+            self.memory.push(memory);
+        }
+
+        for (index, request) in requests.iter().enumerate() {
+            let memory = self.memory[self.blocks[index]];
+            match (&mut self.resources[index], request.kind) {
+                (Allocated::Image(image, view), RequestKind::Image { desc, aspect }) => {
+                    unsafe { device.bind_image_memory(*image, memory, 0) }
+                        .map_err(Error::Vulkan)?;
+                    let view_create_info = ImageViewCreateInfo::default()
+                        .image(*image)
+                        .view_type(ImageViewType::TYPE_2D)
+                        .format(desc.format)
+                        .subresource_range(
+                            ImageSubresourceRange::default()
+                                .aspect_mask(aspect)
+                                .base_mip_level(0)
+                                .level_count(1)
+                                .base_array_layer(0)
+                                .layer_count(1),
+                        );
+                    *view = unsafe { device.create_image_view(&view_create_info, None) }
+                        .map_err(Error::Vulkan)?;
+                }
+                (Allocated::Buffer(buffer), RequestKind::Buffer { .. }) => {
+                    unsafe { device.bind_buffer_memory(*buffer, memory, 0) }
+                        .map_err(Error::Vulkan)?;
+                }
+                _ => unreachable!(),
+            }
+        }
+
+        log::trace!(
+            "render graph allocated {} transient resources in {} blocks",
+            requests.len(),
+            blocks.len()
+        );
+        self.requests = requests;
+        Ok(())
+    }
+
+    fn destroy(&mut self, context: &Context) {
+        let device = &context.device;
+        unsafe {
+            for resource in self.resources.drain(..) {
+                match resource {
+                    Allocated::Image(image, view) => {
+                        if view != ImageView::null() {
+                            device.destroy_image_view(view, None);
+                        }
+                        device.destroy_image(image, None);
+                    }
+                    Allocated::Buffer(buffer) => device.destroy_buffer(buffer, None),
+                }
+            }
+            for memory in self.memory.drain(..) {
+                device.free_memory(memory, None);
+            }
+        }
+        self.requests.clear();
+        self.blocks.clear();
+    }
+}
+
+/// Memory for the transient resources of render graphs, one set per frame in flight. While
+/// the graph of a slot asks for the same resources, they are reused across frames.
+#[derive(Default)]
+pub struct TransientPool {
+    slots: Vec<Allocation>,
+}
+
+impl TransientPool {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub(super) fn prepare(
+        &mut self,
+        context: &Context,
+        slot: usize,
+        requests: Vec<Request>,
+    ) -> Result<&Allocation> {
+        if self.slots.len() <= slot {
+            self.slots.resize_with(slot + 1, Default::default);
+        }
+        let allocation = &mut self.slots[slot];
+        if allocation.requests != requests {
+            allocation.destroy(context);
+            if let Err(error) = allocation.create(context, requests) {
+                allocation.destroy(context);
+                return Err(error);
+            }
+        }
+        Ok(allocation)
+    }
+
+    /// The device must be done with every graph executed with this pool.
+    pub fn destroy(&mut self, context: &Context) {
+        for allocation in self.slots.iter_mut() {
+            allocation.destroy(context);
+        }
+    }
+}
@@ -0,0 +1,411 @@
+//! Meshes in vertex and index buffers, with vertex formats described by their Rust types.
+//!
+//! A vertex is a `#[repr(C)]` struct of attribute types like `[f32; 3]`; `impl_vertex!`
+//! maps its fields to shader locations:
+//!
+//! ```ignore
+//! #[repr(C)]
+//! #[derive(Clone, Copy)]
+//! struct ColorVertex {
+//!     position: [f32; 2],
+//!     color: [f32; 3],
+//! }
+//! alovak::impl_vertex!(ColorVertex { position: 0, color: 1 });
+//!
+//! let mesh = Mesh::indexed(context, &vertices, &[0u16, 1, 2])?;
+//! let input = VertexInput::new().vertices::<ColorVertex>(0);
+//! // `input.state()` goes into the pipeline, then per frame:
+//! mesh.bind(context, command_buffer);
+//! mesh.draw(context, command_buffer, 0..1);
+//! ```
+
+use std::{mem, ops::Range, slice};
+
+use ash::vk::{
+    BufferUsageFlags, CommandBuffer, Format, IndexType, PipelineVertexInputStateCreateInfo,
+    VertexInputAttributeDescription, VertexInputBindingDescription, VertexInputRate,
+};
+
+use crate::{
+    error::Result,
+    vulkan::{Context, DeviceBuffer},
+    Error,
+};
+
+/// A type one vertex attribute can hold, with its Vulkan format.
+pub trait VertexAttribute: Copy {
+    const FORMAT: Format;
+}
+
+macro_rules! vertex_attributes {
+    ($($ty:ty => $format:ident),* $(,)?) => {
+        $(impl VertexAttribute for $ty {
+            const FORMAT: Format = Format::$format;
+        })*
+    };
+}
+
+vertex_attributes! {
+    f32 => R32_SFLOAT,
+    [f32; 2] => R32G32_SFLOAT,
+    [f32; 3] => R32G32B32_SFLOAT,
+    [f32; 4] => R32G32B32A32_SFLOAT,
+    u32 => R32_UINT,
+    [u32; 2] => R32G32_UINT,
+    [u32; 3] => R32G32B32_UINT,
+    [u32; 4] => R32G32B32A32_UINT,
+    i32 => R32_SINT,
+    [i32; 2] => R32G32_SINT,
+    [i32; 3] => R32G32B32_SINT,
+    [i32; 4] => R32G32B32A32_SINT,
+    [u16; 2] => R16G16_UNORM,
+    [u16; 4] => R16G16B16A16_UNORM,
+    [u8; 4] => R8G8B8A8_UNORM,
+    [i8; 4] => R8G8B8A8_SNORM,
+}
+
+/// Paths `impl_vertex!` expands to, so it works without naming `ash` itself.
+#[doc(hidden)]
+pub mod __private {
+    pub use ash::vk::{Format, VertexInputAttributeDescription};
+    pub use std::{
+        mem::{offset_of, size_of},
+        vec,
+        vec::Vec,
+    };
+
+    /// Format of the field picked by `field`.
+    pub fn field_format<V, T: super::VertexAttribute>(_field: fn(&V) -> &T) -> Format {
+        T::FORMAT
+    }
+
+    /// Size of the field picked by `field`.
+    pub const fn field_size<V, T>(_field: fn(&V) -> &T) -> usize {
+        size_of::<T>()
+    }
+}
+
+/// Plain data stored in a vertex buffer, per vertex or per instance.
+///
+/// # Safety
+/// The type must be `#[repr(C)]` and have no padding, since its bytes are uploaded as they
+/// are. `impl_vertex!` implements it.
+pub unsafe trait Vertex: Copy + 'static {
+    /// Attributes read from a buffer bound at `binding`.
+    fn attributes(binding: u32) -> Vec<VertexInputAttributeDescription>;
+}
+
+/// Implements `Vertex` for a `#[repr(C)]` struct, mapping each listed field to a shader
+/// location. Field types must implement `VertexAttribute`. Every field must be listed, and
+/// a struct with padding fails to compile.
+#[macro_export]
+macro_rules! impl_vertex {
+    ($ty:ty { $($field:ident : $location:expr),* $(,)? }) => {
+        // The listed fields fill the whole struct, so it has neither padding nor unlisted
+        // fields, which `Vertex` requires.
+        const _: () = assert!(
+            $crate::mesh::__private::size_of::<$ty>()
+                == 0 $(+ $crate::mesh::__private::field_size(|vertex: &$ty| &vertex.$field))*,
+            concat!(
+                "`",
+                stringify!($ty),
+                "` has padding or fields `impl_vertex!` doesn't list"
+            ),
+        );
+
+        unsafe impl $crate::mesh::Vertex for $ty {
+            fn attributes(
+                binding: u32,
+            ) -> $crate::mesh::__private::Vec<$crate::mesh::__private::VertexInputAttributeDescription>
+            {
+                $crate::mesh::__private::vec![$(
+                    $crate::mesh::__private::VertexInputAttributeDescription {
+                        location: $location,
+                        binding,
+                        format: $crate::mesh::__private::field_format(|vertex: &$ty| &vertex.$field),
+                        offset: $crate::mesh::__private::offset_of!($ty, $field) as u32,
+                    }
+                ),*]
+            }
+        }
+    };
+}
+
+fn bytes<T: Copy>(data: &[T]) -> &[u8] {
+    unsafe { slice::from_raw_parts(data.as_ptr() as *const u8, mem::size_of_val(data)) }
+}
+
+/// Vertex buffer bindings and attributes of a pipeline.
+#[derive(Debug, Clone, Default)]
+pub struct VertexInput {
+    bindings: Vec<VertexInputBindingDescription>,
+    attributes: Vec<VertexInputAttributeDescription>,
+}
+
+impl VertexInput {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Reads `V` per vertex from the buffer bound at `binding`.
+    pub fn vertices<V: Vertex>(self, binding: u32) -> Self {
+        self.binding::<V>(binding, VertexInputRate::VERTEX)
+    }
+
+    /// Reads `V` per instance from the buffer bound at `binding`.
+    pub fn instances<V: Vertex>(self, binding: u32) -> Self {
+        self.binding::<V>(binding, VertexInputRate::INSTANCE)
+    }
+
+    fn binding<V: Vertex>(mut self, binding: u32, input_rate: VertexInputRate) -> Self {
+        self.bindings.push(VertexInputBindingDescription {
+            binding,
+            stride: mem::size_of::<V>() as u32,
+            input_rate,
+        });
+        self.attributes.extend(V::attributes(binding));
+        self
+    }
+
+    pub fn bindings(&self) -> &[VertexInputBindingDescription] {
+        &self.bindings
+    }
+
+    pub fn attributes(&self) -> &[VertexInputAttributeDescription] {
+        &self.attributes
+    }
+
+    /// Vertex input state for `GraphicsPipelineCreateInfo`.
+    pub fn state(&self) -> PipelineVertexInputStateCreateInfo<'_> {
+        PipelineVertexInputStateCreateInfo::default()
+            .vertex_binding_descriptions(&self.bindings)
+            .vertex_attribute_descriptions(&self.attributes)
+    }
+}
+
+/// Integer types an index buffer can hold.
+pub trait Index: Copy + 'static {
+    const TYPE: IndexType;
+}
+
+impl Index for u16 {
+    const TYPE: IndexType = IndexType::UINT16;
+}
+
+impl Index for u32 {
+    const TYPE: IndexType = IndexType::UINT32;
+}
+
+/// A device local buffer of vertices or instances of one `Vertex` type.
+#[derive(Debug, Clone, Copy)]
+pub struct VertexBuffer {
+    buffer: DeviceBuffer,
+    count: u32,
+}
+
+impl VertexBuffer {
+    pub fn new<V: Vertex>(context: &Context, data: &[V]) -> Result<Self> {
+        Ok(VertexBuffer {
+            buffer: DeviceBuffer::upload(context, BufferUsageFlags::VERTEX_BUFFER, bytes(data))?,
+            count: data.len() as u32,
+        })
+    }
+
+    pub fn buffer(&self) -> &DeviceBuffer {
+        &self.buffer
+    }
+
+    /// Number of vertices or instances.
+    pub fn len(&self) -> u32 {
+        self.count
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.count == 0
+    }
+
+    pub fn bind(&self, context: &Context, command_buffer: CommandBuffer, binding: u32) {
+        unsafe {
+            context.device.cmd_bind_vertex_buffers(
+                command_buffer,
+                binding,
+                &[self.buffer.buffer()],
+                &[0],
+            )
+        };
+    }
+
+    /// The device must be done with the buffer.
+    pub fn destroy(&mut self, context: &Context) {
+        self.buffer.destroy(context);
+    }
+}
+
+/// A range of a mesh drawn on its own, e.g. the part using one material. For indexed
+/// meshes `first` and `count` select indices and `vertex_offset` is added to each of them;
+/// otherwise they select vertices.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SubMesh {
+    pub first: u32,
+    pub count: u32,
+    pub vertex_offset: i32,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct IndexBuffer {
+    buffer: DeviceBuffer,
+    index_type: IndexType,
+    count: u32,
+}
+
+/// Vertices, optional 16 or 32-bit indices and the sub-meshes drawn from them.
+#[derive(Debug, Clone)]
+pub struct Mesh {
+    vertices: VertexBuffer,
+    indices: Option<IndexBuffer>,
+    submeshes: Vec<SubMesh>,
+}
+
+impl Mesh {
+    /// A mesh drawn straight from its vertices, as one sub-mesh.
+    pub fn new<V: Vertex>(context: &Context, vertices: &[V]) -> Result<Self> {
+        let vertices = VertexBuffer::new(context, vertices)?;
+        Ok(Mesh {
+            submeshes: vec![SubMesh {
+                first: 0,
+                count: vertices.len(),
+                vertex_offset: 0,
+            }],
+            vertices,
+            indices: None,
+        })
+    }
+
+    /// A mesh drawn through `indices`, as one sub-mesh.
+    pub fn indexed<V: Vertex, I: Index>(
+        context: &Context,
+        vertices: &[V],
+        indices: &[I],
+    ) -> Result<Self> {
+        let mut vertices = VertexBuffer::new(context, vertices)?;
+        let buffer =
+            match DeviceBuffer::upload(context, BufferUsageFlags::INDEX_BUFFER, bytes(indices)) {
+                Ok(buffer) => buffer,
+                Err(error) => {
+                    vertices.destroy(context);
+                    return Err(error);
+                }
+            };
+        Ok(Mesh {
+            vertices,
+            indices: Some(IndexBuffer {
+                buffer,
+                index_type: I::TYPE,
+                count: indices.len() as u32,
+            }),
+            submeshes: vec![SubMesh {
+                first: 0,
+                count: indices.len() as u32,
+                vertex_offset: 0,
+            }],
+        })
+    }
+
+    /// Splits the mesh into `submeshes`, replacing the ones it had.
+    pub fn set_submeshes(&mut self, submeshes: Vec<SubMesh>) -> Result<()> {
+        let limit = self
+            .indices
+            .map_or(self.vertices.len(), |indices| indices.count);
+        if let Some(submesh) = submeshes
+            .iter()
+            .find(|submesh| submesh.first as u64 + submesh.count as u64 > limit as u64)
+        {
+            return Err(Error::Other(format!(
+                "Sub-mesh {:?} is out of a mesh of {}",
+                submesh, limit
+            )));
+        }
+        self.submeshes = submeshes;
+        Ok(())
+    }
+
+    pub fn submeshes(&self) -> &[SubMesh] {
+        &self.submeshes
+    }
+
+    pub fn vertices(&self) -> &VertexBuffer {
+        &self.vertices
+    }
+
+    pub fn is_indexed(&self) -> bool {
+        self.indices.is_some()
+    }
+
+    pub fn index_type(&self) -> Option<IndexType> {
+        self.indices.map(|indices| indices.index_type)
+    }
+
+    /// Binds the vertex buffer at binding 0 and the index buffer, if any.
+    pub fn bind(&self, context: &Context, command_buffer: CommandBuffer) {
+        self.vertices.bind(context, command_buffer, 0);
+        if let Some(indices) = &self.indices {
+            unsafe {
+                context.device.cmd_bind_index_buffer(
+                    command_buffer,
+                    indices.buffer.buffer(),
+                    0,
+                    indices.index_type,
+                )
+            };
+        }
+    }
+
+    /// Draws every sub-mesh for each instance in `instances`. The mesh must be bound.
+    pub fn draw(&self, context: &Context, command_buffer: CommandBuffer, instances: Range<u32>) {
+        for index in 0..self.submeshes.len() {
+            self.draw_submesh(context, command_buffer, index, instances.clone());
+        }
+    }
+
+    /// Draws one sub-mesh for each instance in `instances`. The mesh must be bound.
+    pub fn draw_submesh(
+        &self,
+        context: &Context,
+        command_buffer: CommandBuffer,
+        index: usize,
+        instances: Range<u32>,
+    ) {
+        let submesh = self.submeshes[index];
+        let instance_count = instances.end.saturating_sub(instances.start);
+        unsafe {
+            if self.indices.is_some() {
+                context.device.cmd_draw_indexed(
+                    command_buffer,
+                    submesh.count,
+                    instance_count,
+                    submesh.first,
+                    submesh.vertex_offset,
+                    instances.start,
+                );
+            } else {
+                context.device.cmd_draw(
+                    command_buffer,
+                    submesh.count,
+                    instance_count,
+                    submesh.first,
+                    instances.start,
+                );
+            }
+        }
+    }
+
+    /// The device must be done with the mesh.
+    pub fn destroy(&mut self, context: &Context) {
+        self.vertices.destroy(context);
+        if let Some(indices) = &mut self.indices {
+            indices.buffer.destroy(context);
+        }
+        self.indices = None;
+        self.submeshes.clear();
+    }
+}
@@ -1,2 +1,4 @@
+pub mod graph;
+pub mod mesh;
+pub mod record;
 pub mod vulkan;
-
@@ -0,0 +1,164 @@
+//! Recording rendered frames as numbered PNG files or a Y4M video stream.
+//!
+//! A `Recorder` runs on a simulated clock: every frame advances time by exactly one
+//! timestep, so a recording doesn't depend on how fast frames were rendered. With a window,
+//! hand it to `AlovakBuilder::record` or `AppContext::start_recording`. Headless, drive it
+//! yourself: call `next_frame`, render the scene at `time`, and pass the image from
+//! `vulkan::read_image` to `write` whenever `next_frame` returned `true`.
+
+use std::{
+    fs::{self, File},
+    io::{BufWriter, Write},
+    path::PathBuf,
+    time::Duration,
+};
+
+use crate::{error::Result, image::y4m, Error, RgbaImage};
+
+/// Where recorded frames go.
+pub enum RecordOutput {
+    /// One PNG file per frame in `directory`, named `<prefix>000000.png` and up.
+    Png { directory: PathBuf, prefix: String },
+    /// A YUV4MPEG2 stream, e.g. a file or the stdin of an encoder.
+    Y4m(Box<dyn Write + Send>),
+}
+
+impl RecordOutput {
+    /// Numbered PNG files `frame_000000.png`, `frame_000001.png`, ... in `directory`.
+    pub fn png(directory: impl Into<PathBuf>) -> Self {
+        RecordOutput::Png {
+            directory: directory.into(),
+            prefix: "frame_".to_owned(),
+        }
+    }
+
+    /// A Y4M file at `path`, created or truncated.
+    pub fn y4m_file(path: impl Into<PathBuf>) -> Result<Self> {
+        let file = File::create(path.into()).map_err(Error::Io)?;
+        Ok(RecordOutput::Y4m(Box::new(BufWriter::new(file))))
+    }
+}
+
+/// Writes frames to a `RecordOutput` and keeps the simulated clock.
+pub struct Recorder {
+    output: RecordOutput,
+    fps: u32,
+    every: u32,
+    limit: Option<u64>,
+    /// Frames rendered so far, recorded or not.
+    frames: u64,
+    requested: u64,
+    written: u64,
+    /// Size of the Y4M stream, fixed by its first frame.
+    y4m_size: Option<(u32, u32)>,
+}
+
+impl Recorder {
+    /// Records every frame of a clock running at `fps` simulated frames per second.
+    pub fn new(output: RecordOutput, fps: u32) -> Self {
+        Recorder {
+            output,
+            fps: fps.max(1),
+            every: 1,
+            limit: None,
+            frames: 0,
+            requested: 0,
+            written: 0,
+            y4m_size: None,
+        }
+    }
+
+    /// Records only every `n`th frame; the video plays at `fps / n`.
+    pub fn every(mut self, n: u32) -> Self {
+        self.every = n.max(1);
+        self
+    }
+
+    /// Stops after `frames` recorded frames.
+    pub fn limit(mut self, frames: u64) -> Self {
+        self.limit = Some(frames);
+        self
+    }
+
+    /// Simulated time between two frames.
+    pub fn timestep(&self) -> Duration {
+        Duration::from_secs(1) / self.fps
+    }
+
+    /// Simulated time of the current frame, the first one being at zero.
+    pub fn time(&self) -> Duration {
+        let index = self.frames.saturating_sub(1);
+        let fps = self.fps as u64;
+        Duration::from_secs(index / fps) + Duration::from_secs(index % fps) / self.fps
+    }
+
+    /// Frames started with `next_frame`.
+    pub fn frames(&self) -> u64 {
+        self.frames
+    }
+
+    /// Frames written to the output.
+    pub fn written(&self) -> u64 {
+        self.written
+    }
+
+    /// Starts the next frame, returning whether it is to be recorded.
+    pub fn next_frame(&mut self) -> bool {
+        let index = self.frames;
+        self.frames += 1;
+        let wanted = index.is_multiple_of(self.every as u64)
+            && self.limit.is_none_or(|limit| self.requested < limit);
+        if wanted {
+            self.requested += 1;
+        }
+        wanted
+    }
+
+    /// Whether the limit is reached and every frame up to it was written.
+    pub fn is_done(&self) -> bool {
+        self.limit.is_some_and(|limit| self.written >= limit)
+    }
+
+    /// Writes the next recorded frame.
+    pub fn write(&mut self, image: &RgbaImage) -> Result<()> {
+        match &mut self.output {
+            RecordOutput::Png { directory, prefix } => {
+                if self.written == 0 {
+                    fs::create_dir_all(&*directory).map_err(Error::Io)?;
+                }
+                image.save_png(directory.join(format!("{}{:06}.png", prefix, self.written)))?;
+            }
+            RecordOutput::Y4m(writer) => {
+                let size = (image.width(), image.height());
+                match self.y4m_size {
+                    None => {
+                        let header = y4m::header(size.0, size.1, self.fps, self.every);
+                        writer.write_all(header.as_bytes()).map_err(Error::Io)?;
+                        self.y4m_size = Some(size);
+                    }
+                    Some(first) if first != size => {
+                        return Err(Error::Other(format!(
+                            "Y4M stream is {}x{}, frame {} is {}x{}",
+                            first.0, first.1, self.written, size.0, size.1
+                        )));
+                    }
+                    Some(_) => {}
+                }
+                let mut frame = Vec::with_capacity(image.pixels().len() / 4 * 3 + 6);
+                y4m::frame(image.pixels(), &mut frame);
+                writer.write_all(&frame).map_err(Error::Io)?;
+            }
+        }
+        self.written += 1;
+        Ok(())
+    }
+
+    /// Flushes the output.
+    pub fn finish(mut self) -> Result<()> {
+        if let RecordOutput::Y4m(writer) = &mut self.output {
+            writer.flush().map_err(Error::Io)?;
+        }
+        log::trace!("alovak recorded {} frames", self.written);
+        Ok(())
+    }
+}
@@ -0,0 +1,241 @@
+use ash::vk::{
+    DeviceMemory, Extent2D, Extent3D, Format, FormatFeatureFlags, Image, ImageAspectFlags,
+    ImageCreateInfo, ImageLayout, ImageSubresourceRange, ImageTiling, ImageType, ImageUsageFlags,
+    ImageView, ImageViewCreateInfo, ImageViewType, MemoryAllocateInfo, MemoryPropertyFlags,
+    SampleCountFlags, SharingMode,
+};
+
+use crate::{error::Result, Error};
+
+use super::Context;
+
+/// Depth attachment kept alongside the swapchain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum DepthBuffer {
+    /// Depth only, preferring 32-bit float.
+    Depth,
+    /// Depth with an 8-bit stencil.
+    DepthStencil,
+}
+
+impl DepthBuffer {
+    /// Formats to try, most wanted first.
+    pub fn candidates(self) -> &'static [Format] {
+        match self {
+            DepthBuffer::Depth => &[
+                Format::D32_SFLOAT,
+                Format::X8_D24_UNORM_PACK32,
+                Format::D16_UNORM,
+                Format::D32_SFLOAT_S8_UINT,
+                Format::D24_UNORM_S8_UINT,
+            ],
+            DepthBuffer::DepthStencil => &[
+                Format::D24_UNORM_S8_UINT,
+                Format::D32_SFLOAT_S8_UINT,
+                Format::D16_UNORM_S8_UINT,
+            ],
+        }
+    }
+}
+
+/// Image aspects of a format: depth and/or stencil for depth formats, color otherwise.
+pub(crate) fn format_aspect(format: Format) -> ImageAspectFlags {
+    match format {
+        Format::D16_UNORM | Format::X8_D24_UNORM_PACK32 | Format::D32_SFLOAT => {
+            ImageAspectFlags::DEPTH
+        }
+        Format::D16_UNORM_S8_UINT | Format::D24_UNORM_S8_UINT | Format::D32_SFLOAT_S8_UINT => {
+            ImageAspectFlags::DEPTH | ImageAspectFlags::STENCIL
+        }
+        Format::S8_UINT => ImageAspectFlags::STENCIL,
+        _ => ImageAspectFlags::COLOR,
+    }
+}
+
+/// An image the renderer allocates itself to render into, e.g. the depth buffer or the
+/// multisampled color target. It owns the image: `destroy` frees it, and `reference`
+/// hands out its handles.
+#[derive(Debug)]
+pub struct Attachment {
+    pub(crate) image: Image,
+    pub(crate) memory: DeviceMemory,
+    pub(crate) view: ImageView,
+    pub(crate) format: Format,
+    pub(crate) samples: SampleCountFlags,
+    pub(crate) aspect: ImageAspectFlags,
+}
+
+impl Attachment {
+    /// Creates an image with one mip level in device local memory and a view of it, e.g.
+    /// an offscreen target. The view covers every aspect of the format.
+    pub fn new(
+        context: &Context,
+        extent: Extent2D,
+        format: Format,
+        samples: SampleCountFlags,
+        usage: ImageUsageFlags,
+    ) -> Result<Self> {
+        let mut attachment = Attachment {
+            image: Image::null(),
+            memory: DeviceMemory::null(),
+            view: ImageView::null(),
+            format,
+            samples,
+            aspect: format_aspect(format),
+        };
+        match attachment.create(context, extent, usage) {
+            Ok(()) => Ok(attachment),
+            Err(error) => {
+                attachment.destroy(context);
+                Err(error)
+            }
+        }
+    }
+
+    fn create(
+        &mut self,
+        context: &Context,
+        extent: Extent2D,
+        usage: ImageUsageFlags,
+    ) -> Result<()> {
+        let device = &context.device;
+        let image_create_info = ImageCreateInfo::default()
+            .image_type(ImageType::TYPE_2D)
+            .format(self.format)
+            .extent(Extent3D {
+                width: extent.width,
+                height: extent.height,
+                depth: 1,
+            })
+            .mip_levels(1)
+            .array_layers(1)
+            .samples(self.samples)
+            .tiling(ImageTiling::OPTIMAL)
+            .usage(usage)
+            .sharing_mode(SharingMode::EXCLUSIVE)
+            .initial_layout(ImageLayout::UNDEFINED);
+        self.image =
+            unsafe { device.create_image(&image_create_info, None) }.map_err(Error::Vulkan)?;
+
+        let requirements = unsafe { device.get_image_memory_requirements(self.image) };
+        let allocate_info = MemoryAllocateInfo::default()
+            .allocation_size(requirements.size)
+            .memory_type_index(context.memory_type(
+                requirements.memory_type_bits,
+                MemoryPropertyFlags::DEVICE_LOCAL,
+            )?);
+        self.memory =
+            unsafe { device.allocate_memory(&allocate_info, None) }.map_err(Error::Vulkan)?;
+        unsafe { device.bind_image_memory(self.image, self.memory, 0) }.map_err(Error::Vulkan)?;
+
+        let view_create_info = ImageViewCreateInfo::default()
+            .image(self.image)
+            .view_type(ImageViewType::TYPE_2D)
+            .format(self.format)
+            .subresource_range(self.range());
+        self.view =
+            unsafe { device.create_image_view(&view_create_info, None) }.map_err(Error::Vulkan)?;
+        Ok(())
+    }
+
+    pub fn image(&self) -> Image {
+        self.image
+    }
+
+    pub fn view(&self) -> ImageView {
+        self.view
+    }
+
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    pub fn samples(&self) -> SampleCountFlags {
+        self.samples
+    }
+
+    /// The handles of the image, valid until the attachment is destroyed.
+    pub fn reference(&self) -> AttachmentRef {
+        AttachmentRef {
+            image: self.image,
+            view: self.view,
+            format: self.format,
+            samples: self.samples,
+            aspect: self.aspect,
+        }
+    }
+
+    pub(crate) fn range(&self) -> ImageSubresourceRange {
+        self.reference().range()
+    }
+
+    /// The device must be done with the image.
+    pub fn destroy(&mut self, context: &Context) {
+        let device = &context.device;
+        unsafe {
+            if self.view != ImageView::null() {
+                device.destroy_image_view(self.view, None);
+            }
+            if self.image != Image::null() {
+                device.destroy_image(self.image, None);
+            }
+            if self.memory != DeviceMemory::null() {
+                device.free_memory(self.memory, None);
+            }
+        }
+        self.view = ImageView::null();
+        self.image = Image::null();
+        self.memory = DeviceMemory::null();
+    }
+}
+
+/// Handles of an `Attachment` without owning it, e.g. for a frame to render into.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AttachmentRef {
+    pub(crate) image: Image,
+    pub(crate) view: ImageView,
+    pub(crate) format: Format,
+    pub(crate) samples: SampleCountFlags,
+    pub(crate) aspect: ImageAspectFlags,
+}
+
+impl AttachmentRef {
+    pub fn image(&self) -> Image {
+        self.image
+    }
+
+    pub fn view(&self) -> ImageView {
+        self.view
+    }
+
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    pub fn samples(&self) -> SampleCountFlags {
+        self.samples
+    }
+
+    pub(crate) fn range(&self) -> ImageSubresourceRange {
+        ImageSubresourceRange::default()
+            .aspect_mask(self.aspect)
+            .base_mip_level(0)
+            .level_count(1)
+            .base_array_layer(0)
+            .layer_count(1)
+    }
+}
+
+/// First of `candidates` usable as an optimally tiled depth attachment.
+pub(crate) fn pick_depth_format(context: &Context, candidates: &[Format]) -> Option<Format> {
+    candidates.iter().copied().find(|format| {
+        let properties = unsafe {
+            context
+                .instance
+                .get_physical_device_format_properties(context.physical_device, *format)
+        };
+        properties
+            .optimal_tiling_features
+            .contains(FormatFeatureFlags::DEPTH_STENCIL_ATTACHMENT)
+    })
+}
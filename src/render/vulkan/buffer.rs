@@ -0,0 +1,155 @@
+use ash::vk::{
+    Buffer, BufferCopy, BufferCreateInfo, BufferUsageFlags, DeviceMemory, DeviceSize,
+    MemoryAllocateInfo, MemoryMapFlags, MemoryPropertyFlags, SharingMode,
+};
+
+use crate::{error::Result, Error};
+
+use super::Context;
+
+/// A buffer with its own memory allocation, e.g. vertices, indices or a staging buffer.
+#[derive(Debug, Clone, Copy)]
+pub struct DeviceBuffer {
+    pub(crate) buffer: Buffer,
+    pub(crate) memory: DeviceMemory,
+    pub(crate) size: DeviceSize,
+}
+
+impl DeviceBuffer {
+    pub fn new(
+        context: &Context,
+        size: DeviceSize,
+        usage: BufferUsageFlags,
+        properties: MemoryPropertyFlags,
+    ) -> Result<Self> {
+        let mut buffer = DeviceBuffer {
+            buffer: Buffer::null(),
+            memory: DeviceMemory::null(),
+            size,
+        };
+        match buffer.create(context, usage, properties) {
+            Ok(()) => Ok(buffer),
+            Err(error) => {
+                buffer.destroy(context);
+                Err(error)
+            }
+        }
+    }
+
+    fn create(
+        &mut self,
+        context: &Context,
+        usage: BufferUsageFlags,
+        properties: MemoryPropertyFlags,
+    ) -> Result<()> {
+        let device = &context.device;
+        let buffer_create_info = BufferCreateInfo::default()
+            .size(self.size)
+            .usage(usage)
+            .sharing_mode(SharingMode::EXCLUSIVE);
+        self.buffer =
+            unsafe { device.create_buffer(&buffer_create_info, None) }.map_err(Error::Vulkan)?;
+
+        let requirements = unsafe { device.get_buffer_memory_requirements(self.buffer) };
+        let allocate_info = MemoryAllocateInfo::default()
+            .allocation_size(requirements.size)
+            .memory_type_index(context.memory_type(requirements.memory_type_bits, properties)?);
+        self.memory =
+            unsafe { device.allocate_memory(&allocate_info, None) }.map_err(Error::Vulkan)?;
+        unsafe { device.bind_buffer_memory(self.buffer, self.memory, 0) }.map_err(Error::Vulkan)
+    }
+
+    /// A device local buffer filled with `data` through a staging buffer. Blocks until the
+    /// copy is done.
+    pub fn upload(context: &Context, usage: BufferUsageFlags, data: &[u8]) -> Result<Self> {
+        if data.is_empty() {
+            return Err(Error::Other("Buffer can`t be empty".to_owned()));
+        }
+        let mut staging = DeviceBuffer::new(
+            context,
+            data.len() as DeviceSize,
+            BufferUsageFlags::TRANSFER_SRC,
+            MemoryPropertyFlags::HOST_VISIBLE | MemoryPropertyFlags::HOST_COHERENT,
+        )?;
+        let result = staging.write(context, 0, data).and_then(|()| {
+            let mut buffer = DeviceBuffer::new(
+                context,
+                data.len() as DeviceSize,
+                usage | BufferUsageFlags::TRANSFER_DST,
+                MemoryPropertyFlags::DEVICE_LOCAL,
+            )?;
+            let copied = context.submit_and_wait(|command_buffer| {
+                let region = BufferCopy::default().size(data.len() as DeviceSize);
+                unsafe {
+                    context.device.cmd_copy_buffer(
+                        command_buffer,
+                        staging.buffer,
+                        buffer.buffer,
+                        &[region],
+                    )
+                };
+                Ok(())
+            });
+            match copied {
+                Ok(()) => Ok(buffer),
+                Err(error) => {
+                    buffer.destroy(context);
+                    Err(error)
+                }
+            }
+        });
+        staging.destroy(context);
+        result
+    }
+
+    pub fn buffer(&self) -> Buffer {
+        self.buffer
+    }
+
+    pub fn size(&self) -> DeviceSize {
+        self.size
+    }
+
+    /// Copies `data` to `offset`. The memory must be host visible and coherent.
+    pub fn write(&mut self, context: &Context, offset: DeviceSize, data: &[u8]) -> Result<()> {
+        if offset + data.len() as DeviceSize > self.size {
+            return Err(Error::Other(format!(
+                "Write of {} bytes at {} is out of a buffer of {}",
+                data.len(),
+                offset,
+                self.size
+            )));
+        }
+        if data.is_empty() {
+            return Ok(());
+        }
+        unsafe {
+            let mapped = context
+                .device
+                .map_memory(
+                    self.memory,
+                    offset,
+                    data.len() as DeviceSize,
+                    MemoryMapFlags::empty(),
+                )
+                .map_err(Error::Vulkan)?;
+            std::ptr::copy_nonoverlapping(data.as_ptr(), mapped as *mut u8, data.len());
+            context.device.unmap_memory(self.memory);
+        }
+        Ok(())
+    }
+
+    /// The device must be done with the buffer.
+    pub fn destroy(&mut self, context: &Context) {
+        unsafe {
+            if self.buffer != Buffer::null() {
+                context.device.destroy_buffer(self.buffer, None);
+            }
+            if self.memory != DeviceMemory::null() {
+                context.device.free_memory(self.memory, None);
+            }
+        }
+        self.buffer = Buffer::null();
+        self.memory = DeviceMemory::null();
+    }
+}
@@ -0,0 +1,541 @@
+use ash::{
+    ext::{debug_utils, swapchain_colorspace},
+    khr::{surface, swapchain},
+    vk::{
+        self, DebugUtilsMessengerEXT, DeviceQueueCreateInfo, Format, FormatFeatureFlags,
+        MemoryPropertyFlags, PhysicalDevice, PhysicalDeviceFeatures, Queue, QueueFlags,
+        SampleCountFlags, SurfaceKHR,
+    },
+    Device, Entry, Instance,
+};
+use raw_window_handle::RawDisplayHandle;
+use std::{
+    borrow::Cow,
+    collections::HashSet,
+    ffi::{self, c_char, CStr},
+};
+
+use crate::{error::Result, Error};
+
+use super::RenderTarget;
+
+/// Instance, device and queues shared by every surface the renderer presents to.
+pub struct Context {
+    pub entry: Entry,
+    pub instance: Instance,
+    pub physical_device: PhysicalDevice,
+    pub device: Device,
+    /// Features enabled on `device`: the compressed texture families and cubemap arrays, if
+    /// the device supports them.
+    pub features: PhysicalDeviceFeatures,
+    pub queue_graphic: (Queue, u32),
+    pub queue_present: (Queue, u32),
+    pub(crate) surface_loader: surface::Instance,
+    pub(crate) swapchain_loader: swapchain::Device,
+    debug_utils_loader: debug_utils::Instance,
+    debug_utils: DebugUtilsMessengerEXT,
+    extension_names: Vec<&'static CStr>,
+}
+
+/// A device made by `Context::create_device` with what was picked for it.
+struct PickedDevice {
+    device: Device,
+    physical_device: PhysicalDevice,
+    features: PhysicalDeviceFeatures,
+    queue_graphic: (Queue, u32),
+    queue_present: (Queue, u32),
+}
+
+const VALIDATION_LAYER: &CStr = c"VK_LAYER_KHRONOS_validation";
+
+impl Context {
+    /// Creates the instance and the device for `windows`, returning one `SurfaceKHR` per
+    /// window in the same order. Only devices able to present to all of them are picked.
+    pub(crate) fn new(windows: &[&dyn RenderTarget]) -> Result<(Self, Vec<SurfaceKHR>)> {
+        let mut extension_names: Vec<&'static CStr> = Vec::new();
+        for window in windows {
+            let display_handle = window.display_handle().map_err(super::handle_error)?;
+            for name in Self::required_extensions(display_handle.as_raw())? {
+                if !extension_names.contains(&name) {
+                    extension_names.push(name);
+                }
+            }
+        }
+
+        // Without windows there is nothing to present to, so a headless device doesn't need
+        // swapchain support.
+        let device_extension_names = vec![b"VK_KHR_swapchain\0"]
+            .into_iter()
+            .filter(|_| !windows.is_empty())
+            .map(|raw_name| unsafe { ffi::CStr::from_bytes_with_nul_unchecked(raw_name).as_ptr() })
+            .collect();
+
+        let entry = Entry::linked();
+        log::trace!("vulkan entry created");
+
+        // Validation and debug messages are optional, e.g. lavapipe on CI often comes
+        // without the validation layer. The colorspace extension is only needed for color
+        // spaces other than sRGB, e.g. HDR10.
+        let available_extensions = unsafe { entry.enumerate_instance_extension_properties(None) }
+            .map_err(Error::Vulkan)?;
+        let debug_utils_enabled = available_extensions
+            .iter()
+            .any(|extension| extension.extension_name_as_c_str() == Ok(debug_utils::NAME));
+        if debug_utils_enabled {
+            extension_names.push(debug_utils::NAME);
+        }
+        if available_extensions
+            .iter()
+            .any(|extension| extension.extension_name_as_c_str() == Ok(swapchain_colorspace::NAME))
+        {
+            extension_names.push(swapchain_colorspace::NAME);
+        }
+
+        let available_layers =
+            unsafe { entry.enumerate_instance_layer_properties() }.map_err(Error::Vulkan)?;
+        let layer_names: Vec<*const c_char> = available_layers
+            .iter()
+            .filter_map(|layer| layer.layer_name_as_c_str().ok())
+            .filter(|name| *name == VALIDATION_LAYER)
+            .map(|_| VALIDATION_LAYER.as_ptr())
+            .take(1)
+            .collect();
+        if layer_names.is_empty() {
+            log::trace!("vulkan validation layer isn`t available");
+        }
+
+        let instance = Self::create_instance(
+            &entry,
+            layer_names,
+            extension_names.iter().map(|name| name.as_ptr()).collect(),
+        )?;
+        log::trace!("vulkan instance created");
+
+        let debug_utils_loader = debug_utils::Instance::new(&entry, &instance);
+        let surface_loader = surface::Instance::new(&entry, &instance);
+        let mut debug_utils = DebugUtilsMessengerEXT::null();
+        let mut surfaces = Vec::with_capacity(windows.len());
+        let picked = (|| {
+            if debug_utils_enabled {
+                debug_utils = Self::create_debug_utils_messenger(&debug_utils_loader)?;
+                log::trace!("vulkan debug utils messenger created");
+            }
+
+            for window in windows {
+                surfaces.push(Self::create_surface_khr(&entry, &instance, *window)?);
+            }
+            log::trace!("vulkan surfaces created");
+
+            Self::create_device(
+                &instance,
+                &surface_loader,
+                device_extension_names,
+                &surfaces,
+            )
+        })();
+        // Nothing owns the instance yet, so whatever was made with it goes down with it.
+        let PickedDevice {
+            device,
+            physical_device,
+            features,
+            queue_graphic,
+            queue_present,
+        } = match picked {
+            Ok(picked) => picked,
+            Err(error) => {
+                unsafe {
+                    for surface in surfaces {
+                        surface_loader.destroy_surface(surface, None);
+                    }
+                    if debug_utils != DebugUtilsMessengerEXT::null() {
+                        debug_utils_loader.destroy_debug_utils_messenger(debug_utils, None);
+                    }
+                    instance.destroy_instance(None);
+                }
+                return Err(error);
+            }
+        };
+        log::trace!("vulkan device created");
+
+        let swapchain_loader = swapchain::Device::new(&instance, &device);
+
+        Ok((
+            Context {
+                entry,
+                instance,
+                physical_device,
+                device,
+                features,
+                queue_graphic,
+                queue_present,
+                surface_loader,
+                swapchain_loader,
+                debug_utils_loader,
+                debug_utils,
+                extension_names,
+            },
+            surfaces,
+        ))
+    }
+
+    /// Creates the instance and a device without any window, e.g. for offscreen rendering
+    /// and tests. Point `VK_DRIVER_FILES` at lavapipe to render on the CPU.
+    pub fn headless() -> Result<Self> {
+        let (context, _) = Self::new(&[])?;
+        Ok(context)
+    }
+
+    /// Records commands into a one-time command buffer, submits it to the graphic queue
+    /// and waits for it to finish.
+    pub fn submit_and_wait(
+        &self,
+        record: impl FnOnce(vk::CommandBuffer) -> Result<()>,
+    ) -> Result<()> {
+        let device = &self.device;
+        let pool_create_info = vk::CommandPoolCreateInfo::default()
+            .flags(vk::CommandPoolCreateFlags::TRANSIENT)
+            .queue_family_index(self.queue_graphic.1);
+        let command_pool = unsafe { device.create_command_pool(&pool_create_info, None) }
+            .map_err(Error::Vulkan)?;
+        let result = self.submit_in(command_pool, record);
+        unsafe { device.destroy_command_pool(command_pool, None) };
+        result
+    }
+
+    fn submit_in(
+        &self,
+        command_pool: vk::CommandPool,
+        record: impl FnOnce(vk::CommandBuffer) -> Result<()>,
+    ) -> Result<()> {
+        let device = &self.device;
+        let allocate_info = vk::CommandBufferAllocateInfo::default()
+            .command_pool(command_pool)
+            .level(vk::CommandBufferLevel::PRIMARY)
+            .command_buffer_count(1);
+        let command_buffer =
+            unsafe { device.allocate_command_buffers(&allocate_info) }.map_err(Error::Vulkan)?[0];
+        let begin_info = vk::CommandBufferBeginInfo::default()
+            .flags(vk::CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+        unsafe { device.begin_command_buffer(command_buffer, &begin_info) }
+            .map_err(Error::Vulkan)?;
+        record(command_buffer)?;
+        unsafe { device.end_command_buffer(command_buffer) }.map_err(Error::Vulkan)?;
+
+        let fence = unsafe { device.create_fence(&vk::FenceCreateInfo::default(), None) }
+            .map_err(Error::Vulkan)?;
+        let command_buffers = [command_buffer];
+        let submit_info = vk::SubmitInfo::default().command_buffers(&command_buffers);
+        let submitted = unsafe {
+            device
+                .queue_submit(self.queue_graphic.0, &[submit_info], fence)
+                .and_then(|()| device.wait_for_fences(&[fence], true, u64::MAX))
+        };
+        unsafe { device.destroy_fence(fence, None) };
+        submitted.map_err(Error::Vulkan)
+    }
+
+    /// Creates a surface for a window added after the device was chosen. Fails if the
+    /// present queue of the device cannot present to it.
+    pub(crate) fn create_surface(&self, window: &dyn RenderTarget) -> Result<SurfaceKHR> {
+        let display_handle = window.display_handle().map_err(super::handle_error)?;
+        for name in Self::required_extensions(display_handle.as_raw())? {
+            if !self.extension_names.contains(&name) {
+                return Err(Error::Other(format!(
+                    "Instance extension {} isn`t enabled",
+                    name.to_string_lossy()
+                )));
+            }
+        }
+
+        let surface = Self::create_surface_khr(&self.entry, &self.instance, window)?;
+
+        let supported = unsafe {
+            self.surface_loader.get_physical_device_surface_support(
+                self.physical_device,
+                self.queue_present.1,
+                surface,
+            )
+        }
+        .unwrap_or(false);
+        if !supported {
+            unsafe { self.surface_loader.destroy_surface(surface, None) };
+            return Err(Error::Other(
+                "Surface can`t be presented by the selected device".to_owned(),
+            ));
+        }
+
+        Ok(surface)
+    }
+
+    /// Index of a memory type allowed by `type_bits` with all of `flags`.
+    pub(crate) fn memory_type(&self, type_bits: u32, flags: MemoryPropertyFlags) -> Result<u32> {
+        let properties = unsafe {
+            self.instance
+                .get_physical_device_memory_properties(self.physical_device)
+        };
+        properties.memory_types[..properties.memory_type_count as usize]
+            .iter()
+            .enumerate()
+            .position(|(index, memory_type)| {
+                type_bits & (1 << index) != 0 && memory_type.property_flags.contains(flags)
+            })
+            .map(|index| index as u32)
+            .ok_or_else(|| Error::Other(format!("Memory type {:?} dont found", flags)))
+    }
+
+    /// What optimally tiled images of `format` can be used for on the device.
+    pub fn format_features(&self, format: Format) -> FormatFeatureFlags {
+        unsafe {
+            self.instance
+                .get_physical_device_format_properties(self.physical_device, format)
+        }
+        .optimal_tiling_features
+    }
+
+    /// Whether textures of `format` can be sampled with linear filtering.
+    pub fn supports_texture(&self, format: Format) -> bool {
+        self.format_features(format).contains(
+            FormatFeatureFlags::SAMPLED_IMAGE | FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR,
+        )
+    }
+
+    /// Sample counts the device supports for color attachments, and for depth attachments
+    /// too when `depth` is set.
+    pub fn sample_counts(&self, depth: bool) -> SampleCountFlags {
+        let limits = unsafe {
+            self.instance
+                .get_physical_device_properties(self.physical_device)
+        }
+        .limits;
+        if depth {
+            limits.framebuffer_color_sample_counts & limits.framebuffer_depth_sample_counts
+        } else {
+            limits.framebuffer_color_sample_counts
+        }
+    }
+
+    fn required_extensions(display_handle: RawDisplayHandle) -> Result<Vec<&'static CStr>> {
+        let names =
+            ash_window::enumerate_required_extensions(display_handle).map_err(Error::Vulkan)?;
+        Ok(names
+            .iter()
+            .map(|name| unsafe { CStr::from_ptr(*name) })
+            .collect())
+    }
+
+    fn create_instance(
+        entry: &Entry,
+        layer_names: Vec<*const c_char>,
+        extension_names: Vec<*const c_char>,
+    ) -> Result<Instance> {
+        let app_name = ffi::CString::new("Alovan App").unwrap();
+        let eng_name = ffi::CString::new("Alovan Eng").unwrap();
+
+        let appinfo = vk::ApplicationInfo::default()
+            .application_name(&app_name)
+            .application_version(0)
+            .engine_name(&eng_name)
+            .engine_version(vk::make_api_version(0, 0, 0, 3))
+            .api_version(vk::make_api_version(0, 1, 0, 0));
+
+        let create_flags = if cfg!(any(target_os = "macos", target_os = "ios")) {
+            vk::InstanceCreateFlags::ENUMERATE_PORTABILITY_KHR
+        } else {
+            vk::InstanceCreateFlags::default()
+        };
+
+        let create_info = vk::InstanceCreateInfo::default()
+            .application_info(&appinfo)
+            .enabled_layer_names(&layer_names)
+            .enabled_extension_names(&extension_names)
+            .flags(create_flags);
+
+        match unsafe { entry.create_instance(&create_info, None) } {
+            Ok(value) => Ok(value),
+            Err(error) => Err(Error::Vulkan(error)),
+        }
+    }
+
+    fn create_surface_khr(
+        entry: &Entry,
+        instance: &Instance,
+        window: &dyn RenderTarget,
+    ) -> Result<SurfaceKHR> {
+        let window_handle = window.window_handle().map_err(super::handle_error)?;
+        let display_handle = window.display_handle().map_err(super::handle_error)?;
+
+        match unsafe {
+            ash_window::create_surface(
+                entry,
+                instance,
+                display_handle.as_raw(),
+                window_handle.as_raw(),
+                None,
+            )
+        } {
+            Ok(value) => Ok(value),
+            Err(error) => Err(Error::Vulkan(error)),
+        }
+    }
+
+    /// The texture features of `physical_device` to enable on its device.
+    fn enabled_features(
+        instance: &Instance,
+        physical_device: PhysicalDevice,
+    ) -> PhysicalDeviceFeatures {
+        let supported = unsafe { instance.get_physical_device_features(physical_device) };
+        PhysicalDeviceFeatures::default()
+            .texture_compression_bc(supported.texture_compression_bc != 0)
+            .texture_compression_etc2(supported.texture_compression_etc2 != 0)
+            .texture_compression_astc_ldr(supported.texture_compression_astc_ldr != 0)
+            .image_cube_array(supported.image_cube_array != 0)
+    }
+
+    fn create_device(
+        instance: &Instance,
+        instance_surface: &surface::Instance,
+        extension: Vec<*const c_char>,
+        surfaces: &[SurfaceKHR],
+    ) -> Result<PickedDevice> {
+        let physical_devices =
+            unsafe { instance.enumerate_physical_devices() }.map_err(Error::Vulkan)?;
+
+        for physical_device in physical_devices {
+            let queue_family_properties =
+                unsafe { instance.get_physical_device_queue_family_properties(physical_device) };
+
+            let presents_all = |index: u32| {
+                surfaces.iter().all(|surface| unsafe {
+                    instance_surface
+                        .get_physical_device_surface_support(physical_device, index, *surface)
+                        .unwrap_or(false)
+                })
+            };
+
+            let queue_family_id_graphic = queue_family_properties
+                .iter()
+                .position(|property| property.queue_flags.contains(QueueFlags::GRAPHICS))
+                .map(|index| index as u32);
+
+            // Presenting from the graphic queue avoids sharing swapchain images between
+            // families, so it is preferred when it can present to every surface.
+            let queue_family_id_present = match queue_family_id_graphic {
+                Some(index) if presents_all(index) => Some(index),
+                _ => (0..queue_family_properties.len() as u32).find(|index| presents_all(*index)),
+            };
+
+            let (Some(queue_family_id_graphic), Some(queue_family_id_present)) =
+                (queue_family_id_graphic, queue_family_id_present)
+            else {
+                log::trace!(
+                    "vulkan device {:?} skipped: queue not found",
+                    physical_device
+                );
+                continue;
+            };
+
+            let mut queue_family_ids = HashSet::new();
+            queue_family_ids.insert(queue_family_id_graphic);
+            queue_family_ids.insert(queue_family_id_present);
+
+            let queue_create_infos: Vec<DeviceQueueCreateInfo> = queue_family_ids
+                .iter()
+                .map(|id| {
+                    DeviceQueueCreateInfo::default()
+                        .queue_family_index(*id)
+                        .queue_priorities(&[1.0f32; 1])
+                })
+                .collect();
+
+            let features = Self::enabled_features(instance, physical_device);
+            let device_create_info = vk::DeviceCreateInfo::default()
+                .queue_create_infos(&queue_create_infos)
+                .enabled_extension_names(&extension)
+                .enabled_features(&features);
+
+            let device =
+                match unsafe { instance.create_device(physical_device, &device_create_info, None) }
+                {
+                    Ok(value) => value,
+                    Err(error) => {
+                        log::trace!("vulkan device {:?} skipped: {}", physical_device, error);
+                        continue;
+                    }
+                };
+
+            let queue_graphic = unsafe { device.get_device_queue(queue_family_id_graphic, 0) };
+            let queue_present = unsafe { device.get_device_queue(queue_family_id_present, 0) };
+
+            return Ok(PickedDevice {
+                device,
+                physical_device,
+                features,
+                queue_graphic: (queue_graphic, queue_family_id_graphic),
+                queue_present: (queue_present, queue_family_id_present),
+            });
+        }
+
+        Err(Error::Other("Device dont found".to_owned()))
+    }
+
+    fn create_debug_utils_messenger(
+        debug_utils_loader: &debug_utils::Instance,
+    ) -> Result<DebugUtilsMessengerEXT> {
+        let debug_info = vk::DebugUtilsMessengerCreateInfoEXT::default()
+            .message_severity(
+                vk::DebugUtilsMessageSeverityFlagsEXT::ERROR
+                    | vk::DebugUtilsMessageSeverityFlagsEXT::WARNING, //| vk::DebugUtilsMessageSeverityFlagsEXT::INFO,
+            )
+            .message_type(
+                vk::DebugUtilsMessageTypeFlagsEXT::GENERAL
+                    | vk::DebugUtilsMessageTypeFlagsEXT::VALIDATION
+                    | vk::DebugUtilsMessageTypeFlagsEXT::PERFORMANCE,
+            )
+            .pfn_user_callback(Some(Self::vulkan_debug_callback));
+
+        unsafe { debug_utils_loader.create_debug_utils_messenger(&debug_info, None) }
+            .map_err(Error::Vulkan)
+    }
+
+    unsafe extern "system" fn vulkan_debug_callback(
+        message_severity: vk::DebugUtilsMessageSeverityFlagsEXT,
+        message_type: vk::DebugUtilsMessageTypeFlagsEXT,
+        p_callback_data: *const vk::DebugUtilsMessengerCallbackDataEXT<'_>,
+        _user_data: *mut std::os::raw::c_void,
+    ) -> vk::Bool32 {
+        let callback_data = *p_callback_data;
+        let message_id_number = callback_data.message_id_number;
+
+        let message_id_name = if callback_data.p_message_id_name.is_null() {
+            Cow::from("")
+        } else {
+            ffi::CStr::from_ptr(callback_data.p_message_id_name).to_string_lossy()
+        };
+
+        let message = if callback_data.p_message.is_null() {
+            Cow::from("")
+        } else {
+            ffi::CStr::from_ptr(callback_data.p_message).to_string_lossy()
+        };
+
+        log::warn!(
+            "{message_severity:?}:\n{message_type:?} [{message_id_name} ({message_id_number})] : {message}\n",
+        );
+
+        vk::FALSE
+    }
+}
+
+impl Drop for Context {
+    fn drop(&mut self) {
+        unsafe {
+            _ = self.device.device_wait_idle();
+            self.device.destroy_device(None);
+            if self.debug_utils != DebugUtilsMessengerEXT::null() {
+                self.debug_utils_loader
+                    .destroy_debug_utils_messenger(self.debug_utils, None);
+            }
+            self.instance.destroy_instance(None);
+        }
+    }
+}
@@ -0,0 +1,243 @@
+use ash::vk::{
+    AccessFlags, CommandBuffer, CommandBufferAllocateInfo, CommandBufferBeginInfo,
+    CommandBufferLevel, CommandBufferUsageFlags, CommandPool, CommandPoolCreateFlags,
+    CommandPoolCreateInfo, DependencyFlags, Extent2D, Fence, FenceCreateFlags, FenceCreateInfo,
+    Format, Image, ImageAspectFlags, ImageLayout, ImageMemoryBarrier, ImageSubresourceRange,
+    ImageView, PipelineStageFlags, SampleCountFlags, Semaphore, SemaphoreCreateInfo,
+    QUEUE_FAMILY_IGNORED,
+};
+
+use crate::{error::Result, Error};
+
+use super::{AttachmentRef, Context};
+
+/// Frames the CPU may record ahead of the GPU.
+pub const FRAMES_IN_FLIGHT: usize = 2;
+
+/// A swapchain image being rendered. The command buffer is recording, and the image is in
+/// `COLOR_ATTACHMENT_OPTIMAL` layout, already cleared; it is presented by `end_frame`.
+///
+/// The depth buffer and the multisampled color target, when enabled, are cleared too and
+/// in `DEPTH_STENCIL_ATTACHMENT_OPTIMAL` and `COLOR_ATTACHMENT_OPTIMAL` layout. With
+/// multisampling, draw into `msaa`: `end_frame` resolves it into `image`, leaving what
+/// was drawn into `image` itself to be overwritten.
+pub struct Frame {
+    pub(crate) slot: usize,
+    pub(crate) image_index: u32,
+    pub(crate) command_buffer: CommandBuffer,
+    pub(crate) image: Image,
+    pub(crate) image_view: ImageView,
+    pub(crate) extent: Extent2D,
+    pub(crate) format: Format,
+    pub(crate) depth: Option<AttachmentRef>,
+    pub(crate) msaa: Option<AttachmentRef>,
+}
+
+impl Frame {
+    pub fn command_buffer(&self) -> CommandBuffer {
+        self.command_buffer
+    }
+
+    pub fn image(&self) -> Image {
+        self.image
+    }
+
+    pub fn image_view(&self) -> ImageView {
+        self.image_view
+    }
+
+    /// Frame-in-flight slot, below `FRAMES_IN_FLIGHT`. Resources used by the frame can be
+    /// kept per slot: the previous frame of the slot is done once `begin_frame` returns.
+    pub fn slot(&self) -> usize {
+        self.slot
+    }
+
+    pub fn image_index(&self) -> u32 {
+        self.image_index
+    }
+
+    pub fn extent(&self) -> Extent2D {
+        self.extent
+    }
+
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    pub fn depth(&self) -> Option<AttachmentRef> {
+        self.depth
+    }
+
+    /// Multisampled color target, `None` with a single sample.
+    pub fn msaa(&self) -> Option<AttachmentRef> {
+        self.msaa
+    }
+
+    /// Sample count of the attachments draws go into.
+    pub fn samples(&self) -> SampleCountFlags {
+        self.msaa
+            .map_or(SampleCountFlags::TYPE_1, |msaa| msaa.samples)
+    }
+}
+
+/// Command buffers and synchronisation for the frames in flight of one surface.
+pub(crate) struct Frames {
+    command_pool: CommandPool,
+    pub command_buffers: Vec<CommandBuffer>,
+    pub image_available: Vec<Semaphore>,
+    pub in_flight: Vec<Fence>,
+    /// One per swapchain image, since presentation may still wait on it when the slot is
+    /// reused.
+    pub render_finished: Vec<Semaphore>,
+    pub current: usize,
+}
+
+impl Frames {
+    pub fn new(context: &Context) -> Result<Self> {
+        let mut frames = Frames {
+            command_pool: CommandPool::null(),
+            command_buffers: Vec::new(),
+            image_available: Vec::new(),
+            in_flight: Vec::new(),
+            render_finished: Vec::new(),
+            current: 0,
+        };
+
+        match frames.create(context) {
+            Ok(()) => Ok(frames),
+            Err(error) => {
+                frames.destroy(context);
+                Err(error)
+            }
+        }
+    }
+
+    fn create(&mut self, context: &Context) -> Result<()> {
+        let device = &context.device;
+        let pool_create_info = CommandPoolCreateInfo::default()
+            .flags(CommandPoolCreateFlags::RESET_COMMAND_BUFFER)
+            .queue_family_index(context.queue_graphic.1);
+        self.command_pool = unsafe { device.create_command_pool(&pool_create_info, None) }
+            .map_err(Error::Vulkan)?;
+
+        let allocate_info = CommandBufferAllocateInfo::default()
+            .command_pool(self.command_pool)
+            .level(CommandBufferLevel::PRIMARY)
+            .command_buffer_count(FRAMES_IN_FLIGHT as u32);
+        self.command_buffers =
+            unsafe { device.allocate_command_buffers(&allocate_info) }.map_err(Error::Vulkan)?;
+
+        for _ in 0..FRAMES_IN_FLIGHT {
+            let semaphore =
+                unsafe { device.create_semaphore(&SemaphoreCreateInfo::default(), None) }
+                    .map_err(Error::Vulkan)?;
+            self.image_available.push(semaphore);
+
+            let fence_create_info = FenceCreateInfo::default().flags(FenceCreateFlags::SIGNALED);
+            let fence =
+                unsafe { device.create_fence(&fence_create_info, None) }.map_err(Error::Vulkan)?;
+            self.in_flight.push(fence);
+        }
+        Ok(())
+    }
+
+    /// Matches the per-image semaphores to a new swapchain. The device must be idle.
+    pub fn resize(&mut self, context: &Context, image_count: usize) -> Result<()> {
+        while self.render_finished.len() > image_count {
+            let semaphore = self.render_finished.pop().unwrap();
+            unsafe { context.device.destroy_semaphore(semaphore, None) };
+        }
+        while self.render_finished.len() < image_count {
+            let semaphore = unsafe {
+                context
+                    .device
+                    .create_semaphore(&SemaphoreCreateInfo::default(), None)
+            }
+            .map_err(Error::Vulkan)?;
+            self.render_finished.push(semaphore);
+        }
+        Ok(())
+    }
+
+    /// The device must be idle.
+    pub fn destroy(&mut self, context: &Context) {
+        let device = &context.device;
+        unsafe {
+            for semaphore in self
+                .image_available
+                .drain(..)
+                .chain(self.render_finished.drain(..))
+            {
+                device.destroy_semaphore(semaphore, None);
+            }
+            for fence in self.in_flight.drain(..) {
+                device.destroy_fence(fence, None);
+            }
+            if self.command_pool != CommandPool::null() {
+                device.destroy_command_pool(self.command_pool, None);
+            }
+        }
+        self.command_pool = CommandPool::null();
+        self.command_buffers.clear();
+    }
+}
+
+pub(crate) fn begin_commands(context: &Context, command_buffer: CommandBuffer) -> Result<()> {
+    let begin_info =
+        CommandBufferBeginInfo::default().flags(CommandBufferUsageFlags::ONE_TIME_SUBMIT);
+    unsafe {
+        context
+            .device
+            .reset_command_buffer(command_buffer, Default::default())
+            .map_err(Error::Vulkan)?;
+        context
+            .device
+            .begin_command_buffer(command_buffer, &begin_info)
+            .map_err(Error::Vulkan)
+    }
+}
+
+/// Records a layout transition of `range` of an image.
+#[allow(clippy::too_many_arguments)]
+pub(crate) fn transition_image(
+    context: &Context,
+    command_buffer: CommandBuffer,
+    image: Image,
+    range: ImageSubresourceRange,
+    old_layout: ImageLayout,
+    new_layout: ImageLayout,
+    src_access: AccessFlags,
+    dst_access: AccessFlags,
+    src_stage: PipelineStageFlags,
+    dst_stage: PipelineStageFlags,
+) {
+    let barrier = ImageMemoryBarrier::default()
+        .old_layout(old_layout)
+        .new_layout(new_layout)
+        .src_access_mask(src_access)
+        .dst_access_mask(dst_access)
+        .src_queue_family_index(QUEUE_FAMILY_IGNORED)
+        .dst_queue_family_index(QUEUE_FAMILY_IGNORED)
+        .image(image)
+        .subresource_range(range);
+    unsafe {
+        context.device.cmd_pipeline_barrier(
+            command_buffer,
+            src_stage,
+            dst_stage,
+            DependencyFlags::empty(),
+            &[],
+            &[],
+            &[barrier],
+        );
+    }
+}
+
+pub(crate) fn color_range() -> ImageSubresourceRange {
+    ImageSubresourceRange::default()
+        .aspect_mask(ImageAspectFlags::COLOR)
+        .base_mip_level(0)
+        .level_count(1)
+        .base_array_layer(0)
+        .layer_count(1)
+}
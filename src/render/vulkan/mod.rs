@@ -0,0 +1,219 @@
+use raw_window_handle::{HasDisplayHandle, HasWindowHandle};
+use std::collections::HashMap;
+
+use crate::{error::Result, Error, RgbaImage};
+
+mod attachment;
+mod buffer;
+mod context;
+mod frame;
+mod readback;
+mod sampler;
+mod surface;
+mod texture;
+
+pub(crate) use attachment::format_aspect;
+pub use attachment::{Attachment, AttachmentRef, DepthBuffer};
+pub use buffer::DeviceBuffer;
+pub use context::Context;
+pub use frame::{Frame, FRAMES_IN_FLIGHT};
+pub use readback::read_image;
+pub use sampler::{SamplerCache, SamplerInfo};
+pub use surface::{ColorFormat, PresentMode, Surface};
+pub use texture::{mip_levels, DeviceTexture, TextureUsage};
+
+/// Anything Vulkan can present into: alovak windows as well as windows made by winit,
+/// SDL or a GUI toolkit.
+pub trait RenderTarget: HasWindowHandle + HasDisplayHandle {}
+
+impl<T: HasWindowHandle + HasDisplayHandle + ?Sized> RenderTarget for T {}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct SurfaceId(usize);
+
+/// One device rendering into any number of windows.
+pub struct Vulkan<'a> {
+    context: Context,
+    surfaces: HashMap<SurfaceId, Surface<'a>>,
+    next_surface_id: usize,
+}
+
+impl<'a> Vulkan<'a> {
+    pub fn init<T: RenderTarget>(window: &'a T) -> Result<Self> {
+        let (vulkan, _) = Self::init_many(&[window])?;
+        Ok(vulkan)
+    }
+
+    /// Picks a device able to present to every window. Surface ids are returned in the
+    /// order of `windows`.
+    pub fn init_many(windows: &[&'a dyn RenderTarget]) -> Result<(Self, Vec<SurfaceId>)> {
+        let (context, surfaces) = Context::new(windows)?;
+
+        let mut vulkan = Vulkan {
+            context,
+            surfaces: HashMap::new(),
+            next_surface_id: 0,
+        };
+
+        let mut ids = Vec::with_capacity(windows.len());
+        let mut surfaces = windows.iter().zip(surfaces);
+        for (window, surface) in surfaces.by_ref() {
+            match vulkan.insert_surface(*window, surface) {
+                Ok(id) => ids.push(id),
+                Err(error) => {
+                    // The surfaces inserted so far are destroyed by `Drop`, the rest here.
+                    for (_, surface) in surfaces {
+                        unsafe { vulkan.context.surface_loader.destroy_surface(surface, None) };
+                    }
+                    return Err(error);
+                }
+            }
+        }
+        log::trace!("vulkan swapchains created");
+
+        Ok((vulkan, ids))
+    }
+
+    /// Starts rendering into one more window with the existing device.
+    pub fn add_surface<T: RenderTarget>(&mut self, window: &'a T) -> Result<SurfaceId> {
+        let surface = self.context.create_surface(window)?;
+        self.insert_surface(window, surface)
+    }
+
+    pub fn remove_surface(&mut self, id: SurfaceId) -> Result<()> {
+        let Some(mut surface) = self.surfaces.remove(&id) else {
+            return Err(missing_surface(id));
+        };
+        unsafe { self.context.device.device_wait_idle() }.map_err(Error::Vulkan)?;
+        surface.destroy(&self.context);
+        Ok(())
+    }
+
+    pub fn context(&self) -> &Context {
+        &self.context
+    }
+
+    pub fn surface(&self, id: SurfaceId) -> Option<&Surface<'a>> {
+        self.surfaces.get(&id)
+    }
+
+    pub fn surface_mut(&mut self, id: SurfaceId) -> Option<&mut Surface<'a>> {
+        self.surfaces.get_mut(&id)
+    }
+
+    pub fn surface_ids(&self) -> impl Iterator<Item = SurfaceId> + '_ {
+        self.surfaces.keys().copied()
+    }
+
+    pub fn window(&self, id: SurfaceId) -> Option<&'a dyn RenderTarget> {
+        self.surfaces.get(&id).map(|surface| surface.window())
+    }
+
+    /// Rebuilds the swapchain of `id`, e.g. after its window was resized.
+    pub fn recreate_swapchain(&mut self, id: SurfaceId) -> Result<()> {
+        let (context, surface) = self.surface_in(id)?;
+        surface.recreate_swapchain(context)
+    }
+
+    /// See `Surface::set_window_size`.
+    pub fn set_window_size(&mut self, id: SurfaceId, width: u32, height: u32) -> Result<()> {
+        let (_, surface) = self.surface_in(id)?;
+        surface.set_window_size(width, height);
+        Ok(())
+    }
+
+    /// See `Surface::set_color_format`.
+    pub fn set_color_format(&mut self, id: SurfaceId, color_format: ColorFormat) -> Result<()> {
+        let (context, surface) = self.surface_in(id)?;
+        surface.set_color_format(context, color_format)
+    }
+
+    /// See `Surface::set_depth_buffer`.
+    pub fn set_depth_buffer(
+        &mut self,
+        id: SurfaceId,
+        depth_buffer: Option<DepthBuffer>,
+    ) -> Result<()> {
+        let (context, surface) = self.surface_in(id)?;
+        surface.set_depth_buffer(context, depth_buffer)
+    }
+
+    /// See `Surface::set_samples`.
+    pub fn set_samples(&mut self, id: SurfaceId, samples: u32) -> Result<()> {
+        let (context, surface) = self.surface_in(id)?;
+        surface.set_samples(context, samples)
+    }
+
+    /// See `Surface::set_present_mode`.
+    pub fn set_present_mode(&mut self, id: SurfaceId, mode: PresentMode) -> Result<()> {
+        let (context, surface) = self.surface_in(id)?;
+        surface.set_present_mode(context, mode)
+    }
+
+    /// See `Surface::begin_frame`.
+    pub fn begin_frame(&mut self, id: SurfaceId) -> Result<Option<Frame>> {
+        let (context, surface) = self.surface_in(id)?;
+        surface.begin_frame(context)
+    }
+
+    pub fn end_frame(&mut self, id: SurfaceId, frame: Frame) -> Result<()> {
+        let (context, surface) = self.surface_in(id)?;
+        surface.end_frame(context, frame)
+    }
+
+    /// See `Surface::capture`.
+    pub fn capture(&mut self, id: SurfaceId) -> Result<()> {
+        let (_, surface) = self.surface_in(id)?;
+        surface.capture();
+        Ok(())
+    }
+
+    /// See `Surface::take_capture`.
+    pub fn take_capture(&mut self, id: SurfaceId) -> Result<Option<RgbaImage>> {
+        let (_, surface) = self.surface_in(id)?;
+        Ok(surface.take_capture())
+    }
+
+    /// See `Surface::flush_captures`.
+    pub fn flush_captures(&mut self, id: SurfaceId) -> Result<()> {
+        let (context, surface) = self.surface_in(id)?;
+        surface.flush_captures(context)
+    }
+
+    /// The surface `id` with the context its methods take.
+    fn surface_in(&mut self, id: SurfaceId) -> Result<(&Context, &mut Surface<'a>)> {
+        match self.surfaces.get_mut(&id) {
+            Some(surface) => Ok((&self.context, surface)),
+            None => Err(missing_surface(id)),
+        }
+    }
+
+    fn insert_surface(
+        &mut self,
+        window: &'a dyn RenderTarget,
+        surface: ash::vk::SurfaceKHR,
+    ) -> Result<SurfaceId> {
+        let surface = Surface::new(&self.context, window, surface)?;
+        let id = SurfaceId(self.next_surface_id);
+        self.next_surface_id += 1;
+        self.surfaces.insert(id, surface);
+        Ok(id)
+    }
+}
+
+impl Drop for Vulkan<'_> {
+    fn drop(&mut self) {
+        _ = unsafe { self.context.device.device_wait_idle() };
+        for surface in self.surfaces.values_mut() {
+            surface.destroy(&self.context);
+        }
+    }
+}
+
+fn missing_surface(id: SurfaceId) -> Error {
+    Error::Other(format!("Surface {:?} dont found", id))
+}
+
+fn handle_error(error: raw_window_handle::HandleError) -> Error {
+    Error::Other(error.to_string())
+}
@@ -0,0 +1,482 @@
+use ash::vk::{
+    AccessFlags, Buffer, BufferCreateInfo, BufferImageCopy, BufferMemoryBarrier, BufferUsageFlags,
+    ColorSpaceKHR, CommandBuffer, DependencyFlags, DeviceMemory, Extent2D, Extent3D, Format, Image,
+    ImageAspectFlags, ImageLayout, ImageSubresourceLayers, MemoryAllocateInfo, MemoryMapFlags,
+    MemoryPropertyFlags, PipelineStageFlags, SharingMode, QUEUE_FAMILY_IGNORED, WHOLE_SIZE,
+};
+
+use crate::{error::Result, Error, RgbaImage};
+
+use super::{frame, Context};
+
+/// Bytes per pixel of the formats that can be read back.
+fn pixel_size(format: Format) -> Result<usize> {
+    match format {
+        Format::B8G8R8A8_UNORM
+        | Format::B8G8R8A8_SRGB
+        | Format::R8G8B8A8_UNORM
+        | Format::R8G8B8A8_SRGB
+        | Format::A2B10G10R10_UNORM_PACK32
+        | Format::A2R10G10B10_UNORM_PACK32 => Ok(4),
+        Format::R16G16B16A16_SFLOAT => Ok(8),
+        _ => Err(Error::Other(format!(
+            "Format {:?} isn`t supported for readback",
+            format
+        ))),
+    }
+}
+
+/// Fails unless `to_rgba8` can convert `format` from `color_space`.
+fn check_color_space(format: Format, color_space: ColorSpaceKHR) -> Result<()> {
+    let supported = match color_space {
+        ColorSpaceKHR::SRGB_NONLINEAR => true,
+        ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT => format == Format::R16G16B16A16_SFLOAT,
+        ColorSpaceKHR::HDR10_ST2084_EXT => matches!(
+            format,
+            Format::A2B10G10R10_UNORM_PACK32 | Format::A2R10G10B10_UNORM_PACK32
+        ),
+        _ => false,
+    };
+    if supported {
+        Ok(())
+    } else {
+        Err(Error::Other(format!(
+            "Color space {:?} of {:?} isn`t supported for readback",
+            color_space, format
+        )))
+    }
+}
+
+/// Host-visible buffer an image is copied into.
+pub(crate) struct ReadbackBuffer {
+    buffer: Buffer,
+    memory: DeviceMemory,
+    size: u64,
+    /// Format, color space and extent of a copy that was recorded but not read yet.
+    pub pending: Option<(Format, ColorSpaceKHR, Extent2D)>,
+}
+
+impl ReadbackBuffer {
+    pub fn new(context: &Context, size: u64) -> Result<Self> {
+        let mut readback = ReadbackBuffer {
+            buffer: Buffer::null(),
+            memory: DeviceMemory::null(),
+            size,
+            pending: None,
+        };
+        match readback.create(context) {
+            Ok(()) => Ok(readback),
+            Err(error) => {
+                readback.destroy(context);
+                Err(error)
+            }
+        }
+    }
+
+    fn create(&mut self, context: &Context) -> Result<()> {
+        let device = &context.device;
+        let buffer_create_info = BufferCreateInfo::default()
+            .size(self.size)
+            .usage(BufferUsageFlags::TRANSFER_DST)
+            .sharing_mode(SharingMode::EXCLUSIVE);
+        self.buffer =
+            unsafe { device.create_buffer(&buffer_create_info, None) }.map_err(Error::Vulkan)?;
+
+        let requirements = unsafe { device.get_buffer_memory_requirements(self.buffer) };
+        let allocate_info = MemoryAllocateInfo::default()
+            .allocation_size(requirements.size)
+            .memory_type_index(context.memory_type(
+                requirements.memory_type_bits,
+                MemoryPropertyFlags::HOST_VISIBLE | MemoryPropertyFlags::HOST_COHERENT,
+            )?);
+        self.memory =
+            unsafe { device.allocate_memory(&allocate_info, None) }.map_err(Error::Vulkan)?;
+        unsafe { device.bind_buffer_memory(self.buffer, self.memory, 0) }.map_err(Error::Vulkan)
+    }
+
+    pub fn size(&self) -> u64 {
+        self.size
+    }
+
+    /// Bytes needed to read back an image.
+    pub fn required_size(format: Format, extent: Extent2D) -> Result<u64> {
+        Ok(pixel_size(format)? as u64 * extent.width as u64 * extent.height as u64)
+    }
+
+    /// Records a copy of a color image in `layout` into the buffer. The image is back in
+    /// `layout` afterwards.
+    #[allow(clippy::too_many_arguments)]
+    pub fn record(
+        &mut self,
+        context: &Context,
+        command_buffer: CommandBuffer,
+        image: Image,
+        layout: ImageLayout,
+        format: Format,
+        color_space: ColorSpaceKHR,
+        extent: Extent2D,
+    ) -> Result<()> {
+        check_color_space(format, color_space)?;
+        if Self::required_size(format, extent)? > self.size {
+            return Err(Error::Other("Readback buffer is too small".to_owned()));
+        }
+
+        frame::transition_image(
+            context,
+            command_buffer,
+            image,
+            frame::color_range(),
+            layout,
+            ImageLayout::TRANSFER_SRC_OPTIMAL,
+            AccessFlags::MEMORY_WRITE,
+            AccessFlags::TRANSFER_READ,
+            PipelineStageFlags::ALL_COMMANDS,
+            PipelineStageFlags::TRANSFER,
+        );
+        let region = BufferImageCopy::default()
+            .buffer_offset(0)
+            .buffer_row_length(0)
+            .buffer_image_height(0)
+            .image_subresource(
+                ImageSubresourceLayers::default()
+                    .aspect_mask(ImageAspectFlags::COLOR)
+                    .mip_level(0)
+                    .base_array_layer(0)
+                    .layer_count(1),
+            )
+            .image_extent(Extent3D {
+                width: extent.width,
+                height: extent.height,
+                depth: 1,
+            });
+        let buffer_barrier = BufferMemoryBarrier::default()
+            .src_access_mask(AccessFlags::TRANSFER_WRITE)
+            .dst_access_mask(AccessFlags::HOST_READ)
+            .src_queue_family_index(QUEUE_FAMILY_IGNORED)
+            .dst_queue_family_index(QUEUE_FAMILY_IGNORED)
+            .buffer(self.buffer)
+            .offset(0)
+            .size(WHOLE_SIZE);
+        unsafe {
+            context.device.cmd_copy_image_to_buffer(
+                command_buffer,
+                image,
+                ImageLayout::TRANSFER_SRC_OPTIMAL,
+                self.buffer,
+                &[region],
+            );
+            context.device.cmd_pipeline_barrier(
+                command_buffer,
+                PipelineStageFlags::TRANSFER,
+                PipelineStageFlags::HOST,
+                DependencyFlags::empty(),
+                &[],
+                &[buffer_barrier],
+                &[],
+            );
+        }
+        frame::transition_image(
+            context,
+            command_buffer,
+            image,
+            frame::color_range(),
+            ImageLayout::TRANSFER_SRC_OPTIMAL,
+            layout,
+            AccessFlags::TRANSFER_READ,
+            AccessFlags::MEMORY_READ | AccessFlags::MEMORY_WRITE,
+            PipelineStageFlags::TRANSFER,
+            PipelineStageFlags::ALL_COMMANDS,
+        );
+        self.pending = Some((format, color_space, extent));
+        Ok(())
+    }
+
+    /// Converts the pending copy. The commands recording it must have finished.
+    pub fn read(&mut self, context: &Context) -> Result<Option<RgbaImage>> {
+        let Some((format, color_space, extent)) = self.pending.take() else {
+            return Ok(None);
+        };
+        let size = Self::required_size(format, extent)? as usize;
+        let image = unsafe {
+            let data = context
+                .device
+                .map_memory(self.memory, 0, size as u64, MemoryMapFlags::empty())
+                .map_err(Error::Vulkan)?;
+            let bytes = std::slice::from_raw_parts(data as *const u8, size);
+            let image = to_rgba8(format, color_space, extent, bytes);
+            context.device.unmap_memory(self.memory);
+            image
+        };
+        image.map(Some)
+    }
+
+    /// The device must be done with the buffer.
+    pub fn destroy(&mut self, context: &Context) {
+        unsafe {
+            if self.buffer != Buffer::null() {
+                context.device.destroy_buffer(self.buffer, None);
+            }
+            if self.memory != DeviceMemory::null() {
+                context.device.free_memory(self.memory, None);
+            }
+        }
+        self.buffer = Buffer::null();
+        self.memory = DeviceMemory::null();
+        self.pending = None;
+    }
+}
+
+/// Copies a color image to the host and waits for it, e.g. an offscreen target after
+/// rendering. The image needs `TRANSFER_SRC` usage and must be in `layout`, which it is
+/// left in. Blocks until the graphic queue is done with the copy.
+pub fn read_image(
+    context: &Context,
+    image: Image,
+    layout: ImageLayout,
+    format: Format,
+    extent: Extent2D,
+) -> Result<RgbaImage> {
+    let mut readback =
+        ReadbackBuffer::new(context, ReadbackBuffer::required_size(format, extent)?)?;
+    let result = context
+        .submit_and_wait(|command_buffer| {
+            readback.record(
+                context,
+                command_buffer,
+                image,
+                layout,
+                format,
+                ColorSpaceKHR::SRGB_NONLINEAR,
+                extent,
+            )
+        })
+        .and_then(|()| readback.read(context)?.ok_or(Error::Unknown));
+    readback.destroy(context);
+    result
+}
+
+/// Converts pixels of `format` to 8-bit sRGB RGBA. In the sRGB color space UNORM formats
+/// are taken as already encoded, like the swapchain presents them, and float formats as
+/// linear. HDR10 is decoded to BT.709 with its 203 nit reference white at 1.0, and
+/// whatever is brighter or out of gamut clipped.
+pub(crate) fn to_rgba8(
+    format: Format,
+    color_space: ColorSpaceKHR,
+    extent: Extent2D,
+    bytes: &[u8],
+) -> Result<RgbaImage> {
+    check_color_space(format, color_space)?;
+    let count = extent.width as usize * extent.height as usize;
+    let mut pixels = Vec::with_capacity(count * 4);
+    match format {
+        Format::R8G8B8A8_UNORM | Format::R8G8B8A8_SRGB => {
+            pixels.extend_from_slice(&bytes[..count * 4]);
+        }
+        Format::B8G8R8A8_UNORM | Format::B8G8R8A8_SRGB => {
+            for pixel in bytes[..count * 4].chunks_exact(4) {
+                pixels.extend([pixel[2], pixel[1], pixel[0], pixel[3]]);
+            }
+        }
+        Format::A2B10G10R10_UNORM_PACK32 | Format::A2R10G10B10_UNORM_PACK32 => {
+            for pixel in bytes[..count * 4].chunks_exact(4) {
+                let value = u32::from_le_bytes(pixel.try_into().unwrap());
+                let channel = |shift: u32| (value >> shift) & 0x3ff;
+                let (low, high) = (channel(0), channel(20));
+                let (r, b) = if format == Format::A2B10G10R10_UNORM_PACK32 {
+                    (low, high)
+                } else {
+                    (high, low)
+                };
+                let [r, g, b] = if color_space == ColorSpaceKHR::HDR10_ST2084_EXT {
+                    bt2020_to_srgb([r, channel(10), b].map(|c| decode_pq(c as f32 / 1023.0)))
+                } else {
+                    [r, channel(10), b].map(|c| (c >> 2) as u8)
+                };
+                pixels.extend([r, g, b, ((value >> 30) * 85) as u8]);
+            }
+        }
+        Format::R16G16B16A16_SFLOAT => {
+            for pixel in bytes[..count * 8].chunks_exact(8) {
+                let channel = |index: usize| {
+                    f16_to_f32(u16::from_le_bytes([pixel[index * 2], pixel[index * 2 + 1]]))
+                };
+                pixels.extend([
+                    encode_srgb(channel(0)),
+                    encode_srgb(channel(1)),
+                    encode_srgb(channel(2)),
+                    (channel(3).clamp(0.0, 1.0) * 255.0).round() as u8,
+                ]);
+            }
+        }
+        _ => return Err(pixel_size(format).unwrap_err()),
+    }
+    RgbaImage::new(extent.width, extent.height, pixels)
+}
+
+/// ST 2084 (PQ) to linear light, 1.0 being the 203 nit reference white of BT.2408.
+fn decode_pq(encoded: f32) -> f32 {
+    const M1: f32 = 2610.0 / 16384.0;
+    const M2: f32 = 2523.0 / 4096.0 * 128.0;
+    const C1: f32 = 3424.0 / 4096.0;
+    const C2: f32 = 2413.0 / 4096.0 * 32.0;
+    const C3: f32 = 2392.0 / 4096.0 * 32.0;
+    let power = encoded.clamp(0.0, 1.0).powf(1.0 / M2);
+    let nits = 10_000.0 * ((power - C1).max(0.0) / (C2 - C3 * power)).powf(1.0 / M1);
+    nits / 203.0
+}
+
+/// Linear BT.2020 to sRGB encoded BT.709.
+fn bt2020_to_srgb([r, g, b]: [f32; 3]) -> [u8; 3] {
+    [
+        encode_srgb(1.6605 * r - 0.5876 * g - 0.0728 * b),
+        encode_srgb(-0.1246 * r + 1.1329 * g - 0.0083 * b),
+        encode_srgb(-0.0182 * r - 0.1006 * g + 1.1187 * b),
+    ]
+}
+
+/// Linear to sRGB transfer function, clamped to the displayable range.
+fn encode_srgb(linear: f32) -> u8 {
+    let linear = linear.clamp(0.0, 1.0);
+    let encoded = if linear <= 0.003_130_8 {
+        linear * 12.92
+    } else {
+        1.055 * linear.powf(1.0 / 2.4) - 0.055
+    };
+    (encoded * 255.0).round() as u8
+}
+
+fn f16_to_f32(half: u16) -> f32 {
+    let sign = if half & 0x8000 != 0 { -1.0 } else { 1.0 };
+    let exponent = ((half >> 10) & 0x1f) as i32;
+    let mantissa = (half & 0x3ff) as f32;
+    match exponent {
+        0 => sign * mantissa * 2f32.powi(-24),
+        0x1f if mantissa == 0.0 => sign * f32::INFINITY,
+        0x1f => f32::NAN,
+        _ => sign * (1.0 + mantissa / 1024.0) * 2f32.powi(exponent - 15),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const EXTENT: Extent2D = Extent2D {
+        width: 2,
+        height: 1,
+    };
+
+    fn convert(format: Format, color_space: ColorSpaceKHR, bytes: &[u8]) -> Vec<u8> {
+        to_rgba8(format, color_space, EXTENT, bytes)
+            .unwrap()
+            .into_pixels()
+    }
+
+    fn pack10(r: u32, g: u32, b: u32, a: u32) -> [u8; 4] {
+        (a << 30 | b << 20 | g << 10 | r).to_le_bytes()
+    }
+
+    #[test]
+    fn bgra_is_swizzled() {
+        let bytes = [1, 2, 3, 4, 5, 6, 7, 8];
+        let srgb = ColorSpaceKHR::SRGB_NONLINEAR;
+        assert_eq!(
+            convert(Format::B8G8R8A8_UNORM, srgb, &bytes),
+            [3, 2, 1, 4, 7, 6, 5, 8]
+        );
+        assert_eq!(convert(Format::R8G8B8A8_SRGB, srgb, &bytes), bytes);
+    }
+
+    #[test]
+    fn ten_bit_channels_are_unpacked_in_order() {
+        let mut bytes = pack10(1023, 512, 4, 3).to_vec();
+        bytes.extend(pack10(0, 1023, 0, 1));
+        let srgb = ColorSpaceKHR::SRGB_NONLINEAR;
+        assert_eq!(
+            convert(Format::A2B10G10R10_UNORM_PACK32, srgb, &bytes),
+            [255, 128, 1, 255, 0, 255, 0, 85]
+        );
+        // The same bits with red and blue trading places.
+        assert_eq!(
+            convert(Format::A2R10G10B10_UNORM_PACK32, srgb, &bytes),
+            [1, 128, 255, 255, 0, 255, 0, 85]
+        );
+    }
+
+    #[test]
+    fn hdr10_is_decoded_to_srgb() {
+        // 594 is 203 nits in PQ, the reference white.
+        let mut bytes = pack10(594, 594, 594, 3).to_vec();
+        bytes.extend(pack10(0, 1023, 0, 3));
+        let pixels = convert(
+            Format::A2B10G10R10_UNORM_PACK32,
+            ColorSpaceKHR::HDR10_ST2084_EXT,
+            &bytes,
+        );
+        assert!(pixels[..3].iter().all(|c| *c >= 254), "{:?}", pixels);
+        // 10000 nit BT.2020 green clips to BT.709 green.
+        assert_eq!(pixels[4..], [0, 255, 0, 255]);
+    }
+
+    #[test]
+    fn unknown_color_spaces_are_rejected() {
+        let bytes = [0; 8];
+        for (format, color_space) in [
+            (
+                Format::B8G8R8A8_UNORM,
+                ColorSpaceKHR::DISPLAY_P3_NONLINEAR_EXT,
+            ),
+            (Format::B8G8R8A8_UNORM, ColorSpaceKHR::HDR10_ST2084_EXT),
+            (
+                Format::A2B10G10R10_UNORM_PACK32,
+                ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT,
+            ),
+        ] {
+            assert!(to_rgba8(format, color_space, EXTENT, &bytes).is_err());
+        }
+    }
+
+    #[test]
+    fn half_floats_decode_edge_cases() {
+        assert_eq!(f16_to_f32(0x0000), 0.0);
+        assert!(f16_to_f32(0x8000).is_sign_negative());
+        assert_eq!(f16_to_f32(0x3c00), 1.0);
+        assert_eq!(f16_to_f32(0xc000), -2.0);
+        assert_eq!(f16_to_f32(0x0001), 2f32.powi(-24));
+        assert_eq!(f16_to_f32(0x03ff), 1023.0 * 2f32.powi(-24));
+        assert_eq!(f16_to_f32(0x0400), 2f32.powi(-14));
+        assert_eq!(f16_to_f32(0x7bff), 65504.0);
+        assert_eq!(f16_to_f32(0x7c00), f32::INFINITY);
+        assert_eq!(f16_to_f32(0xfc00), f32::NEG_INFINITY);
+        assert!(f16_to_f32(0x7e00).is_nan());
+    }
+
+    #[test]
+    fn srgb_encoding_clamps() {
+        assert_eq!(encode_srgb(-1.0), 0);
+        assert_eq!(encode_srgb(0.0), 0);
+        assert_eq!(encode_srgb(0.002), 7);
+        assert_eq!(encode_srgb(0.5), 188);
+        assert_eq!(encode_srgb(1.0), 255);
+        assert_eq!(encode_srgb(f32::INFINITY), 255);
+    }
+
+    #[test]
+    fn half_floats_are_linear() {
+        let half = |value: u16| value.to_le_bytes();
+        let bytes: Vec<u8> = [
+            0x3800, 0x3c00, 0xbc00, 0x3800, 0x7c00, 0x0000, 0x3c00, 0x4000,
+        ]
+        .into_iter()
+        .flat_map(half)
+        .collect();
+        assert_eq!(
+            convert(
+                Format::R16G16B16A16_SFLOAT,
+                ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT,
+                &bytes
+            ),
+            [188, 255, 0, 128, 255, 0, 255, 255]
+        );
+    }
+}
@@ -0,0 +1,104 @@
+use std::collections::HashMap;
+
+use ash::vk::{
+    BorderColor, CompareOp, Filter, Sampler, SamplerAddressMode, SamplerCreateInfo,
+    SamplerMipmapMode, LOD_CLAMP_NONE,
+};
+
+use crate::{error::Result, Error};
+
+use super::Context;
+
+/// How a sampler filters and wraps a texture.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct SamplerInfo {
+    pub mag_filter: Filter,
+    pub min_filter: Filter,
+    pub mipmap_mode: SamplerMipmapMode,
+    /// `false` samples the base level only.
+    pub mipmaps: bool,
+    pub address_u: SamplerAddressMode,
+    pub address_v: SamplerAddressMode,
+}
+
+impl Default for SamplerInfo {
+    fn default() -> Self {
+        SamplerInfo {
+            mag_filter: Filter::LINEAR,
+            min_filter: Filter::LINEAR,
+            mipmap_mode: SamplerMipmapMode::LINEAR,
+            mipmaps: true,
+            address_u: SamplerAddressMode::REPEAT,
+            address_v: SamplerAddressMode::REPEAT,
+        }
+    }
+}
+
+impl SamplerInfo {
+    /// Nearest filtering without mipmaps, e.g. for pixel art.
+    pub fn nearest() -> Self {
+        SamplerInfo {
+            mag_filter: Filter::NEAREST,
+            min_filter: Filter::NEAREST,
+            mipmap_mode: SamplerMipmapMode::NEAREST,
+            mipmaps: false,
+            ..Default::default()
+        }
+    }
+
+    pub fn address(mut self, mode: SamplerAddressMode) -> Self {
+        self.address_u = mode;
+        self.address_v = mode;
+        self
+    }
+}
+
+/// Samplers by description, so textures with the same settings share one.
+#[derive(Debug, Default)]
+pub struct SamplerCache {
+    samplers: HashMap<SamplerInfo, Sampler>,
+}
+
+impl SamplerCache {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// The sampler for `info`, created on first use.
+    pub fn get(&mut self, context: &Context, info: SamplerInfo) -> Result<Sampler> {
+        if let Some(sampler) = self.samplers.get(&info) {
+            return Ok(*sampler);
+        }
+        let sampler_create_info = SamplerCreateInfo::default()
+            .mag_filter(info.mag_filter)
+            .min_filter(info.min_filter)
+            .mipmap_mode(info.mipmap_mode)
+            .address_mode_u(info.address_u)
+            .address_mode_v(info.address_v)
+            .address_mode_w(info.address_u)
+            .compare_op(CompareOp::ALWAYS)
+            .border_color(BorderColor::FLOAT_TRANSPARENT_BLACK)
+            .min_lod(0.0)
+            .max_lod(if info.mipmaps { LOD_CLAMP_NONE } else { 0.0 });
+        let sampler = unsafe { context.device.create_sampler(&sampler_create_info, None) }
+            .map_err(Error::Vulkan)?;
+        log::trace!("vulkan sampler created for {:?}", info);
+        self.samplers.insert(info, sampler);
+        Ok(sampler)
+    }
+
+    pub fn len(&self) -> usize {
+        self.samplers.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.samplers.is_empty()
+    }
+
+    /// The device must be done with every sampler.
+    pub fn destroy(&mut self, context: &Context) {
+        for (_, sampler) in self.samplers.drain() {
+            unsafe { context.device.destroy_sampler(sampler, None) };
+        }
+    }
+}
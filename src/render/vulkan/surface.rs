@@ -0,0 +1,990 @@
+use ash::vk::{
+    self, AccessFlags, ClearColorValue, ClearDepthStencilValue, ColorSpaceKHR,
+    CompositeAlphaFlagsKHR, Extent2D, Extent3D, Fence, Format, Image, ImageAspectFlags,
+    ImageLayout, ImageResolve, ImageSubresourceLayers, ImageSubresourceRange, ImageUsageFlags,
+    ImageView, ImageViewCreateInfo, ImageViewType, PipelineStageFlags, PresentInfoKHR,
+    PresentModeKHR, SampleCountFlags, SharingMode, SubmitInfo, SurfaceFormatKHR, SurfaceKHR,
+    SwapchainCreateInfoKHR, SwapchainKHR,
+};
+
+use std::collections::VecDeque;
+
+use crate::{error::Result, Error, RgbaImage};
+
+use super::{
+    attachment::{self, Attachment, AttachmentRef, DepthBuffer},
+    frame::{self, Frame, Frames, FRAMES_IN_FLIGHT},
+    readback::ReadbackBuffer,
+    Context, RenderTarget,
+};
+
+/// Stages the frame's commands wait for the acquired image at. The transition out of
+/// `UNDEFINED` starts there too, so it comes after the presentation engine let go of it.
+const ACQUIRE_WAIT_STAGES: PipelineStageFlags = PipelineStageFlags::from_raw(
+    PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT.as_raw() | PipelineStageFlags::TRANSFER.as_raw(),
+);
+
+/// Presentation policy, mapped onto the Vulkan present modes the surface supports.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PresentMode {
+    /// Waits for vertical blank (FIFO). Never tears, supported everywhere.
+    VSync,
+    /// Doesn't wait for vertical blank but still doesn't tear (MAILBOX). Falls back to
+    /// IMMEDIATE, then FIFO.
+    VSyncOff,
+    /// Waits for vertical blank unless the frame is late, then presents at once and may
+    /// tear (FIFO_RELAXED). Falls back to FIFO.
+    Adaptive,
+    /// Presents at once and may tear (IMMEDIATE). Falls back to MAILBOX, then FIFO.
+    LowLatency,
+}
+
+impl PresentMode {
+    /// Present modes to try, most wanted first. FIFO is used when none is supported.
+    pub fn candidates(self) -> &'static [PresentModeKHR] {
+        match self {
+            PresentMode::VSync => &[PresentModeKHR::FIFO],
+            PresentMode::VSyncOff => &[PresentModeKHR::MAILBOX, PresentModeKHR::IMMEDIATE],
+            PresentMode::Adaptive => &[PresentModeKHR::FIFO_RELAXED],
+            PresentMode::LowLatency => &[PresentModeKHR::IMMEDIATE, PresentModeKHR::MAILBOX],
+        }
+    }
+}
+
+/// Pixel format and color space of the swapchain images.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ColorFormat {
+    /// 8-bit sRGB; shaders write linear values which are encoded on store.
+    Srgb8,
+    /// 8-bit UNORM in the sRGB color space; shaders write already encoded values.
+    Unorm8,
+    /// 10-bit UNORM in the sRGB color space.
+    Rgb10,
+    /// 10-bit HDR10: BT.2020 primaries with the ST 2084 (PQ) transfer function.
+    Hdr10,
+    /// 16-bit float scRGB: linear BT.709 primaries, values above 1.0 are brighter than
+    /// SDR white.
+    ExtendedSrgbLinear,
+}
+
+impl ColorFormat {
+    /// Surface formats that give this color format, most wanted first.
+    pub fn candidates(self) -> &'static [(Format, ColorSpaceKHR)] {
+        match self {
+            ColorFormat::Srgb8 => &[
+                (Format::B8G8R8A8_SRGB, ColorSpaceKHR::SRGB_NONLINEAR),
+                (Format::R8G8B8A8_SRGB, ColorSpaceKHR::SRGB_NONLINEAR),
+            ],
+            ColorFormat::Unorm8 => &[
+                (Format::B8G8R8A8_UNORM, ColorSpaceKHR::SRGB_NONLINEAR),
+                (Format::R8G8B8A8_UNORM, ColorSpaceKHR::SRGB_NONLINEAR),
+            ],
+            ColorFormat::Rgb10 => &[
+                (
+                    Format::A2B10G10R10_UNORM_PACK32,
+                    ColorSpaceKHR::SRGB_NONLINEAR,
+                ),
+                (
+                    Format::A2R10G10B10_UNORM_PACK32,
+                    ColorSpaceKHR::SRGB_NONLINEAR,
+                ),
+            ],
+            ColorFormat::Hdr10 => &[
+                (
+                    Format::A2B10G10R10_UNORM_PACK32,
+                    ColorSpaceKHR::HDR10_ST2084_EXT,
+                ),
+                (
+                    Format::A2R10G10B10_UNORM_PACK32,
+                    ColorSpaceKHR::HDR10_ST2084_EXT,
+                ),
+            ],
+            ColorFormat::ExtendedSrgbLinear => &[(
+                Format::R16G16B16A16_SFLOAT,
+                ColorSpaceKHR::EXTENDED_SRGB_LINEAR_EXT,
+            )],
+        }
+    }
+
+    /// The color format a surface format belongs to, if any.
+    pub fn of(format: SurfaceFormatKHR) -> Option<Self> {
+        [
+            ColorFormat::Srgb8,
+            ColorFormat::Unorm8,
+            ColorFormat::Rgb10,
+            ColorFormat::Hdr10,
+            ColorFormat::ExtendedSrgbLinear,
+        ]
+        .into_iter()
+        .find(|color_format| {
+            color_format
+                .candidates()
+                .contains(&(format.format, format.color_space))
+        })
+    }
+
+    /// Picks the surface format for this color format. When the surface doesn't support it,
+    /// falls back to `Srgb8`, then `Unorm8`, then whatever the surface lists first.
+    fn pick(self, formats: &[SurfaceFormatKHR]) -> Option<SurfaceFormatKHR> {
+        [self, ColorFormat::Srgb8, ColorFormat::Unorm8]
+            .iter()
+            .flat_map(|color_format| color_format.candidates())
+            .find_map(|(format, color_space)| {
+                formats
+                    .iter()
+                    .find(|f| f.format == *format && f.color_space == *color_space)
+            })
+            .or(formats.first())
+            .copied()
+    }
+}
+
+/// Per-window presentation state: the surface and its swapchain.
+pub struct Surface<'a> {
+    window: &'a dyn RenderTarget,
+    surface: SurfaceKHR,
+    swapchain: SwapchainKHR,
+    format: SurfaceFormatKHR,
+    requested_color_format: ColorFormat,
+    present_mode: PresentModeKHR,
+    /// Present modes to use, most wanted first.
+    preferred_present_modes: Vec<PresentModeKHR>,
+    extent: Extent2D,
+    /// Size of the window, for surfaces leaving the extent to the swapchain.
+    window_size: Option<Extent2D>,
+    usage: ImageUsageFlags,
+    images: Vec<Image>,
+    image_views: Vec<ImageView>,
+    frames: Frames,
+    clear_color: [f32; 4],
+    depth_buffer: Option<DepthBuffer>,
+    requested_samples: u32,
+    depth: Option<Attachment>,
+    msaa: Option<Attachment>,
+    capture_requested: bool,
+    /// Per frame slot, read once the slot's fence has been waited for.
+    readbacks: Vec<Option<ReadbackBuffer>>,
+    captures: VecDeque<RgbaImage>,
+}
+
+impl<'a> Surface<'a> {
+    pub(crate) fn new(
+        context: &Context,
+        window: &'a dyn RenderTarget,
+        surface: SurfaceKHR,
+    ) -> Result<Self> {
+        let frames = match Frames::new(context) {
+            Ok(frames) => frames,
+            Err(error) => {
+                unsafe { context.surface_loader.destroy_surface(surface, None) };
+                return Err(error);
+            }
+        };
+        let mut value = Surface {
+            window,
+            surface,
+            swapchain: SwapchainKHR::null(),
+            format: SurfaceFormatKHR::default(),
+            requested_color_format: ColorFormat::Srgb8,
+            present_mode: PresentModeKHR::FIFO,
+            preferred_present_modes: vec![PresentModeKHR::MAILBOX],
+            extent: Extent2D::default(),
+            window_size: None,
+            usage: ImageUsageFlags::COLOR_ATTACHMENT,
+            images: Vec::new(),
+            image_views: Vec::new(),
+            frames,
+            clear_color: [0.0, 0.0, 0.0, 1.0],
+            depth_buffer: None,
+            requested_samples: 1,
+            depth: None,
+            msaa: None,
+            capture_requested: false,
+            readbacks: (0..FRAMES_IN_FLIGHT).map(|_| None).collect(),
+            captures: VecDeque::new(),
+        };
+        if let Err(error) = value.create_swapchain(context) {
+            value.destroy(context);
+            return Err(error);
+        }
+        Ok(value)
+    }
+
+    pub fn window(&self) -> &'a dyn RenderTarget {
+        self.window
+    }
+
+    pub fn swapchain(&self) -> SwapchainKHR {
+        self.swapchain
+    }
+
+    pub fn format(&self) -> SurfaceFormatKHR {
+        self.format
+    }
+
+    /// Color format of the swapchain, which may differ from the requested one. `None` if
+    /// the surface only offered a format alovak doesn't know.
+    pub fn color_format(&self) -> Option<ColorFormat> {
+        ColorFormat::of(self.format)
+    }
+
+    pub fn requested_color_format(&self) -> ColorFormat {
+        self.requested_color_format
+    }
+
+    /// Requests another color format. Takes effect on the next swapchain rebuild.
+    pub fn request_color_format(&mut self, color_format: ColorFormat) {
+        self.requested_color_format = color_format;
+    }
+
+    /// Requests another color format, rebuilding the swapchain if it changes.
+    pub fn set_color_format(&mut self, context: &Context, color_format: ColorFormat) -> Result<()> {
+        if self.requested_color_format == color_format {
+            return Ok(());
+        }
+        self.request_color_format(color_format);
+        self.recreate_swapchain(context)
+    }
+
+    pub fn present_mode(&self) -> PresentModeKHR {
+        self.present_mode
+    }
+
+    pub fn preferred_present_modes(&self) -> &[PresentModeKHR] {
+        &self.preferred_present_modes
+    }
+
+    /// Present modes to pick from, most wanted first. The first one the surface supports is
+    /// used, and FIFO, which every surface supports, when none is. Takes effect on the next
+    /// swapchain rebuild.
+    pub fn set_preferred_present_modes(&mut self, modes: &[PresentModeKHR]) {
+        self.preferred_present_modes = modes.to_vec();
+    }
+
+    /// Switches the presentation policy, rebuilding the swapchain if it changes. Check
+    /// `present_mode` for the mode actually picked.
+    pub fn set_present_mode(&mut self, context: &Context, mode: PresentMode) -> Result<()> {
+        if self.preferred_present_modes == mode.candidates() {
+            return Ok(());
+        }
+        self.set_preferred_present_modes(mode.candidates());
+        self.recreate_swapchain(context)
+    }
+
+    pub fn extent(&self) -> Extent2D {
+        self.extent
+    }
+
+    /// Tells the surface the size of its window's client area. Surfaces on Wayland and some
+    /// headless platforms have no size of their own and make their swapchain this large;
+    /// others ignore it. Takes effect on the next swapchain rebuild.
+    pub fn set_window_size(&mut self, width: u32, height: u32) {
+        self.window_size = Some(Extent2D { width, height });
+    }
+
+    pub fn images(&self) -> &[Image] {
+        &self.images
+    }
+
+    pub fn image_views(&self) -> &[ImageView] {
+        &self.image_views
+    }
+
+    pub fn clear_color(&self) -> [f32; 4] {
+        self.clear_color
+    }
+
+    /// Color every frame starts with.
+    pub fn set_clear_color(&mut self, color: [f32; 4]) {
+        self.clear_color = color;
+    }
+
+    pub fn depth_buffer(&self) -> Option<DepthBuffer> {
+        self.depth_buffer
+    }
+
+    /// Adds, changes or removes the depth buffer. Takes effect on the next swapchain
+    /// rebuild.
+    pub fn request_depth_buffer(&mut self, depth_buffer: Option<DepthBuffer>) {
+        self.depth_buffer = depth_buffer;
+    }
+
+    /// Adds, changes or removes the depth buffer, rebuilding the swapchain if it changes.
+    pub fn set_depth_buffer(
+        &mut self,
+        context: &Context,
+        depth_buffer: Option<DepthBuffer>,
+    ) -> Result<()> {
+        if self.depth_buffer == depth_buffer {
+            return Ok(());
+        }
+        self.request_depth_buffer(depth_buffer);
+        self.recreate_swapchain(context)
+    }
+
+    /// Depth buffer in use, if any.
+    pub fn depth(&self) -> Option<&Attachment> {
+        self.depth.as_ref()
+    }
+
+    /// Multisampled color target, `None` with a single sample.
+    pub fn msaa(&self) -> Option<&Attachment> {
+        self.msaa.as_ref()
+    }
+
+    pub fn requested_samples(&self) -> u32 {
+        self.requested_samples
+    }
+
+    /// Sample count in use, which may be lower than the requested one.
+    pub fn samples(&self) -> SampleCountFlags {
+        self.msaa
+            .as_ref()
+            .map_or(SampleCountFlags::TYPE_1, |msaa| msaa.samples)
+    }
+
+    /// Requests multisampling with `samples` per pixel, 1 turning it off. The count is
+    /// lowered to the nearest one the device supports. Takes effect on the next swapchain
+    /// rebuild.
+    pub fn request_samples(&mut self, samples: u32) {
+        self.requested_samples = samples;
+    }
+
+    /// Like `request_samples`, rebuilding the swapchain if the count changes.
+    pub fn set_samples(&mut self, context: &Context, samples: u32) -> Result<()> {
+        if self.requested_samples == samples {
+            return Ok(());
+        }
+        self.request_samples(samples);
+        self.recreate_swapchain(context)
+    }
+
+    /// Copies the next presented frame to the host. It shows up in `take_capture` once the
+    /// GPU is done with it, usually a couple of frames later.
+    pub fn capture(&mut self) {
+        self.capture_requested = true;
+    }
+
+    /// Oldest finished capture, with opaque alpha.
+    pub fn take_capture(&mut self) -> Option<RgbaImage> {
+        self.captures.pop_front()
+    }
+
+    /// Waits for every frame in flight and moves their captures to `take_capture`, e.g.
+    /// before shutting down so the last frames aren't lost.
+    pub fn flush_captures(&mut self, context: &Context) -> Result<()> {
+        if !self.frames.in_flight.is_empty() {
+            unsafe {
+                context
+                    .device
+                    .wait_for_fences(&self.frames.in_flight, true, u64::MAX)
+            }
+            .map_err(Error::Vulkan)?;
+        }
+        // The current slot is the next to be reused, so it holds the oldest frame.
+        for offset in 0..FRAMES_IN_FLIGHT {
+            self.collect_capture(context, (self.frames.current + offset) % FRAMES_IN_FLIGHT)?;
+        }
+        Ok(())
+    }
+
+    /// Reads the capture recorded in `slot`, whose commands must have finished.
+    fn collect_capture(&mut self, context: &Context, slot: usize) -> Result<()> {
+        if let Some(readback) = &mut self.readbacks[slot] {
+            if let Some(mut image) = readback.read(context)? {
+                for pixel in image.pixels_mut().chunks_exact_mut(4) {
+                    pixel[3] = u8::MAX;
+                }
+                self.captures.push_back(image);
+            }
+        }
+        Ok(())
+    }
+
+    /// Waits for a free frame slot, acquires the next swapchain image and starts recording.
+    /// `None` means there is nothing to render into this time: the window is minimized or
+    /// the swapchain was out of date and has been rebuilt.
+    pub fn begin_frame(&mut self, context: &Context) -> Result<Option<Frame>> {
+        if self.extent.width == 0 || self.extent.height == 0 {
+            self.recreate_swapchain(context)?;
+            if self.extent.width == 0 || self.extent.height == 0 {
+                return Ok(None);
+            }
+        }
+
+        let device = &context.device;
+        let slot = self.frames.current;
+        let fence = self.frames.in_flight[slot];
+        unsafe { device.wait_for_fences(&[fence], true, u64::MAX) }.map_err(Error::Vulkan)?;
+        self.collect_capture(context, slot)?;
+
+        let acquired = unsafe {
+            context.swapchain_loader.acquire_next_image(
+                self.swapchain,
+                u64::MAX,
+                self.frames.image_available[slot],
+                Fence::null(),
+            )
+        };
+        let image_index = match acquired {
+            Ok((index, _)) => index,
+            Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => {
+                self.recreate_swapchain(context)?;
+                return Ok(None);
+            }
+            Err(error) => return Err(Error::Vulkan(error)),
+        };
+        // Only reset once work is sure to be submitted, or the next wait would never end.
+        unsafe { device.reset_fences(&[fence]) }.map_err(Error::Vulkan)?;
+
+        let command_buffer = self.frames.command_buffers[slot];
+        let image = self.images[image_index as usize];
+        frame::begin_commands(context, command_buffer)?;
+
+        if self.usage.contains(ImageUsageFlags::TRANSFER_DST) {
+            frame::transition_image(
+                context,
+                command_buffer,
+                image,
+                frame::color_range(),
+                ImageLayout::UNDEFINED,
+                ImageLayout::TRANSFER_DST_OPTIMAL,
+                AccessFlags::empty(),
+                AccessFlags::TRANSFER_WRITE,
+                ACQUIRE_WAIT_STAGES,
+                PipelineStageFlags::TRANSFER,
+            );
+            unsafe {
+                device.cmd_clear_color_image(
+                    command_buffer,
+                    image,
+                    ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &ClearColorValue {
+                        float32: self.clear_color,
+                    },
+                    &[frame::color_range()],
+                );
+            }
+            frame::transition_image(
+                context,
+                command_buffer,
+                image,
+                frame::color_range(),
+                ImageLayout::TRANSFER_DST_OPTIMAL,
+                ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                AccessFlags::TRANSFER_WRITE,
+                AccessFlags::COLOR_ATTACHMENT_READ | AccessFlags::COLOR_ATTACHMENT_WRITE,
+                PipelineStageFlags::TRANSFER,
+                PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            );
+        } else {
+            frame::transition_image(
+                context,
+                command_buffer,
+                image,
+                frame::color_range(),
+                ImageLayout::UNDEFINED,
+                ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                AccessFlags::empty(),
+                AccessFlags::COLOR_ATTACHMENT_READ | AccessFlags::COLOR_ATTACHMENT_WRITE,
+                ACQUIRE_WAIT_STAGES,
+                PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            );
+        }
+
+        self.clear_attachments(context, command_buffer);
+
+        Ok(Some(Frame {
+            slot,
+            image_index,
+            command_buffer,
+            image,
+            image_view: self.image_views[image_index as usize],
+            extent: self.extent,
+            format: self.format.format,
+            depth: self.depth.as_ref().map(Attachment::reference),
+            msaa: self.msaa.as_ref().map(Attachment::reference),
+        }))
+    }
+
+    /// Records a copy of the frame's image into the readback buffer of its slot.
+    fn record_capture(&mut self, context: &Context, frame: &Frame) -> Result<()> {
+        if !self.usage.contains(ImageUsageFlags::TRANSFER_SRC) {
+            return Err(Error::Other(
+                "Surface images can`t be copied, capture isn`t supported".to_owned(),
+            ));
+        }
+        let size = ReadbackBuffer::required_size(frame.format, frame.extent)?;
+        let readback = &mut self.readbacks[frame.slot];
+        if readback
+            .as_ref()
+            .is_none_or(|readback| readback.size() < size)
+        {
+            if let Some(mut old) = readback.take() {
+                old.destroy(context);
+            }
+            *readback = Some(ReadbackBuffer::new(context, size)?);
+        }
+        readback.as_mut().unwrap().record(
+            context,
+            frame.command_buffer,
+            frame.image,
+            ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            frame.format,
+            self.format.color_space,
+            frame.extent,
+        )
+    }
+
+    /// Clears the depth buffer and the multisampled color target. Both are shared by the
+    /// frames in flight, so the barriers also wait for the previous frame to be done, which
+    /// last read the color target in its resolve.
+    fn clear_attachments(&self, context: &Context, command_buffer: vk::CommandBuffer) {
+        let device = &context.device;
+        if let Some(msaa) = &self.msaa {
+            frame::transition_image(
+                context,
+                command_buffer,
+                msaa.image,
+                msaa.range(),
+                ImageLayout::UNDEFINED,
+                ImageLayout::TRANSFER_DST_OPTIMAL,
+                AccessFlags::COLOR_ATTACHMENT_WRITE,
+                AccessFlags::TRANSFER_WRITE,
+                PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT | PipelineStageFlags::TRANSFER,
+                PipelineStageFlags::TRANSFER,
+            );
+            unsafe {
+                device.cmd_clear_color_image(
+                    command_buffer,
+                    msaa.image,
+                    ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &ClearColorValue {
+                        float32: self.clear_color,
+                    },
+                    &[msaa.range()],
+                );
+            }
+            frame::transition_image(
+                context,
+                command_buffer,
+                msaa.image,
+                msaa.range(),
+                ImageLayout::TRANSFER_DST_OPTIMAL,
+                ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+                AccessFlags::TRANSFER_WRITE,
+                AccessFlags::COLOR_ATTACHMENT_READ | AccessFlags::COLOR_ATTACHMENT_WRITE,
+                PipelineStageFlags::TRANSFER,
+                PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            );
+        }
+
+        if let Some(depth) = &self.depth {
+            let depth_stages =
+                PipelineStageFlags::EARLY_FRAGMENT_TESTS | PipelineStageFlags::LATE_FRAGMENT_TESTS;
+            let depth_access = AccessFlags::DEPTH_STENCIL_ATTACHMENT_READ
+                | AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE;
+            frame::transition_image(
+                context,
+                command_buffer,
+                depth.image,
+                depth.range(),
+                ImageLayout::UNDEFINED,
+                ImageLayout::TRANSFER_DST_OPTIMAL,
+                AccessFlags::DEPTH_STENCIL_ATTACHMENT_WRITE,
+                AccessFlags::TRANSFER_WRITE,
+                depth_stages,
+                PipelineStageFlags::TRANSFER,
+            );
+            unsafe {
+                device.cmd_clear_depth_stencil_image(
+                    command_buffer,
+                    depth.image,
+                    ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &ClearDepthStencilValue {
+                        depth: 1.0,
+                        stencil: 0,
+                    },
+                    &[depth.range()],
+                );
+            }
+            frame::transition_image(
+                context,
+                command_buffer,
+                depth.image,
+                depth.range(),
+                ImageLayout::TRANSFER_DST_OPTIMAL,
+                ImageLayout::DEPTH_STENCIL_ATTACHMENT_OPTIMAL,
+                AccessFlags::TRANSFER_WRITE,
+                depth_access,
+                PipelineStageFlags::TRANSFER,
+                depth_stages,
+            );
+        }
+    }
+
+    /// Resolves the multisampled color target into the frame's image, leaving the image in
+    /// `COLOR_ATTACHMENT_OPTIMAL` as it was.
+    fn record_resolve(&self, context: &Context, frame: &Frame, msaa: AttachmentRef) {
+        frame::transition_image(
+            context,
+            frame.command_buffer,
+            msaa.image,
+            msaa.range(),
+            ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            ImageLayout::TRANSFER_SRC_OPTIMAL,
+            AccessFlags::COLOR_ATTACHMENT_WRITE,
+            AccessFlags::TRANSFER_READ,
+            PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            PipelineStageFlags::TRANSFER,
+        );
+        frame::transition_image(
+            context,
+            frame.command_buffer,
+            frame.image,
+            frame::color_range(),
+            ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            ImageLayout::TRANSFER_DST_OPTIMAL,
+            AccessFlags::COLOR_ATTACHMENT_WRITE,
+            AccessFlags::TRANSFER_WRITE,
+            PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            PipelineStageFlags::TRANSFER,
+        );
+        let layers = ImageSubresourceLayers::default()
+            .aspect_mask(ImageAspectFlags::COLOR)
+            .mip_level(0)
+            .base_array_layer(0)
+            .layer_count(1);
+        let region = ImageResolve::default()
+            .src_subresource(layers)
+            .dst_subresource(layers)
+            .extent(Extent3D {
+                width: frame.extent.width,
+                height: frame.extent.height,
+                depth: 1,
+            });
+        unsafe {
+            context.device.cmd_resolve_image(
+                frame.command_buffer,
+                msaa.image,
+                ImageLayout::TRANSFER_SRC_OPTIMAL,
+                frame.image,
+                ImageLayout::TRANSFER_DST_OPTIMAL,
+                &[region],
+            )
+        };
+        frame::transition_image(
+            context,
+            frame.command_buffer,
+            frame.image,
+            frame::color_range(),
+            ImageLayout::TRANSFER_DST_OPTIMAL,
+            ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            AccessFlags::TRANSFER_WRITE,
+            AccessFlags::COLOR_ATTACHMENT_READ | AccessFlags::COLOR_ATTACHMENT_WRITE,
+            PipelineStageFlags::TRANSFER,
+            PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+        );
+    }
+
+    /// Finishes recording, submits and presents a frame from `begin_frame`. A multisampled
+    /// frame is resolved first, so captures and presentation see the resolved image.
+    pub fn end_frame(&mut self, context: &Context, frame: Frame) -> Result<()> {
+        let device = &context.device;
+        if let Some(msaa) = frame.msaa {
+            self.record_resolve(context, &frame, msaa);
+        }
+        if self.capture_requested {
+            self.capture_requested = false;
+            // The frame has to be submitted either way, or its fence is never signaled.
+            if let Err(error) = self.record_capture(context, &frame) {
+                log::warn!("vulkan capture failed: {}", error);
+            }
+        }
+        frame::transition_image(
+            context,
+            frame.command_buffer,
+            frame.image,
+            frame::color_range(),
+            ImageLayout::COLOR_ATTACHMENT_OPTIMAL,
+            ImageLayout::PRESENT_SRC_KHR,
+            AccessFlags::COLOR_ATTACHMENT_WRITE,
+            AccessFlags::empty(),
+            PipelineStageFlags::COLOR_ATTACHMENT_OUTPUT,
+            PipelineStageFlags::BOTTOM_OF_PIPE,
+        );
+        unsafe { device.end_command_buffer(frame.command_buffer) }.map_err(Error::Vulkan)?;
+
+        let wait_semaphores = [self.frames.image_available[frame.slot]];
+        let wait_stages = [ACQUIRE_WAIT_STAGES];
+        let command_buffers = [frame.command_buffer];
+        let signal_semaphores = [self.frames.render_finished[frame.image_index as usize]];
+        let submit_info = SubmitInfo::default()
+            .wait_semaphores(&wait_semaphores)
+            .wait_dst_stage_mask(&wait_stages)
+            .command_buffers(&command_buffers)
+            .signal_semaphores(&signal_semaphores);
+        unsafe {
+            device.queue_submit(
+                context.queue_graphic.0,
+                &[submit_info],
+                self.frames.in_flight[frame.slot],
+            )
+        }
+        .map_err(Error::Vulkan)?;
+        self.frames.current = (frame.slot + 1) % FRAMES_IN_FLIGHT;
+
+        let swapchains = [self.swapchain];
+        let image_indices = [frame.image_index];
+        let present_info = PresentInfoKHR::default()
+            .wait_semaphores(&signal_semaphores)
+            .swapchains(&swapchains)
+            .image_indices(&image_indices);
+        let presented = unsafe {
+            context
+                .swapchain_loader
+                .queue_present(context.queue_present.0, &present_info)
+        };
+        match presented {
+            Ok(false) => Ok(()),
+            Ok(true) | Err(vk::Result::ERROR_OUT_OF_DATE_KHR) => self.recreate_swapchain(context),
+            Err(error) => Err(Error::Vulkan(error)),
+        }
+    }
+
+    /// Rebuilds the swapchain, e.g. after the window was resized.
+    pub fn recreate_swapchain(&mut self, context: &Context) -> Result<()> {
+        unsafe { context.device.device_wait_idle() }.map_err(Error::Vulkan)?;
+        self.create_swapchain(context)
+    }
+
+    fn create_swapchain(&mut self, context: &Context) -> Result<()> {
+        let physical_device = context.physical_device;
+        let instance_surface = &context.surface_loader;
+
+        let surface_capability = unsafe {
+            instance_surface.get_physical_device_surface_capabilities(physical_device, self.surface)
+        }
+        .map_err(Error::Vulkan)?;
+        let surface_formats = unsafe {
+            instance_surface.get_physical_device_surface_formats(physical_device, self.surface)
+        }
+        .map_err(Error::Vulkan)?;
+        let surface_present_mods = unsafe {
+            instance_surface
+                .get_physical_device_surface_present_modes(physical_device, self.surface)
+        }
+        .map_err(Error::Vulkan)?;
+
+        let Some(image_format) = self.requested_color_format.pick(&surface_formats) else {
+            return Err(Error::Other("Surface has no formats".to_owned()));
+        };
+        if ColorFormat::of(image_format) != Some(self.requested_color_format) {
+            log::warn!(
+                "vulkan surface has no {:?} format, using {:?}",
+                self.requested_color_format,
+                image_format
+            );
+        }
+
+        let image_present_mode = self
+            .preferred_present_modes
+            .iter()
+            .find(|mode| surface_present_mods.contains(mode))
+            .copied()
+            .unwrap_or(PresentModeKHR::FIFO);
+
+        let mut image_count = surface_capability.min_image_count + 1;
+
+        if surface_capability.max_image_count > 0
+            && image_count > surface_capability.max_image_count
+        {
+            image_count = surface_capability.max_image_count;
+        }
+
+        // u32::MAX means the surface has no size and the swapchain decides it, so it is
+        // made as large as the window.
+        let image_extent = if surface_capability.current_extent.width == u32::MAX {
+            let (min, max) = (
+                surface_capability.min_image_extent,
+                surface_capability.max_image_extent,
+            );
+            let size = self.window_size.unwrap_or(min);
+            Extent2D {
+                width: size.width.clamp(min.width, max.width),
+                height: size.height.clamp(min.height, max.height),
+            }
+        } else {
+            surface_capability.current_extent
+        };
+        // A minimized window can't be presented to; the old swapchain is kept until it has
+        // an area again.
+        if image_extent.width == 0 || image_extent.height == 0 {
+            self.extent = image_extent;
+            return Ok(());
+        }
+
+        // Clearing frames needs transfer writes and capturing them transfer reads, so both
+        // only work when supported.
+        let image_usage = ImageUsageFlags::COLOR_ATTACHMENT
+            | (surface_capability.supported_usage_flags
+                & (ImageUsageFlags::TRANSFER_DST | ImageUsageFlags::TRANSFER_SRC));
+
+        let (queue_graphic_index, queue_present_index) =
+            (context.queue_graphic.1, context.queue_present.1);
+
+        let mut swapchain_create_info = SwapchainCreateInfoKHR::default()
+            .surface(self.surface)
+            .min_image_count(image_count)
+            .image_format(image_format.format)
+            .image_color_space(image_format.color_space)
+            .image_extent(image_extent)
+            .image_array_layers(1)
+            .image_usage(image_usage)
+            .pre_transform(surface_capability.current_transform)
+            .composite_alpha(CompositeAlphaFlagsKHR::OPAQUE)
+            .present_mode(image_present_mode)
+            .clipped(true)
+            .old_swapchain(self.swapchain);
+
+        let queue_indexes = &[queue_graphic_index, queue_present_index];
+
+        if queue_graphic_index != queue_present_index {
+            swapchain_create_info = swapchain_create_info
+                .image_sharing_mode(SharingMode::CONCURRENT)
+                .queue_family_indices(queue_indexes);
+        } else {
+            swapchain_create_info = swapchain_create_info
+                .image_sharing_mode(SharingMode::EXCLUSIVE)
+                .queue_family_indices(&[]);
+        }
+
+        let swapchain = unsafe {
+            context
+                .swapchain_loader
+                .create_swapchain(&swapchain_create_info, None)
+        }
+        .map_err(Error::Vulkan)?;
+
+        self.destroy_swapchain(context);
+        self.swapchain = swapchain;
+        self.format = image_format;
+        self.present_mode = image_present_mode;
+        self.extent = image_extent;
+        self.usage = image_usage;
+
+        self.images = unsafe { context.swapchain_loader.get_swapchain_images(swapchain) }
+            .map_err(Error::Vulkan)?;
+
+        for image in self.images.iter() {
+            let image_view_create_info = ImageViewCreateInfo::default()
+                .image(*image)
+                .view_type(ImageViewType::TYPE_2D)
+                .format(image_format.format)
+                //.components(ComponentMapping::default())
+                .subresource_range(
+                    ImageSubresourceRange::default()
+                        .aspect_mask(ImageAspectFlags::COLOR)
+                        .base_mip_level(0)
+                        .level_count(1)
+                        .base_array_layer(0)
+                        .layer_count(1),
+                );
+            let image_view = unsafe {
+                context
+                    .device
+                    .create_image_view(&image_view_create_info, None)
+            }
+            .map_err(Error::Vulkan)?;
+            self.image_views.push(image_view);
+        }
+        self.frames.resize(context, self.images.len())?;
+        self.create_attachments(context)?;
+
+        Ok(())
+    }
+
+    /// Makes the depth buffer and the multisampled color target for the current extent.
+    fn create_attachments(&mut self, context: &Context) -> Result<()> {
+        let samples = self.pick_samples(context);
+        if samples != SampleCountFlags::from_raw(self.requested_samples.max(1)) {
+            log::warn!(
+                "vulkan device can`t do {} samples, using {:?}",
+                self.requested_samples,
+                samples
+            );
+        }
+
+        if let Some(depth_buffer) = self.depth_buffer {
+            let Some(format) = attachment::pick_depth_format(context, depth_buffer.candidates())
+            else {
+                return Err(Error::Other(format!(
+                    "Depth format for {:?} dont found",
+                    depth_buffer
+                )));
+            };
+            self.depth = Some(Attachment::new(
+                context,
+                self.extent,
+                format,
+                samples,
+                ImageUsageFlags::DEPTH_STENCIL_ATTACHMENT | ImageUsageFlags::TRANSFER_DST,
+            )?);
+        }
+
+        if samples != SampleCountFlags::TYPE_1 {
+            self.msaa = Some(Attachment::new(
+                context,
+                self.extent,
+                self.format.format,
+                samples,
+                ImageUsageFlags::COLOR_ATTACHMENT | ImageUsageFlags::TRANSFER_DST,
+            )?);
+        }
+        Ok(())
+    }
+
+    /// Highest supported sample count not above the requested one. Resolving into the
+    /// swapchain images takes transfer writes, without them there is no multisampling.
+    fn pick_samples(&self, context: &Context) -> SampleCountFlags {
+        if !self.usage.contains(ImageUsageFlags::TRANSFER_DST) {
+            return SampleCountFlags::TYPE_1;
+        }
+        let supported = context.sample_counts(self.depth_buffer.is_some());
+        [64, 32, 16, 8, 4, 2]
+            .into_iter()
+            .filter(|count| *count <= self.requested_samples)
+            .map(SampleCountFlags::from_raw)
+            .find(|samples| supported.contains(*samples))
+            .unwrap_or(SampleCountFlags::TYPE_1)
+    }
+
+    fn destroy_swapchain(&mut self, context: &Context) {
+        for mut attachment in self.depth.take().into_iter().chain(self.msaa.take()) {
+            attachment.destroy(context);
+        }
+        unsafe {
+            for image_view in self.image_views.drain(..) {
+                context.device.destroy_image_view(image_view, None);
+            }
+            if self.swapchain != SwapchainKHR::null() {
+                context
+                    .swapchain_loader
+                    .destroy_swapchain(self.swapchain, None);
+            }
+        }
+        self.swapchain = SwapchainKHR::null();
+        self.images.clear();
+    }
+
+    /// The device must be idle or at least done with this surface.
+    pub(crate) fn destroy(&mut self, context: &Context) {
+        self.destroy_swapchain(context);
+        for mut readback in self.readbacks.iter_mut().filter_map(Option::take) {
+            readback.destroy(context);
+        }
+        self.frames.destroy(context);
+        unsafe { context.surface_loader.destroy_surface(self.surface, None) };
+        self.surface = SurfaceKHR::null();
+    }
+}
@@ -0,0 +1,628 @@
+use std::{borrow::Cow, path::Path};
+
+use ash::vk::{
+    AccessFlags, BufferImageCopy, BufferUsageFlags, CommandBuffer, DeviceMemory, DeviceSize,
+    Extent2D, Extent3D, Filter, Format, FormatFeatureFlags, Image, ImageAspectFlags, ImageBlit,
+    ImageCreateFlags, ImageCreateInfo, ImageLayout, ImageSubresourceLayers, ImageSubresourceRange,
+    ImageTiling, ImageType, ImageUsageFlags, ImageView, ImageViewCreateInfo, ImageViewType,
+    MemoryAllocateInfo, MemoryPropertyFlags, Offset3D, PipelineStageFlags, SampleCountFlags,
+    SharingMode,
+};
+
+use crate::{error::Result, BasisTranscoder, Error, Ktx2, RgbaImage, TranscodeTarget};
+
+use super::{frame, Context, DeviceBuffer};
+
+/// What a texture holds, which decides whether it is sampled as sRGB or linear.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum TextureUsage {
+    /// Colors to look at, e.g. base color or emissive. Decoded from sRGB when sampled.
+    Color,
+    /// Values read as they are, e.g. normals, roughness or occlusion.
+    Data,
+}
+
+impl TextureUsage {
+    pub fn format(self) -> Format {
+        match self {
+            TextureUsage::Color => Format::R8G8B8A8_SRGB,
+            TextureUsage::Data => Format::R8G8B8A8_UNORM,
+        }
+    }
+}
+
+/// Mip levels of a full chain down to 1x1.
+pub fn mip_levels(width: u32, height: u32) -> u32 {
+    32 - width.max(height).max(1).leading_zeros()
+}
+
+fn srgb_to_linear(value: u8) -> f32 {
+    let value = value as f32 / 255.0;
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+fn linear_to_srgb(value: f32) -> u8 {
+    let value = if value <= 0.0031308 {
+        value * 12.92
+    } else {
+        1.055 * value.powf(1.0 / 2.4) - 0.055
+    };
+    (value.clamp(0.0, 1.0) * 255.0 + 0.5) as u8
+}
+
+/// Halves an image with a 2x2 box filter, averaging sRGB colors in linear space.
+fn downsample(image: &RgbaImage, srgb: bool) -> RgbaImage {
+    let (width, height) = (image.width(), image.height());
+    let (next_width, next_height) = ((width / 2).max(1), (height / 2).max(1));
+    let decode: Vec<f32> = (0..=255u8)
+        .map(|value| {
+            if srgb {
+                srgb_to_linear(value)
+            } else {
+                value as f32 / 255.0
+            }
+        })
+        .collect();
+    let mut pixels = Vec::with_capacity(next_width as usize * next_height as usize * 4);
+    for y in 0..next_height {
+        for x in 0..next_width {
+            let xs = [(x * 2).min(width - 1), (x * 2 + 1).min(width - 1)];
+            let ys = [(y * 2).min(height - 1), (y * 2 + 1).min(height - 1)];
+            for channel in 0..4 {
+                let sum: f32 = ys
+                    .iter()
+                    .flat_map(|sy| xs.iter().map(move |sx| (*sx, *sy)))
+                    .map(|(sx, sy)| {
+                        let value = image.pixel(sx, sy)[channel];
+                        // Alpha is always linear.
+                        if channel == 3 {
+                            value as f32 / 255.0
+                        } else {
+                            decode[value as usize]
+                        }
+                    })
+                    .sum();
+                let average = sum / 4.0;
+                pixels.push(if srgb && channel != 3 {
+                    linear_to_srgb(average)
+                } else {
+                    (average.clamp(0.0, 1.0) * 255.0 + 0.5) as u8
+                });
+            }
+        }
+    }
+    RgbaImage::new(next_width, next_height, pixels).unwrap()
+}
+
+/// Formats Basis Universal textures are transcoded to, best first: ASTC and BC7 keep UASTC
+/// quality, ETC2 is what most mobile devices have, RGBA8 works everywhere.
+const TRANSCODE_TARGETS: [TranscodeTarget; 4] = [
+    TranscodeTarget::Astc4x4,
+    TranscodeTarget::Bc7,
+    TranscodeTarget::Etc2Rgba,
+    TranscodeTarget::Rgba8,
+];
+
+/// A sampled image in device local memory with its mip chain: an 8-bit RGBA image, or any
+/// format, layer count and cubemap from a KTX2 file.
+#[derive(Debug, Clone, Copy)]
+pub struct DeviceTexture {
+    pub(crate) image: Image,
+    pub(crate) memory: DeviceMemory,
+    pub(crate) view: ImageView,
+    pub(crate) view_type: ImageViewType,
+    pub(crate) format: Format,
+    pub(crate) extent: Extent2D,
+    pub(crate) mip_levels: u32,
+    /// Array layers of the image, six per cubemap.
+    pub(crate) array_layers: u32,
+}
+
+impl DeviceTexture {
+    /// Uploads `image`, with a full mip chain when `mipmaps` is set. Mips are blitted on the
+    /// device if the format supports linear filtering there, and computed on the host
+    /// otherwise. Blocks until the upload is done.
+    pub fn new(
+        context: &Context,
+        image: &RgbaImage,
+        usage: TextureUsage,
+        mipmaps: bool,
+    ) -> Result<Self> {
+        let format = usage.format();
+        let mut texture = DeviceTexture {
+            image: Image::null(),
+            memory: DeviceMemory::null(),
+            view: ImageView::null(),
+            view_type: ImageViewType::TYPE_2D,
+            format,
+            extent: Extent2D {
+                width: image.width(),
+                height: image.height(),
+            },
+            mip_levels: if mipmaps {
+                mip_levels(image.width(), image.height())
+            } else {
+                1
+            },
+            array_layers: 1,
+        };
+        match texture
+            .create(context)
+            .and_then(|()| texture.upload(context, image, usage))
+        {
+            Ok(()) => Ok(texture),
+            Err(error) => {
+                texture.destroy(context);
+                Err(error)
+            }
+        }
+    }
+
+    /// Decodes a PNG or JPEG file and uploads it like `new`.
+    pub fn load(
+        context: &Context,
+        path: impl AsRef<Path>,
+        usage: TextureUsage,
+        mipmaps: bool,
+    ) -> Result<Self> {
+        Self::new(context, &RgbaImage::load(path)?, usage, mipmaps)
+    }
+
+    /// Uploads every level, layer and face of `ktx2` as they are, blitting the mips when
+    /// the file leaves them to the loader and the format allows it. Basis Universal textures
+    /// are transcoded by `transcoder` to the best format the device samples; the others must
+    /// already be in one. Blocks until the upload is done.
+    pub fn from_ktx2(
+        context: &Context,
+        ktx2: &Ktx2,
+        transcoder: Option<&dyn BasisTranscoder>,
+    ) -> Result<Self> {
+        let (format, block, levels) = match ktx2.basis {
+            None => {
+                let block = ktx2.block().ok_or_else(|| {
+                    Error::Other(format!(
+                        "KTX2 texture format {:?} isn`t supported",
+                        ktx2.format
+                    ))
+                })?;
+                if !context.supports_texture(ktx2.format) {
+                    return Err(Error::Other(format!(
+                        "KTX2 texture format {:?} isn`t supported by the device",
+                        ktx2.format
+                    )));
+                }
+                for (level, data) in ktx2.levels.iter().enumerate() {
+                    let expected = ktx2.level_size(level, block);
+                    if data.len() != expected {
+                        return Err(Error::Other(format!(
+                            "KTX2 level {} has {} bytes, {:?} needs {}",
+                            level,
+                            data.len(),
+                            ktx2.format,
+                            expected
+                        )));
+                    }
+                }
+                (ktx2.format, block, Cow::Borrowed(&ktx2.levels))
+            }
+            Some(basis) => {
+                let transcoder = transcoder.ok_or_else(|| {
+                    Error::Other(format!(
+                        "{:?} texture needs a Basis transcoder; alovak has none built in",
+                        basis
+                    ))
+                })?;
+                let target = Self::transcode_target(context, ktx2);
+                log::trace!("vulkan {:?} texture transcoded to {:?}", basis, target);
+                let mut levels = Vec::with_capacity(ktx2.levels.len());
+                for level in 0..ktx2.levels.len() {
+                    let data = transcoder.transcode(ktx2, level, target)?;
+                    let expected = ktx2.level_size(level, target.block());
+                    if data.len() != expected {
+                        return Err(Error::Other(format!(
+                            "Transcoded level {} has {} bytes, {:?} needs {}",
+                            level,
+                            data.len(),
+                            target,
+                            expected
+                        )));
+                    }
+                    levels.push(data);
+                }
+                (target.format(ktx2.srgb), target.block(), Cow::Owned(levels))
+            }
+        };
+
+        let view_type = match (ktx2.is_cubemap(), ktx2.layers > 0) {
+            (true, true) => ImageViewType::CUBE_ARRAY,
+            (true, false) => ImageViewType::CUBE,
+            (false, true) => ImageViewType::TYPE_2D_ARRAY,
+            (false, false) => ImageViewType::TYPE_2D,
+        };
+        if view_type == ImageViewType::CUBE_ARRAY && context.features.image_cube_array == 0 {
+            return Err(Error::Other(
+                "Cubemap arrays aren`t supported by the device".to_owned(),
+            ));
+        }
+        let mut texture = DeviceTexture {
+            image: Image::null(),
+            memory: DeviceMemory::null(),
+            view: ImageView::null(),
+            view_type,
+            format,
+            extent: Extent2D {
+                width: ktx2.width,
+                height: ktx2.height,
+            },
+            mip_levels: levels.len() as u32,
+            array_layers: ktx2.array_layers(),
+        };
+        let blit = ktx2.generate_mips && levels.len() == 1 && texture.can_blit(context);
+        if blit {
+            texture.mip_levels = mip_levels(ktx2.width, ktx2.height);
+        } else if ktx2.generate_mips {
+            log::trace!(
+                "vulkan {:?} can`t be blitted linearly, the KTX2 texture keeps one level",
+                format
+            );
+        }
+        match texture
+            .create(context)
+            .and_then(|()| texture.upload_levels(context, &levels, block.2, blit))
+        {
+            Ok(()) => Ok(texture),
+            Err(error) => {
+                texture.destroy(context);
+                Err(error)
+            }
+        }
+    }
+
+    /// Loads a KTX2 file and uploads it like `from_ktx2`.
+    pub fn load_ktx2(
+        context: &Context,
+        path: impl AsRef<Path>,
+        transcoder: Option<&dyn BasisTranscoder>,
+    ) -> Result<Self> {
+        Self::from_ktx2(context, &Ktx2::load(path)?, transcoder)
+    }
+
+    /// The first of `TRANSCODE_TARGETS` the device samples, with ETC2 dropping alpha when the
+    /// texture has none.
+    fn transcode_target(context: &Context, ktx2: &Ktx2) -> TranscodeTarget {
+        TRANSCODE_TARGETS
+            .into_iter()
+            .map(|target| match target {
+                TranscodeTarget::Etc2Rgba if !ktx2.alpha => TranscodeTarget::Etc2Rgb,
+                target => target,
+            })
+            .find(|target| context.supports_texture(target.format(ktx2.srgb)))
+            .unwrap_or(TranscodeTarget::Rgba8)
+    }
+
+    fn create(&mut self, context: &Context) -> Result<()> {
+        if self.extent.width == 0 || self.extent.height == 0 {
+            return Err(Error::Other("Texture can`t be empty".to_owned()));
+        }
+        let device = &context.device;
+        let flags = if matches!(
+            self.view_type,
+            ImageViewType::CUBE | ImageViewType::CUBE_ARRAY
+        ) {
+            ImageCreateFlags::CUBE_COMPATIBLE
+        } else {
+            ImageCreateFlags::empty()
+        };
+        let image_create_info = ImageCreateInfo::default()
+            .flags(flags)
+            .image_type(ImageType::TYPE_2D)
+            .format(self.format)
+            .extent(Extent3D {
+                width: self.extent.width,
+                height: self.extent.height,
+                depth: 1,
+            })
+            .mip_levels(self.mip_levels)
+            .array_layers(self.array_layers)
+            .samples(SampleCountFlags::TYPE_1)
+            .tiling(ImageTiling::OPTIMAL)
+            .usage(
+                ImageUsageFlags::SAMPLED
+                    | ImageUsageFlags::TRANSFER_DST
+                    | ImageUsageFlags::TRANSFER_SRC,
+            )
+            .sharing_mode(SharingMode::EXCLUSIVE)
+            .initial_layout(ImageLayout::UNDEFINED);
+        self.image =
+            unsafe { device.create_image(&image_create_info, None) }.map_err(Error::Vulkan)?;
+
+        let requirements = unsafe { device.get_image_memory_requirements(self.image) };
+        let allocate_info = MemoryAllocateInfo::default()
+            .allocation_size(requirements.size)
+            .memory_type_index(context.memory_type(
+                requirements.memory_type_bits,
+                MemoryPropertyFlags::DEVICE_LOCAL,
+            )?);
+        self.memory =
+            unsafe { device.allocate_memory(&allocate_info, None) }.map_err(Error::Vulkan)?;
+        unsafe { device.bind_image_memory(self.image, self.memory, 0) }.map_err(Error::Vulkan)?;
+
+        let view_create_info = ImageViewCreateInfo::default()
+            .image(self.image)
+            .view_type(self.view_type)
+            .format(self.format)
+            .subresource_range(self.range(0, self.mip_levels));
+        self.view =
+            unsafe { device.create_image_view(&view_create_info, None) }.map_err(Error::Vulkan)?;
+        Ok(())
+    }
+
+    fn range(&self, level: u32, count: u32) -> ImageSubresourceRange {
+        ImageSubresourceRange::default()
+            .aspect_mask(ImageAspectFlags::COLOR)
+            .base_mip_level(level)
+            .level_count(count)
+            .base_array_layer(0)
+            .layer_count(self.array_layers)
+    }
+
+    fn layers(&self, level: u32) -> ImageSubresourceLayers {
+        ImageSubresourceLayers::default()
+            .aspect_mask(ImageAspectFlags::COLOR)
+            .mip_level(level)
+            .base_array_layer(0)
+            .layer_count(self.array_layers)
+    }
+
+    /// Whether mips can be made with linear blits from the level above.
+    fn can_blit(&self, context: &Context) -> bool {
+        context.format_features(self.format).contains(
+            FormatFeatureFlags::BLIT_SRC
+                | FormatFeatureFlags::BLIT_DST
+                | FormatFeatureFlags::SAMPLED_IMAGE_FILTER_LINEAR,
+        )
+    }
+
+    fn upload(&self, context: &Context, image: &RgbaImage, usage: TextureUsage) -> Result<()> {
+        let blit = self.mip_levels > 1 && self.can_blit(context);
+        let levels = if blit { 1 } else { self.mip_levels };
+        if self.mip_levels > 1 && !blit {
+            log::trace!(
+                "vulkan {:?} can`t be blitted linearly, mips are made on the host",
+                self.format
+            );
+        }
+
+        let mut chain = vec![image.clone()];
+        while (chain.len() as u32) < levels {
+            let next = downsample(chain.last().unwrap(), usage == TextureUsage::Color);
+            chain.push(next);
+        }
+        let mut data = Vec::new();
+        let mut regions = Vec::with_capacity(chain.len());
+        for (level, mip) in chain.iter().enumerate() {
+            regions.push(
+                BufferImageCopy::default()
+                    .buffer_offset(data.len() as DeviceSize)
+                    .image_subresource(self.layers(level as u32))
+                    .image_extent(Extent3D {
+                        width: mip.width(),
+                        height: mip.height(),
+                        depth: 1,
+                    }),
+            );
+            data.extend_from_slice(mip.pixels());
+        }
+        self.copy(context, &data, &regions, blit)
+    }
+
+    /// Uploads levels of tightly packed blocks of `block_size` bytes, level 0 first, each
+    /// with every layer. With `blit`, the levels after the ones given are blitted from them.
+    fn upload_levels(
+        &self,
+        context: &Context,
+        levels: &[Vec<u8>],
+        block_size: u32,
+        blit: bool,
+    ) -> Result<()> {
+        // Copies must start at a multiple of the block size and of 4: their least common
+        // multiple, e.g. 12 for 3 byte texels.
+        let alignment = ((block_size * 4) >> block_size.trailing_zeros().min(2)) as usize;
+        let mut data = Vec::new();
+        let mut regions = Vec::with_capacity(levels.len());
+        for (level, level_data) in levels.iter().enumerate() {
+            data.resize(data.len().next_multiple_of(alignment), 0);
+            regions.push(
+                BufferImageCopy::default()
+                    .buffer_offset(data.len() as DeviceSize)
+                    .image_subresource(self.layers(level as u32))
+                    .image_extent(Extent3D {
+                        width: (self.extent.width >> level).max(1),
+                        height: (self.extent.height >> level).max(1),
+                        depth: 1,
+                    }),
+            );
+            data.extend_from_slice(level_data);
+        }
+        self.copy(context, &data, &regions, blit)
+    }
+
+    /// Copies `data` into the image through a staging buffer, then blits the mips from level
+    /// 0 when `blit` is set, leaving every level ready for sampling.
+    fn copy(
+        &self,
+        context: &Context,
+        data: &[u8],
+        regions: &[BufferImageCopy],
+        blit: bool,
+    ) -> Result<()> {
+        let mut staging = DeviceBuffer::new(
+            context,
+            data.len() as DeviceSize,
+            BufferUsageFlags::TRANSFER_SRC,
+            MemoryPropertyFlags::HOST_VISIBLE | MemoryPropertyFlags::HOST_COHERENT,
+        )?;
+        let result = staging.write(context, 0, data).and_then(|()| {
+            context.submit_and_wait(|command_buffer| {
+                frame::transition_image(
+                    context,
+                    command_buffer,
+                    self.image,
+                    self.range(0, self.mip_levels),
+                    ImageLayout::UNDEFINED,
+                    ImageLayout::TRANSFER_DST_OPTIMAL,
+                    AccessFlags::empty(),
+                    AccessFlags::TRANSFER_WRITE,
+                    PipelineStageFlags::TOP_OF_PIPE,
+                    PipelineStageFlags::TRANSFER,
+                );
+                unsafe {
+                    context.device.cmd_copy_buffer_to_image(
+                        command_buffer,
+                        staging.buffer,
+                        self.image,
+                        ImageLayout::TRANSFER_DST_OPTIMAL,
+                        regions,
+                    )
+                };
+                if blit {
+                    self.record_blits(context, command_buffer);
+                } else {
+                    frame::transition_image(
+                        context,
+                        command_buffer,
+                        self.image,
+                        self.range(0, self.mip_levels),
+                        ImageLayout::TRANSFER_DST_OPTIMAL,
+                        ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                        AccessFlags::TRANSFER_WRITE,
+                        AccessFlags::SHADER_READ,
+                        PipelineStageFlags::TRANSFER,
+                        PipelineStageFlags::FRAGMENT_SHADER,
+                    );
+                }
+                Ok(())
+            })
+        });
+        staging.destroy(context);
+        result
+    }
+
+    /// Fills every level from the one above. Level 0 must be in `TRANSFER_DST_OPTIMAL` with
+    /// its data and leaves all levels in `SHADER_READ_ONLY_OPTIMAL`.
+    fn record_blits(&self, context: &Context, command_buffer: CommandBuffer) {
+        let offset = |width: u32, height: u32| Offset3D {
+            x: width as i32,
+            y: height as i32,
+            z: 1,
+        };
+        let (mut width, mut height) = (self.extent.width, self.extent.height);
+        for level in 1..self.mip_levels {
+            let (next_width, next_height) = ((width / 2).max(1), (height / 2).max(1));
+            frame::transition_image(
+                context,
+                command_buffer,
+                self.image,
+                self.range(level - 1, 1),
+                ImageLayout::TRANSFER_DST_OPTIMAL,
+                ImageLayout::TRANSFER_SRC_OPTIMAL,
+                AccessFlags::TRANSFER_WRITE,
+                AccessFlags::TRANSFER_READ,
+                PipelineStageFlags::TRANSFER,
+                PipelineStageFlags::TRANSFER,
+            );
+            let region = ImageBlit::default()
+                .src_subresource(self.layers(level - 1))
+                .src_offsets([Offset3D::default(), offset(width, height)])
+                .dst_subresource(self.layers(level))
+                .dst_offsets([Offset3D::default(), offset(next_width, next_height)]);
+            unsafe {
+                context.device.cmd_blit_image(
+                    command_buffer,
+                    self.image,
+                    ImageLayout::TRANSFER_SRC_OPTIMAL,
+                    self.image,
+                    ImageLayout::TRANSFER_DST_OPTIMAL,
+                    &[region],
+                    Filter::LINEAR,
+                )
+            };
+            frame::transition_image(
+                context,
+                command_buffer,
+                self.image,
+                self.range(level - 1, 1),
+                ImageLayout::TRANSFER_SRC_OPTIMAL,
+                ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+                AccessFlags::TRANSFER_READ,
+                AccessFlags::SHADER_READ,
+                PipelineStageFlags::TRANSFER,
+                PipelineStageFlags::FRAGMENT_SHADER,
+            );
+            (width, height) = (next_width, next_height);
+        }
+        frame::transition_image(
+            context,
+            command_buffer,
+            self.image,
+            self.range(self.mip_levels - 1, 1),
+            ImageLayout::TRANSFER_DST_OPTIMAL,
+            ImageLayout::SHADER_READ_ONLY_OPTIMAL,
+            AccessFlags::TRANSFER_WRITE,
+            AccessFlags::SHADER_READ,
+            PipelineStageFlags::TRANSFER,
+            PipelineStageFlags::FRAGMENT_SHADER,
+        );
+    }
+
+    pub fn image(&self) -> Image {
+        self.image
+    }
+
+    pub fn view(&self) -> ImageView {
+        self.view
+    }
+
+    pub fn format(&self) -> Format {
+        self.format
+    }
+
+    pub fn extent(&self) -> Extent2D {
+        self.extent
+    }
+
+    pub fn mip_levels(&self) -> u32 {
+        self.mip_levels
+    }
+
+    pub fn view_type(&self) -> ImageViewType {
+        self.view_type
+    }
+
+    pub fn array_layers(&self) -> u32 {
+        self.array_layers
+    }
+
+    /// The device must be done with the texture.
+    pub fn destroy(&mut self, context: &Context) {
+        let device = &context.device;
+        unsafe {
+            if self.view != ImageView::null() {
+                device.destroy_image_view(self.view, None);
+            }
+            if self.image != Image::null() {
+                device.destroy_image(self.image, None);
+            }
+            if self.memory != DeviceMemory::null() {
+                device.free_memory(self.memory, None);
+            }
+        }
+        self.view = ImageView::null();
+        self.image = Image::null();
+        self.memory = DeviceMemory::null();
+    }
+}
@@ -0,0 +1,38 @@
+//! Clipboard data shared by the backends. Only Win32 has a clipboard so far, with text,
+//! images and `Event::ClipboardChanged`.
+
+use crate::{error::Result, Error};
+
+/// Clipboard image in straight RGBA8, rows top to bottom.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClipboardImage {
+    rgba: Vec<u8>,
+    width: u32,
+    height: u32,
+}
+
+impl ClipboardImage {
+    pub fn from_rgba(rgba: Vec<u8>, width: u32, height: u32) -> Result<Self> {
+        if rgba.len() != width as usize * height as usize * 4 {
+            return Err(Error::Other(format!(
+                "Clipboard image of {} bytes doesn`t match {}x{}",
+                rgba.len(),
+                width,
+                height
+            )));
+        }
+        Ok(ClipboardImage {
+            rgba,
+            width,
+            height,
+        })
+    }
+
+    pub fn rgba(&self) -> &[u8] {
+        &self.rgba
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+}
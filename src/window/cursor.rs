@@ -0,0 +1,84 @@
+use crate::{error::Result, Error};
+
+/// System cursor shapes, mapped onto the closest native cursor of each backend.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum CursorIcon {
+    #[default]
+    Default,
+    Text,
+    Crosshair,
+    Hand,
+    Wait,
+    Progress,
+    Move,
+    NotAllowed,
+    Help,
+    ResizeHorizontal,
+    ResizeVertical,
+    ResizeNwse,
+    ResizeNesw,
+}
+
+/// Cursor image in straight (not premultiplied) RGBA8, rows top to bottom.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CustomCursor {
+    rgba: Vec<u8>,
+    width: u32,
+    height: u32,
+    hotspot_x: u32,
+    hotspot_y: u32,
+}
+
+impl CustomCursor {
+    pub fn from_rgba(
+        rgba: Vec<u8>,
+        width: u32,
+        height: u32,
+        hotspot_x: u32,
+        hotspot_y: u32,
+    ) -> Result<Self> {
+        if width == 0 || height == 0 || rgba.len() != width as usize * height as usize * 4 {
+            return Err(Error::Other(format!(
+                "Cursor image of {} bytes doesn`t match {}x{}",
+                rgba.len(),
+                width,
+                height
+            )));
+        }
+        if hotspot_x >= width || hotspot_y >= height {
+            return Err(Error::Other(
+                "Cursor hotspot is outside the image".to_owned(),
+            ));
+        }
+        Ok(CustomCursor {
+            rgba,
+            width,
+            height,
+            hotspot_x,
+            hotspot_y,
+        })
+    }
+
+    pub fn rgba(&self) -> &[u8] {
+        &self.rgba
+    }
+
+    pub fn size(&self) -> (u32, u32) {
+        (self.width, self.height)
+    }
+
+    pub fn hotspot(&self) -> (u32, u32) {
+        (self.hotspot_x, self.hotspot_y)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub enum CursorGrab {
+    /// The cursor moves freely.
+    #[default]
+    None,
+    /// The cursor can't leave the client area.
+    Confined,
+    /// The cursor stays in place; use `Event::MouseMotion` for relative movement.
+    Locked,
+}
@@ -0,0 +1,101 @@
+use std::path::PathBuf;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum Event {
+    Resized {
+        width: u32,
+        height: u32,
+    },
+    Moved {
+        x: i32,
+        y: i32,
+    },
+    Focused(bool),
+    CursorMoved {
+        x: i32,
+        y: i32,
+    },
+    /// Raw relative mouse movement, also reported while the cursor is locked.
+    MouseMotion {
+        dx: f64,
+        dy: f64,
+    },
+    MouseInput {
+        button: MouseButton,
+        state: ElementState,
+    },
+    MouseWheel {
+        delta: f32,
+    },
+    KeyboardInput {
+        scancode: u32,
+        key: u32,
+        state: ElementState,
+    },
+    /// Text typed on the keyboard, after keyboard layout and dead keys are applied.
+    Text(String),
+    Ime(Ime),
+    /// The clipboard content was changed, by this or another application.
+    ClipboardChanged,
+    DragDrop(DragDrop),
+    RedrawRequested,
+    /// The user asked to close the window, e.g. with its close button. It stays open until
+    /// `Window::close`.
+    CloseRequested,
+    Destroyed,
+}
+
+/// Text composition through an input method, e.g. for CJK input. Only reported by the
+/// Win32 backend, from `WM_IME_*`; XIM and text-input-v3 aren't implemented.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum Ime {
+    /// A composition started; pre-edit text follows.
+    Start,
+    /// Text being composed. `cursor` is a byte offset into `text`; an empty `text`
+    /// clears the pre-edit.
+    Preedit {
+        text: String,
+        cursor: Option<usize>,
+    },
+    /// Final text of a composition. It isn't repeated as `Event::Text`.
+    Commit(String),
+    End,
+}
+
+/// Files dragged onto the window from another application. Positions are in client
+/// area coordinates. Only reported by the Win32 backend, through an OLE drop target;
+/// XDND and the Wayland data device aren't implemented.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DragDrop {
+    /// Files entered the window; `Moved` events follow while they are dragged over it.
+    Hovered {
+        paths: Vec<PathBuf>,
+        x: i32,
+        y: i32,
+    },
+    Moved {
+        x: i32,
+        y: i32,
+    },
+    Dropped {
+        paths: Vec<PathBuf>,
+        x: i32,
+        y: i32,
+    },
+    /// The files left the window or the drag was aborted.
+    Cancelled,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum MouseButton {
+    Left,
+    Right,
+    Middle,
+    Other(u16),
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ElementState {
+    Pressed,
+    Released,
+}
@@ -1,13 +1,71 @@
-use crate::error::Result;
+//! Native windows and their events. The only backend is Win32 in `win32`; there is no
+//! X11 or Wayland backend yet, so on Linux alovak renders into windows made by other
+//! crates through `vulkan::RenderTarget`, without the clipboard and the other `Window`
+//! features.
 
+use crate::error::{Error, Result};
+
+use raw_window_handle::{HasDisplayHandle, HasWindowHandle};
+
+pub mod clipboard;
+pub mod cursor;
+pub mod event;
+#[cfg(windows)]
 pub mod win32;
 
-pub trait Window {
-    fn handle(self) -> Result<Handle>;
-}
+pub use clipboard::ClipboardImage;
+pub use cursor::{CursorGrab, CursorIcon, CustomCursor};
+pub use event::{DragDrop, ElementState, Event, Ime, MouseButton};
+
+/// Native handles are exposed through `raw-window-handle`, so alovak windows can be
+/// handed to any crate that renders into them.
+pub trait Window: HasWindowHandle + HasDisplayHandle {
+    /// Size of the client area in pixels.
+    fn inner_size(&self) -> Result<(u32, u32)>;
+
+    fn set_title(&self, title: &str) -> Result<()>;
+
+    fn request_redraw(&self) -> Result<()>;
+
+    /// Destroys the native window; `Event::Destroyed` follows. Whatever renders into the
+    /// window has to be done with it first, e.g. after `device_wait_idle`.
+    fn close(&self) -> Result<()>;
+
+    /// Takes the next pending event without blocking.
+    fn poll_event(&self) -> Option<Event>;
+
+    fn set_cursor(&self, icon: CursorIcon) -> Result<()>;
+
+    fn set_custom_cursor(&self, cursor: &CustomCursor) -> Result<()>;
+
+    /// Hides the cursor while it is over the window.
+    fn set_cursor_visible(&self, visible: bool) -> Result<()>;
+
+    /// `CursorGrab::Locked` together with a hidden cursor gives FPS-style camera control.
+    fn set_cursor_grab(&self, grab: CursorGrab) -> Result<()>;
+
+    /// Enables or disables input methods for the window. They are allowed by default.
+    /// Input methods are only supported on Win32, see `Ime`.
+    fn set_ime_allowed(&self, allowed: bool) -> Result<()>;
+
+    /// Client area rectangle of the text being edited, so the IME can place its candidate
+    /// list next to it.
+    fn set_ime_cursor_area(&self, x: i32, y: i32, width: u32, height: u32) -> Result<()>;
+
+    /// UTF-8 text on the clipboard, `None` if it holds no text. Only the Win32 clipboard is
+    /// implemented; X11 selections and the Wayland data device aren't.
+    fn clipboard_text(&self) -> Result<Option<String>>;
+
+    fn set_clipboard_text(&self, text: &str) -> Result<()>;
 
+    /// Image on the clipboard. Backends without image support report `None`.
+    fn clipboard_image(&self) -> Result<Option<ClipboardImage>> {
+        Ok(None)
+    }
 
-pub enum Handle{
-    Win32(isize),
-    Custom(i32)
+    fn set_clipboard_image(&self, _image: &ClipboardImage) -> Result<()> {
+        Err(Error::Other(
+            "Clipboard images aren`t supported by this window".to_owned(),
+        ))
+    }
 }
@@ -0,0 +1,197 @@
+use windows::Win32::{
+    Foundation::*,
+    Graphics::Gdi::{BITMAPINFOHEADER, BI_BITFIELDS, BI_RGB},
+    System::{DataExchange::*, Memory::*},
+};
+
+use crate::{error::Result, window::ClipboardImage, Error};
+
+use super::win32_error;
+
+// Standard clipboard formats, from winuser.h.
+const CF_DIB: u32 = 8;
+const CF_UNICODETEXT: u32 = 13;
+
+/// Keeps the clipboard open for the lifetime of the guard.
+struct OpenedClipboard;
+
+impl OpenedClipboard {
+    fn open(hwnd: HWND) -> Result<Self> {
+        // Another application may hold the clipboard for a short moment.
+        let mut attempt = 0;
+        loop {
+            match unsafe { OpenClipboard(hwnd) } {
+                Ok(()) => return Ok(OpenedClipboard),
+                Err(_) if attempt < 10 => {
+                    attempt += 1;
+                    std::thread::sleep(std::time::Duration::from_millis(5));
+                }
+                Err(error) => return Err(win32_error(error)),
+            }
+        }
+    }
+}
+
+impl Drop for OpenedClipboard {
+    fn drop(&mut self) {
+        _ = unsafe { CloseClipboard() };
+    }
+}
+
+pub(super) fn get_text(hwnd: HWND) -> Result<Option<String>> {
+    let _clipboard = OpenedClipboard::open(hwnd)?;
+    if unsafe { IsClipboardFormatAvailable(CF_UNICODETEXT) }.is_err() {
+        return Ok(None);
+    }
+    read_global(CF_UNICODETEXT, |bytes| {
+        let utf16: Vec<u16> = bytes
+            .chunks_exact(2)
+            .map(|pair| u16::from_le_bytes([pair[0], pair[1]]))
+            .take_while(|unit| *unit != 0)
+            .collect();
+        Ok(String::from_utf16_lossy(&utf16))
+    })
+    .map(Some)
+}
+
+pub(super) fn set_text(hwnd: HWND, text: &str) -> Result<()> {
+    let bytes: Vec<u8> = text
+        .encode_utf16()
+        .chain(std::iter::once(0))
+        .flat_map(u16::to_le_bytes)
+        .collect();
+    let _clipboard = OpenedClipboard::open(hwnd)?;
+    write_global(CF_UNICODETEXT, &bytes)
+}
+
+pub(super) fn get_image(hwnd: HWND) -> Result<Option<ClipboardImage>> {
+    let _clipboard = OpenedClipboard::open(hwnd)?;
+    // Windows synthesizes CF_DIB from bitmaps and CF_DIBV5 put by other applications.
+    if unsafe { IsClipboardFormatAvailable(CF_DIB) }.is_err() {
+        return Ok(None);
+    }
+    read_global(CF_DIB, decode_dib).map(Some)
+}
+
+pub(super) fn set_image(hwnd: HWND, image: &ClipboardImage) -> Result<()> {
+    let bytes = encode_dib(image);
+    let _clipboard = OpenedClipboard::open(hwnd)?;
+    write_global(CF_DIB, &bytes)
+}
+
+fn read_global<T>(format: u32, read: impl FnOnce(&[u8]) -> Result<T>) -> Result<T> {
+    unsafe {
+        let handle = GetClipboardData(format).map_err(win32_error)?;
+        let global = HGLOBAL(handle.0);
+        let data = GlobalLock(global) as *const u8;
+        if data.is_null() {
+            return Err(win32_error(windows::core::Error::from_win32()));
+        }
+        let result = read(std::slice::from_raw_parts(data, GlobalSize(global)));
+        _ = GlobalUnlock(global);
+        result
+    }
+}
+
+fn write_global(format: u32, bytes: &[u8]) -> Result<()> {
+    unsafe {
+        EmptyClipboard().map_err(win32_error)?;
+        let global = GlobalAlloc(GMEM_MOVEABLE, bytes.len()).map_err(win32_error)?;
+        let data = GlobalLock(global) as *mut u8;
+        if data.is_null() {
+            _ = GlobalFree(global);
+            return Err(win32_error(windows::core::Error::from_win32()));
+        }
+        std::ptr::copy_nonoverlapping(bytes.as_ptr(), data, bytes.len());
+        _ = GlobalUnlock(global);
+        // On success the clipboard owns the memory.
+        if let Err(error) = SetClipboardData(format, HANDLE(global.0)) {
+            _ = GlobalFree(global);
+            return Err(win32_error(error));
+        }
+        Ok(())
+    }
+}
+
+fn decode_dib(bytes: &[u8]) -> Result<ClipboardImage> {
+    let header_size = std::mem::size_of::<BITMAPINFOHEADER>();
+    if bytes.len() < header_size {
+        return Err(Error::Other("Clipboard bitmap is truncated".to_owned()));
+    }
+    let header = unsafe { std::ptr::read_unaligned(bytes.as_ptr() as *const BITMAPINFOHEADER) };
+
+    let width = header.biWidth.unsigned_abs();
+    let height = header.biHeight.unsigned_abs();
+    let bottom_up = header.biHeight > 0;
+    let bytes_per_pixel = match (header.biBitCount, header.biCompression) {
+        (32, compression) if compression == BI_RGB.0 || compression == BI_BITFIELDS.0 => 4,
+        (24, compression) if compression == BI_RGB.0 => 3,
+        (bit_count, compression) => {
+            return Err(Error::Other(format!(
+                "Clipboard bitmap with {} bits per pixel and compression {} isn`t supported",
+                bit_count, compression
+            )))
+        }
+    };
+
+    // BI_BITFIELDS stores three color masks after the header; they are assumed to be BGR.
+    let mut offset = header.biSize as usize;
+    if header.biCompression == BI_BITFIELDS.0 && header.biSize as usize == header_size {
+        offset += 12;
+    }
+    let stride = (width as usize * bytes_per_pixel + 3) & !3;
+    if bytes.len() < offset + stride * height as usize {
+        return Err(Error::Other("Clipboard bitmap is truncated".to_owned()));
+    }
+
+    // Many applications leave the alpha channel of 32-bit bitmaps zeroed.
+    let mut has_alpha = false;
+    let mut rgba = Vec::with_capacity(width as usize * height as usize * 4);
+    for row in 0..height as usize {
+        let source_row = if bottom_up {
+            height as usize - 1 - row
+        } else {
+            row
+        };
+        let start = offset + source_row * stride;
+        for pixel in
+            bytes[start..start + width as usize * bytes_per_pixel].chunks_exact(bytes_per_pixel)
+        {
+            let alpha = if bytes_per_pixel == 4 { pixel[3] } else { 255 };
+            has_alpha |= alpha != 0;
+            rgba.extend_from_slice(&[pixel[2], pixel[1], pixel[0], alpha]);
+        }
+    }
+    if !has_alpha {
+        rgba.chunks_exact_mut(4).for_each(|pixel| pixel[3] = 255);
+    }
+
+    ClipboardImage::from_rgba(rgba, width, height)
+}
+
+fn encode_dib(image: &ClipboardImage) -> Vec<u8> {
+    let (width, height) = image.size();
+    let header = BITMAPINFOHEADER {
+        biSize: std::mem::size_of::<BITMAPINFOHEADER>() as u32,
+        biWidth: width as i32,
+        // Negative height stores the rows top to bottom.
+        biHeight: -(height as i32),
+        biPlanes: 1,
+        biBitCount: 32,
+        biCompression: BI_RGB.0,
+        biSizeImage: width * height * 4,
+        ..Default::default()
+    };
+
+    let mut bytes = Vec::with_capacity(header.biSize as usize + image.rgba().len());
+    bytes.extend_from_slice(unsafe {
+        std::slice::from_raw_parts(
+            &header as *const BITMAPINFOHEADER as *const u8,
+            std::mem::size_of::<BITMAPINFOHEADER>(),
+        )
+    });
+    for pixel in image.rgba().chunks_exact(4) {
+        bytes.extend_from_slice(&[pixel[2], pixel[1], pixel[0], pixel[3]]);
+    }
+    bytes
+}
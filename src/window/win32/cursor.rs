@@ -0,0 +1,234 @@
+use windows::Win32::{
+    Foundation::*,
+    Graphics::Gdi::{
+        ClientToScreen, CreateBitmap, CreateDIBSection, DeleteObject, ScreenToClient, BITMAPINFO,
+        BITMAPINFOHEADER, BI_RGB, DIB_RGB_COLORS, HDC,
+    },
+    UI::{Input::*, WindowsAndMessaging::*},
+};
+
+use crate::{
+    error::Result,
+    window::{CursorGrab, CursorIcon, CustomCursor},
+};
+
+use super::win32_error;
+
+/// Cursor settings shared between the window and its message thread.
+pub(super) struct CursorState {
+    /// `HCURSOR`, kept as an integer so the state can cross threads.
+    cursor: isize,
+    /// Whether `cursor` was created by us and has to be destroyed.
+    custom: bool,
+    pub visible: bool,
+    pub grab: CursorGrab,
+    pub focused: bool,
+}
+
+impl CursorState {
+    pub fn new() -> Result<Self> {
+        Ok(CursorState {
+            cursor: load_icon(CursorIcon::Default)?.0 as isize,
+            custom: false,
+            visible: true,
+            grab: CursorGrab::None,
+            focused: false,
+        })
+    }
+
+    /// The cursor to show over the client area, a null cursor hides it.
+    pub fn current(&self) -> HCURSOR {
+        if self.visible {
+            HCURSOR(self.cursor as _)
+        } else {
+            HCURSOR::default()
+        }
+    }
+
+    pub fn replace(&mut self, cursor: HCURSOR, custom: bool) {
+        self.release();
+        self.cursor = cursor.0 as isize;
+        self.custom = custom;
+    }
+
+    fn release(&mut self) {
+        if self.custom {
+            _ = unsafe { DestroyCursor(HCURSOR(self.cursor as _)) };
+            self.custom = false;
+        }
+    }
+}
+
+impl Drop for CursorState {
+    fn drop(&mut self) {
+        self.release();
+    }
+}
+
+pub(super) fn load_icon(icon: CursorIcon) -> Result<HCURSOR> {
+    let name = match icon {
+        CursorIcon::Default => IDC_ARROW,
+        CursorIcon::Text => IDC_IBEAM,
+        CursorIcon::Crosshair => IDC_CROSS,
+        CursorIcon::Hand => IDC_HAND,
+        CursorIcon::Wait => IDC_WAIT,
+        CursorIcon::Progress => IDC_APPSTARTING,
+        CursorIcon::Move => IDC_SIZEALL,
+        CursorIcon::NotAllowed => IDC_NO,
+        CursorIcon::Help => IDC_HELP,
+        CursorIcon::ResizeHorizontal => IDC_SIZEWE,
+        CursorIcon::ResizeVertical => IDC_SIZENS,
+        CursorIcon::ResizeNwse => IDC_SIZENWSE,
+        CursorIcon::ResizeNesw => IDC_SIZENESW,
+    };
+    unsafe { LoadCursorW(None, name) }.map_err(win32_error)
+}
+
+pub(super) fn create_custom(cursor: &CustomCursor) -> Result<HCURSOR> {
+    let (width, height) = cursor.size();
+    let (hotspot_x, hotspot_y) = cursor.hotspot();
+
+    let bitmap_info = BITMAPINFO {
+        bmiHeader: BITMAPINFOHEADER {
+            biSize: std::mem::size_of::<BITMAPINFOHEADER>() as u32,
+            biWidth: width as i32,
+            // Negative height makes the bitmap top-down, like the RGBA rows.
+            biHeight: -(height as i32),
+            biPlanes: 1,
+            biBitCount: 32,
+            biCompression: BI_RGB.0,
+            ..Default::default()
+        },
+        ..Default::default()
+    };
+
+    unsafe {
+        let mut bits = std::ptr::null_mut();
+        let color = CreateDIBSection(
+            HDC::default(),
+            &bitmap_info,
+            DIB_RGB_COLORS,
+            &mut bits,
+            HANDLE::default(),
+            0,
+        )
+        .map_err(win32_error)?;
+
+        let pixels = std::slice::from_raw_parts_mut(bits as *mut u8, cursor.rgba().len());
+        for (bgra, rgba) in pixels
+            .chunks_exact_mut(4)
+            .zip(cursor.rgba().chunks_exact(4))
+        {
+            bgra.copy_from_slice(&[rgba[2], rgba[1], rgba[0], rgba[3]]);
+        }
+
+        // With a 32-bit color bitmap the alpha channel is used, the mask only has to exist.
+        let mask = CreateBitmap(width as i32, height as i32, 1, 1, None);
+
+        let icon_info = ICONINFO {
+            fIcon: FALSE,
+            xHotspot: hotspot_x,
+            yHotspot: hotspot_y,
+            hbmMask: mask,
+            hbmColor: color,
+        };
+        let icon = CreateIconIndirect(&icon_info);
+
+        _ = DeleteObject(color);
+        _ = DeleteObject(mask);
+
+        icon.map(|icon| HCURSOR(icon.0)).map_err(win32_error)
+    }
+}
+
+/// Sets the cursor image if the cursor is over the client area of `hwnd`.
+pub(super) fn apply_cursor(hwnd: HWND, state: &CursorState) {
+    unsafe {
+        let mut point = POINT::default();
+        let mut rect = RECT::default();
+        if GetCursorPos(&mut point).is_err()
+            || !ScreenToClient(hwnd, &mut point).as_bool()
+            || GetClientRect(hwnd, &mut rect).is_err()
+        {
+            return;
+        }
+        if point.x >= 0 && point.y >= 0 && point.x < rect.right && point.y < rect.bottom {
+            SetCursor(state.current());
+        }
+    }
+}
+
+/// Confines the cursor according to the grab mode. Grabs only hold while focused.
+pub(super) fn apply_grab(hwnd: HWND, state: &CursorState) -> Result<()> {
+    unsafe {
+        let grab = if state.focused {
+            state.grab
+        } else {
+            CursorGrab::None
+        };
+        match grab {
+            CursorGrab::None => ClipCursor(None).map_err(win32_error),
+            CursorGrab::Confined | CursorGrab::Locked => {
+                let mut rect = RECT::default();
+                GetClientRect(hwnd, &mut rect).map_err(win32_error)?;
+                let mut origin = POINT::default();
+                _ = ClientToScreen(hwnd, &mut origin);
+                let mut clip = RECT {
+                    left: origin.x,
+                    top: origin.y,
+                    right: origin.x + rect.right,
+                    bottom: origin.y + rect.bottom,
+                };
+                if grab == CursorGrab::Locked {
+                    let center = POINT {
+                        x: (clip.left + clip.right) / 2,
+                        y: (clip.top + clip.bottom) / 2,
+                    };
+                    clip = RECT {
+                        left: center.x,
+                        top: center.y,
+                        right: center.x + 1,
+                        bottom: center.y + 1,
+                    };
+                }
+                ClipCursor(Some(&clip as *const RECT)).map_err(win32_error)
+            }
+        }
+    }
+}
+
+/// Subscribes `hwnd` to raw mouse input, which keeps reporting motion when the cursor
+/// is locked or at the edge of the screen.
+pub(super) fn register_raw_mouse(hwnd: HWND) -> Result<()> {
+    let device = RAWINPUTDEVICE {
+        usUsagePage: 0x01, // HID_USAGE_PAGE_GENERIC
+        usUsage: 0x02,     // HID_USAGE_GENERIC_MOUSE
+        dwFlags: RAWINPUTDEVICE_FLAGS(0),
+        hwndTarget: hwnd,
+    };
+    unsafe { RegisterRawInputDevices(&[device], std::mem::size_of::<RAWINPUTDEVICE>() as u32) }
+        .map_err(win32_error)
+}
+
+/// Relative motion from a `WM_INPUT` message, if it came from a mouse.
+pub(super) fn read_raw_motion(lparam: LPARAM) -> Option<(f64, f64)> {
+    let mut input = RAWINPUT::default();
+    let mut size = std::mem::size_of::<RAWINPUT>() as u32;
+    let read = unsafe {
+        GetRawInputData(
+            HRAWINPUT(lparam.0 as _),
+            RID_INPUT,
+            Some(&mut input as *mut RAWINPUT as *mut core::ffi::c_void),
+            &mut size,
+            std::mem::size_of::<RAWINPUTHEADER>() as u32,
+        )
+    };
+    if read == u32::MAX || input.header.dwType != RIM_TYPEMOUSE.0 {
+        return None;
+    }
+    let mouse = unsafe { input.data.mouse };
+    if mouse.usFlags.0 & MOUSE_MOVE_ABSOLUTE.0 != 0 || (mouse.lLastX == 0 && mouse.lLastY == 0) {
+        return None;
+    }
+    Some((mouse.lLastX as f64, mouse.lLastY as f64))
+}
@@ -0,0 +1,175 @@
+use std::{
+    cell::Cell, ffi::OsString, os::windows::ffi::OsStringExt, path::PathBuf, sync::mpsc::Sender,
+};
+
+use windows::{
+    core::*,
+    Win32::{
+        Foundation::*,
+        Graphics::Gdi::ScreenToClient,
+        System::{
+            Com::{IDataObject, DVASPECT_CONTENT, FORMATETC, TYMED_HGLOBAL},
+            Ole::*,
+            SystemServices::MODIFIERKEYS_FLAGS,
+        },
+        UI::Shell::{DragQueryFileW, HDROP},
+    },
+};
+
+use crate::{error::Result, window::DragDrop, Event};
+
+use super::win32_error;
+
+/// OLE drop target of a window. Only file lists (`CF_HDROP`) are accepted.
+#[implement(IDropTarget)]
+struct DropTarget {
+    hwnd: HWND,
+    events: Sender<Event>,
+    /// Whether the current drag carries files, so other data shows the "no drop" cursor.
+    accepted: Cell<bool>,
+    position: Cell<(i32, i32)>,
+}
+
+impl DropTarget {
+    fn client_position(&self, point: &POINTL) -> (i32, i32) {
+        let mut point = POINT {
+            x: point.x,
+            y: point.y,
+        };
+        unsafe {
+            _ = ScreenToClient(self.hwnd, &mut point);
+        }
+        (point.x, point.y)
+    }
+
+    fn effect(&self, effect: *mut DROPEFFECT) {
+        if !effect.is_null() {
+            unsafe {
+                *effect = if self.accepted.get() {
+                    DROPEFFECT_COPY
+                } else {
+                    DROPEFFECT_NONE
+                };
+            }
+        }
+    }
+}
+
+impl IDropTarget_Impl for DropTarget_Impl {
+    fn DragEnter(
+        &self,
+        data: Option<&IDataObject>,
+        _keys: MODIFIERKEYS_FLAGS,
+        point: &POINTL,
+        effect: *mut DROPEFFECT,
+    ) -> windows::core::Result<()> {
+        let paths = data.and_then(paths).unwrap_or_default();
+        self.accepted.set(!paths.is_empty());
+        self.effect(effect);
+
+        if self.accepted.get() {
+            let (x, y) = self.client_position(point);
+            self.position.set((x, y));
+            _ = self
+                .events
+                .send(Event::DragDrop(DragDrop::Hovered { paths, x, y }));
+        }
+        Ok(())
+    }
+
+    fn DragOver(
+        &self,
+        _keys: MODIFIERKEYS_FLAGS,
+        point: &POINTL,
+        effect: *mut DROPEFFECT,
+    ) -> windows::core::Result<()> {
+        self.effect(effect);
+
+        // Sent continuously while the drag is over the window, even without movement.
+        let (x, y) = self.client_position(point);
+        if self.accepted.get() && self.position.replace((x, y)) != (x, y) {
+            _ = self.events.send(Event::DragDrop(DragDrop::Moved { x, y }));
+        }
+        Ok(())
+    }
+
+    fn DragLeave(&self) -> windows::core::Result<()> {
+        if self.accepted.replace(false) {
+            _ = self.events.send(Event::DragDrop(DragDrop::Cancelled));
+        }
+        Ok(())
+    }
+
+    fn Drop(
+        &self,
+        data: Option<&IDataObject>,
+        _keys: MODIFIERKEYS_FLAGS,
+        point: &POINTL,
+        effect: *mut DROPEFFECT,
+    ) -> windows::core::Result<()> {
+        self.effect(effect);
+        if !self.accepted.replace(false) {
+            return Ok(());
+        }
+
+        let paths = data.and_then(paths).unwrap_or_default();
+        let (x, y) = self.client_position(point);
+        let event = if paths.is_empty() {
+            DragDrop::Cancelled
+        } else {
+            DragDrop::Dropped { paths, x, y }
+        };
+        _ = self.events.send(Event::DragDrop(event));
+        Ok(())
+    }
+}
+
+/// Paths of the files in a drag, `None` if it doesn't carry any.
+fn paths(data: &IDataObject) -> Option<Vec<PathBuf>> {
+    let format = FORMATETC {
+        cfFormat: CF_HDROP.0,
+        ptd: std::ptr::null_mut(),
+        dwAspect: DVASPECT_CONTENT.0,
+        lindex: -1,
+        tymed: TYMED_HGLOBAL.0 as u32,
+    };
+    unsafe {
+        let mut medium = data.GetData(&format).ok()?;
+        let hdrop = HDROP(medium.u.hGlobal.0);
+
+        let count = DragQueryFileW(hdrop, u32::MAX, None);
+        let mut paths = Vec::with_capacity(count as usize);
+        for index in 0..count {
+            let len = DragQueryFileW(hdrop, index, None) as usize;
+            let mut buffer = vec![0u16; len + 1];
+            let copied = DragQueryFileW(hdrop, index, Some(&mut buffer)) as usize;
+            paths.push(PathBuf::from(OsString::from_wide(&buffer[..copied])));
+        }
+
+        ReleaseStgMedium(&mut medium);
+        Some(paths)
+    }
+}
+
+/// Makes the window accept files dragged from other applications. Must be called on the
+/// window thread; the target stays registered until `revoke`.
+pub fn register(hwnd: HWND, events: Sender<Event>) -> Result<()> {
+    let target: IDropTarget = DropTarget {
+        hwnd,
+        events,
+        accepted: Cell::new(false),
+        position: Cell::new((0, 0)),
+    }
+    .into();
+    unsafe {
+        OleInitialize(None).map_err(win32_error)?;
+        RegisterDragDrop(hwnd, &target).map_err(win32_error)
+    }
+}
+
+pub fn revoke(hwnd: HWND) {
+    unsafe {
+        _ = RevokeDragDrop(hwnd);
+        OleUninitialize();
+    }
+}
@@ -0,0 +1,113 @@
+use windows::Win32::{
+    Foundation::*,
+    UI::Input::Ime::{
+        ImmAssociateContextEx, ImmGetCompositionStringW, ImmGetContext, ImmReleaseContext,
+        ImmSetCandidateWindow, ImmSetCompositionWindow, CANDIDATEFORM, CFS_EXCLUDE, CFS_POINT,
+        COMPOSITIONFORM, GCS_CURSORPOS, HIMC, IACE_DEFAULT, IME_COMPOSITION_STRING,
+    },
+};
+
+/// IME settings shared between the window and its message thread.
+pub(super) struct ImeState {
+    pub allowed: bool,
+    /// Client area rectangle of the text being edited: x, y, width, height.
+    pub area: (i32, i32, u32, u32),
+}
+
+impl ImeState {
+    pub fn new() -> Self {
+        ImeState {
+            allowed: true,
+            area: (0, 0, 0, 0),
+        }
+    }
+}
+
+/// Applies `state` to `hwnd`. Input contexts belong to the window thread, so this has to
+/// run there.
+pub(super) fn apply(hwnd: HWND, state: &ImeState) {
+    unsafe {
+        // Detaching the input context turns the IME off for this window.
+        let flags = if state.allowed { IACE_DEFAULT } else { 0 };
+        _ = ImmAssociateContextEx(hwnd, HIMC::default(), flags);
+        if !state.allowed {
+            return;
+        }
+
+        let himc = ImmGetContext(hwnd);
+        if himc.is_invalid() {
+            return;
+        }
+        let (x, y, width, height) = state.area;
+        let position = POINT { x, y };
+        let composition = COMPOSITIONFORM {
+            dwStyle: CFS_POINT,
+            ptCurrentPos: position,
+            rcArea: RECT::default(),
+        };
+        _ = ImmSetCompositionWindow(himc, &composition);
+        // The candidate list is kept out of the edited area.
+        let candidate = CANDIDATEFORM {
+            dwIndex: 0,
+            dwStyle: CFS_EXCLUDE,
+            ptCurrentPos: position,
+            rcArea: RECT {
+                left: x,
+                top: y,
+                right: x + width as i32,
+                bottom: y + height as i32,
+            },
+        };
+        _ = ImmSetCandidateWindow(himc, &candidate);
+        _ = ImmReleaseContext(hwnd, himc);
+    }
+}
+
+/// Reads one of the `GCS_*STR` strings of the current composition.
+pub(super) fn composition_string(hwnd: HWND, kind: IME_COMPOSITION_STRING) -> Option<String> {
+    unsafe {
+        let himc = ImmGetContext(hwnd);
+        if himc.is_invalid() {
+            return None;
+        }
+        let size = ImmGetCompositionStringW(himc, kind, None, 0);
+        let result = if size >= 0 {
+            let mut buffer = vec![0u16; size as usize / 2];
+            ImmGetCompositionStringW(
+                himc,
+                kind,
+                Some(buffer.as_mut_ptr() as *mut core::ffi::c_void),
+                size as u32,
+            );
+            Some(String::from_utf16_lossy(&buffer))
+        } else {
+            None
+        };
+        _ = ImmReleaseContext(hwnd, himc);
+        result
+    }
+}
+
+/// Cursor of the current composition as a byte offset into `text`.
+pub(super) fn composition_cursor(hwnd: HWND, text: &str) -> Option<usize> {
+    unsafe {
+        let himc = ImmGetContext(hwnd);
+        if himc.is_invalid() {
+            return None;
+        }
+        let units = ImmGetCompositionStringW(himc, GCS_CURSORPOS, None, 0);
+        _ = ImmReleaseContext(hwnd, himc);
+        if units < 0 {
+            return None;
+        }
+        // The IME counts UTF-16 code units.
+        let mut counted = 0;
+        for (offset, char) in text.char_indices() {
+            if counted >= units as usize {
+                return Some(offset);
+            }
+            counted += char.len_utf16();
+        }
+        Some(text.len())
+    }
+}
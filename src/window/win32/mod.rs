@@ -0,0 +1,531 @@
+use std::{
+    cell::Cell,
+    num::NonZeroIsize,
+    sync::{
+        mpsc::{self, Receiver, Sender},
+        Arc, Mutex,
+    },
+    thread,
+};
+
+use raw_window_handle::{
+    DisplayHandle, HandleError, HasDisplayHandle, HasWindowHandle, RawWindowHandle,
+    Win32WindowHandle, WindowHandle,
+};
+use windows::{
+    core::*,
+    Win32::{
+        Foundation::*,
+        Graphics::Gdi::{InvalidateRect, ValidateRect},
+        System::{DataExchange::AddClipboardFormatListener, LibraryLoader::GetModuleHandleA},
+        UI::{
+            Input::Ime::{GCS_COMPSTR, GCS_RESULTSTR, ISC_SHOWUICOMPOSITIONWINDOW},
+            WindowsAndMessaging::*,
+        },
+    },
+};
+
+use crate::error::{Error, Result};
+
+use super::{
+    ClipboardImage, CursorGrab, CursorIcon, CustomCursor, ElementState, Event, Ime, MouseButton,
+    Window,
+};
+
+mod clipboard;
+mod cursor;
+mod dragdrop;
+mod ime;
+
+use cursor::CursorState;
+use ime::ImeState;
+
+/// Private message asking the window thread to re-apply the cursor settings.
+const WM_APP_CURSOR: u32 = WM_APP + 1;
+/// Private message asking the window thread to re-apply the IME settings.
+const WM_APP_IME: u32 = WM_APP + 2;
+/// Private message asking the window thread to destroy the window.
+const WM_APP_CLOSE: u32 = WM_APP + 3;
+
+pub struct WindowWin32 {
+    pub hwnd: HWND,
+    events: Mutex<Receiver<Event>>,
+    cursor: Arc<Mutex<CursorState>>,
+    ime: Arc<Mutex<ImeState>>,
+}
+
+unsafe impl Send for WindowWin32 {}
+
+/// Lives in `GWLP_USERDATA` of the window for the lifetime of the native window.
+struct WindowState {
+    events: Sender<Event>,
+    cursor: Arc<Mutex<CursorState>>,
+    ime: Arc<Mutex<ImeState>>,
+    /// High surrogate of a character split over two `WM_CHAR` messages.
+    high_surrogate: Cell<Option<u16>>,
+}
+
+impl WindowState {
+    fn send(&self, event: Event) {
+        // The receiving window may already be dropped, nothing to do then.
+        _ = self.events.send(event);
+    }
+}
+
+impl WindowWin32 {
+    pub extern "system" fn wndproc(
+        window: HWND,
+        message: u32,
+        wparam: WPARAM,
+        lparam: LPARAM,
+    ) -> LRESULT {
+        unsafe {
+            if message == WM_NCCREATE {
+                let create_struct = &*(lparam.0 as *const CREATESTRUCTW);
+                SetWindowLongPtrW(window, GWLP_USERDATA, create_struct.lpCreateParams as isize);
+                return DefWindowProcW(window, message, wparam, lparam);
+            }
+
+            let state_ptr = GetWindowLongPtrW(window, GWLP_USERDATA) as *mut WindowState;
+            if state_ptr.is_null() {
+                return DefWindowProcW(window, message, wparam, lparam);
+            }
+            let state = &*state_ptr;
+
+            match message {
+                WM_PAINT => {
+                    log::trace!("WM_PAINT");
+                    state.send(Event::RedrawRequested);
+                    _ = ValidateRect(window, None);
+                    LRESULT(0)
+                }
+                WM_SIZE => {
+                    state.send(Event::Resized {
+                        width: loword(lparam.0 as usize) as u32,
+                        height: hiword(lparam.0 as usize) as u32,
+                    });
+                    _ = cursor::apply_grab(window, &state.cursor.lock().unwrap());
+                    LRESULT(0)
+                }
+                WM_MOVE => {
+                    state.send(Event::Moved {
+                        x: loword(lparam.0 as usize) as i16 as i32,
+                        y: hiword(lparam.0 as usize) as i16 as i32,
+                    });
+                    _ = cursor::apply_grab(window, &state.cursor.lock().unwrap());
+                    LRESULT(0)
+                }
+                WM_SETFOCUS | WM_KILLFOCUS => {
+                    let focused = message == WM_SETFOCUS;
+                    state.send(Event::Focused(focused));
+                    let mut cursor = state.cursor.lock().unwrap();
+                    cursor.focused = focused;
+                    _ = cursor::apply_grab(window, &cursor);
+                    LRESULT(0)
+                }
+                WM_SETCURSOR if loword(lparam.0 as usize) as u32 == HTCLIENT => {
+                    SetCursor(state.cursor.lock().unwrap().current());
+                    LRESULT(1)
+                }
+                WM_APP_CURSOR => {
+                    let cursor = state.cursor.lock().unwrap();
+                    cursor::apply_cursor(window, &cursor);
+                    _ = cursor::apply_grab(window, &cursor);
+                    LRESULT(0)
+                }
+                WM_INPUT => {
+                    if let Some((dx, dy)) = cursor::read_raw_motion(lparam) {
+                        state.send(Event::MouseMotion { dx, dy });
+                    }
+                    DefWindowProcW(window, message, wparam, lparam)
+                }
+                WM_MOUSEMOVE => {
+                    state.send(Event::CursorMoved {
+                        x: loword(lparam.0 as usize) as i16 as i32,
+                        y: hiword(lparam.0 as usize) as i16 as i32,
+                    });
+                    LRESULT(0)
+                }
+                WM_LBUTTONDOWN | WM_LBUTTONUP | WM_RBUTTONDOWN | WM_RBUTTONUP | WM_MBUTTONDOWN
+                | WM_MBUTTONUP | WM_XBUTTONDOWN | WM_XBUTTONUP => {
+                    let button = match message {
+                        WM_LBUTTONDOWN | WM_LBUTTONUP => MouseButton::Left,
+                        WM_RBUTTONDOWN | WM_RBUTTONUP => MouseButton::Right,
+                        WM_MBUTTONDOWN | WM_MBUTTONUP => MouseButton::Middle,
+                        _ => MouseButton::Other(hiword(wparam.0)),
+                    };
+                    let state_button = match message {
+                        WM_LBUTTONDOWN | WM_RBUTTONDOWN | WM_MBUTTONDOWN | WM_XBUTTONDOWN => {
+                            ElementState::Pressed
+                        }
+                        _ => ElementState::Released,
+                    };
+                    state.send(Event::MouseInput {
+                        button,
+                        state: state_button,
+                    });
+                    LRESULT(0)
+                }
+                WM_MOUSEWHEEL => {
+                    state.send(Event::MouseWheel {
+                        delta: hiword(wparam.0) as i16 as f32 / WHEEL_DELTA as f32,
+                    });
+                    LRESULT(0)
+                }
+                WM_KEYDOWN | WM_SYSKEYDOWN | WM_KEYUP | WM_SYSKEYUP => {
+                    state.send(Event::KeyboardInput {
+                        scancode: ((lparam.0 >> 16) & 0xff) as u32,
+                        key: wparam.0 as u32,
+                        state: match message {
+                            WM_KEYDOWN | WM_SYSKEYDOWN => ElementState::Pressed,
+                            _ => ElementState::Released,
+                        },
+                    });
+                    DefWindowProcW(window, message, wparam, lparam)
+                }
+                WM_CHAR => {
+                    let unit = wparam.0 as u16;
+                    let text = if (0xD800..0xDC00).contains(&unit) {
+                        state.high_surrogate.set(Some(unit));
+                        None
+                    } else if let Some(high) = state.high_surrogate.take() {
+                        Some(String::from_utf16_lossy(&[high, unit]))
+                    } else {
+                        Some(String::from_utf16_lossy(&[unit]))
+                    };
+                    // Control characters are already reported as keyboard input.
+                    if let Some(text) = text.filter(|text| !text.chars().any(char::is_control)) {
+                        state.send(Event::Text(text));
+                    }
+                    LRESULT(0)
+                }
+                WM_IME_SETCONTEXT => {
+                    // Pre-edit text is drawn by the application, not by the IME window.
+                    let lparam = LPARAM(lparam.0 & !(ISC_SHOWUICOMPOSITIONWINDOW as isize));
+                    DefWindowProcW(window, message, wparam, lparam)
+                }
+                WM_IME_STARTCOMPOSITION => {
+                    state.send(Event::Ime(Ime::Start));
+                    ime::apply(window, &state.ime.lock().unwrap());
+                    LRESULT(0)
+                }
+                WM_IME_COMPOSITION => {
+                    if lparam.0 as u32 & GCS_RESULTSTR.0 != 0 {
+                        if let Some(text) = ime::composition_string(window, GCS_RESULTSTR) {
+                            state.send(Event::Ime(Ime::Commit(text)));
+                        }
+                    }
+                    if lparam.0 as u32 & GCS_COMPSTR.0 != 0 {
+                        if let Some(text) = ime::composition_string(window, GCS_COMPSTR) {
+                            let cursor = ime::composition_cursor(window, &text);
+                            state.send(Event::Ime(Ime::Preedit { text, cursor }));
+                        }
+                    }
+                    // Not passed on, so the committed text doesn't come again as WM_CHAR.
+                    LRESULT(0)
+                }
+                WM_IME_ENDCOMPOSITION => {
+                    state.send(Event::Ime(Ime::Preedit {
+                        text: String::new(),
+                        cursor: None,
+                    }));
+                    state.send(Event::Ime(Ime::End));
+                    LRESULT(0)
+                }
+                WM_APP_IME => {
+                    ime::apply(window, &state.ime.lock().unwrap());
+                    LRESULT(0)
+                }
+                WM_CLIPBOARDUPDATE => {
+                    state.send(Event::ClipboardChanged);
+                    LRESULT(0)
+                }
+                WM_CLOSE => {
+                    log::trace!("WM_CLOSE");
+                    // Only reported: the window stays until `close`, so whatever renders
+                    // into it can finish first.
+                    state.send(Event::CloseRequested);
+                    LRESULT(0)
+                }
+                WM_APP_CLOSE => {
+                    _ = DestroyWindow(window);
+                    LRESULT(0)
+                }
+                WM_DESTROY => {
+                    log::trace!("WM_DESTROY");
+                    _ = ClipCursor(None);
+                    dragdrop::revoke(window);
+                    state.send(Event::Destroyed);
+                    PostQuitMessage(0);
+                    LRESULT(0)
+                }
+                WM_NCDESTROY => {
+                    SetWindowLongPtrW(window, GWLP_USERDATA, 0);
+                    drop(Box::from_raw(state_ptr));
+                    DefWindowProcW(window, message, wparam, lparam)
+                }
+                _ => {
+                    //log::trace!("{:?}", message);
+                    DefWindowProcW(window, message, wparam, lparam)
+                }
+            }
+        }
+    }
+
+    pub fn create(title: &str) -> Result<Self> {
+        Self::create_window(title, None)
+    }
+
+    /// Creates a window whose client area is `width` x `height` pixels.
+    pub fn create_with_size(title: &str, width: u32, height: u32) -> Result<Self> {
+        Self::create_window(title, Some((width, height)))
+    }
+
+    /// Creates the window on a thread of its own, which runs its message loop until the
+    /// window is destroyed.
+    fn create_window(title: &str, size: Option<(u32, u32)>) -> Result<Self> {
+        let title = String::from(title);
+        let (sender, receiver) = mpsc::channel();
+        let (created_sender, created) = mpsc::channel();
+        let cursor = Arc::new(Mutex::new(CursorState::new()?));
+        let ime = Arc::new(Mutex::new(ImeState::new()));
+        thread::Builder::new()
+            .name("alovak window".to_owned())
+            .spawn(move || unsafe {
+                let instance = match GetModuleHandleA(None) {
+                    Ok(instance) => instance,
+                    Err(error) => {
+                        _ = created_sender.send(Err(win32_error(error)));
+                        return;
+                    }
+                };
+                let title: Vec<u16> = title
+                    .trim_end_matches('\0')
+                    .encode_utf16()
+                    .chain(std::iter::once(0))
+                    .collect();
+                let window_class = PCWSTR::from_raw(title.as_ptr());
+
+                let wc = WNDCLASSW {
+                    hInstance: instance.into(),
+                    lpszClassName: window_class,
+
+                    style: CS_HREDRAW | CS_VREDRAW,
+                    lpfnWndProc: Some(Self::wndproc),
+                    ..Default::default()
+                };
+
+                let atom = RegisterClassW(&wc);
+                debug_assert!(atom != 0);
+
+                let state = Box::into_raw(Box::new(WindowState {
+                    events: sender.clone(),
+                    cursor: cursor.clone(),
+                    ime: ime.clone(),
+                    high_surrogate: Cell::new(None),
+                }));
+
+                let style = WS_OVERLAPPED | WS_VISIBLE | WS_SYSMENU | WS_MINIMIZEBOX;
+                // The requested size is of the client area, so borders are added on top.
+                let (width, height) = match size {
+                    Some((width, height)) => {
+                        let mut rect = RECT {
+                            left: 0,
+                            top: 0,
+                            right: width as i32,
+                            bottom: height as i32,
+                        };
+                        _ = AdjustWindowRectEx(&mut rect, style, false, WINDOW_EX_STYLE::default());
+                        (rect.right - rect.left, rect.bottom - rect.top)
+                    }
+                    None => (CW_USEDEFAULT, CW_USEDEFAULT),
+                };
+
+                let hwnd = match CreateWindowExW(
+                    WINDOW_EX_STYLE::default(),
+                    window_class,
+                    window_class,
+                    style,
+                    CW_USEDEFAULT,
+                    CW_USEDEFAULT,
+                    width,
+                    height,
+                    None,
+                    None,
+                    instance,
+                    Some(state as *const core::ffi::c_void),
+                ) {
+                    Ok(hwnd) => hwnd,
+                    // The state is leaked: a window failing after WM_NCCREATE has freed
+                    // it already in WM_NCDESTROY.
+                    Err(error) => {
+                        _ = created_sender.send(Err(win32_error(error)));
+                        return;
+                    }
+                };
+
+                if let Err(error) = cursor::register_raw_mouse(hwnd) {
+                    log::warn!("raw mouse input unavailable: {}", error);
+                }
+                if let Err(error) = AddClipboardFormatListener(hwnd) {
+                    log::warn!("clipboard updates unavailable: {}", error);
+                }
+                if let Err(error) = dragdrop::register(hwnd, sender) {
+                    log::warn!("file drag and drop unavailable: {}", error);
+                }
+
+                _ = created_sender.send(Ok(WindowWin32 {
+                    hwnd,
+                    events: Mutex::new(receiver),
+                    cursor,
+                    ime,
+                }));
+
+                let mut message = MSG::default();
+                while GetMessageW(&mut message, None, 0, 0).into() {
+                    log::trace!("{:?}", message);
+                    _ = TranslateMessage(&message);
+                    DispatchMessageW(&message);
+                }
+            })
+            .map_err(Error::Io)?;
+        created.recv().unwrap_or_else(|_| {
+            Err(Error::Other(
+                "Window thread ended before creating the window".to_owned(),
+            ))
+        })
+    }
+}
+
+impl HasWindowHandle for WindowWin32 {
+    fn window_handle(&self) -> std::result::Result<WindowHandle<'_>, HandleError> {
+        let hwnd = NonZeroIsize::new(self.hwnd.0 as isize).ok_or(HandleError::Unavailable)?;
+        let mut handle = Win32WindowHandle::new(hwnd);
+        handle.hinstance =
+            NonZeroIsize::new(unsafe { GetWindowLongPtrW(self.hwnd, GWLP_HINSTANCE) });
+        // Only `close` and dropping the window destroy it, so the handle stays valid while
+        // the window is borrowed unless `close` is called, which renderers have to be done
+        // with the window for.
+        Ok(unsafe { WindowHandle::borrow_raw(RawWindowHandle::Win32(handle)) })
+    }
+}
+
+impl HasDisplayHandle for WindowWin32 {
+    fn display_handle(&self) -> std::result::Result<DisplayHandle<'_>, HandleError> {
+        Ok(DisplayHandle::windows())
+    }
+}
+
+impl Window for WindowWin32 {
+    fn inner_size(&self) -> Result<(u32, u32)> {
+        let mut rect = RECT::default();
+        unsafe { GetClientRect(self.hwnd, &mut rect) }.map_err(win32_error)?;
+        Ok((
+            (rect.right - rect.left) as u32,
+            (rect.bottom - rect.top) as u32,
+        ))
+    }
+
+    fn set_title(&self, title: &str) -> Result<()> {
+        if title.contains('\0') {
+            return Err(Error::Other("Title contains a nul character".to_owned()));
+        }
+        let title: Vec<u16> = title.encode_utf16().chain(std::iter::once(0)).collect();
+        unsafe { SetWindowTextW(self.hwnd, PCWSTR::from_raw(title.as_ptr())) }.map_err(win32_error)
+    }
+
+    fn request_redraw(&self) -> Result<()> {
+        if unsafe { InvalidateRect(self.hwnd, None, false) }.as_bool() {
+            Ok(())
+        } else {
+            Err(win32_error(windows::core::Error::from_win32()))
+        }
+    }
+
+    fn close(&self) -> Result<()> {
+        unsafe { PostMessageW(self.hwnd, WM_APP_CLOSE, WPARAM(0), LPARAM(0)) }.map_err(win32_error)
+    }
+
+    fn poll_event(&self) -> Option<Event> {
+        self.events.lock().unwrap().try_recv().ok()
+    }
+
+    fn set_cursor(&self, icon: CursorIcon) -> Result<()> {
+        let handle = cursor::load_icon(icon)?;
+        self.cursor.lock().unwrap().replace(handle, false);
+        self.update_cursor()
+    }
+
+    fn set_custom_cursor(&self, custom: &CustomCursor) -> Result<()> {
+        let handle = cursor::create_custom(custom)?;
+        self.cursor.lock().unwrap().replace(handle, true);
+        self.update_cursor()
+    }
+
+    fn set_cursor_visible(&self, visible: bool) -> Result<()> {
+        self.cursor.lock().unwrap().visible = visible;
+        self.update_cursor()
+    }
+
+    fn set_cursor_grab(&self, grab: CursorGrab) -> Result<()> {
+        self.cursor.lock().unwrap().grab = grab;
+        self.update_cursor()
+    }
+
+    fn set_ime_allowed(&self, allowed: bool) -> Result<()> {
+        self.ime.lock().unwrap().allowed = allowed;
+        self.update_ime()
+    }
+
+    fn set_ime_cursor_area(&self, x: i32, y: i32, width: u32, height: u32) -> Result<()> {
+        self.ime.lock().unwrap().area = (x, y, width, height);
+        self.update_ime()
+    }
+
+    fn clipboard_text(&self) -> Result<Option<String>> {
+        clipboard::get_text(self.hwnd)
+    }
+
+    fn set_clipboard_text(&self, text: &str) -> Result<()> {
+        clipboard::set_text(self.hwnd, text)
+    }
+
+    fn clipboard_image(&self) -> Result<Option<ClipboardImage>> {
+        clipboard::get_image(self.hwnd)
+    }
+
+    fn set_clipboard_image(&self, image: &ClipboardImage) -> Result<()> {
+        clipboard::set_image(self.hwnd, image)
+    }
+}
+
+impl WindowWin32 {
+    /// Cursor and clip changes only take effect on the window thread.
+    fn update_cursor(&self) -> Result<()> {
+        unsafe { PostMessageW(self.hwnd, WM_APP_CURSOR, WPARAM(0), LPARAM(0)) }.map_err(win32_error)
+    }
+
+    /// Input contexts belong to the window thread, so IME changes are applied there.
+    fn update_ime(&self) -> Result<()> {
+        unsafe { PostMessageW(self.hwnd, WM_APP_IME, WPARAM(0), LPARAM(0)) }.map_err(win32_error)
+    }
+}
+
+impl Drop for WindowWin32 {
+    fn drop(&mut self) {
+        // Fails if `close` destroyed the window already.
+        _ = unsafe { PostMessageW(self.hwnd, WM_APP_CLOSE, WPARAM(0), LPARAM(0)) };
+    }
+}
+
+fn win32_error(error: windows::core::Error) -> Error {
+    Error::Other(error.message())
+}
+
+fn loword(value: usize) -> u16 {
+    (value & 0xffff) as u16
+}
+
+fn hiword(value: usize) -> u16 {
+    ((value >> 16) & 0xffff) as u16
+}
@@ -0,0 +1,179 @@
+//! Drives the evdev backend with a virtual gamepad created through uinput. Needs write
+//! access to `/dev/uinput` and the uinput module, so it only runs when asked for with
+//! `cargo test --test gamepad_uinput -- --ignored`.
+#![cfg(target_os = "linux")]
+
+use std::{
+    fs::{File, OpenOptions},
+    io::Write,
+    mem,
+    os::{fd::AsRawFd, unix::fs::OpenOptionsExt},
+    slice, thread,
+    time::{Duration, Instant},
+};
+
+use alovak::{ElementState, GamepadAxis, GamepadButton, GamepadEvent, GamepadId, Gamepads};
+
+const NAME: &str = "alovak virtual gamepad";
+
+const EV_SYN: u16 = 0x00;
+const EV_KEY: u16 = 0x01;
+const EV_ABS: u16 = 0x03;
+const BTN_SOUTH: u16 = 0x130;
+const BTN_EAST: u16 = 0x131;
+const ABS_X: u16 = 0x00;
+const ABS_Y: u16 = 0x01;
+
+const fn ioc(dir: u64, nr: u64, size: usize) -> u64 {
+    (dir << 30) | ((size as u64) << 16) | ((b'U' as u64) << 8) | nr
+}
+
+const UI_DEV_CREATE: u64 = ioc(0, 1, 0);
+const UI_DEV_DESTROY: u64 = ioc(0, 2, 0);
+const UI_DEV_SETUP: u64 = ioc(1, 3, mem::size_of::<libc::uinput_setup>());
+const UI_ABS_SETUP: u64 = ioc(1, 4, mem::size_of::<libc::uinput_abs_setup>());
+const UI_SET_EVBIT: u64 = ioc(1, 100, mem::size_of::<libc::c_int>());
+const UI_SET_KEYBIT: u64 = ioc(1, 101, mem::size_of::<libc::c_int>());
+const UI_SET_ABSBIT: u64 = ioc(1, 103, mem::size_of::<libc::c_int>());
+
+struct VirtualPad {
+    file: File,
+}
+
+impl VirtualPad {
+    fn create() -> Option<Self> {
+        let file = OpenOptions::new()
+            .write(true)
+            .custom_flags(libc::O_NONBLOCK)
+            .open("/dev/uinput")
+            .ok()?;
+        let fd = file.as_raw_fd();
+
+        unsafe {
+            libc::ioctl(fd, UI_SET_EVBIT as _, EV_KEY as libc::c_int);
+            libc::ioctl(fd, UI_SET_KEYBIT as _, BTN_SOUTH as libc::c_int);
+            libc::ioctl(fd, UI_SET_KEYBIT as _, BTN_EAST as libc::c_int);
+            libc::ioctl(fd, UI_SET_EVBIT as _, EV_ABS as libc::c_int);
+            for code in [ABS_X, ABS_Y] {
+                libc::ioctl(fd, UI_SET_ABSBIT as _, code as libc::c_int);
+                let mut abs: libc::uinput_abs_setup = mem::zeroed();
+                abs.code = code;
+                abs.absinfo.minimum = -32768;
+                abs.absinfo.maximum = 32767;
+                libc::ioctl(fd, UI_ABS_SETUP as _, &abs);
+            }
+
+            let mut setup: libc::uinput_setup = mem::zeroed();
+            setup.id.bustype = 0x03;
+            setup.id.vendor = 0x1234;
+            setup.id.product = 0x5678;
+            for (dst, src) in setup.name.iter_mut().zip(NAME.bytes()) {
+                *dst = src as libc::c_char;
+            }
+            if libc::ioctl(fd, UI_DEV_SETUP as _, &setup) < 0
+                || libc::ioctl(fd, UI_DEV_CREATE as _) < 0
+            {
+                return None;
+            }
+        }
+        Some(VirtualPad { file })
+    }
+
+    fn emit(&mut self, type_: u16, code: u16, value: i32) {
+        let mut event: libc::input_event = unsafe { mem::zeroed() };
+        event.type_ = type_;
+        event.code = code;
+        event.value = value;
+        let buf = unsafe {
+            slice::from_raw_parts(
+                &event as *const libc::input_event as *const u8,
+                mem::size_of::<libc::input_event>(),
+            )
+        };
+        self.file.write_all(buf).unwrap();
+    }
+
+    fn sync(&mut self) {
+        self.emit(EV_SYN, 0, 0);
+    }
+}
+
+impl Drop for VirtualPad {
+    fn drop(&mut self) {
+        unsafe { libc::ioctl(self.file.as_raw_fd(), UI_DEV_DESTROY as _) };
+    }
+}
+
+/// Polls until `matches` accepts an event, failing after a few seconds.
+fn wait_for(gamepads: &mut Gamepads, mut matches: impl FnMut(&Gamepads, &GamepadEvent) -> bool) {
+    let start = Instant::now();
+    while start.elapsed() < Duration::from_secs(5) {
+        gamepads.poll();
+        while let Some(event) = gamepads.next_event() {
+            if matches(gamepads, &event) {
+                return;
+            }
+        }
+        thread::sleep(Duration::from_millis(10));
+    }
+    panic!("timed out waiting for a gamepad event");
+}
+
+#[test]
+#[ignore = "needs write access to /dev/uinput"]
+fn virtual_gamepad() {
+    let mut pad = VirtualPad::create().expect("/dev/uinput can`t be opened");
+    let mut gamepads = Gamepads::new().unwrap();
+
+    // Real gamepads may be plugged in as well, so look for the virtual one by name.
+    let mut id = None::<GamepadId>;
+    wait_for(&mut gamepads, |gamepads, event| match event {
+        GamepadEvent::Connected(connected) => {
+            id = Some(*connected);
+            gamepads.gamepad(*connected).unwrap().name() == NAME
+        }
+        _ => false,
+    });
+    let id = id.unwrap();
+
+    pad.emit(EV_KEY, BTN_SOUTH, 1);
+    pad.sync();
+    wait_for(&mut gamepads, |_, event| {
+        *event
+            == GamepadEvent::Button {
+                id,
+                button: GamepadButton::South,
+                state: ElementState::Pressed,
+            }
+    });
+    assert!(gamepads
+        .gamepad(id)
+        .unwrap()
+        .is_pressed(GamepadButton::South));
+
+    // Full deflection up: evdev Y grows downwards, alovak reports up as positive.
+    pad.emit(EV_ABS, ABS_Y, -32768);
+    pad.sync();
+    wait_for(&mut gamepads, |_, event| match event {
+        GamepadEvent::Axis { axis, value, .. } => *axis == GamepadAxis::LeftStickY && *value > 0.99,
+        _ => false,
+    });
+
+    // Inside the dead zone the stick reads zero.
+    pad.emit(EV_ABS, ABS_Y, 0);
+    pad.emit(EV_ABS, ABS_X, 2000);
+    pad.sync();
+    wait_for(&mut gamepads, |_, event| match event {
+        GamepadEvent::Axis { axis, value, .. } => *axis == GamepadAxis::LeftStickY && *value == 0.0,
+        _ => false,
+    });
+    assert_eq!(
+        gamepads.gamepad(id).unwrap().axis(GamepadAxis::LeftStickX),
+        0.0
+    );
+
+    drop(pad);
+    wait_for(&mut gamepads, |_, event| {
+        *event == GamepadEvent::Disconnected(id)
+    });
+}
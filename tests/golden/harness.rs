@@ -0,0 +1,234 @@
+use std::{env, fs, path::PathBuf};
+
+use alovak::{
+    vulkan::{read_image, Attachment, Context},
+    Result, RgbaImage,
+};
+use ash::vk::{Extent2D, Format, ImageLayout, ImageUsageFlags, SampleCountFlags};
+
+pub const EXTENT: Extent2D = Extent2D {
+    width: 128,
+    height: 128,
+};
+pub const FORMAT: Format = Format::R8G8B8A8_UNORM;
+
+/// How far a rendering may drift from its reference, e.g. across drivers.
+#[derive(Debug, Clone, Copy)]
+pub struct Tolerance {
+    /// Largest difference of any channel for a pixel to still count as equal.
+    pub channel: u8,
+    /// Share of pixels allowed to differ by more than `channel`.
+    pub differing: f64,
+    /// Largest mean CIE76 color difference; 2.3 is about what one can just notice.
+    pub mean_delta_e: f64,
+}
+
+impl Default for Tolerance {
+    fn default() -> Self {
+        Tolerance {
+            channel: 2,
+            differing: 0.001,
+            mean_delta_e: 1.0,
+        }
+    }
+}
+
+#[derive(Debug)]
+pub struct Comparison {
+    pub differing: usize,
+    pub max_channel: u8,
+    pub mean_delta_e: f64,
+    pub max_delta_e: f64,
+    /// The reference dimmed to gray, with differing pixels in red by how far off they are.
+    pub diff: RgbaImage,
+}
+
+impl Comparison {
+    pub fn passes(&self, tolerance: &Tolerance) -> bool {
+        let pixels = (self.diff.width() * self.diff.height()) as f64;
+        self.differing as f64 <= tolerance.differing * pixels
+            && self.mean_delta_e <= tolerance.mean_delta_e
+    }
+}
+
+fn linear(value: u8) -> f64 {
+    let value = value as f64 / 255.0;
+    if value <= 0.04045 {
+        value / 12.92
+    } else {
+        ((value + 0.055) / 1.055).powf(2.4)
+    }
+}
+
+/// CIE L*a*b* of an sRGB color under D65.
+fn lab(pixel: [u8; 4]) -> [f64; 3] {
+    let (r, g, b) = (linear(pixel[0]), linear(pixel[1]), linear(pixel[2]));
+    let x = (0.4124 * r + 0.3576 * g + 0.1805 * b) / 0.95047;
+    let y = 0.2126 * r + 0.7152 * g + 0.0722 * b;
+    let z = (0.0193 * r + 0.1192 * g + 0.9505 * b) / 1.08883;
+    let f = |t: f64| {
+        if t > 216.0 / 24389.0 {
+            t.cbrt()
+        } else {
+            (24389.0 / 27.0 * t + 16.0) / 116.0
+        }
+    };
+    let (fx, fy, fz) = (f(x), f(y), f(z));
+    [116.0 * fy - 16.0, 500.0 * (fx - fy), 200.0 * (fy - fz)]
+}
+
+/// Compares two images of the same size pixel by pixel. Alpha counts toward the channel
+/// difference but not toward the perceptual one.
+pub fn compare(actual: &RgbaImage, reference: &RgbaImage, tolerance: &Tolerance) -> Comparison {
+    let mut differing = 0;
+    let mut max_channel = 0;
+    let (mut sum_delta_e, mut max_delta_e) = (0.0, 0.0f64);
+    let mut diff = Vec::with_capacity(reference.pixels().len());
+    for (a, b) in actual
+        .pixels()
+        .chunks_exact(4)
+        .zip(reference.pixels().chunks_exact(4))
+    {
+        let (a, b): ([u8; 4], [u8; 4]) = (a.try_into().unwrap(), b.try_into().unwrap());
+        let channel = (0..4).map(|i| a[i].abs_diff(b[i])).max().unwrap();
+        let (la, lb) = (lab(a), lab(b));
+        let delta_e =
+            ((la[0] - lb[0]).powi(2) + (la[1] - lb[1]).powi(2) + (la[2] - lb[2]).powi(2)).sqrt();
+        max_channel = max_channel.max(channel);
+        sum_delta_e += delta_e;
+        max_delta_e = max_delta_e.max(delta_e);
+        if channel > tolerance.channel {
+            differing += 1;
+            let red = (128.0 + delta_e * 12.7).min(255.0) as u8;
+            diff.extend([red, 0, 0, 255]);
+        } else {
+            let gray = (lb[0] * 0.8) as u8;
+            diff.extend([gray, gray, gray, 255]);
+        }
+    }
+    Comparison {
+        differing,
+        max_channel,
+        mean_delta_e: sum_delta_e / (reference.width() * reference.height()) as f64,
+        max_delta_e,
+        diff: RgbaImage::new(reference.width(), reference.height(), diff).unwrap(),
+    }
+}
+
+fn references() -> PathBuf {
+    PathBuf::from(env!("CARGO_MANIFEST_DIR")).join("tests/golden/references")
+}
+
+fn failures() -> PathBuf {
+    PathBuf::from(env!("CARGO_TARGET_TMPDIR")).join("golden")
+}
+
+fn flag(name: &str) -> bool {
+    env::var(name).is_ok_and(|value| !value.is_empty() && value != "0")
+}
+
+fn blessing() -> bool {
+    flag("ALOVAK_BLESS")
+}
+
+/// Machines without any Vulkan driver can opt out instead of failing.
+fn skipping() -> bool {
+    flag("ALOVAK_GOLDEN_SKIP")
+}
+
+/// Records a scene into the target. It starts out in `UNDEFINED` layout and the scene
+/// returns the layout it leaves it in.
+pub type Scene = fn(&Context, &Attachment) -> Result<ImageLayout>;
+
+fn render(context: &Context, scene: Scene) -> Result<RgbaImage> {
+    let mut target = Attachment::new(
+        context,
+        EXTENT,
+        FORMAT,
+        SampleCountFlags::TYPE_1,
+        ImageUsageFlags::COLOR_ATTACHMENT
+            | ImageUsageFlags::TRANSFER_SRC
+            | ImageUsageFlags::TRANSFER_DST,
+    )?;
+    let image = scene(context, &target)
+        .and_then(|layout| read_image(context, target.image(), layout, FORMAT, EXTENT));
+    target.destroy(context);
+    image
+}
+
+/// Renders `scene` and checks it against its reference.
+pub fn run(name: &str, scene: Scene) {
+    let tolerance = &Tolerance::default();
+    let context = match Context::headless() {
+        Ok(context) => context,
+        Err(error) if skipping() => {
+            eprintln!("golden `{}` skipped: no Vulkan device: {:?}", name, error);
+            return;
+        }
+        Err(error) => panic!(
+            "golden `{}`: no Vulkan device ({:?}); point VK_DRIVER_FILES at lavapipe's ICD, \
+             or set ALOVAK_GOLDEN_SKIP=1 to skip",
+            name, error
+        ),
+    };
+    let actual = render(&context, scene).expect("rendering failed");
+    drop(context);
+
+    let reference_path = references().join(format!("{}.png", name));
+    if blessing() {
+        fs::create_dir_all(references()).unwrap();
+        actual.save_png(&reference_path).unwrap();
+        eprintln!("golden `{}` blessed: {}", name, reference_path.display());
+        return;
+    }
+
+    let reference = match RgbaImage::load_png(&reference_path) {
+        Ok(reference) => reference,
+        Err(error) => panic!(
+            "golden `{}`: reference {} can`t be loaded ({:?}); run with ALOVAK_BLESS=1 to create it",
+            name,
+            reference_path.display(),
+            error
+        ),
+    };
+    let failures = failures();
+    let save_failure = |diff: Option<&RgbaImage>| {
+        fs::create_dir_all(&failures).unwrap();
+        actual
+            .save_png(failures.join(format!("{}.actual.png", name)))
+            .unwrap();
+        if let Some(diff) = diff {
+            diff.save_png(failures.join(format!("{}.diff.png", name)))
+                .unwrap();
+        }
+    };
+
+    if (actual.width(), actual.height()) != (reference.width(), reference.height()) {
+        save_failure(None);
+        panic!(
+            "golden `{}`: rendered {}x{}, reference is {}x{}; output in {}",
+            name,
+            actual.width(),
+            actual.height(),
+            reference.width(),
+            reference.height(),
+            failures.display()
+        );
+    }
+
+    let comparison = compare(&actual, &reference, tolerance);
+    if !comparison.passes(tolerance) {
+        save_failure(Some(&comparison.diff));
+        panic!(
+            "golden `{}` differs: {} pixels off by more than {} (largest {}), mean delta E {:.3} \
+             (largest {:.3}); output in {}",
+            name,
+            comparison.differing,
+            tolerance.channel,
+            comparison.max_channel,
+            comparison.mean_delta_e,
+            comparison.max_delta_e,
+            failures.display()
+        );
+    }
+}
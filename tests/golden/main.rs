@@ -0,0 +1,25 @@
+//! Golden-image tests: each scene is rendered headlessly into an offscreen target and
+//! compared with `tests/golden/references/<scene>.png`. They fail when no Vulkan device can
+//! be created; on CI point `VK_DRIVER_FILES` at lavapipe's ICD, and on machines without a
+//! driver set `ALOVAK_GOLDEN_SKIP=1` to skip them.
+//!
+//! On a mismatch the rendered image and a diff image are written to `target/tmp/golden`.
+//! Run with `ALOVAK_BLESS=1` to replace the references with what was rendered.
+
+mod harness;
+mod scenes;
+
+#[test]
+fn clear() {
+    harness::run("clear", scenes::clear);
+}
+
+#[test]
+fn triangle() {
+    harness::run("triangle", scenes::triangle);
+}
+
+#[test]
+fn graph() {
+    harness::run("graph", scenes::graph);
+}
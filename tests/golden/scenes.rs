@@ -0,0 +1,369 @@
+use std::io::Cursor;
+
+use alovak::{
+    graph::{ImageAccess, ImageDesc, RenderGraph, TransientPool},
+    impl_vertex,
+    mesh::{Mesh, VertexInput},
+    vulkan::{Attachment, Context},
+    Error, Result,
+};
+use ash::{
+    util::read_spv,
+    vk::{
+        AccessFlags, AttachmentDescription, AttachmentLoadOp, AttachmentReference,
+        AttachmentStoreOp, ClearColorValue, ClearValue, ColorComponentFlags, CommandBuffer,
+        CullModeFlags, DependencyFlags, Extent3D, Framebuffer, FramebufferCreateInfo, FrontFace,
+        GraphicsPipelineCreateInfo, Image, ImageAspectFlags, ImageCopy, ImageLayout,
+        ImageMemoryBarrier, ImageSubresourceLayers, ImageSubresourceRange, Offset2D, Offset3D,
+        Pipeline, PipelineBindPoint, PipelineCache, PipelineColorBlendAttachmentState,
+        PipelineColorBlendStateCreateInfo, PipelineInputAssemblyStateCreateInfo, PipelineLayout,
+        PipelineLayoutCreateInfo, PipelineMultisampleStateCreateInfo,
+        PipelineRasterizationStateCreateInfo, PipelineShaderStageCreateInfo, PipelineStageFlags,
+        PipelineViewportStateCreateInfo, PolygonMode, PrimitiveTopology, Rect2D, RenderPass,
+        RenderPassBeginInfo, RenderPassCreateInfo, SampleCountFlags, ShaderModule,
+        ShaderModuleCreateInfo, ShaderStageFlags, SubpassContents, SubpassDescription, Viewport,
+        QUEUE_FAMILY_IGNORED,
+    },
+};
+
+use crate::harness::{EXTENT, FORMAT};
+
+const VERTEX_SHADER: &[u8] =
+    include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/shaders/vert.spv"));
+const FRAGMENT_SHADER: &[u8] =
+    include_bytes!(concat!(env!("CARGO_MANIFEST_DIR"), "/shaders/frag.spv"));
+
+fn color_range() -> ImageSubresourceRange {
+    ImageSubresourceRange::default()
+        .aspect_mask(ImageAspectFlags::COLOR)
+        .level_count(1)
+        .layer_count(1)
+}
+
+fn color_layers() -> ImageSubresourceLayers {
+    ImageSubresourceLayers::default()
+        .aspect_mask(ImageAspectFlags::COLOR)
+        .layer_count(1)
+}
+
+fn clear_value(rgba: [f32; 4]) -> ClearColorValue {
+    ClearColorValue { float32: rgba }
+}
+
+/// Moves the whole target to `TRANSFER_DST_OPTIMAL`, discarding what it held.
+fn discard(context: &Context, command_buffer: CommandBuffer, image: Image) {
+    let barrier = ImageMemoryBarrier::default()
+        .image(image)
+        .subresource_range(color_range())
+        .old_layout(ImageLayout::UNDEFINED)
+        .new_layout(ImageLayout::TRANSFER_DST_OPTIMAL)
+        .src_access_mask(AccessFlags::empty())
+        .dst_access_mask(AccessFlags::TRANSFER_WRITE)
+        .src_queue_family_index(QUEUE_FAMILY_IGNORED)
+        .dst_queue_family_index(QUEUE_FAMILY_IGNORED);
+    unsafe {
+        context.device.cmd_pipeline_barrier(
+            command_buffer,
+            PipelineStageFlags::TOP_OF_PIPE,
+            PipelineStageFlags::TRANSFER,
+            DependencyFlags::empty(),
+            &[],
+            &[],
+            &[barrier],
+        )
+    };
+}
+
+/// The target cleared to a single color.
+pub fn clear(context: &Context, target: &Attachment) -> Result<ImageLayout> {
+    context.submit_and_wait(|command_buffer| {
+        discard(context, command_buffer, target.image());
+        unsafe {
+            context.device.cmd_clear_color_image(
+                command_buffer,
+                target.image(),
+                ImageLayout::TRANSFER_DST_OPTIMAL,
+                &clear_value([0.2, 0.4, 0.6, 1.0]),
+                &[color_range()],
+            )
+        };
+        Ok(())
+    })?;
+    Ok(ImageLayout::TRANSFER_DST_OPTIMAL)
+}
+
+fn shader_module(context: &Context, spv: &[u8]) -> Result<ShaderModule> {
+    let code = read_spv(&mut Cursor::new(spv)).map_err(Error::Io)?;
+    let create_info = ShaderModuleCreateInfo::default().code(&code);
+    unsafe { context.device.create_shader_module(&create_info, None) }.map_err(Error::Vulkan)
+}
+
+#[repr(C)]
+#[derive(Clone, Copy)]
+struct ColorVertex {
+    position: [f32; 2],
+    color: [f32; 3],
+}
+
+impl_vertex!(ColorVertex {
+    position: 0,
+    color: 1,
+});
+
+const TRIANGLE: [ColorVertex; 3] = [
+    ColorVertex {
+        position: [0.0, -0.5],
+        color: [0.8, 0.1765, 0.1765],
+    },
+    ColorVertex {
+        position: [0.5, 0.5],
+        color: [0.302, 0.4235, 0.702],
+    },
+    ColorVertex {
+        position: [-0.5, 0.5],
+        color: [0.6941, 0.1373, 0.8784],
+    },
+];
+
+/// Objects of the triangle scene, destroyed together whatever got created.
+#[derive(Default)]
+struct Triangle {
+    render_pass: RenderPass,
+    framebuffer: Framebuffer,
+    layout: PipelineLayout,
+    pipeline: Pipeline,
+    shaders: Vec<ShaderModule>,
+    mesh: Option<Mesh>,
+}
+
+impl Triangle {
+    fn create(&mut self, context: &Context, target: &Attachment) -> Result<()> {
+        let device = &context.device;
+        let attachments = [AttachmentDescription::default()
+            .format(FORMAT)
+            .samples(SampleCountFlags::TYPE_1)
+            .load_op(AttachmentLoadOp::CLEAR)
+            .store_op(AttachmentStoreOp::STORE)
+            .initial_layout(ImageLayout::UNDEFINED)
+            .final_layout(ImageLayout::COLOR_ATTACHMENT_OPTIMAL)];
+        let color_references = [AttachmentReference::default()
+            .attachment(0)
+            .layout(ImageLayout::COLOR_ATTACHMENT_OPTIMAL)];
+        let subpasses = [SubpassDescription::default()
+            .pipeline_bind_point(PipelineBindPoint::GRAPHICS)
+            .color_attachments(&color_references)];
+        let render_pass_create_info = RenderPassCreateInfo::default()
+            .attachments(&attachments)
+            .subpasses(&subpasses);
+        self.render_pass = unsafe { device.create_render_pass(&render_pass_create_info, None) }
+            .map_err(Error::Vulkan)?;
+
+        let views = [target.view()];
+        let framebuffer_create_info = FramebufferCreateInfo::default()
+            .render_pass(self.render_pass)
+            .attachments(&views)
+            .width(EXTENT.width)
+            .height(EXTENT.height)
+            .layers(1);
+        self.framebuffer = unsafe { device.create_framebuffer(&framebuffer_create_info, None) }
+            .map_err(Error::Vulkan)?;
+
+        self.layout =
+            unsafe { device.create_pipeline_layout(&PipelineLayoutCreateInfo::default(), None) }
+                .map_err(Error::Vulkan)?;
+
+        self.shaders.push(shader_module(context, VERTEX_SHADER)?);
+        self.shaders.push(shader_module(context, FRAGMENT_SHADER)?);
+        let stages = [
+            PipelineShaderStageCreateInfo::default()
+                .stage(ShaderStageFlags::VERTEX)
+                .module(self.shaders[0])
+                .name(c"main"),
+            PipelineShaderStageCreateInfo::default()
+                .stage(ShaderStageFlags::FRAGMENT)
+                .module(self.shaders[1])
+                .name(c"main"),
+        ];
+        let vertex_input = VertexInput::new().vertices::<ColorVertex>(0);
+        let vertex_input_state = vertex_input.state();
+        let input_assembly = PipelineInputAssemblyStateCreateInfo::default()
+            .topology(PrimitiveTopology::TRIANGLE_LIST);
+        let viewports = [Viewport {
+            x: 0.0,
+            y: 0.0,
+            width: EXTENT.width as f32,
+            height: EXTENT.height as f32,
+            min_depth: 0.0,
+            max_depth: 1.0,
+        }];
+        let scissors = [Rect2D {
+            offset: Offset2D { x: 0, y: 0 },
+            extent: EXTENT,
+        }];
+        let viewport = PipelineViewportStateCreateInfo::default()
+            .viewports(&viewports)
+            .scissors(&scissors);
+        let rasterization = PipelineRasterizationStateCreateInfo::default()
+            .polygon_mode(PolygonMode::FILL)
+            .cull_mode(CullModeFlags::NONE)
+            .front_face(FrontFace::CLOCKWISE)
+            .line_width(1.0);
+        let multisample = PipelineMultisampleStateCreateInfo::default()
+            .rasterization_samples(SampleCountFlags::TYPE_1);
+        let blend_attachments = [PipelineColorBlendAttachmentState::default()
+            .color_write_mask(ColorComponentFlags::RGBA)];
+        let blend = PipelineColorBlendStateCreateInfo::default().attachments(&blend_attachments);
+        let pipeline_create_info = GraphicsPipelineCreateInfo::default()
+            .stages(&stages)
+            .vertex_input_state(&vertex_input_state)
+            .input_assembly_state(&input_assembly)
+            .viewport_state(&viewport)
+            .rasterization_state(&rasterization)
+            .multisample_state(&multisample)
+            .color_blend_state(&blend)
+            .layout(self.layout)
+            .render_pass(self.render_pass)
+            .subpass(0);
+        self.pipeline = unsafe {
+            device.create_graphics_pipelines(PipelineCache::null(), &[pipeline_create_info], None)
+        }
+        .map_err(|(_, error)| Error::Vulkan(error))?[0];
+
+        self.mesh = Some(Mesh::indexed(context, &TRIANGLE, &[0u16, 1, 2])?);
+        Ok(())
+    }
+
+    fn destroy(&mut self, context: &Context) {
+        if let Some(mut mesh) = self.mesh.take() {
+            mesh.destroy(context);
+        }
+        let device = &context.device;
+        unsafe {
+            device.destroy_pipeline(self.pipeline, None);
+            for shader in self.shaders.drain(..) {
+                device.destroy_shader_module(shader, None);
+            }
+            device.destroy_pipeline_layout(self.layout, None);
+            device.destroy_framebuffer(self.framebuffer, None);
+            device.destroy_render_pass(self.render_pass, None);
+        }
+    }
+}
+
+/// A triangle mesh drawn with the bundled shaders on a dark background.
+pub fn triangle(context: &Context, target: &Attachment) -> Result<ImageLayout> {
+    let mut triangle = Triangle::default();
+    let result = triangle.create(context, target).and_then(|()| {
+        context.submit_and_wait(|command_buffer| {
+            let clear_values = [ClearValue {
+                color: clear_value([0.05, 0.05, 0.05, 1.0]),
+            }];
+            let begin_info = RenderPassBeginInfo::default()
+                .render_pass(triangle.render_pass)
+                .framebuffer(triangle.framebuffer)
+                .render_area(Rect2D {
+                    offset: Offset2D { x: 0, y: 0 },
+                    extent: EXTENT,
+                })
+                .clear_values(&clear_values);
+            let device = &context.device;
+            unsafe {
+                device.cmd_begin_render_pass(command_buffer, &begin_info, SubpassContents::INLINE);
+                device.cmd_bind_pipeline(
+                    command_buffer,
+                    PipelineBindPoint::GRAPHICS,
+                    triangle.pipeline,
+                );
+                if let Some(mesh) = &triangle.mesh {
+                    mesh.bind(context, command_buffer);
+                    mesh.draw(context, command_buffer, 0..1);
+                }
+                device.cmd_end_render_pass(command_buffer);
+            }
+            Ok(())
+        })
+    });
+    triangle.destroy(context);
+    result.map(|()| ImageLayout::COLOR_ATTACHMENT_OPTIMAL)
+}
+
+/// Two transient images, cleared and copied into the top and the bottom half of the target.
+/// Their lifetimes don't overlap, so the pool aliases them, and a pass nobody reads from is
+/// culled.
+pub fn graph(context: &Context, target: &Attachment) -> Result<ImageLayout> {
+    let mut pool = TransientPool::new();
+    let result = context.submit_and_wait(|command_buffer| {
+        discard(context, command_buffer, target.image());
+
+        let mut graph = RenderGraph::new();
+        let desc = ImageDesc::new(FORMAT, EXTENT);
+        let output = graph.import_image(
+            "target",
+            target.image(),
+            target.view(),
+            desc,
+            ImageLayout::TRANSFER_DST_OPTIMAL,
+        );
+        graph.mark_output(output);
+
+        let half = Extent3D {
+            width: EXTENT.width,
+            height: EXTENT.height / 2,
+            depth: 1,
+        };
+        for (name, color, y) in [
+            ("top", [0.8, 0.4, 0.2, 1.0], 0),
+            ("bottom", [0.2, 0.6, 0.4, 1.0], half.height as i32),
+        ] {
+            let image = graph.create_image(name, desc);
+            graph
+                .add_pass(&format!("clear {}", name))
+                .image(image, ImageAccess::TransferDst)
+                .execute(move |pass| {
+                    unsafe {
+                        pass.device().cmd_clear_color_image(
+                            pass.command_buffer(),
+                            pass.image(image),
+                            ImageLayout::TRANSFER_DST_OPTIMAL,
+                            &clear_value(color),
+                            &[color_range()],
+                        )
+                    };
+                    Ok(())
+                });
+            graph
+                .add_pass(&format!("copy {}", name))
+                .image(image, ImageAccess::TransferSrc)
+                .image(output, ImageAccess::TransferDst)
+                .execute(move |pass| {
+                    let region = ImageCopy::default()
+                        .src_subresource(color_layers())
+                        .src_offset(Offset3D { x: 0, y, z: 0 })
+                        .dst_subresource(color_layers())
+                        .dst_offset(Offset3D { x: 0, y, z: 0 })
+                        .extent(half);
+                    unsafe {
+                        pass.device().cmd_copy_image(
+                            pass.command_buffer(),
+                            pass.image(image),
+                            ImageLayout::TRANSFER_SRC_OPTIMAL,
+                            pass.image(output),
+                            ImageLayout::TRANSFER_DST_OPTIMAL,
+                            &[region],
+                        )
+                    };
+                    Ok(())
+                });
+        }
+
+        let unused = graph.create_image("unused", desc);
+        graph
+            .add_pass("unused")
+            .image(unused, ImageAccess::TransferDst)
+            .execute(|_| Err(Error::Other("Culled pass ran".to_string())));
+
+        graph.execute(context, command_buffer, 0, &mut pool)
+    });
+    pool.destroy(context);
+    result.map(|()| ImageLayout::TRANSFER_DST_OPTIMAL)
+}